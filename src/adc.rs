@@ -6,10 +6,16 @@ use embedded_hal::digital::v2::OutputPin;
 use core::marker::PhantomData;
 
 use crate::spi::{SckPolarity::IdleHigh, SckPhase::SampleSecondEdge};
-use crate::{spi::PayloadSPI, PayloadSPIController};
-use crate::pcb_mapping::{peripheral_vcc_values::*, pin_name_types::*};
+use crate::{spi::{PayloadSPI, PayloadSPIAnyMode}, PayloadSPIController};
+#[cfg(any(test, feature = "sim"))]
+use crate::spi_mock::MockCSPin;
+use crate::calibration::ADCCalibration;
+use crate::units::Millivolts;
+use crate::pcb_mapping::peripheral_vcc_values::*;
+#[cfg(target_arch = "msp430")]
+use crate::pcb_mapping::pin_name_types::*;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Copy, Clone)]
 pub enum TargetADC {
 	TetherADC,
 	TemperatureADC,
@@ -28,16 +34,20 @@ pub enum ADCChannel {
 	IN7=7,
 }
 // Shorthand type for each ADC instance
-pub type TetherADC      = ADC<TetherADCCSPin, TetherSensor, ISOLATED_ADC_VCC_VOLTAGE_MILLIVOLTS>;
-pub type TemperatureADC = ADC<TemperatureADCCSPin, TemperatureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>;
-pub type MiscADC        = ADC<MiscADCCSPin, MiscSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>;
-pub type ApertureADC    = ADC<ApertureADCCSPin, ApertureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>;
+pub type TetherADC<CsPin>      = ADC<CsPin, TetherSensor, ISOLATED_ADC_VCC_VOLTAGE_MILLIVOLTS>;
+pub type TemperatureADC<CsPin> = ADC<CsPin, TemperatureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>;
+pub type MiscADC<CsPin>        = ADC<CsPin, MiscSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>;
+pub type ApertureADC<CsPin>    = ADC<CsPin, ApertureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>;
 
 // Generic ADC chip select pin type 
 pub trait ADCCSPin: OutputPin{}
+#[cfg(target_arch = "msp430")]
 impl ADCCSPin for TetherADCCSPin{}
+#[cfg(target_arch = "msp430")]
 impl ADCCSPin for TemperatureADCCSPin{}
+#[cfg(target_arch = "msp430")]
 impl ADCCSPin for MiscADCCSPin{}
+#[cfg(target_arch = "msp430")]
 impl ADCCSPin for ApertureADCCSPin{}
 
 //Types to make sure that we can't read sensor X from ADC Y, because otherwise voltage conversion will be incorrect, etc.
@@ -60,30 +70,117 @@ pub enum VccType {
 //temperature_adc.read_count_from(TemperatureSensor{adc:TemperatureADC, channel:ADCChannel::IN0}) // ok
 //temperature_adc.read_count_from(TetherSensor{adc:TetherADC, channel:ADCChannel::IN0}) // compile error!
 
-const ADC_RESOLUTION: u16 = 4095;
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum ADCError {
+    /// One of the leading zero bits at the start of a reading was set.
+    BadFraming,
+    /// MISO read as all ones, e.g. an unpowered isolator or a disconnected ADC with a pullup.
+    StuckHigh,
+    /// MISO read as all zeroes. Only reported if ADCReadConfig::detect_stuck_low is set.
+    StuckLow,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ADCReadConfig {
+    /// How many more times to try after a bad frame before giving up.
+    pub max_retries: u8,
+    /// All zeroes is a valid frame when every input is at 0V, so this is off by default.
+    pub detect_stuck_low: bool,
+}
+impl Default for ADCReadConfig {
+    fn default() -> Self {
+        ADCReadConfig { max_retries: 2, detect_stuck_low: false }
+    }
+}
+
+pub const MAX_FILTER_SAMPLES: usize = 16;
+
+/// How read_voltage_from and scan_voltages_from combine several samples of one channel into a reading. Chosen per sensor with ADC::set_filter.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ADCFilter {
+    Single,
+    Mean(u8),
+    Median(u8),
+    /// Drop the `trim` highest and lowest samples, then average the rest.
+    TrimmedMean{samples: u8, trim: u8},
+}
+impl ADCFilter {
+    /// Between 1 and MAX_FILTER_SAMPLES.
+    pub fn num_samples(&self) -> usize {
+        let samples = match *self {
+            ADCFilter::Single => 1,
+            ADCFilter::Mean(samples) | ADCFilter::Median(samples) | ADCFilter::TrimmedMean{samples, ..} => samples,
+        };
+        (samples as usize).clamp(1, MAX_FILTER_SAMPLES)
+    }
+    // Expects sorted samples
+    fn combine(&self, samples: &[u16]) -> u16 {
+        let len = samples.len();
+        match *self {
+            ADCFilter::Single | ADCFilter::Mean(_) => mean(samples),
+            ADCFilter::Median(_) if len % 2 == 1 => samples[len / 2],
+            ADCFilter::Median(_) => mean(&samples[len/2 - 1..=len/2]),
+            ADCFilter::TrimmedMean{trim, ..} => {
+                // Always keep at least one sample
+                let trim = (trim as usize).min((len - 1) / 2);
+                mean(&samples[trim..len - trim])
+            },
+        }
+    }
+}
+// Rounds to nearest
+fn mean(samples: &[u16]) -> u16 {
+    let sum: u32 = samples.iter().map(|&sample| sample as u32).sum();
+    let len = samples.len() as u32;
+    (sum + len / 2).checked_div(len).unwrap_or(0) as u16
+}
+
 pub struct ADC<CsPin: ADCCSPin, SensorType:ADCSensor, const VCC_MV: u16>{
     pub cs_pin: CsPin,
+    pub read_config: ADCReadConfig,
+    /// Ideal (based on VCC_MV) until a stored calibration is loaded.
+    pub calibration: ADCCalibration,
+    filters: [ADCFilter; 8],
+    spreads: [u16; 8], // In counts, one per channel
+    cs_held: bool, // Set by read_filtered_count_while_selected_from, transactions leave cs_pin alone while it is
     _adc_type: PhantomData<SensorType>
 }
 // Only allow construction of the ADC type when all fields match
-impl TetherADC{
-    pub fn new(cs_pin: TetherADCCSPin) -> TetherADC {
-        ADC::<TetherADCCSPin, TetherSensor, ISOLATED_ADC_VCC_VOLTAGE_MILLIVOLTS>{cs_pin, _adc_type: PhantomData}
+#[cfg(target_arch = "msp430")]
+impl TetherADC<TetherADCCSPin>{
+    pub fn new(cs_pin: TetherADCCSPin) -> TetherADC<TetherADCCSPin> {
+        ADC::<TetherADCCSPin, TetherSensor, ISOLATED_ADC_VCC_VOLTAGE_MILLIVOLTS>::from_cs_pin(cs_pin)
+    }
+}
+#[cfg(target_arch = "msp430")]
+impl TemperatureADC<TemperatureADCCSPin>{
+    pub fn new(cs_pin: TemperatureADCCSPin) -> TemperatureADC<TemperatureADCCSPin> {
+        ADC::<TemperatureADCCSPin, TemperatureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>::from_cs_pin(cs_pin)
     }
 }
-impl TemperatureADC{
-    pub fn new(cs_pin: TemperatureADCCSPin) -> TemperatureADC {
-        ADC::<TemperatureADCCSPin, TemperatureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>{cs_pin, _adc_type: PhantomData}
+#[cfg(target_arch = "msp430")]
+impl MiscADC<MiscADCCSPin>{
+    pub fn new(cs_pin: MiscADCCSPin) -> MiscADC<MiscADCCSPin> {
+        ADC::<MiscADCCSPin, MiscSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>::from_cs_pin(cs_pin)
     }
 }
-impl MiscADC{
-    pub fn new(cs_pin: MiscADCCSPin) -> MiscADC {
-        ADC::<MiscADCCSPin, MiscSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>{cs_pin, _adc_type: PhantomData}
+#[cfg(target_arch = "msp430")]
+impl ApertureADC<ApertureADCCSPin>{
+    pub fn new(cs_pin: ApertureADCCSPin) -> ApertureADC<ApertureADCCSPin> {
+        ADC::<ApertureADCCSPin, ApertureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>::from_cs_pin(cs_pin)
     }
 }
-impl ApertureADC{
-    pub fn new(cs_pin: ApertureADCCSPin) -> ApertureADC {
-        ADC::<ApertureADCCSPin, ApertureSensor, ADC_VCC_VOLTAGE_MILLIVOLTS>{cs_pin, _adc_type: PhantomData}
+// For testing against MockPayloadSPI. Pick SensorType and VCC_MV to match the ADC being imitated.
+#[cfg(any(test, feature = "sim"))]
+impl<'a, SensorType:ADCSensor, const VCC_MV: u16> ADC<MockCSPin<'a>, SensorType, VCC_MV>{
+    pub fn new_mock(cs_pin: MockCSPin<'a>) -> Self {
+        ADC::from_cs_pin(cs_pin)
+    }
+}
+impl<CsPin: ADCCSPin, SensorType:ADCSensor, const VCC_MV: u16> ADC<CsPin, SensorType, VCC_MV>{
+    // Private so that the constructors above stay the only way to pick the type parameters
+    fn from_cs_pin(cs_pin: CsPin) -> Self {
+        ADC{cs_pin, read_config: ADCReadConfig::default(), calibration: ADCCalibration::ideal(VCC_MV), filters: [ADCFilter::Single; 8], spreads: [0; 8], cs_held: false, _adc_type: PhantomData}
     }
 }
 
@@ -95,13 +192,44 @@ pub const NUM_CYCLES_FOR_TWO_READINGS: u8 = NUM_CYCLES_FOR_ONE_READING * 2;
 pub const NUM_ADDRESS_BITS: u8 = 3;
 pub const NUM_LEADING_ZEROES: u8 = 2;
 
+const BYTES_PER_FRAME: usize = NUM_CYCLES_FOR_ONE_READING as usize / 8;
+// Passed to the bus in place of an ADC's cs_pin. While held the caller keeps the ADC selected, so the bus's select and deselect do nothing.
+struct ChipSelect<'a, CsPin: OutputPin> {
+    pin: &'a mut CsPin,
+    held: bool,
+}
+impl<CsPin: OutputPin> OutputPin for ChipSelect<'_, CsPin> {
+    type Error = CsPin::Error;
+    fn set_low(&mut self) -> Result<(), CsPin::Error> {
+        if self.held { Ok(()) } else { self.pin.set_low() }
+    }
+    fn set_high(&mut self) -> Result<(), CsPin::Error> {
+        if self.held { Ok(()) } else { self.pin.set_high() }
+    }
+}
+
+/// Longest scan done with a single chip select assertion. Longer scans are split into several.
+pub const MAX_CHANNELS_PER_SCAN: usize = 8;
+const MAX_SCAN_BYTES: usize = BYTES_PER_FRAME * (MAX_CHANNELS_PER_SCAN + 1);
+
 impl<CsPin: ADCCSPin, SensorType:ADCSensor, const VCC_MV: u16> ADC<CsPin, SensorType, VCC_MV>{
     // Note: ADC always sends the value of IN0 when first selected, second reading will be from the channel provided.
-    pub fn read_count_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> u16{
+    /// Bad frames are retried up to read_config.max_retries times. If every attempt fails the last error is returned.
+    pub fn read_count_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> Result<u16, ADCError>{
+        let mut result = self.try_read_count_from(wanted_sensor, spi_bus);
+        for _ in 0..self.read_config.max_retries {
+            if result.is_ok() { break; }
+            result = self.try_read_count_from(wanted_sensor, spi_bus);
+        }
+        result
+    }
+    fn try_read_count_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> Result<u16, ADCError>{
         // When SPI packet begins the ADC will track and read channel 1 regardless. 
         // If we want another channel we have to wait until it's finished sending this.
         if wanted_sensor.channel() == ADCChannel::IN0 {
-            return spi_bus.receive(NUM_CYCLES_FOR_ONE_READING, &mut self.cs_pin) as u16;
+            let result = spi_bus.receive(NUM_CYCLES_FOR_ONE_READING, &mut self.chip_select());
+            self.check_frame(result, NUM_CYCLES_FOR_ONE_READING)?;
+            Ok(result as u16)
         }
         else{
             // We need to send the channel we want to read two edges after the start, and it's three bits long.
@@ -109,15 +237,225 @@ impl<CsPin: ADCCSPin, SensorType:ADCSensor, const VCC_MV: u16> ADC<CsPin, Sensor
             // 1 << 31 would put the one-bit-long payload in the MSB, so shift by two fewer for a three-bit payload, and two fewer again to have two zeroes out front
             let data_packet = (wanted_sensor.channel() as u32) << (NUM_CYCLES_FOR_TWO_READINGS - NUM_ADDRESS_BITS - NUM_LEADING_ZEROES);
 
-            let result = spi_bus.send_receive(NUM_CYCLES_FOR_TWO_READINGS, data_packet, &mut self.cs_pin);
-            return (result & 0xFFF) as u16; // We only care about the last reading, which is transmitted in the last 12 edges.
+            let result = spi_bus.send_receive(NUM_CYCLES_FOR_TWO_READINGS, data_packet, &mut self.chip_select());
+            // Both readings are checked, since the IN0 reading is just as good a sign of a broken link.
+            self.check_frame(result, NUM_CYCLES_FOR_TWO_READINGS)?;
+            Ok((result & 0xFFF) as u16) // We only care about the last reading, which is transmitted in the last 12 edges.
+        }
+    }
+    /// Read several sensors with one chip select assertion (per MAX_CHANNELS_PER_SCAN sensors), returning counts in the same order.
+    ///
+    /// Each frame returns the channel addressed in the frame before it, so N sensors take N+1 frames rather than the 2N that read_count_from would need.
+    /// A bad frame anywhere in a scan retries that whole scan, as in read_count_from.
+    /// Filters apply as in read_filtered_count_from. The whole scan is repeated as many times as the sensor with the most samples needs,
+    /// and each sensor combines the first samples its own filter asks for. Any failed scan fails the whole read.
+    pub fn scan_counts_from<const N: usize>(&mut self, wanted_sensors: [&SensorType; N], spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> Result<[u16; N], ADCError>{
+        let num_scans = wanted_sensors.iter().map(|sensor| self.filter(sensor).num_samples()).max().unwrap_or(1);
+        let mut samples = [[0; MAX_FILTER_SAMPLES]; N];
+        for scan in 0..num_scans {
+            let counts = self.scan_once_from(&wanted_sensors, spi_bus)?;
+            for (sensor_samples, count) in samples.iter_mut().zip(counts) {
+                sensor_samples[scan] = count;
+            }
         }
+        Ok(core::array::from_fn(|n| {
+            let channel = wanted_sensors[n].channel() as usize;
+            let num_samples = self.filters[channel].num_samples();
+            self.combine_samples(channel, &mut samples[n][..num_samples])
+        }))
     }
-    pub fn count_to_voltage(&self, count: u16) -> u16{
-        ((count as u32 * VCC_MV as u32) / ADC_RESOLUTION as u32) as u16
+    fn scan_once_from<const N: usize>(&mut self, wanted_sensors: &[&SensorType; N], spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> Result<[u16; N], ADCError>{
+        let mut counts = [0; N];
+        for (sensors, counts) in wanted_sensors.chunks(MAX_CHANNELS_PER_SCAN).zip(counts.chunks_mut(MAX_CHANNELS_PER_SCAN)) {
+            let mut result = self.try_scan_counts_from(sensors, counts, spi_bus);
+            for _ in 0..self.read_config.max_retries {
+                if result.is_ok() { break; }
+                result = self.try_scan_counts_from(sensors, counts, spi_bus);
+            }
+            result?;
+        }
+        Ok(counts)
     }
-    pub fn read_voltage_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut PayloadSPIController) -> u16{
-        let count = self.read_count_from(wanted_sensor, spi_bus.borrow());
-        self.count_to_voltage(count)
+    fn try_scan_counts_from(&mut self, wanted_sensors: &[&SensorType], counts: &mut [u16], spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> Result<(), ADCError>{
+        // Frame n carries the address for frame n+1. Nothing useful is addressed in the last frame, it's only there to clock out the final reading.
+        let mut data_packet = [0; MAX_SCAN_BYTES];
+        for (frame, sensor) in data_packet.chunks_exact_mut(BYTES_PER_FRAME).zip(wanted_sensors) {
+            let address = (sensor.channel() as u16) << (NUM_CYCLES_FOR_ONE_READING - NUM_ADDRESS_BITS - NUM_LEADING_ZEROES);
+            frame.copy_from_slice(&address.to_be_bytes());
+        }
+        let num_bytes = BYTES_PER_FRAME * (wanted_sensors.len() + 1);
+        let mut result = [0; MAX_SCAN_BYTES];
+        spi_bus.send_receive_slice(num_bytes * 8, &data_packet, &mut result, &mut self.chip_select());
+
+        let frames = &result[..num_bytes];
+        self.check_frames(frames)?;
+        // The first frame is IN0, which the ADC always sends first.
+        for (count, frame) in counts.iter_mut().zip(frames.chunks_exact(BYTES_PER_FRAME).skip(1)) {
+            *count = u16::from_be_bytes([frame[0], frame[1]]) & 0xFFF;
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+    fn check_frame(&self, result: u32, len: u8) -> Result<(), ADCError> {
+        let bytes = result.to_be_bytes();
+        self.check_frames(&bytes[bytes.len() - len as usize / 8..])
+    }
+    // The ADC clocks out four zeroes before each 12-bit reading.
+    fn check_frames(&self, frames: &[u8]) -> Result<(), ADCError> {
+        if frames.iter().all(|&byte| byte == 0xFF) {
+            Err(ADCError::StuckHigh)
+        } else if frames.iter().all(|&byte| byte == 0) && self.read_config.detect_stuck_low {
+            Err(ADCError::StuckLow)
+        } else if frames.chunks(BYTES_PER_FRAME).any(|frame| frame[0] & 0xF0 != 0) {
+            Err(ADCError::BadFraming)
+        } else {
+            Ok(())
+        }
+    }
+    pub fn set_filter(&mut self, sensor: &SensorType, filter: ADCFilter) {
+        self.filters[sensor.channel() as usize] = filter;
+    }
+    pub fn filter(&self, sensor: &SensorType) -> ADCFilter {
+        self.filters[sensor.channel() as usize]
+    }
+    /// Difference between the highest and lowest samples behind the last filtered read or scan of this sensor, in millivolts at the ADC input.
+    pub fn last_spread_millivolts(&self, sensor: &SensorType) -> Millivolts {
+        Millivolts::from(self.calibration.count_difference_to_millivolts(self.spreads[sensor.channel() as usize]))
+    }
+    /// Takes as many samples as the sensor's filter asks for and combines them. Any failed sample fails the whole read.
+    pub fn read_filtered_count_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> Result<u16, ADCError>{
+        let channel = wanted_sensor.channel() as usize;
+        let filter = self.filters[channel];
+        let mut samples = [0; MAX_FILTER_SAMPLES];
+        let samples = &mut samples[..filter.num_samples()];
+        for sample in samples.iter_mut() {
+            *sample = self.read_count_from(wanted_sensor, spi_bus)?;
+        }
+        Ok(self.combine_samples(channel, samples))
+    }
+    // Applies the channel's filter and records the spread. Sorts samples in place.
+    fn combine_samples(&mut self, channel: usize, samples: &mut [u16]) -> u16 {
+        samples.sort_unstable();
+        self.spreads[channel] = samples[samples.len() - 1] - samples[0];
+        self.filters[channel].combine(samples)
+    }
+    pub fn count_to_voltage(&self, count: u16) -> Millivolts{
+        Millivolts::from(self.calibration.count_to_millivolts(count))
+    }
+    pub fn read_voltage_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>) -> Result<Millivolts, ADCError>{
+        let count = self.read_filtered_count_from(wanted_sensor, &mut spi_bus.borrow())?;
+        Ok(self.count_to_voltage(count))
+    }
+    /// As read_filtered_count_from, but for a caller that has already selected the ADC (set cs_pin low) and deselects it afterwards.
+    /// Every sample and retry then happens without raising CS, which matters when CS also switches the ADC's power, as on the aperture ADC.
+    /// While selected the ADC converts continuously, each frame returning the channel addressed in the frame before, so reads work as usual.
+    pub fn read_filtered_count_while_selected_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut impl PayloadSPI<{IdleHigh}, {SampleSecondEdge}>) -> Result<u16, ADCError>{
+        self.cs_held = true;
+        let result = self.read_filtered_count_from(wanted_sensor, spi_bus);
+        self.cs_held = false;
+        result
+    }
+    /// As read_voltage_from, but leaves CS alone. See read_filtered_count_while_selected_from.
+    pub fn read_voltage_while_selected_from(&mut self, wanted_sensor: &SensorType, spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>) -> Result<Millivolts, ADCError>{
+        let count = self.read_filtered_count_while_selected_from(wanted_sensor, &mut spi_bus.borrow())?;
+        Ok(self.count_to_voltage(count))
+    }
+    fn chip_select(&mut self) -> ChipSelect<'_, CsPin> {
+        ChipSelect{pin: &mut self.cs_pin, held: self.cs_held}
+    }
+    pub fn scan_voltages_from<const N: usize>(&mut self, wanted_sensors: [&SensorType; N], spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>) -> Result<[Millivolts; N], ADCError>{
+        let counts = self.scan_counts_from(wanted_sensors, &mut spi_bus.borrow())?;
+        Ok(counts.map(|count| self.count_to_voltage(count)))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcb_mapping::sensor_locations::*;
+    use crate::spi_mock::{MockChipSelects, MockPayloadSPI, TransactionKind};
+
+    type MockTetherADC<'a> = TetherADC<MockCSPin<'a>>;
+
+    #[test]
+    fn in0_is_read_from_the_first_frame() {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleHigh}, {SampleSecondEdge}>::new(&chip_selects);
+        let mut adc = MockTetherADC::new_mock(chip_selects.pin(2));
+        bus.script_response(0x0123);
+        assert_eq!(adc.read_count_from(&CATHODE_OFFSET_CURRENT_SENSOR, &mut bus), Ok(0x123));
+        let [transaction] = bus.transactions() else { panic!() };
+        assert_eq!((transaction.kind, transaction.len, transaction.cs_id), (TransactionKind::Receive, 16, Some(2)));
+    }
+
+    #[test]
+    fn channel_address_follows_two_leading_zeroes() {
+        for (sensor, address) in [(&TETHER_BIAS_CURRENT_SENSOR, 0b001), (&REPELLER_VOLTAGE_SENSOR, 0b101), (&HEATER_CURRENT_SENSOR, 0b111)] {
+            let chip_selects = MockChipSelects::new();
+            let mut bus = MockPayloadSPI::<{IdleHigh}, {SampleSecondEdge}>::new(&chip_selects);
+            let mut adc = MockTetherADC::new_mock(chip_selects.pin(2));
+            bus.script_response(0x0FFF_0ABC);
+            assert_eq!(adc.read_count_from(sensor, &mut bus), Ok(0xABC));
+            let [transaction] = bus.transactions() else { panic!() };
+            // 00AAA000 00000000 ... in the first of the two frames
+            assert_eq!((transaction.kind, transaction.len), (TransactionKind::SendReceive, 32));
+            assert_eq!(transaction.mosi_u32(), address << 27, "address {:03b}", address);
+        }
+    }
+
+    #[test]
+    fn scans_address_each_frame_for_the_next() {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleHigh}, {SampleSecondEdge}>::new(&chip_selects);
+        let mut adc = MockTetherADC::new_mock(chip_selects.pin(2));
+        bus.script_response_bits(&[0x00, 0x01, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+        let counts = adc.scan_counts_from([&HEATER_VOLTAGE_SENSOR, &TETHER_BIAS_VOLTAGE_SENSOR, &CATHODE_OFFSET_CURRENT_SENSOR], &mut bus);
+        assert_eq!(counts, Ok([0x304, 0x506, 0x708]));
+        let [transaction] = bus.transactions() else { panic!() };
+        assert_eq!(transaction.len, 64);
+        // IN6, IN3 and IN0 addressed in frames 0-2, nothing in the last
+        assert_eq!(transaction.mosi[..8], [0b0011_0000, 0, 0b0001_1000, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn scans_apply_each_sensors_filter() {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleHigh}, {SampleSecondEdge}>::new(&chip_selects);
+        let mut adc = MockTetherADC::new_mock(chip_selects.pin(2));
+        adc.set_filter(&REPELLER_VOLTAGE_SENSOR, ADCFilter::Median(3));
+        // IN0, then the repeller and heater voltage in each scan. The middle scan has a spike on the repeller.
+        for (repeller, heater) in [(0x100, 0x200), (0xF00, 0x210), (0x102, 0x220)] {
+            let [repeller, heater]: [[u8; 2]; 2] = [u16::to_be_bytes(repeller), u16::to_be_bytes(heater)];
+            bus.script_response_bits(&[0x00, 0x01, repeller[0], repeller[1], heater[0], heater[1]]);
+        }
+        let counts = adc.scan_counts_from([&REPELLER_VOLTAGE_SENSOR, &HEATER_VOLTAGE_SENSOR], &mut bus);
+        // The heater has no filter, so only its first sample counts
+        assert_eq!(counts, Ok([0x102, 0x200]));
+        assert_eq!(bus.transactions().len(), 3);
+        assert_eq!(adc.spreads[ADCChannel::IN5 as usize], 0xF00 - 0x100);
+        assert_eq!(adc.spreads[ADCChannel::IN6 as usize], 0);
+    }
+
+    #[test]
+    fn reads_while_selected_leave_the_chip_selected() {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleHigh}, {SampleSecondEdge}>::new(&chip_selects);
+        // A bad frame to force a retry, then three good samples
+        for response in [0xFFFF, 0x100, 0x102, 0x104] {
+            bus.script_response(response);
+        }
+        let mut controller = PayloadSPIController::new_from_any_mode_bus(bus);
+        let mut adc: ApertureADC<MockCSPin> = ADC::new_mock(chip_selects.pin(5));
+        adc.set_filter(&APERTURE_CURRENT_SENSOR, ADCFilter::Mean(3));
+        adc.cs_pin.set_low().ok();
+        let voltage = adc.read_voltage_while_selected_from(&APERTURE_CURRENT_SENSOR, &mut controller);
+        assert_eq!(voltage, Ok(adc.count_to_voltage(0x102)));
+        assert_eq!(chip_selects.selected(), Some(5));
+        let transactions = controller.spi_bus().transactions();
+        assert_eq!(transactions.len(), 4);
+        assert!(transactions.iter().all(|transaction| transaction.cs_id == Some(5)));
+
+        // Ordinary reads go back to selecting and deselecting the ADC themselves
+        adc.cs_pin.set_high().ok();
+        adc.read_voltage_from(&APERTURE_CURRENT_SENSOR, &mut controller).ok();
+        assert_eq!(chip_selects.selected(), None);
+    }
+}
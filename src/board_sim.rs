@@ -0,0 +1,784 @@
+// This file is a behavioural model of the payload board, so Payload and the automated tests in testing.rs can run without hardware.
+// SimBoard answers the payload SPI bus as the DAC, digipot and ADCs would. Each ADC input is found by running the sensor_equations backwards
+// on a simple model of the supplies driving the mock loads from testing.rs, so a correctly working board model reads back what was set.
+// Faults can be injected into the model to check that the tests which claim to catch them actually report FAIL.
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal::{serial, timer::CountDown};
+use void::Void;
+
+use crate::adc::{ADCChannel, ADC};
+use crate::calibration::CalibrationStore;
+use crate::dac::{DAC, DACChannel, DACCommand};
+use crate::digipot::{self, Digipot, DIGIPOT_MIDSCALE_COUNT};
+use crate::housekeeping::{NUM_LMS_RECEIVERS, NUM_TEMPERATURE_SENSORS, TEMPERATURE_SENSORS};
+use crate::payload::{HVDCSupply, HeaterState::*, Payload, PayloadBuilder, PayloadState::*};
+use crate::pcb_common::PayloadHardware;
+use crate::pcb_mapping::{peripheral_vcc_values::*, power_supply_equations::*, power_supply_locations::*, sensor_equations::*, sensor_locations::*, *};
+use crate::power::{PowerDomains, PowerTimer};
+use crate::spi::{bit_is_set, bits_to_u32, clear_bits, set_bit, BusMode, PayloadSPIAnyMode, PayloadSPIController};
+use crate::spi::{SckPhase::*, SckPolarity::*};
+use crate::spi_mock::{MockCSPin, MockChipSelects};
+use crate::testing::{heater_mock, hvdc_mock, pinpuller_mock, test_temperature_sensors_against_known_temp, AutomatedFunctionalTests, AutomatedPerformanceTests};
+use crate::units::{Kelvin, Microamps, Milliamps, Millivolts, Ohms};
+use crate::println;
+use ufmt::{uwrite, uwriteln};
+
+const ADC_MAX_COUNT: i32 = 4095;
+const ADC_FRAME_BITS: usize = 16;
+const NUM_ADC_CHANNELS: usize = 8;
+const ADC_CHANNELS: [ADCChannel; NUM_ADC_CHANNELS] = [ADCChannel::IN0, ADCChannel::IN1, ADCChannel::IN2, ADCChannel::IN3, ADCChannel::IN4, ADCChannel::IN5, ADCChannel::IN6, ADCChannel::IN7];
+/// Longer transactions still happen, but MISO reads high past this point.
+pub const MAX_SIM_TRANSACTION_BITS: usize = 256;
+const MAX_SIM_TRANSACTION_BYTES: usize = MAX_SIM_TRANSACTION_BITS / 8;
+const NUM_DAC_CHANNELS: usize = 4;
+const NUM_DIGIPOT_CHANNELS: usize = 2;
+/// How many faults can be injected at once.
+pub const MAX_SIM_FAULTS: usize = 4;
+
+/// The chips on the payload SPI bus. Also the id of each chip's MockCSPin.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum SimChip {
+    Digipot = 0,
+    DAC = 1,
+    TetherADC = 2,
+    TemperatureADC = 3,
+    MiscADC = 4,
+    ApertureADC = 5,
+}
+const SIM_CHIPS: [SimChip; 6] = [SimChip::Digipot, SimChip::DAC, SimChip::TetherADC, SimChip::TemperatureADC, SimChip::MiscADC, SimChip::ApertureADC];
+impl SimChip {
+    fn from_id(id: u8) -> Option<SimChip> {
+        SIM_CHIPS.get(id as usize).copied()
+    }
+    /// Chips behind the payload enable switch. The others run from the always-on supplies.
+    fn needs_payload_power(self) -> bool {
+        matches!(self, SimChip::Digipot | SimChip::DAC | SimChip::TetherADC)
+    }
+    /// The bus mode the chip reads and writes in. In any other mode it's treated as not answering.
+    pub fn mode(self) -> BusMode {
+        match self {
+            SimChip::Digipot | SimChip::DAC => BusMode::of::<{IdleLow}, {SampleFirstEdge}>(),
+            SimChip::TetherADC | SimChip::TemperatureADC | SimChip::MiscADC | SimChip::ApertureADC => BusMode::of::<{IdleHigh}, {SampleSecondEdge}>(),
+        }
+    }
+}
+
+/// Every digital line between the MSP430 and the payload circuitry, other than chip selects.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum SimLine {
+    PayloadEnable,
+    HeaterEnable,
+    CathodeSwitch,
+    TetherSwitch,
+    BurnWire1,
+    BurnWire1Backup,
+    BurnWire2,
+    BurnWire2Backup,
+    LMSReceiverEnable,
+    LMSLEDEnable,
+    RedLED,
+    YellowLED,
+    GreenLED,
+    /// Inputs. Set these with SimBoard::set_line.
+    EndmassSense1,
+    EndmassSense2,
+    PinpullerSense,
+}
+const BURN_WIRES: [SimLine; 4] = [SimLine::BurnWire1, SimLine::BurnWire1Backup, SimLine::BurnWire2, SimLine::BurnWire2Backup];
+
+/// What's attached to the board and what it can sense. Defaults to the mock loads from testing.rs on a bench at room temperature.
+#[derive(Copy, Clone, Debug)]
+pub struct SimEnvironment {
+    /// In the order of housekeeping::TEMPERATURE_SENSORS.
+    pub temperatures: [Kelvin; NUM_TEMPERATURE_SENSORS],
+    /// LMS receiver outputs with the LEDs off.
+    pub lms_ambient: [Millivolts; NUM_LMS_RECEIVERS],
+    /// Added to each receiver's output while the LEDs are on.
+    pub lms_lit: [Millivolts; NUM_LMS_RECEIVERS],
+    pub repeller_voltage: Millivolts,
+    pub aperture_current: Microamps,
+    /// Loads between each HVDC supply and cathode-, connected while the supply's switch is closed.
+    pub tether_bias_load: Ohms,
+    pub cathode_offset_load: Ohms,
+    pub heater_load_milliohms: u32,
+    /// Current drawn by the pinpuller while any burn wire is on.
+    pub pinpuller_current: Milliamps,
+}
+impl Default for SimEnvironment {
+    fn default() -> Self {
+        SimEnvironment {
+            temperatures: [Kelvin::from_celcius(25); NUM_TEMPERATURE_SENSORS],
+            lms_ambient: [Millivolts(200); NUM_LMS_RECEIVERS],
+            lms_lit: [Millivolts(2000); NUM_LMS_RECEIVERS],
+            repeller_voltage: Millivolts::ZERO,
+            aperture_current: Microamps::ZERO,
+            tether_bias_load: hvdc_mock::MOCK_TETHER_BIAS_RESISTANCE_OHMS,
+            cathode_offset_load: hvdc_mock::MOCK_CATHODE_OFFSET_RESISTANCE_OHMS,
+            heater_load_milliohms: (heater_mock::CIRCUIT_RESISTANCE_MOHMS as u32) - HEATER_SENSE_RESISTANCE_MILLIOHMS,
+            pinpuller_current: Milliamps(pinpuller_mock::EXPECTED_ON_CURRENT.to_num()),
+        }
+    }
+}
+
+/// A hardware fault that can be injected into a SimBoard.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum SimFault {
+    /// The chip drives MISO to this level regardless of what it was asked.
+    StuckMISO(SimChip, bool),
+    /// This bit (counting from the start of each transaction) of the chip's responses is inverted.
+    FlippedMISOBit(SimChip, usize),
+    /// The chip never sees its chip select, so ignores commands and leaves MISO pulled high.
+    DeadChipSelect(SimChip),
+    /// Heater element disconnected. The supply still regulates, but no current flows.
+    OpenHeater,
+    /// Thermistor shorted to ground, pulling its ADC input to 0V. Indexes housekeeping::TEMPERATURE_SENSORS.
+    ShortedThermistor(usize),
+    /// The supply's output stays at 0V whatever the DAC asks for.
+    HVDCStuckAtZero(HVDCSupply),
+    /// The pinpuller draws nothing when only this burn wire is on.
+    DeadBurnWire(SimLine),
+}
+
+// The registers of the chips that can't be read back
+#[derive(Copy, Clone)]
+struct SimRegisters {
+    dac_input: [u16; NUM_DAC_CHANNELS],
+    dac_output: [u16; NUM_DAC_CHANNELS],
+    dac_powered_down: [bool; NUM_DAC_CHANNELS],
+    wipers: [u8; NUM_DIGIPOT_CHANNELS],
+}
+impl SimRegisters {
+    // The DAC powers up at zero scale and the digipot at midscale.
+    const POWER_ON: SimRegisters = SimRegisters {
+        dac_input: [0; NUM_DAC_CHANNELS],
+        dac_output: [0; NUM_DAC_CHANNELS],
+        dac_powered_down: [false; NUM_DAC_CHANNELS],
+        wipers: [DIGIPOT_MIDSCALE_COUNT; NUM_DIGIPOT_CHANNELS],
+    };
+}
+
+/// The simulated board. Pins, timers and the SPI bus handed to the Payload all refer back to it, so it has to outlive the Payload.
+///
+/// Everything settles instantly: supply outputs follow their setpoints as soon as they're written.
+pub struct SimBoard {
+    chip_selects: MockChipSelects,
+    // Bit n is set while the SimLine with discriminant n is high
+    lines: Cell<u32>,
+    registers: Cell<SimRegisters>,
+    environment: Cell<SimEnvironment>,
+    faults: Cell<[Option<SimFault>; MAX_SIM_FAULTS]>,
+    ticks: Cell<u32>,
+    transactions: Cell<u32>,
+}
+impl SimBoard {
+    pub fn new() -> SimBoard {
+        SimBoard {
+            chip_selects: MockChipSelects::new(),
+            lines: Cell::new(0),
+            registers: Cell::new(SimRegisters::POWER_ON),
+            environment: Cell::new(SimEnvironment::default()),
+            faults: Cell::new([None; MAX_SIM_FAULTS]),
+            ticks: Cell::new(0),
+            transactions: Cell::new(0),
+        }
+    }
+    /// A Payload wired to this board. calibration_memory stands in for the FRAM calibration records, blank memory leaves every ADC ideal.
+    /// serial_input is what the debug serial port receives, for tests that ask the user for input.
+    pub fn build_payload<'a>(&'a self, calibration_memory: &'static mut [u8; 512], serial_input: &'a [u8]) -> Payload<{PayloadOff}, {HeaterOff}, SimHardware<'a>> {
+        let cs_pin = |chip: SimChip| self.chip_selects.pin(chip as u8);
+        let pin = |line: SimLine| SimPin { board: self, line };
+        let periph = PayloadPeripherals {
+            digipot:         Digipot::new(cs_pin(SimChip::Digipot)),
+            dac:             DAC::new(cs_pin(SimChip::DAC)),
+            tether_adc:      ADC::new_mock(cs_pin(SimChip::TetherADC)),
+            temperature_adc: ADC::new_mock(cs_pin(SimChip::TemperatureADC)),
+            misc_adc:        ADC::new_mock(cs_pin(SimChip::MiscADC)),
+            aperture_adc:    ADC::new_mock(cs_pin(SimChip::ApertureADC)),
+        };
+        let pins = PayloadControlPins {
+            payload_enable: pin(SimLine::PayloadEnable),
+            heater_enable:  pin(SimLine::HeaterEnable),
+            cathode_switch: pin(SimLine::CathodeSwitch),
+            tether_switch:  pin(SimLine::TetherSwitch),
+        };
+        let pinpuller_pins = PinpullerActivationPins {
+            burn_wire_1:        pin(SimLine::BurnWire1),
+            burn_wire_1_backup: pin(SimLine::BurnWire1Backup),
+            burn_wire_2:        pin(SimLine::BurnWire2),
+            burn_wire_2_backup: pin(SimLine::BurnWire2Backup),
+        };
+        let lms_control_pins = TetherLMSPins { lms_receiver_enable: pin(SimLine::LMSReceiverEnable), lms_led_enable: pin(SimLine::LMSLEDEnable) };
+        let deploy_sense_pins = DeploySensePins {
+            endmass_sense_1: pin(SimLine::EndmassSense1),
+            endmass_sense_2: pin(SimLine::EndmassSense2),
+            pinpuller_sense: pin(SimLine::PinpullerSense),
+        };
+        let led_pins = LEDPins { red_led: pin(SimLine::RedLED), yellow_led: pin(SimLine::YellowLED), green_led: pin(SimLine::GreenLED) };
+        let spi = PayloadSPIController::new_from_any_mode_bus(SimPayloadSPI { board: self, applied_mode: BusMode::of::<{IdleHigh}, {SampleFirstEdge}>() });
+
+        PayloadBuilder::build(periph, pins, spi, pinpuller_pins, lms_control_pins, deploy_sense_pins, SimSerial { input: serial_input },
+                              led_pins, SimTimer { board: self }, CalibrationStore::new(calibration_memory), PowerDomains::new(SimTimer { board: self }))
+    }
+
+    pub fn environment(&self) -> SimEnvironment {
+        self.environment.get()
+    }
+    pub fn set_environment(&self, environment: SimEnvironment) {
+        self.environment.set(environment);
+    }
+    /// Returns false if MAX_SIM_FAULTS are already injected.
+    pub fn inject_fault(&self, fault: SimFault) -> bool {
+        let mut faults = self.faults.get();
+        let Some(slot) = faults.iter_mut().find(|slot| slot.is_none()) else { return false };
+        *slot = Some(fault);
+        self.faults.set(faults);
+        true
+    }
+    pub fn clear_faults(&self) {
+        self.faults.set([None; MAX_SIM_FAULTS]);
+    }
+    pub fn has_fault(&self, fault: SimFault) -> bool {
+        self.faults.get().contains(&Some(fault))
+    }
+    pub fn line(&self, line: SimLine) -> bool {
+        self.lines.get() & (1 << line as u8) != 0
+    }
+    /// Drive a line, as the MSP430 does for outputs or the outside world does for inputs.
+    pub fn set_line(&self, line: SimLine, high: bool) {
+        if line == SimLine::PayloadEnable && high && !self.line(line) {
+            self.registers.set(SimRegisters::POWER_ON);
+        }
+        let lines = self.lines.get();
+        self.lines.set(if high { lines | (1 << line as u8) } else { lines & !(1 << line as u8) });
+    }
+    /// Power domain timer ticks so far. Time only passes when the timer is read.
+    pub fn ticks(&self) -> u32 {
+        self.ticks.get()
+    }
+    /// Payload SPI transactions so far, whether or not a chip answered.
+    pub fn transactions(&self) -> u32 {
+        self.transactions.get()
+    }
+
+    /* Model */
+    fn payload_on(&self) -> bool {
+        self.line(SimLine::PayloadEnable)
+    }
+    pub fn dac_output_voltage(&self, channel: DACChannel) -> Millivolts {
+        let registers = self.registers.get();
+        let n = channel as usize;
+        if !self.payload_on() || registers.dac_powered_down[n] {
+            return Millivolts::ZERO;
+        }
+        Millivolts((registers.dac_output[n] as u32 * DAC_VCC_VOLTAGE_MILLIVOLTS as u32 / DAC_RESOLUTION.max_count() as u32) as i32)
+    }
+    pub fn wiper_resistance(&self, channel: crate::digipot::DigipotChannel) -> Ohms {
+        digipot::count_to_resistance(self.registers.get().wipers[channel as usize])
+    }
+    pub fn tether_bias_voltage(&self) -> Millivolts {
+        if !self.payload_on() || self.has_fault(SimFault::HVDCStuckAtZero(HVDCSupply::TetherBias)) { return Millivolts::ZERO; }
+        dac_voltage_to_tether_bias_voltage(self.dac_output_voltage(TETHER_BIAS_SUPPLY_CONTROL_CHANNEL))
+    }
+    pub fn cathode_offset_voltage(&self) -> Millivolts {
+        if !self.payload_on() || self.has_fault(SimFault::HVDCStuckAtZero(HVDCSupply::CathodeOffset)) { return Millivolts::ZERO; }
+        dac_voltage_to_cathode_offset_voltage(self.dac_output_voltage(CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL))
+    }
+    pub fn tether_bias_current(&self) -> Microamps {
+        if !self.line(SimLine::TetherSwitch) { return Microamps::ZERO; }
+        let load = self.environment().tether_bias_load + TETHER_SENSE_RESISTANCE_OHMS;
+        self.tether_bias_voltage().current_through(load).unwrap_or(Microamps::ZERO)
+    }
+    pub fn cathode_offset_current(&self) -> Microamps {
+        if !self.line(SimLine::CathodeSwitch) { return Microamps::ZERO; }
+        let load = self.environment().cathode_offset_load + CATHODE_SENSE_RESISTANCE_OHMS;
+        self.cathode_offset_voltage().current_through(load).unwrap_or(Microamps::ZERO)
+    }
+    pub fn heater_voltage(&self) -> Millivolts {
+        if !self.payload_on() || !self.line(SimLine::HeaterEnable) { return Millivolts::ZERO; }
+        digipot_resistance_to_heater_voltage(self.wiper_resistance(HEATER_DIGIPOT_CHANNEL))
+    }
+    pub fn heater_current(&self) -> Milliamps {
+        if self.has_fault(SimFault::OpenHeater) { return Milliamps::ZERO; }
+        let circuit_milliohms = self.environment().heater_load_milliohms + HEATER_SENSE_RESISTANCE_MILLIOHMS;
+        Milliamps((self.heater_voltage().0 as i64 * 1000 / circuit_milliohms.max(1) as i64) as i32)
+    }
+    pub fn pinpuller_current(&self) -> Milliamps {
+        if BURN_WIRES.iter().any(|&line| self.line(line) && !self.has_fault(SimFault::DeadBurnWire(line))) { self.environment().pinpuller_current } else { Milliamps::ZERO }
+    }
+    pub fn lms_receiver_voltage(&self, receiver: usize) -> Millivolts {
+        if !self.line(SimLine::LMSReceiverEnable) { return Millivolts::ZERO; }
+        let environment = self.environment();
+        let lit = if self.line(SimLine::LMSLEDEnable) { environment.lms_lit[receiver] } else { Millivolts::ZERO };
+        environment.lms_ambient[receiver] + lit
+    }
+
+    // Voltage at an ADC input. Unconnected channels read zero.
+    fn adc_input(&self, chip: SimChip, channel: ADCChannel) -> Millivolts {
+        let environment = self.environment();
+        let tether_vcc = Millivolts::from(ISOLATED_ADC_VCC_VOLTAGE_MILLIVOLTS);
+        let vcc = Millivolts::from(ADC_VCC_VOLTAGE_MILLIVOLTS);
+        match chip {
+            SimChip::TetherADC => {
+                if channel == CATHODE_OFFSET_CURRENT_SENSOR.channel { invert(cathode_offset_current_eq, self.cathode_offset_current().0, tether_vcc) }
+                else if channel == TETHER_BIAS_CURRENT_SENSOR.channel { invert(tether_bias_current_eq, self.tether_bias_current().0, tether_vcc) }
+                else if channel == TETHER_BIAS_VOLTAGE_SENSOR.channel { invert(tether_bias_voltage_eq, self.tether_bias_voltage().0, tether_vcc) }
+                else if channel == CATHODE_OFFSET_VOLTAGE_SENSOR.channel { invert(cathode_offset_voltage_eq, self.cathode_offset_voltage().0, tether_vcc) }
+                else if channel == REPELLER_VOLTAGE_SENSOR.channel { invert(repeller_voltage_eq, environment.repeller_voltage.0, tether_vcc) }
+                else if channel == HEATER_VOLTAGE_SENSOR.channel { invert(heater_voltage_eq, self.heater_voltage().0, tether_vcc) }
+                else if channel == HEATER_CURRENT_SENSOR.channel { invert(heater_current_eq, self.heater_current().0, tether_vcc) }
+                else { Millivolts::ZERO }
+            },
+            SimChip::TemperatureADC => {
+                let Some(n) = TEMPERATURE_SENSORS.iter().position(|(sensor, _)| sensor.channel == channel) else { return Millivolts::ZERO };
+                if self.has_fault(SimFault::ShortedThermistor(n)) { return Millivolts::ZERO; }
+                let (sensor, _) = &TEMPERATURE_SENSORS[n];
+                // The thermistor divider's top is the sensor's own supply, and both ends of its range are singular
+                let (eq, sensor_vcc): (fn(Millivolts) -> Kelvin, i32) = match sensor.vcc {
+                    crate::adc::VccType::LMS => (lms_temperature_eq, 3300),
+                    crate::adc::VccType::Payload => (payload_temperature_eq, 5000),
+                };
+                invert_between(eq, environment.temperatures[n].0, Millivolts(1), Millivolts(sensor_vcc.min(vcc.0) - 1))
+            },
+            SimChip::MiscADC => {
+                let receivers = [LMS_RECEIVER_1_SENSOR, LMS_RECEIVER_2_SENSOR, LMS_RECEIVER_3_SENSOR];
+                if channel == PINPULLER_CURRENT_SENSOR.channel { invert(pinpuller_current_sensor_eq, self.pinpuller_current().0, vcc) }
+                else if let Some(n) = receivers.iter().position(|sensor| sensor.channel == channel) { self.lms_receiver_voltage(n) }
+                else { Millivolts::ZERO }
+            },
+            SimChip::ApertureADC => {
+                if channel == APERTURE_CURRENT_SENSOR.channel { invert(aperture_current_sensor_eq, environment.aperture_current.0, vcc) }
+                else { Millivolts::ZERO }
+            },
+            SimChip::Digipot | SimChip::DAC => Millivolts::ZERO,
+        }
+    }
+    fn adc_count(&self, chip: SimChip, channel: ADCChannel) -> u16 {
+        let vcc = if chip == SimChip::TetherADC { ISOLATED_ADC_VCC_VOLTAGE_MILLIVOLTS } else { ADC_VCC_VOLTAGE_MILLIVOLTS } as i32;
+        let millivolts = self.adc_input(chip, channel).0.clamp(0, vcc);
+        ((millivolts * ADC_MAX_COUNT + vcc / 2) / vcc) as u16
+    }
+
+    /* SPI */
+    // Fills miso (MSB-first, already all ones) with the selected chip's response to mosi, then applies any bus faults.
+    fn respond(&self, chip: SimChip, len: usize, mosi: &[u8], miso: &mut [u8]) {
+        let powered = !chip.needs_payload_power() || self.payload_on();
+        if powered && !self.has_fault(SimFault::DeadChipSelect(chip)) {
+            self.respond_ideal(chip, len, mosi, miso); // otherwise MISO stays pulled high
+        }
+        for fault in self.faults.get().into_iter().flatten() {
+            match fault {
+                SimFault::StuckMISO(faulty_chip, high) if faulty_chip == chip => miso.fill(if high { 0xFF } else { 0x00 }),
+                SimFault::FlippedMISOBit(faulty_chip, n) if faulty_chip == chip && n < MAX_SIM_TRANSACTION_BITS => miso[n / 8] ^= 0x80 >> (n % 8),
+                _ => (),
+            }
+        }
+    }
+    fn respond_ideal(&self, chip: SimChip, len: usize, mosi: &[u8], miso: &mut [u8]) {
+        let word = bits_to_u32(len.min(32) as u8, [mosi[0], mosi[1], mosi[2], mosi[3]]);
+        match chip {
+            SimChip::DAC => self.dac_command(word),
+            SimChip::Digipot => self.digipot_command(word),
+            SimChip::TetherADC | SimChip::TemperatureADC | SimChip::MiscADC | SimChip::ApertureADC => self.adc_frames(chip, len, mosi, miso),
+        }
+    }
+    // See the packet format in dac.rs
+    fn dac_command(&self, word: u32) {
+        let command = (word >> 20) & 0xF;
+        let address = ((word >> 16) & 0xF) as usize;
+        let value = ((word & 0xFFFF) >> (16 - DAC_RESOLUTION.num_data_bits())) as u16;
+        let channels = if address == DACChannel::AllChannels as usize { 0..NUM_DAC_CHANNELS } else { address..(address + 1).min(NUM_DAC_CHANNELS) };
+        let mut registers = self.registers.get();
+        let update = |registers: &mut SimRegisters, n: usize| { registers.dac_output[n] = registers.dac_input[n]; registers.dac_powered_down[n] = false; };
+        for n in channels.clone() {
+            match command {
+                c if c == DACCommand::WriteToRegisterX as u32 => registers.dac_input[n] = value,
+                c if c == DACCommand::UpdateRegisterX as u32 => update(&mut registers, n),
+                c if c == DACCommand::WriteToAndUpdateRegisterX as u32 => { registers.dac_input[n] = value; update(&mut registers, n) },
+                c if c == DACCommand::WriteToRegisterXAndUpdateAll as u32 => registers.dac_input[n] = value,
+                c if c == DACCommand::PowerOffChannelX as u32 => registers.dac_powered_down[n] = true,
+                _ => (),
+            }
+        }
+        if command == DACCommand::WriteToRegisterXAndUpdateAll as u32 {
+            for n in 0..NUM_DAC_CHANNELS { update(&mut registers, n); }
+        } else if command == DACCommand::PowerOffChip as u32 {
+            registers.dac_powered_down = [true; NUM_DAC_CHANNELS];
+        }
+        self.registers.set(registers);
+    }
+    // A1 D7..D0, see digipot.rs
+    fn digipot_command(&self, word: u32) {
+        let mut registers = self.registers.get();
+        registers.wipers[((word >> 8) & 1) as usize] = word as u8;
+        self.registers.set(registers);
+    }
+    // Each 16-bit frame returns four zeroes then the channel addressed in the previous frame. The first frame returns IN0.
+    fn adc_frames(&self, chip: SimChip, len: usize, mosi: &[u8], miso: &mut [u8]) {
+        let mut channel = ADCChannel::IN0;
+        for (frame, (mosi_frame, miso_frame)) in mosi.chunks_exact(2).zip(miso.chunks_exact_mut(2)).enumerate() {
+            if frame * ADC_FRAME_BITS >= len { break; }
+            miso_frame.copy_from_slice(&self.adc_count(chip, channel).to_be_bytes());
+            channel = ADC_CHANNELS[((u16::from_be_bytes([mosi_frame[0], mosi_frame[1]]) >> 11) & 0x7) as usize];
+        }
+    }
+}
+impl Default for SimBoard {
+    fn default() -> Self {
+        SimBoard::new()
+    }
+}
+
+// ADC input voltage (between 0 and max) at which eq gives the value closest to target. eq must be monotonic in that range.
+fn invert<T: Into<i32>>(eq: fn(Millivolts) -> T, target: i32, max: Millivolts) -> Millivolts {
+    invert_between(eq, target, Millivolts::ZERO, max)
+}
+fn invert_between<T: Into<i32>>(eq: fn(Millivolts) -> T, target: i32, min: Millivolts, max: Millivolts) -> Millivolts {
+    let value = |millivolts: i32| -> i32 { eq(Millivolts(millivolts)).into() };
+    let rising = value(max.0) >= value(min.0);
+    let (mut low, mut high) = (min.0, max.0);
+    // Bisect for the pair of neighbouring inputs either side of target
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if (value(mid) < target) == rising { low = mid; } else { high = mid; }
+    }
+    if (value(low) - target).abs() <= (value(high) - target).abs() { Millivolts(low) } else { Millivolts(high) }
+}
+
+/// Payload SPI bus connected to a SimBoard. A chip only responds if the bus is in its mode (see SimChip::mode), otherwise MISO stays pulled high.
+pub struct SimPayloadSPI<'a> {
+    board: &'a SimBoard,
+    applied_mode: BusMode,
+}
+impl SimPayloadSPI<'_> {
+    pub fn applied_mode(&self) -> BusMode {
+        self.applied_mode
+    }
+    fn transaction(&mut self, len: usize, data: Option<&[u8]>, result: Option<&mut [u8]>, cs_pin: &mut impl OutputPin) {
+        let mut mosi = [0; MAX_SIM_TRANSACTION_BYTES];
+        if let Some(data) = data {
+            for n in (0..len.min(MAX_SIM_TRANSACTION_BITS)).filter(|&n| bit_is_set(data, n)) { set_bit(&mut mosi, n); }
+        }
+        let mut miso = [0xFF; MAX_SIM_TRANSACTION_BYTES];
+
+        self.board.transactions.set(self.board.transactions.get() + 1);
+        cs_pin.set_low().ok();
+        if let Some(chip) = self.board.chip_selects.selected().and_then(SimChip::from_id).filter(|chip| chip.mode() == self.applied_mode) {
+            self.board.respond(chip, len, &mosi, &mut miso);
+        }
+        cs_pin.set_high().ok();
+
+        if let Some(result) = result {
+            clear_bits(result, len);
+            for n in (0..len).filter(|&n| n >= MAX_SIM_TRANSACTION_BITS || bit_is_set(&miso, n)) { set_bit(result, n); }
+        }
+    }
+}
+impl PayloadSPIAnyMode for SimPayloadSPI<'_> {
+    fn set_mode(&mut self, mode: BusMode) {
+        self.applied_mode = mode;
+    }
+    fn send_in_mode(&mut self, _mode: BusMode, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(len, Some(data), None, cs_pin)
+    }
+    fn receive_in_mode(&mut self, _mode: BusMode, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(len, None, Some(result), cs_pin)
+    }
+    fn send_receive_in_mode(&mut self, _mode: BusMode, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(len, Some(data), Some(result), cs_pin)
+    }
+}
+
+/// A digital line on a SimBoard. Works as either an output or an input.
+pub struct SimPin<'a> {
+    board: &'a SimBoard,
+    line: SimLine,
+}
+impl OutputPin for SimPin<'_> {
+    type Error = Void;
+    fn set_low(&mut self) -> Result<(), Void> {
+        self.board.set_line(self.line, false);
+        Ok(())
+    }
+    fn set_high(&mut self) -> Result<(), Void> {
+        self.board.set_line(self.line, true);
+        Ok(())
+    }
+}
+impl StatefulOutputPin for SimPin<'_> {
+    fn is_set_high(&self) -> Result<bool, Void> {
+        Ok(self.board.line(self.line))
+    }
+    fn is_set_low(&self) -> Result<bool, Void> {
+        Ok(!self.board.line(self.line))
+    }
+}
+impl InputPin for SimPin<'_> {
+    type Error = Void;
+    fn is_high(&self) -> Result<bool, Void> {
+        Ok(self.board.line(self.line))
+    }
+    fn is_low(&self) -> Result<bool, Void> {
+        Ok(!self.board.line(self.line))
+    }
+}
+
+/// Stands in for both the general purpose timer and the power domain timer.
+/// Each read of the power timer advances the board's clock by one tick, so busy-waits end. Countdowns expire immediately.
+pub struct SimTimer<'a> {
+    board: &'a SimBoard,
+}
+impl PowerTimer for SimTimer<'_> {
+    fn start(&mut self, _period: u16) {}
+    fn count(&mut self) -> u16 {
+        let ticks = self.board.ticks.get().wrapping_add(1);
+        self.board.ticks.set(ticks);
+        ticks as u16
+    }
+}
+impl CountDown for SimTimer<'_> {
+    type Time = u16;
+    fn start<T: Into<u16>>(&mut self, _count: T) {}
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        Ok(())
+    }
+}
+
+/// Debug serial receiver that plays back a fixed input, then blocks forever.
+pub struct SimSerial<'a> {
+    input: &'a [u8],
+}
+impl serial::Read<u8> for SimSerial<'_> {
+    type Error = Void;
+    fn read(&mut self) -> nb::Result<u8, Void> {
+        let (&byte, rest) = self.input.split_first().ok_or(nb::Error::WouldBlock)?;
+        self.input = rest;
+        Ok(byte)
+    }
+}
+
+/// PayloadHardware for a Payload built by SimBoard::build_payload.
+pub struct SimHardware<'a>(PhantomData<&'a SimBoard>);
+impl<'a> PayloadHardware for SimHardware<'a> {
+    type RedLEDPin = SimPin<'a>;
+    type YellowLEDPin = SimPin<'a>;
+    type GreenLEDPin = SimPin<'a>;
+
+    type DigipotCSPin = MockCSPin<'a>;
+    type DACCSPin = MockCSPin<'a>;
+    type TetherADCCSPin = MockCSPin<'a>;
+    type TemperatureADCCSPin = MockCSPin<'a>;
+    type MiscADCCSPin = MockCSPin<'a>;
+    type ApertureADCCSPin = MockCSPin<'a>;
+    type PayloadSPIBus = SimPayloadSPI<'a>;
+
+    type PayloadEnablePin = SimPin<'a>;
+    type HeaterEnablePin = SimPin<'a>;
+    type CathodeSwitchPin = SimPin<'a>;
+    type TetherSwitchPin = SimPin<'a>;
+
+    type EndmassSense1Pin = SimPin<'a>;
+    type EndmassSense2Pin = SimPin<'a>;
+    type PinpullerDeploySensePin = SimPin<'a>;
+
+    type BurnWire1Pin = SimPin<'a>;
+    type BurnWire1BackupPin = SimPin<'a>;
+    type BurnWire2Pin = SimPin<'a>;
+    type BurnWire2BackupPin = SimPin<'a>;
+
+    type TetherLMSReceiverEnablePin = SimPin<'a>;
+    type TetherLMSLEDEnablePin = SimPin<'a>;
+
+    type SerialReader = SimSerial<'a>;
+    type Timer = SimTimer<'a>;
+    type PowerTimer = SimTimer<'a>;
+}
+
+/// Runs the automated functional and performance tests against board, printing the same report as on hardware.
+/// Returns whether every test passed.
+pub fn run_automated_tests(board: &SimBoard, calibration_memory: &'static mut [u8; 512]) -> bool {
+    let payload = board.build_payload(calibration_memory, &[]);
+    let mut payload = payload.into_enabled_payload().into_enabled_heater();
+
+    let functional_passed = AutomatedFunctionalTests::full_system_test(&mut payload);
+    let performance_passed = AutomatedPerformanceTests::full_system_test(&mut payload);
+
+    payload.into_disabled_heater().into_disabled_payload();
+    functional_passed && performance_passed
+}
+
+/// Each fault, and the results from the automated tests that should report FAIL while it's injected.
+pub const FAULT_CHECKS: [(SimFault, &[&str]); 10] = [
+    (SimFault::StuckMISO(SimChip::TetherADC, true),         &["Tether ADC"]),
+    (SimFault::StuckMISO(SimChip::MiscADC, false),          &["Pinpuller channel 1", "Length measurement system 1", "Pinpuller current sense"]),
+    (SimFault::FlippedMISOBit(SimChip::TemperatureADC, 0),  &["Temperature ADC"]),
+    (SimFault::DeadChipSelect(SimChip::ApertureADC),        &["Aperture ADC"]),
+    (SimFault::OpenHeater,                                  &["Heater current"]),
+    (SimFault::ShortedThermistor(2),                        &["MSP430"]),
+    (SimFault::ShortedThermistor(6),                        &["Tether connector"]),
+    (SimFault::HVDCStuckAtZero(HVDCSupply::TetherBias),     &["Tether bias voltage", "Tether bias current"]),
+    (SimFault::HVDCStuckAtZero(HVDCSupply::CathodeOffset),  &["Cathode offset voltage", "Cathode offset current"]),
+    (SimFault::DeadBurnWire(SimLine::BurnWire2Backup),      &["Pinpuller channel 2 backup"]),
+];
+
+const MAX_EXPECTED_FAILURES: usize = 4;
+
+/// Which of a fault's expected failures were reported.
+pub struct FaultCheck<'a> {
+    pub expected_failures: &'a [&'a str],
+    reported: [bool; MAX_EXPECTED_FAILURES],
+}
+impl<'a> FaultCheck<'a> {
+    fn new(expected_failures: &'a [&'a str]) -> FaultCheck<'a> {
+        assert!(expected_failures.len() <= MAX_EXPECTED_FAILURES);
+        FaultCheck { expected_failures, reported: [false; MAX_EXPECTED_FAILURES] }
+    }
+    fn record(&mut self, name: &str, failed: bool, result: &impl ufmt::uDisplay) {
+        if let Some(n) = self.expected_failures.iter().position(|&expected| expected == name) {
+            println!("{}", result);
+            self.reported[n] |= failed;
+        }
+    }
+    /// Expected failures that passed instead.
+    pub fn missed(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.expected_failures.iter().zip(self.reported.iter()).filter(|(_, &reported)| !reported).map(|(&name, _)| name)
+    }
+    pub fn num_reported(&self) -> usize {
+        self.reported.iter().filter(|&&reported| reported).count()
+    }
+    pub fn caught(&self) -> bool {
+        self.missed().next().is_none()
+    }
+}
+
+/// Injects fault, runs the automated tests and the known-temperature check, and prints the results that should have caught it.
+/// The fault is cleared again afterwards.
+pub fn check_fault<'a, H: PayloadHardware>(board: &SimBoard, payload: &mut Payload<{PayloadOn}, {HeaterOn}, H>, fault: SimFault, expected_failures: &'a [&'a str]) -> FaultCheck<'a> {
+    // The default environment has every sensor at the same temperature
+    let room_temp_k = board.environment().temperatures[0];
+    println!("==== Injected {:?} ====", fault);
+    board.clear_faults();
+    board.inject_fault(fault);
+    let mut check = FaultCheck::new(expected_failures);
+
+    for adc_test_fn in [AutomatedFunctionalTests::tether_adc_functional_test, AutomatedFunctionalTests::temperature_adc_functional_test,
+                        AutomatedFunctionalTests::misc_adc_functional_test, AutomatedFunctionalTests::aperture_adc_functional_test].iter() {
+        let result = adc_test_fn(payload);
+        check.record(result.name(), result.failed(), &result);
+    }
+    for result in AutomatedFunctionalTests::pinpuller_functional_test(payload).iter() {
+        check.record(result.name(), result.failed(), result);
+    }
+    let result = AutomatedFunctionalTests::heater_functional_test(payload);
+    check.record(result.name(), result.failed(), &result);
+    for result in AutomatedFunctionalTests::lms_functional_test(payload).iter() {
+        check.record(result.name(), result.failed(), result);
+    }
+
+    for sensor_fn in [AutomatedPerformanceTests::test_cathode_offset, AutomatedPerformanceTests::test_tether_bias, AutomatedPerformanceTests::test_heater].iter() {
+        for result in sensor_fn(payload).iter() {
+            check.record(result.name(), result.failed(), result);
+        }
+    }
+    let result = AutomatedPerformanceTests::test_pinpuller_current_sensor(payload);
+    check.record(result.name(), result.failed(), &result);
+    for result in test_temperature_sensors_against_known_temp(room_temp_k, payload).iter() {
+        check.record(result.name(), result.failed(), result);
+    }
+
+    println!("{} of {} expected failures reported", check.num_reported(), expected_failures.len());
+    board.clear_faults();
+    check
+}
+
+/// Runs check_fault for each of FAULT_CHECKS. Returns whether every fault was caught.
+pub fn run_fault_detection_checks(board: &SimBoard, calibration_memory: &'static mut [u8; 512]) -> bool {
+    let payload = board.build_payload(calibration_memory, &[]);
+    let mut payload = payload.into_enabled_payload().into_enabled_heater();
+    let mut all_caught = true;
+
+    for (fault, expected_failures) in FAULT_CHECKS.iter() {
+        all_caught &= check_fault(board, &mut payload, *fault, expected_failures).caught();
+    }
+
+    payload.into_disabled_heater().into_disabled_payload();
+    all_caught
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spi::PayloadSPI;
+    use std::{boxed::Box, vec::Vec};
+
+    #[test]
+    fn fault_free_board_passes_automated_tests() {
+        let board = SimBoard::new();
+        assert!(run_automated_tests(&board, Box::leak(Box::new([0; 512]))));
+        assert!(!board.line(SimLine::PayloadEnable));
+    }
+
+    #[test]
+    fn chips_only_answer_in_their_own_mode() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        // Two ADC frames, each starting with four zeroes when the ADC answers
+        let wrong_mode = payload.spi.borrow::<{IdleHigh}, {SampleFirstEdge}>().send_receive(32, 0, &mut payload.tether_adc.cs_pin);
+        assert_eq!(wrong_mode, u32::MAX);
+        let right_mode = payload.spi.borrow::<{IdleHigh}, {SampleSecondEdge}>().send_receive(32, 0, &mut payload.tether_adc.cs_pin);
+        assert_eq!(right_mode & 0xF000_F000, 0);
+    }
+
+    // Injects fault into a fresh board and checks every result FAULT_CHECKS names for it reports FAIL
+    fn assert_caught(fault: SimFault) {
+        let (_, expected_failures) = FAULT_CHECKS.iter().find(|(f, _)| *f == fault).expect("fault not in FAULT_CHECKS");
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload().into_enabled_heater();
+        let check = check_fault(&board, &mut payload, fault, expected_failures);
+        let missed: Vec<_> = check.missed().collect();
+        assert!(missed.is_empty(), "{:?}: {:?} didn't fail", fault, missed);
+    }
+
+    #[test]
+    fn stuck_tether_adc_miso_is_caught() {
+        assert_caught(SimFault::StuckMISO(SimChip::TetherADC, true));
+    }
+    #[test]
+    fn stuck_misc_adc_miso_is_caught() {
+        assert_caught(SimFault::StuckMISO(SimChip::MiscADC, false));
+    }
+    #[test]
+    fn flipped_temperature_adc_bit_is_caught() {
+        assert_caught(SimFault::FlippedMISOBit(SimChip::TemperatureADC, 0));
+    }
+    #[test]
+    fn dead_aperture_adc_chip_select_is_caught() {
+        assert_caught(SimFault::DeadChipSelect(SimChip::ApertureADC));
+    }
+    #[test]
+    fn open_heater_is_caught() {
+        assert_caught(SimFault::OpenHeater);
+    }
+    #[test]
+    fn shorted_msp430_thermistor_is_caught() {
+        assert_caught(SimFault::ShortedThermistor(2));
+    }
+    #[test]
+    fn shorted_tether_connector_thermistor_is_caught() {
+        assert_caught(SimFault::ShortedThermistor(6));
+    }
+    #[test]
+    fn tether_bias_stuck_at_zero_is_caught() {
+        assert_caught(SimFault::HVDCStuckAtZero(HVDCSupply::TetherBias));
+    }
+    #[test]
+    fn cathode_offset_stuck_at_zero_is_caught() {
+        assert_caught(SimFault::HVDCStuckAtZero(HVDCSupply::CathodeOffset));
+    }
+    #[test]
+    fn dead_burn_wire_is_caught() {
+        assert_caught(SimFault::DeadBurnWire(SimLine::BurnWire2Backup));
+    }
+}
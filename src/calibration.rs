@@ -0,0 +1,291 @@
+// This file handles ADC calibration: correcting each ADC's offset, gain and reference voltage, storing the corrections in FRAM, and measuring them over serial.
+// Records live in the MSP430's information memory so they survive reprogramming. Each one is checksummed, and an ADC without a valid record behaves as ideal.
+
+use embedded_hal::serial::Read;
+use ufmt::{uwrite, uwriteln};
+
+use crate::adc::{ADCCSPin, ADCChannel, ADCFilter, ADCSensor, ApertureSensor, MiscSensor, TargetADC, TemperatureSensor, TetherSensor, VccType, ADC};
+use crate::checksum::crc16;
+use crate::payload::{HeaterState, Payload, PayloadState::PayloadOn};
+use crate::pcb_common::PayloadHardware;
+use crate::serial::read_num;
+use crate::spi::{PayloadSPIAnyMode, PayloadSPIController};
+use crate::{print, println};
+
+const ADC_RESOLUTION: i32 = 4095;
+/// ADCCalibration::gain is fixed point, with this value meaning a gain of exactly 1.
+pub const GAIN_ONE: u16 = 1 << 14;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct ADCCalibration {
+    /// Added to every raw count before the gain is applied.
+    pub offset_counts: i16,
+    /// Multiplies the offset-corrected count. See GAIN_ONE.
+    pub gain: u16,
+    /// The ADC's actual VCC, which is also its reference.
+    pub reference_millivolts: u16,
+}
+impl ADCCalibration {
+    /// An ADC that needs no correction.
+    pub const fn ideal(reference_millivolts: u16) -> Self {
+        ADCCalibration { offset_counts: 0, gain: GAIN_ONE, reference_millivolts }
+    }
+    pub fn count_to_millivolts(&self, count: u16) -> u16 {
+        self.scale(count as i32 + self.offset_counts as i32)
+    }
+    /// Converts a difference between counts (e.g. a spread), so the offset doesn't apply.
+    pub fn count_difference_to_millivolts(&self, count_difference: u16) -> u16 {
+        self.scale(count_difference as i32)
+    }
+    // Split into two steps so nothing overflows 32 bits
+    fn scale(&self, count: i32) -> u16 {
+        let corrected = (count.max(0) * self.gain as i32) >> 14;
+        (corrected * self.reference_millivolts as i32 / ADC_RESOLUTION).clamp(0, u16::MAX as i32) as u16
+    }
+    /// Two-point calibration: the counts read while `low_millivolts` and then `high_millivolts` were applied to one channel.
+    /// Returns None if the points are too close together (or the wrong way round) to give a gain, or the result doesn't fit a record.
+    pub fn from_two_points(reference_millivolts: u16, low_millivolts: u16, low_count: u16, high_millivolts: u16, high_count: u16) -> Option<Self> {
+        // In 64 bits, since a small reference makes the ideal counts large enough to overflow 32 bits once multiplied by a span
+        // The counts an ideal ADC with this reference would have read
+        let ideal_low = low_millivolts as i64 * ADC_RESOLUTION as i64 / reference_millivolts.max(1) as i64;
+        let ideal_high = high_millivolts as i64 * ADC_RESOLUTION as i64 / reference_millivolts.max(1) as i64;
+        let ideal_span = ideal_high - ideal_low;
+        let count_span = high_count as i64 - low_count as i64;
+        if ideal_span <= 0 || count_span <= 0 {
+            return None;
+        }
+        let gain = ideal_span * GAIN_ONE as i64 / count_span;
+        // (low_count + offset) * gain = ideal_low
+        let offset_counts = ideal_low * count_span / ideal_span - low_count as i64;
+        Some(ADCCalibration {
+            offset_counts: i16::try_from(offset_counts).ok()?,
+            gain: u16::try_from(gain).ok()?,
+            reference_millivolts,
+        })
+    }
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let [o1, o2] = self.offset_counts.to_le_bytes();
+        let [g1, g2] = self.gain.to_le_bytes();
+        let [r1, r2] = self.reference_millivolts.to_le_bytes();
+        [o1, o2, g1, g2, r1, r2]
+    }
+    fn from_bytes(bytes: [u8; RECORD_LEN]) -> Self {
+        let [o1, o2, g1, g2, r1, r2] = bytes;
+        ADCCalibration {
+            offset_counts: i16::from_le_bytes([o1, o2]),
+            gain: u16::from_le_bytes([g1, g2]),
+            reference_millivolts: u16::from_le_bytes([r1, r2]),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum CalibrationError {
+    /// This slot has never been written, or was written by an incompatible firmware version.
+    NoRecord,
+    BadChecksum,
+}
+
+// Slot layout: [RECORD_VERSION, record..., CRC high, CRC low]. The CRC covers the version and record.
+const RECORD_VERSION: u8 = 1;
+const RECORD_LEN: usize = 6;
+const SLOT_LEN: usize = 1 + RECORD_LEN + 2;
+/// Where the calibration slots start in information memory, in bytes.
+pub const CALIBRATION_INFO_MEM_OFFSET: usize = 0;
+
+fn slot_index(adc: TargetADC) -> usize {
+    CALIBRATION_INFO_MEM_OFFSET + SLOT_LEN * match adc {
+        TargetADC::TetherADC => 0,
+        TargetADC::TemperatureADC => 1,
+        TargetADC::MiscADC => 2,
+        TargetADC::ApertureTestADC => 3,
+    }
+}
+
+/// Calibration records for every ADC, in information memory.
+///
+/// Get info_mem from msp430fr2x5x_hal::info_mem::InfoMemory::as_u8s(), which also turns off write protection.
+pub struct CalibrationStore {
+    info_mem: &'static mut [u8; 512],
+}
+impl CalibrationStore {
+    pub fn new(info_mem: &'static mut [u8; 512]) -> Self {
+        CalibrationStore { info_mem }
+    }
+    pub fn load(&self, adc: TargetADC) -> Result<ADCCalibration, CalibrationError> {
+        let start = slot_index(adc);
+        let slot = &self.info_mem[start..start + SLOT_LEN];
+        if slot[0] != RECORD_VERSION {
+            return Err(CalibrationError::NoRecord);
+        }
+        let crc = u16::from_be_bytes([slot[SLOT_LEN - 2], slot[SLOT_LEN - 1]]);
+        if crc16(&slot[..SLOT_LEN - 2]) != crc {
+            return Err(CalibrationError::BadChecksum);
+        }
+        let mut record = [0; RECORD_LEN];
+        record.copy_from_slice(&slot[1..1 + RECORD_LEN]);
+        Ok(ADCCalibration::from_bytes(record))
+    }
+    pub fn store(&mut self, adc: TargetADC, calibration: &ADCCalibration) {
+        let start = slot_index(adc);
+        let slot = &mut self.info_mem[start..start + SLOT_LEN];
+        slot[0] = RECORD_VERSION;
+        slot[1..1 + RECORD_LEN].copy_from_slice(&calibration.to_bytes());
+        let [crc_hi, crc_lo] = crc16(&slot[..SLOT_LEN - 2]).to_be_bytes();
+        slot[SLOT_LEN - 2] = crc_hi;
+        slot[SLOT_LEN - 1] = crc_lo;
+    }
+    /// Forget the record for this ADC, so it goes back to being treated as ideal on the next boot.
+    pub fn erase(&mut self, adc: TargetADC) {
+        self.info_mem[slot_index(adc)] = 0;
+    }
+}
+
+/// Calibrate each ADC in turn over the debug serial port, then store and apply the results.
+///
+/// Setup: A multimeter, and an adjustable voltage source that can be connected to one input of each ADC.
+pub fn calibrate_adcs<const HSTATE: HeaterState, H: PayloadHardware>(payload: &mut Payload<{ PayloadOn }, HSTATE, H>) {
+    println!("========== ADC CALIBRATION ==========");
+
+    let channel = prompt_channel("Tether ADC", &mut payload.serial_reader);
+    let calibration = calibrate_adc(&mut payload.tether_adc, &TetherSensor { channel }, false, &mut payload.spi, &mut payload.serial_reader);
+    save_calibration(TargetADC::TetherADC, calibration, &mut payload.tether_adc, &mut payload.calibration_store);
+
+    let channel = prompt_channel("Temperature ADC", &mut payload.serial_reader);
+    let calibration = calibrate_adc(&mut payload.temperature_adc, &TemperatureSensor { channel, vcc: VccType::Payload }, false, &mut payload.spi, &mut payload.serial_reader);
+    save_calibration(TargetADC::TemperatureADC, calibration, &mut payload.temperature_adc, &mut payload.calibration_store);
+
+    let channel = prompt_channel("Misc ADC", &mut payload.serial_reader);
+    let calibration = calibrate_adc(&mut payload.misc_adc, &MiscSensor { channel }, false, &mut payload.spi, &mut payload.serial_reader);
+    save_calibration(TargetADC::MiscADC, calibration, &mut payload.misc_adc, &mut payload.calibration_store);
+
+    // The aperture ADC is only powered while its CS pin is held low
+    let channel = prompt_channel("Aperture ADC", &mut payload.serial_reader);
+    let calibration = payload.with_aperture_powered(|payload| {
+        calibrate_adc(&mut payload.aperture_adc, &ApertureSensor { channel }, true, &mut payload.spi, &mut payload.serial_reader)
+    });
+    save_calibration(TargetADC::ApertureTestADC, calibration, &mut payload.aperture_adc, &mut payload.calibration_store);
+
+    println!("========== ADC CALIBRATION COMPLETE ==========");
+}
+
+fn prompt_channel(name: &str, serial_reader: &mut impl Read<u8>) -> ADCChannel {
+    println!("");
+    println!("Calibrating {}.", name);
+    loop {
+        print!("Input the channel the voltage source is connected to (0-7): ");
+        let channel = read_num(serial_reader);
+        println!("");
+        match channel {
+            0 => return ADCChannel::IN0,
+            1 => return ADCChannel::IN1,
+            2 => return ADCChannel::IN2,
+            3 => return ADCChannel::IN3,
+            4 => return ADCChannel::IN4,
+            5 => return ADCChannel::IN5,
+            6 => return ADCChannel::IN6,
+            7 => return ADCChannel::IN7,
+            _ => println!("No such channel."),
+        }
+    }
+}
+
+fn prompt_millivolts(prompt: &str, serial_reader: &mut impl Read<u8>) -> u16 {
+    print!("{} (in mV): ", prompt);
+    let millivolts = read_num(serial_reader);
+    println!("");
+    millivolts.clamp(0, u16::MAX as i32) as u16
+}
+
+// Measures two points on one channel. Returns None if the ADC couldn't be read or the points don't give a sensible calibration.
+// If selected the caller is holding the ADC's CS low, so reads must leave it alone.
+fn calibrate_adc<CsPin: ADCCSPin, SensorType: ADCSensor, const VCC_MV: u16>(
+    adc: &mut ADC<CsPin, SensorType, VCC_MV>,
+    sensor: &SensorType,
+    selected: bool,
+    spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>,
+    serial_reader: &mut impl Read<u8>,
+) -> Option<ADCCalibration> {
+    const CALIBRATION_FILTER: ADCFilter = ADCFilter::Mean(16);
+    let reference_millivolts = prompt_millivolts("Measure the ADC's VCC and input it", serial_reader);
+
+    let mut read_count = |adc: &mut ADC<CsPin, SensorType, VCC_MV>| match selected {
+        true => adc.read_filtered_count_while_selected_from(sensor, &mut spi_bus.borrow()),
+        false => adc.read_filtered_count_from(sensor, &mut spi_bus.borrow()),
+    };
+
+    let previous_filter = adc.filter(sensor);
+    adc.set_filter(sensor, CALIBRATION_FILTER);
+    let low_millivolts = prompt_millivolts("Set the source to roughly 10% of VCC, then measure and input it", serial_reader);
+    let low_count = read_count(adc);
+    let high_millivolts = prompt_millivolts("Set the source to roughly 90% of VCC, then measure and input it", serial_reader);
+    let high_count = read_count(adc);
+    adc.set_filter(sensor, previous_filter);
+
+    match (low_count, high_count) {
+        (Ok(low_count), Ok(high_count)) => {
+            println!("Read {} counts at {}mV and {} counts at {}mV", low_count, low_millivolts, high_count, high_millivolts);
+            ADCCalibration::from_two_points(reference_millivolts, low_millivolts, low_count, high_millivolts, high_count)
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            println!("ADC read failed: {:?}", err);
+            None
+        }
+    }
+}
+
+fn save_calibration<CsPin: ADCCSPin, SensorType: ADCSensor, const VCC_MV: u16>(
+    target: TargetADC,
+    calibration: Option<ADCCalibration>,
+    adc: &mut ADC<CsPin, SensorType, VCC_MV>,
+    store: &mut CalibrationStore,
+) {
+    match calibration {
+        Some(calibration) => {
+            println!(
+                "Offset: {} counts, gain: {}/{}, reference: {}mV. Saved.",
+                calibration.offset_counts, calibration.gain, GAIN_ONE, calibration.reference_millivolts
+            );
+            store.store(target, &calibration);
+            adc.calibration = calibration;
+        }
+        None => println!("Calibration failed, keeping the previous values."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::boxed::Box;
+
+    #[test]
+    fn two_points_recover_offset_and_gain() {
+        // An ADC reading 20 counts high with a gain of 1, on a 4095mV reference so a count is a millivolt
+        let calibration = ADCCalibration::from_two_points(4095, 400, 420, 3600, 3620).unwrap();
+        assert_eq!(calibration, ADCCalibration { offset_counts: -20, gain: GAIN_ONE, reference_millivolts: 4095 });
+        assert_eq!(calibration.count_to_millivolts(2020), 2000);
+    }
+
+    #[test]
+    fn unusable_points_give_none() {
+        assert_eq!(ADCCalibration::from_two_points(3300, 2000, 2000, 1000, 1000), None);
+        assert_eq!(ADCCalibration::from_two_points(3300, 1000, 1000, 2000, 1000), None);
+        // Big enough to overflow 32 bit intermediates
+        assert_eq!(ADCCalibration::from_two_points(1, 1000, 0, u16::MAX, u16::MAX), None);
+    }
+
+    #[test]
+    fn records_survive_a_round_trip() {
+        let mut store = CalibrationStore::new(Box::leak(Box::new([0; 512])));
+        let calibration = ADCCalibration { offset_counts: -7, gain: GAIN_ONE + 100, reference_millivolts: 3290 };
+        assert_eq!(store.load(TargetADC::MiscADC), Err(CalibrationError::NoRecord));
+        store.store(TargetADC::MiscADC, &calibration);
+        assert_eq!(store.load(TargetADC::MiscADC), Ok(calibration));
+        assert_eq!(store.load(TargetADC::TetherADC), Err(CalibrationError::NoRecord));
+
+        store.info_mem[slot_index(TargetADC::MiscADC) + 1] ^= 0x01;
+        assert_eq!(store.load(TargetADC::MiscADC), Err(CalibrationError::BadChecksum));
+        store.erase(TargetADC::MiscADC);
+        assert_eq!(store.load(TargetADC::MiscADC), Err(CalibrationError::NoRecord));
+    }
+}
@@ -0,0 +1,24 @@
+// This file contains the checksum shared by the OBC frames (obc.rs) and the calibration records in FRAM (calibration.rs).
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, initial value 0xFFFF, no reflection, no final XOR).
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_matches_reference() {
+        // The standard check value for CRC-16/CCITT-FALSE
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+}
@@ -1,10 +1,14 @@
 // This file interacts with an LTC2634 Digital to Analog Converter (DAC). 
 // PCB-specific values (e.g. reference voltages, channel connections) can be found in the pcb_mapping file.
 
-use crate::pcb_mapping::{peripheral_vcc_values::DAC_VCC_VOLTAGE_MILLIVOLTS, pin_name_types::DACCSPin};
+use embedded_hal::digital::v2::OutputPin;
+use crate::pcb_mapping::{peripheral_vcc_values::DAC_VCC_VOLTAGE_MILLIVOLTS, power_supply_locations::DAC_RESOLUTION};
 use crate::spi::{PayloadSPI, SckPolarity::IdleLow, SckPhase::SampleFirstEdge};
 use crate::dac::{DACCommand::*, DACChannel::*};
+use crate::payload::enforce_bounds;
+use crate::units::Millivolts;
 
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum DACCommand{
     WriteToRegisterX=0b000,
 	UpdateRegisterX=0b0001,
@@ -17,6 +21,7 @@ pub enum DACCommand{
 	NoOp=0b1111,
 }
 
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum DACChannel{
     ChannelA=0b0000,
 	ChannelB=0b0001,
@@ -24,39 +29,221 @@ pub enum DACChannel{
 	ChannelD=0b0011,
 	AllChannels=0b1111,
 }
-// For 12-bit DAC:
-// Packet format: C3 C2 C1 C0 A3 A2 A1 A0 D11 D10 D9 D8 D7 D6 D5 D4 D3 D2 D1 D0 X X X X
-//                24...                                                            ...0
-// Where C is command bits, A is address, D is data, and X is 'dont care'
+const NUM_CHANNELS: usize = 4;
+const CHANNELS: [DACChannel; NUM_CHANNELS] = [ChannelA, ChannelB, ChannelC, ChannelD];
 
-const NUM_DATA_BITS: u8 = 12; // This varies depending on the exact model of LTC2634
+// Data bits of the LTC2634 variant (8, 10 or 12). The fitted one is set in the pcb_mapping file.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, core::marker::ConstParamTy)]
+pub enum DACResolution{
+    Eight=8,
+    Ten=10,
+    Twelve=12,
+}
+impl DACResolution{
+    pub const fn num_data_bits(self) -> u8 {
+        self as u8
+    }
+    pub const fn max_count(self) -> u16 {
+        (1 << self.num_data_bits()) - 1
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum DACReference{
+    Internal,
+    External,
+}
 
+// Packet format: C3 C2 C1 C0 A3 A2 A1 A0 D(N-1) ... D0 X ... X
+//                24...                                     ...0
+// Where C is command bits, A is address, D is N data bits, and X is 'dont care'. Data is left-aligned, so there are 16-N dont care bits.
 const NUM_BITS_IN_PACKET: u8 = 24;
 const NUM_COMMAND_BITS: u8 = 4;
 const NUM_ADDRESS_BITS: u8 = 4;
-const NUM_DONT_CARE_BITS: u8 = NUM_BITS_IN_PACKET - NUM_COMMAND_BITS - NUM_ADDRESS_BITS - NUM_DATA_BITS;
-const DATA_OFFSET: u8 = NUM_DONT_CARE_BITS;
-const ADDRESS_OFFSET: u8 = DATA_OFFSET + NUM_DATA_BITS;
+const ADDRESS_OFFSET: u8 = NUM_BITS_IN_PACKET - NUM_COMMAND_BITS - NUM_ADDRESS_BITS;
 const COMMAND_OFFSET: u8 = ADDRESS_OFFSET + NUM_ADDRESS_BITS;
 
-pub const DAC_RESOLUTION: u16 = (1 << NUM_DATA_BITS) - 1;
-
-pub struct DAC {
-    pub cs_pin: DACCSPin,
+// Keeps a shadow copy of the chip's registers, since it can't be read back. Shadows are None until written, as power-on values depend on the variant.
+pub struct DAC<CsPin: OutputPin, const RESOLUTION: DACResolution = DAC_RESOLUTION> {
+    pub cs_pin: CsPin,
+    input_registers: [Option<u16>; NUM_CHANNELS],
+    dac_registers: [Option<u16>; NUM_CHANNELS],
+    powered_down: [bool; NUM_CHANNELS],
+    reference: Option<DACReference>,
 }
-impl DAC{
-    pub fn new(cs_pin: DACCSPin) -> DAC {
-        DAC{cs_pin}
+impl<CsPin: OutputPin, const RESOLUTION: DACResolution> DAC<CsPin, RESOLUTION>{
+    pub fn new(cs_pin: CsPin) -> DAC<CsPin, RESOLUTION> {
+        DAC{cs_pin, input_registers: [None; NUM_CHANNELS], dac_registers: [None; NUM_CHANNELS], powered_down: [false; NUM_CHANNELS], reference: None}
     }
+    // Values wider than the DAC's resolution are clamped to full scale.
     pub fn send_command(&mut self, command: DACCommand, channel: DACChannel, value: u16, 
                         spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
-        let payload: u32 = ((command as u32) << COMMAND_OFFSET) | ((channel as u32) << ADDRESS_OFFSET) | ((value as u32) << DATA_OFFSET);
+        let value = value.min(RESOLUTION.max_count());
+        let data_offset = ADDRESS_OFFSET - RESOLUTION.num_data_bits();
+        let payload: u32 = ((command as u32) << COMMAND_OFFSET) | ((channel as u32) << ADDRESS_OFFSET) | ((value as u32) << data_offset);
         spi_bus.send(NUM_BITS_IN_PACKET, payload, &mut self.cs_pin);
+        self.update_shadows(command, channel, value);
     }
-    pub fn voltage_to_count(mut target_millivolts: u16) -> u16{
-        if target_millivolts > DAC_VCC_VOLTAGE_MILLIVOLTS {
-            target_millivolts = DAC_VCC_VOLTAGE_MILLIVOLTS;
+    fn update_shadows(&mut self, command: DACCommand, channel: DACChannel, value: u16) {
+        for n in channel_indices(channel) {
+            match command {
+                WriteToRegisterX => self.input_registers[n] = Some(value),
+                UpdateRegisterX => self.update_output(n),
+                WriteToAndUpdateRegisterX => { self.input_registers[n] = Some(value); self.update_output(n) },
+                PowerOffChannelX => self.powered_down[n] = true,
+                WriteToRegisterXAndUpdateAll | PowerOffChip | SelectInternalReference | SelectExternalReference | NoOp => (),
+            }
+        }
+        match command {
+            WriteToRegisterXAndUpdateAll => {
+                for n in channel_indices(channel) { self.input_registers[n] = Some(value); }
+                for n in 0..NUM_CHANNELS { self.update_output(n); }
+            },
+            PowerOffChip => self.powered_down = [true; NUM_CHANNELS],
+            SelectInternalReference => self.reference = Some(DACReference::Internal),
+            SelectExternalReference => self.reference = Some(DACReference::External),
+            _ => (),
         }
-        ((target_millivolts as u32 * DAC_RESOLUTION as u32) / DAC_VCC_VOLTAGE_MILLIVOLTS as u32) as u16
     }
-}
\ No newline at end of file
+    // Updating a channel also powers it back up
+    fn update_output(&mut self, n: usize) {
+        self.dac_registers[n] = self.input_registers[n];
+        self.powered_down[n] = false;
+    }
+
+    /// Write a channel's input register without changing its output.
+    pub fn write(&mut self, channel: DACChannel, count: u16, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
+        self.send_command(WriteToRegisterX, channel, count, spi_bus);
+    }
+    /// Copy a channel's input register to its output, powering it up if needed.
+    pub fn update(&mut self, channel: DACChannel, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
+        self.send_command(UpdateRegisterX, channel, 0, spi_bus);
+    }
+    pub fn write_and_update(&mut self, channel: DACChannel, count: u16, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
+        self.send_command(WriteToAndUpdateRegisterX, channel, count, spi_bus);
+    }
+    /// Write a channel's input register, then update every channel's output.
+    pub fn write_and_update_all(&mut self, channel: DACChannel, count: u16, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
+        self.send_command(WriteToRegisterXAndUpdateAll, channel, count, spi_bus);
+    }
+    /// The channel's output goes high impedance until it is next updated.
+    pub fn power_down_channel(&mut self, channel: DACChannel, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
+        self.send_command(PowerOffChannelX, channel, 0, spi_bus);
+    }
+    pub fn power_down_chip(&mut self, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
+        self.send_command(PowerOffChip, AllChannels, 0, spi_bus);
+    }
+    pub fn select_reference(&mut self, reference: DACReference, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>) {
+        let command = match reference {
+            DACReference::Internal => SelectInternalReference,
+            DACReference::External => SelectExternalReference,
+        };
+        self.send_command(command, AllChannels, 0, spi_bus);
+    }
+    /// Forget the shadow registers, e.g. after the chip loses power.
+    pub fn forget_state(&mut self) {
+        self.input_registers = [None; NUM_CHANNELS];
+        self.dac_registers = [None; NUM_CHANNELS];
+        self.powered_down = [false; NUM_CHANNELS];
+        self.reference = None;
+    }
+
+    /// Last value written to the channel's input register. None for AllChannels, or if it hasn't been written since power-up.
+    pub fn input_count(&self, channel: DACChannel) -> Option<u16> {
+        self.input_registers.get(channel as usize).copied().flatten()
+    }
+    /// Count the channel is currently outputting. None for AllChannels, or if it hasn't been updated since power-up.
+    pub fn output_count(&self, channel: DACChannel) -> Option<u16> {
+        self.dac_registers.get(channel as usize).copied().flatten()
+    }
+    pub fn output_voltage(&self, channel: DACChannel) -> Option<Millivolts> {
+        self.output_count(channel).map(|count| self.count_to_voltage(count))
+    }
+    /// False for AllChannels.
+    pub fn is_powered_down(&self, channel: DACChannel) -> bool {
+        self.powered_down.get(channel as usize).is_some_and(|powered_down| *powered_down)
+    }
+    /// None if no reference has been selected since power-up.
+    pub fn reference(&self) -> Option<DACReference> {
+        self.reference
+    }
+
+    // Targets outside 0..=VCC are clamped
+    pub fn voltage_to_count(&self, target: Millivolts) -> u16{
+        let target = enforce_bounds(Millivolts::ZERO, target, Millivolts::from(DAC_VCC_VOLTAGE_MILLIVOLTS));
+        ((target.0 as u32 * RESOLUTION.max_count() as u32) / DAC_VCC_VOLTAGE_MILLIVOLTS as u32) as u16
+    }
+    pub fn count_to_voltage(&self, count: u16) -> Millivolts{
+        Millivolts(((count as u32 * DAC_VCC_VOLTAGE_MILLIVOLTS as u32) / RESOLUTION.max_count() as u32) as i32)
+    }
+}
+
+fn channel_indices(channel: DACChannel) -> impl Iterator<Item = usize> {
+    CHANNELS.into_iter().filter(move |c| channel == AllChannels || *c == channel).map(|c| c as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spi_mock::{MockChipSelects, MockPayloadSPI, MockCSPin};
+
+    // The 24-bit packet sent by one command
+    fn packet<const RESOLUTION: DACResolution>(command: DACCommand, channel: DACChannel, value: u16) -> (usize, u32) {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleLow}, {SampleFirstEdge}>::new(&chip_selects);
+        let mut dac: DAC<MockCSPin, RESOLUTION> = DAC::new(chip_selects.pin(0));
+        dac.send_command(command, channel, value, &mut bus);
+        let [transaction] = bus.transactions() else { panic!("expected one transaction, got {}", bus.transactions().len()) };
+        assert_eq!(transaction.cs_id, Some(0));
+        (transaction.len, transaction.mosi_u32())
+    }
+
+    #[test]
+    fn packets_are_command_address_then_left_aligned_data() {
+        assert_eq!(packet::<{DACResolution::Twelve}>(WriteToAndUpdateRegisterX, ChannelB, 0xABC), (24, 0x31_ABC0));
+        assert_eq!(packet::<{DACResolution::Ten}>(WriteToRegisterX, ChannelD, 0x3FF), (24, 0x03_FFC0));
+        assert_eq!(packet::<{DACResolution::Eight}>(WriteToRegisterXAndUpdateAll, ChannelA, 0xAB), (24, 0x20_AB00));
+        assert_eq!(packet::<{DACResolution::Twelve}>(SelectExternalReference, AllChannels, 0), (24, 0x7F_0000));
+    }
+
+    #[test]
+    fn values_past_full_scale_are_clamped() {
+        assert_eq!(packet::<{DACResolution::Twelve}>(WriteToRegisterX, ChannelC, 0xFFFF), (24, 0x02_FFF0));
+        assert_eq!(packet::<{DACResolution::Eight}>(WriteToRegisterX, ChannelC, 0x1FF), (24, 0x02_FF00));
+    }
+
+    #[test]
+    fn shadow_registers_follow_the_commands_sent() {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleLow}, {SampleFirstEdge}>::new(&chip_selects);
+        let mut dac: DAC<MockCSPin, {DACResolution::Twelve}> = DAC::new(chip_selects.pin(0));
+        assert_eq!((dac.input_count(ChannelA), dac.output_count(ChannelA), dac.reference()), (None, None, None));
+
+        // Writes only reach the output once updated
+        dac.write(ChannelA, 100, &mut bus);
+        assert_eq!((dac.input_count(ChannelA), dac.output_count(ChannelA)), (Some(100), None));
+        dac.update(ChannelA, &mut bus);
+        assert_eq!((dac.input_count(ChannelA), dac.output_count(ChannelA)), (Some(100), Some(100)));
+
+        // Updating all copies every input register, including ones written earlier without an update
+        dac.write(ChannelB, 200, &mut bus);
+        dac.write_and_update_all(ChannelC, 300, &mut bus);
+        assert_eq!([ChannelA, ChannelB, ChannelC, ChannelD].map(|channel| dac.output_count(channel)), [Some(100), Some(200), Some(300), None]);
+
+        dac.power_down_channel(ChannelB, &mut bus);
+        assert!(dac.is_powered_down(ChannelB) && !dac.is_powered_down(ChannelA));
+        dac.power_down_chip(&mut bus);
+        assert!(CHANNELS.iter().all(|&channel| dac.is_powered_down(channel)));
+        dac.write_and_update(ChannelB, 250, &mut bus);
+        assert!(!dac.is_powered_down(ChannelB) && dac.is_powered_down(ChannelA));
+        assert_eq!(dac.output_count(ChannelB), Some(250));
+
+        // AllChannels isn't a register of its own
+        dac.select_reference(DACReference::Internal, &mut bus);
+        assert_eq!((dac.input_count(AllChannels), dac.output_count(AllChannels), dac.is_powered_down(AllChannels)), (None, None, false));
+
+        dac.forget_state();
+        assert!(CHANNELS.iter().all(|&channel| dac.output_count(channel).is_none() && !dac.is_powered_down(channel)));
+        assert_eq!((dac.input_count(ChannelA), dac.reference()), (None, None));
+    }
+}
@@ -3,42 +3,89 @@
 
 
 // Digipot parameters
-const DIGIPOT_WIPER_RESISTANCE: u32 = 100;
-const DIGIPOT_MAX_INTERNAL_RESISTANCE: u32 = 100_000;
-pub const DIGIPOT_MAX_RESISTANCE: u32 = DIGIPOT_MAX_INTERNAL_RESISTANCE + DIGIPOT_WIPER_RESISTANCE;
-pub const DIGIPOT_MIN_RESISTANCE: u32 = DIGIPOT_WIPER_RESISTANCE;
+const DIGIPOT_WIPER_RESISTANCE: Ohms = Ohms(100);
+const DIGIPOT_MAX_INTERNAL_RESISTANCE: Ohms = Ohms(100_000);
+pub const DIGIPOT_MAX_RESISTANCE: Ohms = Ohms(DIGIPOT_MAX_INTERNAL_RESISTANCE.0 + DIGIPOT_WIPER_RESISTANCE.0);
+pub const DIGIPOT_MIN_RESISTANCE: Ohms = DIGIPOT_WIPER_RESISTANCE;
 pub const DIGIPOT_RESOLUTION: u32 = 255;
+// The AD5162 resets both wipers to midscale at power-up
+pub const DIGIPOT_MIDSCALE_COUNT: u8 = 128;
+const DIGIPOT_NUM_CHANNELS: usize = 2;
 const DIGIPOT_NUM_ADDRESS_BITS: u8 = 1;
 const DIGIPOT_NUM_DATA_BITS: u8 = 8;
 const DIGIPOT_NUM_BITS_IN_PACKET: u8 = DIGIPOT_NUM_ADDRESS_BITS + DIGIPOT_NUM_DATA_BITS;
 
-use crate::{spi::{PayloadSPI, PayloadSPIController, SckPolarity::IdleLow, SckPhase::SampleFirstEdge}, pcb_mapping::pin_name_types::DigipotCSPin};
+use embedded_hal::digital::v2::OutputPin;
+use crate::spi::{PayloadSPI, PayloadSPIAnyMode, PayloadSPIController, SckPolarity::IdleLow, SckPhase::SampleFirstEdge};
 use crate::payload::enforce_bounds;
+use crate::units::Ohms;
 
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum DigipotChannel{
 	Channel1=0,
 	Channel2=1,
 }
 
-pub struct Digipot {
-    cs_pin: DigipotCSPin,
+// The chip can't be read back, so the driver remembers the last count written to each wiper.
+pub struct Digipot<CsPin: OutputPin> {
+    cs_pin: CsPin,
+    wipers: [u8; DIGIPOT_NUM_CHANNELS],
 }
-impl Digipot {
-    pub fn new(cs_pin: DigipotCSPin) -> Digipot {
-        Digipot {cs_pin}
+impl<CsPin: OutputPin> Digipot<CsPin> {
+    pub fn new(cs_pin: CsPin) -> Digipot<CsPin> {
+        Digipot {cs_pin, wipers: [DIGIPOT_MIDSCALE_COUNT; DIGIPOT_NUM_CHANNELS]}
     }
-    pub fn set_channel_to_resistance(&mut self, channel: DigipotChannel, wanted_resistance: u32, spi_bus: &mut PayloadSPIController){
+    pub fn set_channel_to_resistance(&mut self, channel: DigipotChannel, wanted_resistance: Ohms, spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>){
         let count = self.resistance_to_count(wanted_resistance);
-        self.set_channel_to_count(channel, count, spi_bus.borrow());
+        self.set_channel_to_count(channel, count, &mut spi_bus.borrow());
     }
     pub fn set_channel_to_count(&mut self, channel: DigipotChannel, count: u8, spi_bus: &mut impl PayloadSPI<{IdleLow}, {SampleFirstEdge}>){
         let payload: u16 = ((channel as u16) << DIGIPOT_NUM_DATA_BITS) | (count as u16);
         spi_bus.send(DIGIPOT_NUM_BITS_IN_PACKET, payload as u32, &mut self.cs_pin);
+        self.wipers[channel as usize] = count;
     }
-    pub fn resistance_to_count(&self, mut wanted_resistance: u32) -> u8{
+    /// Rewrite both wipers from the shadow, e.g. after the chip has been powered up and reset itself to midscale.
+    pub fn restore_wipers(&mut self, spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>){
+        for channel in [DigipotChannel::Channel1, DigipotChannel::Channel2] {
+            self.set_channel_to_count(channel, self.wipers[channel as usize], &mut spi_bus.borrow());
+        }
+    }
+    /// Last count written to the channel, or midscale if it hasn't been written.
+    pub fn channel_count(&self, channel: DigipotChannel) -> u8 {
+        self.wipers[channel as usize]
+    }
+    pub fn channel_resistance(&self, channel: DigipotChannel) -> Ohms {
+        self.count_to_resistance(self.channel_count(channel))
+    }
+    //NOTE: This is the inverse of count_to_resistance. These two should be kept in sync.
+    pub fn resistance_to_count(&self, mut wanted_resistance: Ohms) -> u8{
         wanted_resistance = enforce_bounds( DIGIPOT_MIN_RESISTANCE, 
                                             wanted_resistance,
                                             DIGIPOT_MAX_RESISTANCE);
-        (((wanted_resistance - DIGIPOT_WIPER_RESISTANCE) * DIGIPOT_RESOLUTION) / DIGIPOT_MAX_RESISTANCE) as u8
+        (((wanted_resistance - DIGIPOT_WIPER_RESISTANCE).0 * DIGIPOT_RESOLUTION) / DIGIPOT_MAX_RESISTANCE.0) as u8
     }
-}
\ No newline at end of file
+    pub fn count_to_resistance(&self, count: u8) -> Ohms{
+        count_to_resistance(count)
+    }
+}
+// Free-standing so a simulated digipot can use the same conversion.
+pub fn count_to_resistance(count: u8) -> Ohms{
+    Ohms((count as u32 * DIGIPOT_MAX_RESISTANCE.0) / DIGIPOT_RESOLUTION) + DIGIPOT_WIPER_RESISTANCE
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spi_mock::{MockChipSelects, MockPayloadSPI};
+
+    #[test]
+    fn packets_are_address_bit_then_count() {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleLow}, {SampleFirstEdge}>::new(&chip_selects);
+        let mut digipot = Digipot::new(chip_selects.pin(3));
+        digipot.set_channel_to_count(DigipotChannel::Channel1, 0xA5, &mut bus);
+        digipot.set_channel_to_count(DigipotChannel::Channel2, 0x3C, &mut bus);
+        let packets: [_; 2] = core::array::from_fn(|n| (bus.transactions()[n].len, bus.transactions()[n].mosi_u32(), bus.transactions()[n].cs_id));
+        assert_eq!(packets, [(9, 0x0A5, Some(3)), (9, 0x13C, Some(3))]);
+        assert_eq!(digipot.channel_count(DigipotChannel::Channel2), 0x3C);
+    }
+}
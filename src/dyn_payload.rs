@@ -0,0 +1,425 @@
+// This file provides DynPayload, which tracks the payload's power state at runtime instead of in the type.
+// Useful when the state depends on something only known at runtime (e.g. a command from the OBC), or has to survive a loop.
+// Transitions and state-dependent methods check the state and return an error rather than refusing to compile.
+// Every housekeeping read is also checked against the protection limits, and a trip shuts the payload down (see get_housekeeping).
+
+use crate::adc::{ADCError, TemperatureSensor};
+use crate::housekeeping::Housekeeping;
+use crate::pcb_common::PayloadHardware;
+use crate::payload::{HVDCSupply, HeaterPowerControl, HeaterPowerReading, Payload, PayloadState::*, HeaterState::*, RampError, RampLimits, SwitchState, TetherADCReadings};
+use crate::power::{LiveDomains, SwitchableDomain};
+use crate::protection::{Protection, ProtectionFault, ProtectionLimits};
+use crate::units::{Kelvin, Millivolts};
+
+/// The three valid combinations of payload and heater state.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum DynState {
+    PayloadOff,
+    PayloadOn,
+    HeaterOn,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum DynPayloadError {
+    /// Refused because the payload is off.
+    PayloadOff,
+    /// Refused because the payload is already on.
+    PayloadOn,
+    /// Refused because the heater is off.
+    HeaterOff,
+    /// Refused because the heater is on.
+    HeaterOn,
+    ADC(ADCError),
+    Ramp(RampError),
+    /// Refused because a protection fault is latched. See DynPayload::clear_fault.
+    FaultLatched(ProtectionFault),
+}
+impl From<ADCError> for DynPayloadError {
+    fn from(err: ADCError) -> Self {
+        DynPayloadError::ADC(err)
+    }
+}
+impl From<RampError> for DynPayloadError {
+    fn from(err: RampError) -> Self {
+        DynPayloadError::Ramp(err)
+    }
+}
+
+enum AnyPayload<H: PayloadHardware> {
+    Off(Payload<{PayloadOff}, {HeaterOff}, H>),
+    On(Payload<{PayloadOn}, {HeaterOff}, H>),
+    Heating(Payload<{PayloadOn}, {HeaterOn}, H>),
+}
+
+// Runs $body with $payload bound to the payload, whatever state it's in.
+macro_rules! any_state {
+    ($self:ident, $payload:ident => $body:expr) => {
+        match $self.payload_mut() {
+            AnyPayload::Off($payload) => $body,
+            AnyPayload::On($payload) => $body,
+            AnyPayload::Heating($payload) => $body,
+        }
+    };
+}
+// Runs $body with $payload bound to the payload if it's on, otherwise returns DynPayloadError::PayloadOff.
+macro_rules! payload_on {
+    ($self:ident, $payload:ident => $body:expr) => {
+        match $self.payload_mut() {
+            AnyPayload::Off(_) => Err(DynPayloadError::PayloadOff),
+            AnyPayload::On($payload) => Ok($body),
+            AnyPayload::Heating($payload) => Ok($body),
+        }
+    };
+}
+
+pub struct DynPayload<H: PayloadHardware> {
+    // Only None partway through a transition
+    payload: Option<AnyPayload<H>>,
+}
+impl<H: PayloadHardware> DynPayload<H> {
+    pub fn state(&self) -> DynState {
+        match self.payload() {
+            AnyPayload::Off(_) => DynState::PayloadOff,
+            AnyPayload::On(_) => DynState::PayloadOn,
+            AnyPayload::Heating(_) => DynState::HeaterOn,
+        }
+    }
+
+    /* Transitions. On error the state is unchanged. */
+    /// Refused while a protection fault is latched.
+    pub fn enable_payload(&mut self) -> Result<(), DynPayloadError> {
+        self.check_no_fault()?;
+        self.transition(|payload| match payload {
+            AnyPayload::Off(payload) => Ok(AnyPayload::On(payload.into_enabled_payload())),
+            other => Err((other, DynPayloadError::PayloadOn)),
+        })
+    }
+    /// The heater must be disabled first.
+    pub fn disable_payload(&mut self) -> Result<(), DynPayloadError> {
+        self.transition(|payload| match payload {
+            AnyPayload::On(payload) => Ok(AnyPayload::Off(payload.into_disabled_payload())),
+            AnyPayload::Off(payload) => Err((AnyPayload::Off(payload), DynPayloadError::PayloadOff)),
+            other => Err((other, DynPayloadError::HeaterOn)),
+        })
+    }
+    /// Refused while a protection fault is latched.
+    pub fn enable_heater(&mut self) -> Result<(), DynPayloadError> {
+        self.check_no_fault()?;
+        self.transition(|payload| match payload {
+            AnyPayload::On(payload) => Ok(AnyPayload::Heating(payload.into_enabled_heater())),
+            AnyPayload::Off(payload) => Err((AnyPayload::Off(payload), DynPayloadError::PayloadOff)),
+            other => Err((other, DynPayloadError::HeaterOn)),
+        })
+    }
+    pub fn disable_heater(&mut self) -> Result<(), DynPayloadError> {
+        self.transition(|payload| match payload {
+            AnyPayload::Heating(payload) => Ok(AnyPayload::On(payload.into_disabled_heater())),
+            other => Err((other, DynPayloadError::HeaterOff)),
+        })
+    }
+
+    /// Shuts down in whatever order the current state needs: relays open and HVDC supplies zeroed, then heater off, then payload off.
+    pub fn safe_shutdown(&mut self) {
+        payload_on!(self, payload => payload.safe_hvdc_shutdown()).ok();
+        self.disable_heater().ok();
+        self.disable_payload().ok();
+    }
+
+    /* Protection */
+    pub fn latched_fault(&self) -> Option<ProtectionFault> {
+        self.protection().latched_fault()
+    }
+    /// Allows the payload to be enabled again. Returns the fault that was latched.
+    pub fn clear_fault(&mut self) -> Option<ProtectionFault> {
+        self.protection_mut().clear_fault()
+    }
+    pub fn protection_limits(&self) -> ProtectionLimits {
+        self.protection().limits
+    }
+    pub fn set_protection_limits(&mut self, limits: ProtectionLimits) {
+        self.protection_mut().limits = limits;
+    }
+    fn check_no_fault(&self) -> Result<(), DynPayloadError> {
+        match self.latched_fault() {
+            Some(fault) => Err(DynPayloadError::FaultLatched(fault)),
+            None => Ok(()),
+        }
+    }
+
+    /* Available in every state */
+    /// If a protection fault is latched, by this snapshot or an earlier one, the payload is shut down (see safe_shutdown) before returning.
+    pub fn get_housekeeping(&mut self) -> Housekeeping {
+        let housekeeping = any_state!(self, payload => payload.get_housekeeping());
+        if self.latched_fault().is_some() {
+            self.safe_shutdown();
+        }
+        housekeeping
+    }
+    pub fn get_temperatures_kelvin<const N: usize>(&mut self, temp_sensors: [&TemperatureSensor; N]) -> Result<[Kelvin; N], DynPayloadError> {
+        Ok(any_state!(self, payload => payload.get_temperatures_kelvin(temp_sensors))?)
+    }
+    pub fn power_up(&mut self, domain: SwitchableDomain) {
+        any_state!(self, payload => payload.power_up(domain))
+    }
+    pub fn power_down(&mut self, domain: SwitchableDomain) {
+        any_state!(self, payload => payload.power_down(domain))
+    }
+    pub fn live_domains(&mut self) -> LiveDomains {
+        any_state!(self, payload => payload.live_domains())
+    }
+    pub fn get_cathode_offset_switch(&self) -> SwitchState {
+        match self.payload() {
+            AnyPayload::Off(payload) => payload.get_cathode_offset_switch(),
+            AnyPayload::On(payload) => payload.get_cathode_offset_switch(),
+            AnyPayload::Heating(payload) => payload.get_cathode_offset_switch(),
+        }
+    }
+    pub fn get_tether_bias_switch(&self) -> SwitchState {
+        match self.payload() {
+            AnyPayload::Off(payload) => payload.get_tether_bias_switch(),
+            AnyPayload::On(payload) => payload.get_tether_bias_switch(),
+            AnyPayload::Heating(payload) => payload.get_tether_bias_switch(),
+        }
+    }
+
+    /* Require the payload to be on */
+    pub fn set_heater_voltage(&mut self, target: Millivolts) -> Result<(), DynPayloadError> {
+        payload_on!(self, payload => payload.set_heater_voltage(target))
+    }
+    pub fn set_tether_bias_voltage(&mut self, target: Millivolts) -> Result<(), DynPayloadError> {
+        payload_on!(self, payload => payload.set_tether_bias_voltage(target))
+    }
+    pub fn set_cathode_offset_voltage(&mut self, target: Millivolts) -> Result<(), DynPayloadError> {
+        payload_on!(self, payload => payload.set_cathode_offset_voltage(target))
+    }
+    pub fn ramp_hvdc_supply(&mut self, supply: HVDCSupply, target: Millivolts, volts_per_second: u32, limits: RampLimits) -> Result<Millivolts, DynPayloadError> {
+        Ok(payload_on!(self, payload => payload.ramp_hvdc_supply(supply, target, volts_per_second, limits))??)
+    }
+    pub fn set_cathode_offset_switch(&mut self, state: SwitchState) -> Result<(), DynPayloadError> {
+        payload_on!(self, payload => payload.set_cathode_offset_switch(state))
+    }
+    pub fn set_tether_bias_switch(&mut self, state: SwitchState) -> Result<(), DynPayloadError> {
+        payload_on!(self, payload => payload.set_tether_bias_switch(state))
+    }
+    pub fn get_tether_adc_readings(&mut self) -> Result<TetherADCReadings, DynPayloadError> {
+        Ok(payload_on!(self, payload => payload.get_tether_adc_readings())??)
+    }
+
+    /* Require the heater to be on */
+    pub fn step_heater_power_control(&mut self, control: &mut HeaterPowerControl) -> Result<HeaterPowerReading, DynPayloadError> {
+        Ok(self.as_heater_on()?.step_heater_power_control(control)?)
+    }
+
+    /* Typed access, for when the state is known */
+    pub fn as_payload_off(&mut self) -> Result<&mut Payload<{PayloadOff}, {HeaterOff}, H>, DynPayloadError> {
+        match self.payload_mut() {
+            AnyPayload::Off(payload) => Ok(payload),
+            _ => Err(DynPayloadError::PayloadOn),
+        }
+    }
+    pub fn as_payload_on(&mut self) -> Result<&mut Payload<{PayloadOn}, {HeaterOff}, H>, DynPayloadError> {
+        match self.payload_mut() {
+            AnyPayload::On(payload) => Ok(payload),
+            AnyPayload::Off(_) => Err(DynPayloadError::PayloadOff),
+            AnyPayload::Heating(_) => Err(DynPayloadError::HeaterOn),
+        }
+    }
+    pub fn as_heater_on(&mut self) -> Result<&mut Payload<{PayloadOn}, {HeaterOn}, H>, DynPayloadError> {
+        match self.payload_mut() {
+            AnyPayload::Heating(payload) => Ok(payload),
+            AnyPayload::Off(_) => Err(DynPayloadError::PayloadOff),
+            AnyPayload::On(_) => Err(DynPayloadError::HeaterOff),
+        }
+    }
+    fn protection(&self) -> &Protection {
+        match self.payload() {
+            AnyPayload::Off(payload) => &payload.protection,
+            AnyPayload::On(payload) => &payload.protection,
+            AnyPayload::Heating(payload) => &payload.protection,
+        }
+    }
+    fn protection_mut(&mut self) -> &mut Protection {
+        any_state!(self, payload => &mut payload.protection)
+    }
+    fn payload(&self) -> &AnyPayload<H> {
+        self.payload.as_ref().expect("DynPayload used mid-transition")
+    }
+    fn payload_mut(&mut self) -> &mut AnyPayload<H> {
+        self.payload.as_mut().expect("DynPayload used mid-transition")
+    }
+    // The typed transitions consume the payload, so it's taken out for the duration and put back afterwards, whether or not the transition happened.
+    fn transition(&mut self, f: impl FnOnce(AnyPayload<H>) -> Result<AnyPayload<H>, (AnyPayload<H>, DynPayloadError)>) -> Result<(), DynPayloadError> {
+        let payload = self.payload.take().expect("DynPayload used mid-transition");
+        let (payload, result) = match f(payload) {
+            Ok(payload) => (payload, Ok(())),
+            Err((payload, err)) => (payload, Err(err)),
+        };
+        self.payload = Some(payload);
+        result
+    }
+}
+
+// Back to the typed form. Gives the DynPayload back if it's in a different state.
+impl<H: PayloadHardware> TryFrom<DynPayload<H>> for Payload<{PayloadOff}, {HeaterOff}, H> {
+    type Error = DynPayload<H>;
+    fn try_from(dyn_payload: DynPayload<H>) -> Result<Self, DynPayload<H>> {
+        match dyn_payload.payload {
+            Some(AnyPayload::Off(payload)) => Ok(payload),
+            payload => Err(DynPayload { payload }),
+        }
+    }
+}
+impl<H: PayloadHardware> TryFrom<DynPayload<H>> for Payload<{PayloadOn}, {HeaterOff}, H> {
+    type Error = DynPayload<H>;
+    fn try_from(dyn_payload: DynPayload<H>) -> Result<Self, DynPayload<H>> {
+        match dyn_payload.payload {
+            Some(AnyPayload::On(payload)) => Ok(payload),
+            payload => Err(DynPayload { payload }),
+        }
+    }
+}
+impl<H: PayloadHardware> TryFrom<DynPayload<H>> for Payload<{PayloadOn}, {HeaterOn}, H> {
+    type Error = DynPayload<H>;
+    fn try_from(dyn_payload: DynPayload<H>) -> Result<Self, DynPayload<H>> {
+        match dyn_payload.payload {
+            Some(AnyPayload::Heating(payload)) => Ok(payload),
+            payload => Err(DynPayload { payload }),
+        }
+    }
+}
+
+// Keeps the payload's protection limits and any latched fault.
+impl<H: PayloadHardware> From<Payload<{PayloadOff}, {HeaterOff}, H>> for DynPayload<H> {
+    fn from(payload: Payload<{PayloadOff}, {HeaterOff}, H>) -> Self {
+        DynPayload { payload: Some(AnyPayload::Off(payload)) }
+    }
+}
+impl<H: PayloadHardware> From<Payload<{PayloadOn}, {HeaterOff}, H>> for DynPayload<H> {
+    fn from(payload: Payload<{PayloadOn}, {HeaterOff}, H>) -> Self {
+        DynPayload { payload: Some(AnyPayload::On(payload)) }
+    }
+}
+impl<H: PayloadHardware> From<Payload<{PayloadOn}, {HeaterOn}, H>> for DynPayload<H> {
+    fn from(payload: Payload<{PayloadOn}, {HeaterOn}, H>) -> Self {
+        DynPayload { payload: Some(AnyPayload::Heating(payload)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_sim::{SimBoard, SimHardware, SimLine};
+    use crate::protection::ProtectionTrip;
+    use std::boxed::Box;
+
+    fn dyn_payload(board: &SimBoard) -> DynPayload<SimHardware<'_>> {
+        DynPayload::from(board.build_payload(Box::leak(Box::new([0; 512])), &[]))
+    }
+    // Limits the board trips at room temperature
+    fn too_cold() -> ProtectionLimits {
+        ProtectionLimits { max_temperature: Kelvin::from_celcius(20), ..ProtectionLimits::default() }
+    }
+
+    #[test]
+    fn transitions_only_go_between_neighbouring_states() {
+        let board = SimBoard::new();
+        let mut payload = dyn_payload(&board);
+        assert_eq!(payload.enable_heater(), Err(DynPayloadError::PayloadOff));
+        assert_eq!(payload.disable_heater(), Err(DynPayloadError::HeaterOff));
+        assert_eq!(payload.disable_payload(), Err(DynPayloadError::PayloadOff));
+        assert_eq!(payload.set_heater_voltage(Millivolts(1000)), Err(DynPayloadError::PayloadOff));
+        assert_eq!(payload.state(), DynState::PayloadOff);
+
+        assert_eq!(payload.enable_payload(), Ok(()));
+        assert_eq!((payload.state(), board.line(SimLine::PayloadEnable)), (DynState::PayloadOn, true));
+        assert_eq!(payload.enable_payload(), Err(DynPayloadError::PayloadOn));
+        assert_eq!(payload.disable_heater(), Err(DynPayloadError::HeaterOff));
+
+        assert_eq!(payload.enable_heater(), Ok(()));
+        assert_eq!((payload.state(), board.line(SimLine::HeaterEnable)), (DynState::HeaterOn, true));
+        assert_eq!(payload.enable_heater(), Err(DynPayloadError::HeaterOn));
+        assert_eq!(payload.disable_payload(), Err(DynPayloadError::HeaterOn));
+        assert!(payload.as_heater_on().is_ok());
+        assert_eq!(payload.as_payload_on().err(), Some(DynPayloadError::HeaterOn));
+
+        assert_eq!(payload.disable_heater(), Ok(()));
+        assert_eq!(payload.disable_payload(), Ok(()));
+        assert_eq!(payload.state(), DynState::PayloadOff);
+        assert!(!board.line(SimLine::HeaterEnable) && !board.line(SimLine::PayloadEnable));
+    }
+
+    #[test]
+    fn enabling_is_refused_until_the_fault_is_cleared() {
+        let board = SimBoard::new();
+        let mut payload = dyn_payload(&board);
+        payload.set_protection_limits(too_cold());
+        payload.get_housekeeping();
+        let fault = payload.latched_fault().expect("no fault latched");
+        assert!(matches!(fault.trip, ProtectionTrip::OverTemperature(..)));
+        assert_eq!(payload.enable_payload(), Err(DynPayloadError::FaultLatched(fault)));
+        assert_eq!(payload.state(), DynState::PayloadOff);
+
+        payload.set_protection_limits(ProtectionLimits::default());
+        assert_eq!(payload.clear_fault(), Some(fault));
+        assert_eq!(payload.enable_payload(), Ok(()));
+        // The typed snapshot latches the fault without leaving the PayloadOn state
+        payload.set_protection_limits(too_cold());
+        payload.as_payload_on().unwrap().get_housekeeping();
+        let fault = payload.latched_fault().expect("no fault latched");
+        assert_eq!(payload.enable_heater(), Err(DynPayloadError::FaultLatched(fault)));
+        assert_eq!(payload.state(), DynState::PayloadOn);
+
+        payload.set_protection_limits(ProtectionLimits::default());
+        assert_eq!(payload.clear_fault(), Some(fault));
+        assert_eq!(payload.clear_fault(), None);
+        assert_eq!(payload.enable_heater(), Ok(()));
+    }
+
+    #[test]
+    fn safe_shutdown_works_from_every_state() {
+        let board = SimBoard::new();
+        let mut payload = dyn_payload(&board);
+        payload.safe_shutdown();
+        assert_eq!(payload.state(), DynState::PayloadOff);
+
+        payload.enable_payload().unwrap();
+        payload.safe_shutdown();
+        assert_eq!(payload.state(), DynState::PayloadOff);
+
+        payload.enable_payload().unwrap();
+        payload.enable_heater().unwrap();
+        payload.set_cathode_offset_switch(SwitchState::Connected).unwrap();
+        payload.set_tether_bias_switch(SwitchState::Connected).unwrap();
+        payload.set_tether_bias_voltage(Millivolts(100_000)).unwrap();
+        assert_eq!(payload.get_cathode_offset_switch(), SwitchState::Connected);
+        payload.safe_shutdown();
+        assert_eq!(payload.state(), DynState::PayloadOff);
+        assert_eq!((payload.get_cathode_offset_switch(), payload.get_tether_bias_switch()), (SwitchState::Disconnected, SwitchState::Disconnected));
+        for line in [SimLine::CathodeSwitch, SimLine::TetherSwitch, SimLine::HeaterEnable, SimLine::PayloadEnable] {
+            assert!(!board.line(line), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn try_from_gives_back_the_dyn_payload_in_the_wrong_state() {
+        let board = SimBoard::new();
+        let mut payload = dyn_payload(&board);
+        payload.set_protection_limits(too_cold());
+
+        let payload = Payload::<{PayloadOn}, {HeaterOff}, _>::try_from(payload).err().expect("converted while off");
+        let payload = Payload::<{PayloadOn}, {HeaterOn}, _>::try_from(payload).err().expect("converted while off");
+        let payload = Payload::<{PayloadOff}, {HeaterOff}, _>::try_from(payload).ok().expect("didn't convert while off");
+        let payload = DynPayload::from(payload.into_enabled_payload());
+        assert_eq!(payload.state(), DynState::PayloadOn);
+        // Limits survive the round trip
+        assert_eq!(payload.protection_limits().max_temperature, too_cold().max_temperature);
+
+        let payload = Payload::<{PayloadOff}, {HeaterOff}, _>::try_from(payload).err().expect("converted while on");
+        let payload = Payload::<{PayloadOn}, {HeaterOff}, _>::try_from(payload).ok().expect("didn't convert while on");
+        let payload = DynPayload::from(payload.into_enabled_heater());
+        assert_eq!(payload.state(), DynState::HeaterOn);
+
+        let payload = Payload::<{PayloadOn}, {HeaterOff}, _>::try_from(payload).err().expect("converted while heating");
+        assert!(Payload::<{PayloadOn}, {HeaterOn}, _>::try_from(payload).is_ok());
+    }
+}
@@ -0,0 +1,283 @@
+// This file defines Housekeeping, a snapshot of every payload sensor taken in one go by Payload::get_housekeeping.
+// A snapshot can be packed into a fixed-size binary record (e.g. for the OBC's Housekeeping frame) or printed as text.
+
+// Record format (multi-byte values are big-endian, signed values are two's complement):
+// VERSION[1] VALID[1] TIMESTAMP_MS[4] TEMPERATURES_K[8x2]
+// TETHER_BIAS_MV[4] TETHER_BIAS_UA[2] CATHODE_OFFSET_MV[4] CATHODE_OFFSET_UA[2] HEATER_MV[2] HEATER_MA[2] REPELLER_MV[4]
+// APERTURE_UA[2] PINPULLER_MA[2] LMS_RECEIVERS_MV[3x2] DIGITAL[1]
+// Two-byte fields saturate rather than wrapping. Readings from groups not set in VALID are zero.
+// DIGITAL bits, LSB first: cathode offset switch connected, tether bias switch connected, endmass sense 1, endmass sense 2, pinpuller sense.
+
+use ufmt::{uDisplay, uWrite, uwrite, uwriteln, Formatter};
+
+use crate::adc::TemperatureSensor;
+use crate::obc::MAX_PAYLOAD_LEN;
+use crate::payload::{SwitchState, TetherADCReadings};
+use crate::pcb_mapping::sensor_locations::*;
+use crate::units::{Kelvin, Microamps, Milliamps, Millivolts};
+
+pub const HOUSEKEEPING_RECORD_VERSION: u8 = 1;
+pub const HOUSEKEEPING_RECORD_LEN: usize = 53;
+const _: () = assert!(HOUSEKEEPING_RECORD_LEN <= MAX_PAYLOAD_LEN);
+
+pub const NUM_TEMPERATURE_SENSORS: usize = 8;
+pub const NUM_LMS_RECEIVERS: usize = 3;
+pub const NUM_READING_GROUPS: usize = 5;
+/// Every temperature sensor, in the order they appear in Housekeeping::temperatures.
+pub const TEMPERATURE_SENSORS: [(TemperatureSensor, &str); NUM_TEMPERATURE_SENSORS] = [
+    (LMS_EMITTER_TEMPERATURE_SENSOR,        "LMS Emitter"),
+    (LMS_RECEIVER_TEMPERATURE_SENSOR,       "LMS Receiver"),
+    (MSP430_TEMPERATURE_SENSOR,             "MSP430"),
+    (HEATER_SUPPLY_TEMPERATURE_SENSOR,      "Heater supply"),
+    (HVDC_SUPPLIES_TEMPERATURE_SENSOR,      "HVDC Supplies"),
+    (TETHER_MONITORING_TEMPERATURE_SENSOR,  "Tether monitoring"),
+    (TETHER_CONNECTOR_TEMPERATURE_SENSOR,   "Tether connector"),
+    (MSP_3V3_TEMPERATURE_SENSOR,            "MSP 3V3 supply"),
+];
+
+/// Readings that are taken together, and so are valid or invalid together.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum ReadingGroup {
+    Temperatures = 0,
+    /// Tether bias, cathode offset, heater and repeller. Only read while the payload is on.
+    TetherADC = 1,
+    Aperture = 2,
+    Pinpuller = 3,
+    /// Only read if the LMS receivers are already live.
+    LMSReceivers = 4,
+}
+impl ReadingGroup {
+    /// Every group, in discriminant order.
+    pub const ALL: [ReadingGroup; NUM_READING_GROUPS] = [ReadingGroup::Temperatures, ReadingGroup::TetherADC, ReadingGroup::Aperture, ReadingGroup::Pinpuller, ReadingGroup::LMSReceivers];
+}
+
+/// Set of reading groups that were read successfully.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct ValidReadings(u8);
+impl ValidReadings {
+    pub fn contains(&self, group: ReadingGroup) -> bool {
+        self.0 & (1 << group as u8) != 0
+    }
+    pub fn insert(&mut self, group: ReadingGroup) {
+        self.0 |= 1 << group as u8;
+    }
+    /// One bit per group, bit n set if the group with discriminant n is valid.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+}
+
+/// Readings in groups not marked valid are zero.
+#[derive(Copy, Clone, Debug)]
+pub struct Housekeeping {
+    /// Milliseconds since boot, from the power domain timer.
+    pub timestamp_millis: u32,
+    pub valid: ValidReadings,
+    pub temperatures: [Kelvin; NUM_TEMPERATURE_SENSORS],
+    pub tether: TetherADCReadings,
+    pub aperture_current_microamps: Microamps,
+    pub pinpuller_current_milliamps: Milliamps,
+    pub lms_receivers_millivolts: [Millivolts; NUM_LMS_RECEIVERS],
+    pub cathode_offset_switch: SwitchState,
+    pub tether_bias_switch: SwitchState,
+    /// Raw deploy sense input levels, true if high.
+    pub endmass_sense_1: bool,
+    pub endmass_sense_2: bool,
+    pub pinpuller_sense: bool,
+}
+impl Housekeeping {
+    pub fn encode(&self) -> [u8; HOUSEKEEPING_RECORD_LEN] {
+        let mut record = RecordWriter { buf: [0; HOUSEKEEPING_RECORD_LEN], len: 0 };
+        record.put_bytes(&[HOUSEKEEPING_RECORD_VERSION, self.valid.bits()]);
+        record.put_bytes(&self.timestamp_millis.to_be_bytes());
+        for temperature in self.temperatures {
+            record.put_i16(temperature.0);
+        }
+        let tether = &self.tether;
+        record.put_i32(tether.tether_bias_voltage_millivolts.0);
+        record.put_i16(tether.tether_bias_current_microamps.0);
+        record.put_i32(tether.cathode_offset_voltage_millivolts.0);
+        record.put_i16(tether.cathode_offset_current_microamps.0);
+        record.put_i16(tether.heater_voltage_millivolts.0);
+        record.put_i16(tether.heater_current_milliamps.0);
+        record.put_i32(tether.repeller_voltage_millivolts.0);
+        record.put_i16(self.aperture_current_microamps.0);
+        record.put_i16(self.pinpuller_current_milliamps.0);
+        for lms_receiver in self.lms_receivers_millivolts {
+            record.put_i16(lms_receiver.0);
+        }
+        let digital = [self.cathode_offset_switch == SwitchState::Connected, self.tether_bias_switch == SwitchState::Connected,
+                       self.endmass_sense_1, self.endmass_sense_2, self.pinpuller_sense];
+        record.put_bytes(&[digital.iter().enumerate().fold(0, |bits, (n, &set)| bits | ((set as u8) << n))]);
+        debug_assert_eq!(record.len, HOUSEKEEPING_RECORD_LEN);
+        record.buf
+    }
+}
+
+struct RecordWriter {
+    buf: [u8; HOUSEKEEPING_RECORD_LEN],
+    len: usize,
+}
+impl RecordWriter {
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+    fn put_i16(&mut self, value: i32) {
+        let saturated = value.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        self.put_bytes(&saturated.to_be_bytes());
+    }
+    fn put_i32(&mut self, value: i32) {
+        self.put_bytes(&value.to_be_bytes());
+    }
+}
+
+impl uDisplay for Housekeeping {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        uwriteln!(f, "Housekeeping at {}ms", self.timestamp_millis)?;
+        if self.valid.contains(ReadingGroup::Temperatures) {
+            for ((_, name), temperature) in TEMPERATURE_SENSORS.iter().zip(self.temperatures) {
+                uwriteln!(f, "{}: {}C", name, temperature.to_celcius())?;
+            }
+        } else {
+            uwriteln!(f, "Temperatures: invalid")?;
+        }
+        if self.valid.contains(ReadingGroup::TetherADC) {
+            let tether = &self.tether;
+            uwriteln!(f, "Tether bias: {}mV, {}uA", tether.tether_bias_voltage_millivolts, tether.tether_bias_current_microamps)?;
+            uwriteln!(f, "Cathode offset: {}mV, {}uA", tether.cathode_offset_voltage_millivolts, tether.cathode_offset_current_microamps)?;
+            uwriteln!(f, "Heater: {}mV, {}mA", tether.heater_voltage_millivolts, tether.heater_current_milliamps)?;
+            uwriteln!(f, "Repeller: {}mV", tether.repeller_voltage_millivolts)?;
+        } else {
+            uwriteln!(f, "Tether ADC: invalid")?;
+        }
+        if self.valid.contains(ReadingGroup::Aperture) {
+            uwriteln!(f, "Aperture current: {}uA", self.aperture_current_microamps)?;
+        } else {
+            uwriteln!(f, "Aperture current: invalid")?;
+        }
+        if self.valid.contains(ReadingGroup::Pinpuller) {
+            uwriteln!(f, "Pinpuller current: {}mA", self.pinpuller_current_milliamps)?;
+        } else {
+            uwriteln!(f, "Pinpuller current: invalid")?;
+        }
+        if self.valid.contains(ReadingGroup::LMSReceivers) {
+            let [lms_1, lms_2, lms_3] = self.lms_receivers_millivolts;
+            uwriteln!(f, "LMS receivers: {}mV, {}mV, {}mV", lms_1, lms_2, lms_3)?;
+        } else {
+            uwriteln!(f, "LMS receivers: invalid")?;
+        }
+        uwriteln!(f, "Switches: cathode offset {:?}, tether bias {:?}", self.cathode_offset_switch, self.tether_bias_switch)?;
+        uwrite!(f, "Deploy sense: endmass 1 {}, endmass 2 {}, pinpuller {}", self.endmass_sense_1 as u8, self.endmass_sense_2 as u8, self.pinpuller_sense as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use std::string::String;
+
+    struct TextWriter(String);
+    impl uWrite for TextWriter {
+        type Error = Infallible;
+        fn write_str(&mut self, string: &str) -> Result<(), Infallible> {
+            self.0.push_str(string);
+            Ok(())
+        }
+    }
+
+    fn all_valid() -> ValidReadings {
+        let mut valid = ValidReadings::default();
+        for group in ReadingGroup::ALL {
+            valid.insert(group);
+        }
+        valid
+    }
+    // Every field distinct, so a misplaced field shows up
+    fn sample() -> Housekeeping {
+        Housekeeping {
+            timestamp_millis: 0x0102_0304, valid: all_valid(),
+            temperatures: [Kelvin(290), Kelvin(291), Kelvin(292), Kelvin(293), Kelvin(294), Kelvin(295), Kelvin(296), Kelvin(297)],
+            tether: TetherADCReadings {
+                tether_bias_voltage_millivolts: Millivolts(-200_000), tether_bias_current_microamps: Microamps(-1500),
+                cathode_offset_voltage_millivolts: Millivolts(150_000), cathode_offset_current_microamps: Microamps(1200),
+                heater_voltage_millivolts: Millivolts(8000), heater_current_milliamps: Milliamps(300), repeller_voltage_millivolts: Millivolts(-90_000),
+            },
+            aperture_current_microamps: Microamps(-25), pinpuller_current_milliamps: Milliamps(1000),
+            lms_receivers_millivolts: [Millivolts(100), Millivolts(200), Millivolts(300)],
+            cathode_offset_switch: SwitchState::Connected, tether_bias_switch: SwitchState::Disconnected,
+            endmass_sense_1: false, endmass_sense_2: true, pinpuller_sense: true,
+        }
+    }
+
+    #[test]
+    fn record_fields_are_big_endian_at_their_offsets() {
+        let record = sample().encode();
+        assert_eq!(record.len(), 53);
+        assert_eq!(record[0..2], [HOUSEKEEPING_RECORD_VERSION, 0b1_1111]);
+        assert_eq!(record[2..6], [0x01, 0x02, 0x03, 0x04]);
+        for (n, temperature) in record[6..22].chunks(2).enumerate() {
+            assert_eq!(temperature, (290 + n as i16).to_be_bytes());
+        }
+        assert_eq!(record[22..26], (-200_000i32).to_be_bytes());
+        assert_eq!(record[26..28], (-1500i16).to_be_bytes());
+        assert_eq!(record[28..32], 150_000i32.to_be_bytes());
+        assert_eq!(record[32..34], 1200i16.to_be_bytes());
+        assert_eq!(record[34..36], 8000i16.to_be_bytes());
+        assert_eq!(record[36..38], 300i16.to_be_bytes());
+        assert_eq!(record[38..42], (-90_000i32).to_be_bytes());
+        assert_eq!(record[42..44], (-25i16).to_be_bytes());
+        assert_eq!(record[44..46], 1000i16.to_be_bytes());
+        assert_eq!(record[46..52], [0, 100, 0, 200, 1, 44]);
+        // Cathode offset switch connected, endmass sense 2 and pinpuller sense high
+        assert_eq!(record[52], 0b1_1001);
+    }
+
+    #[test]
+    fn two_byte_fields_saturate() {
+        let mut housekeeping = sample();
+        housekeeping.temperatures[0] = Kelvin(40_000);
+        housekeeping.tether.tether_bias_current_microamps = Microamps(-40_000);
+        housekeeping.tether.heater_voltage_millivolts = Millivolts(i32::MAX);
+        housekeeping.aperture_current_microamps = Microamps(i32::MIN);
+        let record = housekeeping.encode();
+        assert_eq!(record[6..8], i16::MAX.to_be_bytes());
+        assert_eq!(record[26..28], i16::MIN.to_be_bytes());
+        assert_eq!(record[34..36], i16::MAX.to_be_bytes());
+        assert_eq!(record[42..44], i16::MIN.to_be_bytes());
+        // Four-byte fields fit every reading as is
+        housekeeping.tether.tether_bias_voltage_millivolts = Millivolts(i32::MIN);
+        assert_eq!(housekeeping.encode()[22..26], i32::MIN.to_be_bytes());
+    }
+
+    #[test]
+    fn text_marks_invalid_groups() {
+        let mut housekeeping = sample();
+        let mut valid = ValidReadings::default();
+        valid.insert(ReadingGroup::Temperatures);
+        valid.insert(ReadingGroup::Pinpuller);
+        housekeeping.valid = valid;
+        let mut text = TextWriter(String::new());
+        uwrite!(text, "{}", housekeeping).unwrap();
+        let lines: std::vec::Vec<&str> = text.0.lines().collect();
+        assert_eq!(lines[..3], ["Housekeeping at 16909060ms", "LMS Emitter: 17C", "LMS Receiver: 18C"]);
+        assert_eq!(lines[8], "MSP 3V3 supply: 24C");
+        assert_eq!(lines[9..], [
+            "Tether ADC: invalid",
+            "Aperture current: invalid",
+            "Pinpuller current: 1000mA",
+            "LMS receivers: invalid",
+            "Switches: cathode offset Connected, tether bias Disconnected",
+            "Deploy sense: endmass 1 0, endmass 2 1, pinpuller 1",
+        ]);
+
+        housekeeping.valid = all_valid();
+        let mut text = TextWriter(String::new());
+        uwrite!(text, "{}", housekeeping).unwrap();
+        assert!(text.0.contains("\nTether bias: -200000mV, -1500uA\n"), "{}", text.0);
+        assert!(text.0.contains("\nRepeller: -90000mV\n"), "{}", text.0);
+        assert!(text.0.contains("\nAperture current: -25uA\n"), "{}", text.0);
+        assert!(text.0.contains("\nLMS receivers: 100mV, 200mV, 300mV\n"), "{}", text.0);
+    }
+}
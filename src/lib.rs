@@ -0,0 +1,52 @@
+// The payload board support and test logic. main.rs sets up the MSP430 and calls into this.
+// Everything except the hardware setup in main.rs also builds for the host, so the tests can run against mocks there ('cargo test-host --features 7B').
+#![cfg_attr(not(test), no_std)]
+#![allow(dead_code, unused_variables, unused_imports)] // TODO: Remove when ready
+#![allow(clippy::upper_case_acronyms, clippy::needless_return)]
+#![allow(incomplete_features)]
+#![feature(adt_const_params)]
+#![feature(const_trait_impl)]
+#![feature(isqrt)]
+
+pub mod pcb_common; // pcb_mapping re-exports these values, so no need to interact with this file.
+                    // This line lets every other file do 'use pcb_mapping', we only have to change the version once here.
+pub mod pcb_mapping {
+    include!("pcb_v7_mapping.rs");
+}
+pub mod units;
+pub mod spi;
+use spi::PayloadSPIController;
+#[cfg(any(test, feature = "sim"))]
+pub mod spi_mock;
+pub mod spi_ehal;
+pub mod checksum;
+pub mod obc;
+pub mod dac;
+pub mod adc;
+pub mod calibration;
+pub mod power;
+pub mod regulator;
+pub mod protection;
+pub mod housekeeping;
+pub mod dyn_payload;
+pub mod digipot;
+use digipot::Digipot;
+pub mod payload;
+pub mod serial;
+pub mod tvac;
+#[allow(unused_imports)]
+pub mod testing;
+#[cfg(any(test, feature = "sim"))]
+pub mod board_sim;
+
+/// Approximate busy-wait. There's nothing to wait for on the host, so it returns straight away there.
+pub fn delay_cycles(num_cycles: u32) {
+    //approximate delay fn
+    #[cfg(target_arch = "msp430")]
+    {
+        let delay = (6 * num_cycles) / 128;
+        for _ in 0..delay {
+            msp430::asm::nop()
+        }
+    }
+}
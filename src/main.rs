@@ -1,101 +1,98 @@
-#![no_main]
-#![no_std]
+// Sets up the MSP430 and hands the payload over to the test logic in lib.rs.
+// Only the MSP430 build does anything. On the host this is an empty binary, the tests there run with 'cargo test-host'.
+#![cfg_attr(target_arch = "msp430", no_main, no_std)]
 #![allow(dead_code, unused_variables, unused_imports)] // TODO: Remove when ready
 #![allow(clippy::upper_case_acronyms, clippy::needless_return)]
 #![allow(incomplete_features)]
 #![feature(adt_const_params)]
-#![feature(const_trait_impl)]
 
-use core::{
-    cell::{RefCell, UnsafeCell},
-    ops::DerefMut,
-};
-
-use critical_section::{with, CriticalSection, Mutex};
-use embedded_hal::{
-    blocking::i2c,
-    digital::v2::*,
-    timer::{self, CountDown},
-};
-use msp430_rt::entry;
-use msp430fr2355::{E_USCI_A1, P1, P2, P3, P4, P5, P6, PMM};
-use msp430fr2x5x_hal::{
-    clock::{ClockConfig, DcoclkFreqSel, MclkDiv},
-    fram::Fram,
-    gpio::Batch,
-    pmm::Pmm,
-    rtc::{Rtc, RtcDiv},
-    serial::{BitCount, BitOrder, Loopback, Parity, SerialConfig, SerialUsci, StopBits},
-    timer::{CapCmpTimer3, TBxIV, Timer, TimerConfig, TimerParts3},
-    watchdog::Wdt,
+#[cfg(target_arch = "msp430")]
+use {
+    embedded_hal::digital::v2::*,
+    msp430_rt::entry,
+    msp430fr2355::{P1, P2, P3, P4, P5, P6, PMM},
+    msp430fr2x5x_hal::{
+        clock::{ClockConfig, DcoclkFreqSel, MclkDiv},
+        fram::Fram,
+        gpio::Batch,
+        info_mem::InfoMemory,
+        pmm::Pmm,
+        serial::{BitCount, BitOrder, Loopback, Parity, SerialConfig, StopBits},
+        timer::{TimerConfig, TimerDiv, TimerExDiv, TimerParts3},
+        watchdog::Wdt,
+    },
+    ufmt::{uwrite, uwriteln},
+    msp430_pcb_self_test::{
+        adc::{ApertureADC, MiscADC, TemperatureADC, TetherADC},
+        calibration::{calibrate_adcs, CalibrationStore},
+        dac::DAC,
+        delay_cycles,
+        digipot::Digipot,
+        payload::{HeaterState::*, Payload, PayloadBuilder, PayloadState::*},
+        pcb_common::MSP430Hardware,
+        pcb_mapping::{
+            DebugSerialPins, DeploySensePins, LEDPins, OBCSPIPins, PayloadControlPins,
+            PayloadPeripherals, PayloadSPIBitBangPins, PayloadSPIChipSelectPins, PayloadSPIPins,
+            PinpullerActivationPins, TetherLMSPins,
+        },
+        power::PowerDomains,
+        println,
+        serial::{NoSerialInput, SerialWriter},
+        spi::{OBCSPISlave, PayloadSPIController},
+        testing::{AutomatedFunctionalTests, ManualPerformanceTests},
+    },
 };
-use nb::block;
-use tvac::tvac_test;
-use ufmt::{uwrite, uwriteln};
 
-#[cfg(debug_assertions)]
+// eUSCI_A1 is either the debug serial port or, with the obc_spi feature, the OBC bus.
+// The serial port becomes the global printer, while the OBC bus is handed back from configure_board.
+#[cfg(all(target_arch = "msp430", not(feature = "obc_spi")))]
+type UsciA1Pins = DebugSerialPins;
+#[cfg(all(target_arch = "msp430", not(feature = "obc_spi")))]
+type OBCLink = ();
+#[cfg(all(target_arch = "msp430", feature = "obc_spi"))]
+type UsciA1Pins = OBCSPIPins;
+#[cfg(all(target_arch = "msp430", feature = "obc_spi"))]
+type OBCLink = OBCSPISlave;
+
+#[cfg(all(target_arch = "msp430", debug_assertions))]
 use panic_msp430 as _;
 
-#[cfg(not(debug_assertions))]
+#[cfg(all(target_arch = "msp430", not(debug_assertions)))]
 use panic_never as _;
 
-pub mod pcb_common; // pcb_mapping re-exports these values, so no need to interact with this file.
-                    // This line lets every other file do 'use pcb_mapping', we only have to change the version once here.
-mod pcb_mapping {
-    include!("pcb_v7_mapping.rs");
-}
+#[cfg(all(feature = "adc_calibration", feature = "obc_spi"))]
+compile_error!("ADC calibration is done over the debug serial port, which obc_spi uses for the OBC bus");
 
-use pcb_mapping::{
-    power_supply_limits::HEATER_MIN_VOLTAGE_MILLIVOLTS, DebugSerialPins, DeploySensePins, LEDPins,
-    PayloadControlPins, PayloadPeripherals, PayloadSPIBitBangPins, PayloadSPIChipSelectPins,
-    PinpullerActivationPins, TetherLMSPins,
-};
-mod spi;
-use spi::{PayloadSPI, PayloadSPIController, SckPhase::SampleFirstEdge, SckPolarity::IdleLow};
-mod dac;
-use dac::DAC;
-mod adc;
-use adc::{ApertureADC, MiscADC, TemperatureADC, TetherADC};
-mod digipot;
-use digipot::Digipot;
-mod payload;
-use payload::{
-    HeaterState, HeaterState::*, PayloadBuilder, PayloadState, PayloadState::*, SwitchState,
-};
-mod serial;
-use serial::SerialWriter;
-mod tvac;
-
-#[allow(unused_imports)]
-mod testing;
-use testing::{
-    AutomatedFunctionalTests, AutomatedPerformanceTests, ManualFunctionalTests,
-    ManualPerformanceTests,
-};
-use void::ResultVoidExt;
-
-use crate::{
-    payload::Payload,
-    pcb_mapping::power_supply_limits::{
-        CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS, HEATER_MAX_VOLTAGE_MILLIVOLTS,
-        TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS,
-    },
-};
+#[cfg(not(target_arch = "msp430"))]
+fn main() {}
 
+#[cfg(target_arch = "msp430")]
 #[allow(unused_mut)]
 #[entry]
 fn main() -> ! {
-    let board = configure_board();
+    let (board, mut obc_link) = configure_board();
 
     let mut board = board.into_enabled_payload();
 
-    ManualPerformanceTests::test_cathode_offset_voltage(&mut board);
+    // There's no serial port to print to while the OBC bus is in use, so the result is shown on the LEDs
+    #[cfg(feature = "obc_spi")]
+    {
+        let result = AutomatedFunctionalTests::obc_spi_functional_test(&mut obc_link, &mut board);
+        show_result(!result.failed(), &mut board.led_pins);
+    }
 
-    idle_loop(&mut board.led_pins);
+    #[cfg(not(feature = "obc_spi"))]
+    {
+        #[cfg(feature = "adc_calibration")]
+        calibrate_adcs(&mut board);
+        ManualPerformanceTests::test_cathode_offset_voltage(&mut board);
+        idle_loop(&mut board.led_pins);
+    }
 }
 
 /// Take and configure MCU peripherals
-fn configure_board() -> Payload<{ PayloadOff }, { HeaterOff }> {
+#[cfg(target_arch = "msp430")]
+fn configure_board() -> (Payload<{ PayloadOff }, { HeaterOff }, MSP430Hardware>, OBCLink) {
     let Some(regs) = msp430fr2355::Peripherals::take() else {
         loop {}
     };
@@ -109,7 +106,7 @@ fn configure_board() -> Payload<{ PayloadOff }, { HeaterOff }> {
         mut lms_control_pins,
         deploy_sense_pins,
         payload_peripheral_cs_pins,
-        debug_serial_pins,
+        usci_a1_pins,
     ) = collect_pins(
         regs.PMM, regs.P1, regs.P2, regs.P3, regs.P4, regs.P5, regs.P6,
     );
@@ -120,6 +117,7 @@ fn configure_board() -> Payload<{ PayloadOff }, { HeaterOff }> {
 
     // As the bus's idle state is part of it's type, peripherals will not accept an incorrectly configured bus
     // The SPI controller handles all of this for us. All we need to do is call .borrow() to get a mutable reference to it
+    #[cfg(not(feature = "hardware_payload_spi"))]
     let payload_spi_controller = PayloadSPIController::new(payload_spi_pins);
 
     // Collate peripherals into a single struct
@@ -136,11 +134,30 @@ fn configure_board() -> Payload<{ PayloadOff }, { HeaterOff }> {
 
     led_pins.yellow_led.toggle().ok();
 
+    // The eUSCI peripheral needs SMCLK, so the hardware SPI bus can only be created after clock selection
+    #[cfg(feature = "hardware_payload_spi")]
+    let payload_spi_controller = PayloadSPIController::new(
+        regs.E_USCI_B1,
+        PayloadSPIPins {
+            miso: payload_spi_pins.miso.to_output().to_alternate1(),
+            mosi: payload_spi_pins.mosi.to_alternate1(),
+            sck: payload_spi_pins.sck.to_alternate1(),
+        },
+        &smclk,
+    );
+
+    // ADC calibrations are kept in information memory
+    let (info_mem, _sys) = InfoMemory::as_u8s(regs.SYS);
+    let calibration_store = CalibrationStore::new(info_mem);
+
     // Timer configuration
     let parts = TimerParts3::new(regs.TB0, TimerConfig::aclk(&aclk));
     let timer = parts.timer;
+    let power_timer_config = TimerConfig::aclk(&aclk).clk_div(TimerDiv::_8, TimerExDiv::_8);
+    let power_domains = PowerDomains::new(TimerParts3::new(regs.TB1, power_timer_config).timer);
 
     // Serial configuration
+    #[cfg(not(feature = "obc_spi"))]
     let (serial_tx_pin, serial_reader) = SerialConfig::new(
         regs.E_USCI_A1,
         BitOrder::LsbFirst,
@@ -151,7 +168,12 @@ fn configure_board() -> Payload<{ PayloadOff }, { HeaterOff }> {
         115200,
     )
     .use_smclk(&smclk)
-    .split(debug_serial_pins.tx, debug_serial_pins.rx);
+    .split(usci_a1_pins.tx, usci_a1_pins.rx);
+    #[cfg(not(feature = "obc_spi"))]
+    let obc_link = ();
+
+    #[cfg(feature = "obc_spi")]
+    let (obc_link, serial_reader) = (OBCSPISlave::new(regs.E_USCI_A1, usci_a1_pins), NoSerialInput);
 
     // Create an object to manage payload state
     led_pins.red_led.toggle().ok();
@@ -165,22 +187,27 @@ fn configure_board() -> Payload<{ PayloadOff }, { HeaterOff }> {
         serial_reader,
         led_pins,
         timer,
+        calibration_store,
+        power_domains,
     );
 
     // Wrapper struct so we can use ufmt traits like uwrite! and uwriteln!
+    #[cfg(not(feature = "obc_spi"))]
     let serial_writer = SerialWriter::new(serial_tx_pin);
 
     // Move serial_writer into a static variable so we can print from anywhere without having to carry it around
+    #[cfg(not(feature = "obc_spi"))]
     critical_section::with(|cs| {
-        unsafe { &mut *crate::serial::SERIAL_WR.borrow(cs).get() }.replace(serial_writer);
+        unsafe { &mut *msp430_pcb_self_test::serial::SERIAL_WR.borrow(cs).get() }.replace(serial_writer);
     });
 
     println!("Hello world!");
 
-    payload
+    (payload, obc_link)
 }
 
-fn idle_loop(led_pins: &mut LEDPins) -> ! {
+#[cfg(target_arch = "msp430")]
+fn idle_loop(led_pins: &mut LEDPins<MSP430Hardware>) -> ! {
     let mut counter: u8 = 0;
     loop {
         snake_leds(&mut counter, led_pins);
@@ -189,15 +216,19 @@ fn idle_loop(led_pins: &mut LEDPins) -> ! {
     }
 }
 
-fn delay_cycles(num_cycles: u32) {
-    //approximate delay fn
-    let delay = (6 * num_cycles) / 128;
-    for _ in 0..delay {
-        msp430::asm::nop()
+// Green for a pass, red for a fail. Never returns, so the result stays up.
+#[cfg(all(target_arch = "msp430", feature = "obc_spi"))]
+fn show_result(passed: bool, led_pins: &mut LEDPins<MSP430Hardware>) -> ! {
+    led_pins.red_led.set_state((!passed).into()).ok();
+    led_pins.yellow_led.set_low().ok();
+    led_pins.green_led.set_state(passed.into()).ok();
+    loop {
+        delay_cycles(45000);
     }
 }
 
-fn snake_leds(n: &mut u8, led_pins: &mut LEDPins) {
+#[cfg(target_arch = "msp430")]
+fn snake_leds(n: &mut u8, led_pins: &mut LEDPins<MSP430Hardware>) {
     *n = (*n + 1) % 4;
     match n {
         1 => led_pins.green_led.toggle().ok(),
@@ -207,7 +238,10 @@ fn snake_leds(n: &mut u8, led_pins: &mut LEDPins) {
     };
 }
 
-fn collect_payload_peripherals(cs_pins: PayloadSPIChipSelectPins) -> PayloadPeripherals {
+#[cfg(target_arch = "msp430")]
+fn collect_payload_peripherals(
+    cs_pins: PayloadSPIChipSelectPins<MSP430Hardware>,
+) -> PayloadPeripherals<MSP430Hardware> {
     // Note that the peripherals gain ownership of their associated pins
     let digipot = Digipot::new(cs_pins.digipot);
     let dac = DAC::new(cs_pins.dac);
@@ -226,6 +260,8 @@ fn collect_payload_peripherals(cs_pins: PayloadSPIChipSelectPins) -> PayloadPeri
 }
 
 // Takes raw port peripherals and returns actually useful pin collections
+#[cfg(target_arch = "msp430")]
+#[allow(clippy::type_complexity)]
 fn collect_pins(
     pmm: PMM,
     p1: P1,
@@ -236,13 +272,13 @@ fn collect_pins(
     p6: P6,
 ) -> (
     PayloadSPIBitBangPins,
-    PinpullerActivationPins,
-    LEDPins,
-    PayloadControlPins,
-    TetherLMSPins,
-    DeploySensePins,
-    PayloadSPIChipSelectPins,
-    DebugSerialPins,
+    PinpullerActivationPins<MSP430Hardware>,
+    LEDPins<MSP430Hardware>,
+    PayloadControlPins<MSP430Hardware>,
+    TetherLMSPins<MSP430Hardware>,
+    DeploySensePins<MSP430Hardware>,
+    PayloadSPIChipSelectPins<MSP430Hardware>,
+    UsciA1Pins,
 ) {
     let pmm = Pmm::new(pmm);
     let port1 = Batch::new(p1).split(&pmm);
@@ -299,10 +335,19 @@ fn collect_pins(
         port1.pin3.to_output(),
     );
 
-    let debug_serial_pins = DebugSerialPins {
+    #[cfg(not(feature = "obc_spi"))]
+    let usci_a1_pins = DebugSerialPins {
         rx: port4.pin2.to_output().to_alternate1(),
         tx: port4.pin3.to_output().to_alternate1(),
     };
+    #[cfg(feature = "obc_spi")]
+    let usci_a1_pins = OBCSPIPins {
+        miso: port4.pin2.to_output().to_alternate1(),
+        mosi: port4.pin3.to_output().to_alternate1(),
+        sck: port4.pin1.to_output().to_alternate1(),
+        chip_select: port4.pin0.to_output().to_alternate1(),
+        chip_select_interrupt: port2.pin0.pullup(),
+    };
 
     (
         payload_spi_pins,
@@ -312,13 +357,14 @@ fn collect_pins(
         lms_control_pins,
         deploy_sense_pins,
         payload_peripheral_cs_pins,
-        debug_serial_pins,
+        usci_a1_pins,
     )
 }
 
 // The compiler will emit calls to the abort() compiler intrinsic if debug assertions are
 // enabled (default for dev profile). MSP430 does not actually have meaningful abort() support
 // so for now, we create our own in each application where debug assertions are present.
+#[cfg(target_arch = "msp430")]
 #[no_mangle]
 extern "C" fn abort() -> ! {
     panic!();
@@ -0,0 +1,485 @@
+// This file implements the framed request/response protocol used to talk to the on-board computer (OBC), with the payload acting as an SPI slave.
+// Nothing in here touches hardware, so the codec can be run on a host against SimulatedOBC. The SPI side of the link is OBCSPISlave in spi.rs.
+
+// Frame format: SYNC SEQ TYPE LEN PAYLOAD[LEN] CRC_HI CRC_LO
+// The CRC is CRC-16/CCITT-FALSE, calculated over SEQ, TYPE, LEN and PAYLOAD.
+//
+// The link is full duplex, so the response to a request is clocked out during the *next* transaction.
+// The OBC reads a response by clocking a transaction full of IDLE_BYTEs (or by sending its next request).
+// If the OBC repeats a sequence number the previous response is resent without handling the request again, so retries are safe.
+
+use crate::checksum::crc16;
+
+pub const FRAME_SYNC: u8 = 0x7E;
+pub const IDLE_BYTE: u8 = 0x00;
+pub const MAX_PAYLOAD_LEN: usize = 64;
+const NUM_HEADER_BYTES: usize = 4; // SYNC, SEQ, TYPE, LEN
+const NUM_CRC_BYTES: usize = 2;
+pub const MAX_FRAME_LEN: usize = NUM_HEADER_BYTES + MAX_PAYLOAD_LEN + NUM_CRC_BYTES;
+
+const SEQ_OFFSET: usize = 1;
+const TYPE_OFFSET: usize = 2;
+const LEN_OFFSET: usize = 3;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum FrameType {
+    // OBC -> payload
+    Ping = 0x01,
+    Command = 0x02,
+    GetHousekeeping = 0x03,
+    GetTestResults = 0x04,
+    // payload -> OBC
+    Ack = 0x81,
+    Nack = 0x82,
+    Housekeeping = 0x83,
+    TestResults = 0x84,
+}
+impl FrameType {
+    fn from_u8(byte: u8) -> Option<FrameType> {
+        match byte {
+            0x01 => Some(FrameType::Ping),
+            0x02 => Some(FrameType::Command),
+            0x03 => Some(FrameType::GetHousekeeping),
+            0x04 => Some(FrameType::GetTestResults),
+            0x81 => Some(FrameType::Ack),
+            0x82 => Some(FrameType::Nack),
+            0x83 => Some(FrameType::Housekeeping),
+            0x84 => Some(FrameType::TestResults),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum FrameError {
+    /// The first byte wasn't FRAME_SYNC.
+    BadSync,
+    /// The transaction ended before the whole frame arrived.
+    Incomplete,
+    /// LEN is larger than MAX_PAYLOAD_LEN.
+    BadLength,
+    BadCrc,
+    UnknownType,
+}
+
+/// Sent as the single payload byte of a Nack frame.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum NackReason {
+    BadFrame = 0x01,
+    BadLength = 0x02,
+    BadCrc = 0x03,
+    UnknownType = 0x04,
+    UnknownCommand = 0x05,
+    /// The command is valid, but can't be carried out right now (e.g. the payload is in the wrong state).
+    InvalidState = 0x06,
+}
+impl From<FrameError> for NackReason {
+    fn from(err: FrameError) -> NackReason {
+        match err {
+            FrameError::BadSync | FrameError::Incomplete => NackReason::BadFrame,
+            FrameError::BadLength => NackReason::BadLength,
+            FrameError::BadCrc => NackReason::BadCrc,
+            FrameError::UnknownType => NackReason::UnknownType,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Frame {
+    pub seq: u8,
+    pub frame_type: FrameType,
+    len: u8,
+    payload: [u8; MAX_PAYLOAD_LEN],
+}
+impl Frame {
+    pub fn new(seq: u8, frame_type: FrameType, payload: &[u8]) -> Result<Frame, FrameError> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(FrameError::BadLength);
+        }
+        let mut frame = Frame { seq, frame_type, len: payload.len() as u8, payload: [0; MAX_PAYLOAD_LEN] };
+        frame.payload[..payload.len()].copy_from_slice(payload);
+        Ok(frame)
+    }
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.len as usize]
+    }
+    /// Write the frame into buf, returning the number of bytes used.
+    pub fn encode(&self, buf: &mut [u8; MAX_FRAME_LEN]) -> usize {
+        let crc_offset = NUM_HEADER_BYTES + self.len as usize;
+        buf[0] = FRAME_SYNC;
+        buf[SEQ_OFFSET] = self.seq;
+        buf[TYPE_OFFSET] = self.frame_type as u8;
+        buf[LEN_OFFSET] = self.len;
+        buf[NUM_HEADER_BYTES..crc_offset].copy_from_slice(self.payload());
+        let crc = crc16(&buf[SEQ_OFFSET..crc_offset]);
+        buf[crc_offset] = (crc >> 8) as u8;
+        buf[crc_offset + 1] = crc as u8;
+        crc_offset + NUM_CRC_BYTES
+    }
+    /// Parse a frame starting at bytes[0]. Any bytes after the end of the frame are ignored.
+    pub fn decode(bytes: &[u8]) -> Result<Frame, FrameError> {
+        if bytes.len() < NUM_HEADER_BYTES {
+            return Err(FrameError::Incomplete);
+        }
+        if bytes[0] != FRAME_SYNC {
+            return Err(FrameError::BadSync);
+        }
+        let len = bytes[LEN_OFFSET] as usize;
+        if len > MAX_PAYLOAD_LEN {
+            return Err(FrameError::BadLength);
+        }
+        let crc_offset = NUM_HEADER_BYTES + len;
+        if bytes.len() < crc_offset + NUM_CRC_BYTES {
+            return Err(FrameError::Incomplete);
+        }
+        let received_crc = ((bytes[crc_offset] as u16) << 8) | (bytes[crc_offset + 1] as u16);
+        if crc16(&bytes[SEQ_OFFSET..crc_offset]) != received_crc {
+            return Err(FrameError::BadCrc);
+        }
+        let frame_type = FrameType::from_u8(bytes[TYPE_OFFSET]).ok_or(FrameError::UnknownType)?;
+        Frame::new(bytes[SEQ_OFFSET], frame_type, &bytes[NUM_HEADER_BYTES..crc_offset])
+    }
+}
+
+/// Collects the bytes clocked in during one transaction. Anything before the first FRAME_SYNC (e.g. idle bytes) is ignored.
+pub struct FrameReceiver {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+}
+impl FrameReceiver {
+    pub fn new() -> FrameReceiver {
+        FrameReceiver { buf: [0; MAX_FRAME_LEN], len: 0 }
+    }
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+    pub fn push(&mut self, byte: u8) {
+        if self.len == 0 && byte != FRAME_SYNC {
+            return;
+        }
+        if self.len < MAX_FRAME_LEN {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+    /// Sequence number of the frame being received, if it's arrived yet.
+    pub fn seq(&self) -> Option<u8> {
+        if self.len > SEQ_OFFSET { Some(self.buf[SEQ_OFFSET]) } else { None }
+    }
+    /// None if no frame was started during the transaction.
+    pub fn finish(&self) -> Option<Result<Frame, FrameError>> {
+        match self.len {
+            0 => None,
+            len => Some(Frame::decode(&self.buf[..len])),
+        }
+    }
+}
+impl Default for FrameReceiver {
+    fn default() -> Self {
+        FrameReceiver::new()
+    }
+}
+
+/// Holds the bytes to clock out during the next transaction.
+///
+/// Sends IDLE_BYTE once it runs out, or once the frame has been cleared. A cleared frame is kept so it can be resent if the OBC asks for it again.
+pub struct FrameTransmitter {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    pos: usize,
+    active: bool,
+}
+impl FrameTransmitter {
+    pub fn new() -> FrameTransmitter {
+        FrameTransmitter { buf: [IDLE_BYTE; MAX_FRAME_LEN], len: 0, pos: 0, active: false }
+    }
+    pub fn stage(&mut self, frame: &Frame) {
+        self.len = frame.encode(&mut self.buf);
+        self.pos = 0;
+        self.active = true;
+    }
+    pub fn clear(&mut self) {
+        self.active = false;
+    }
+    /// Stage the most recent frame again, even if it's been cleared.
+    pub fn restage(&mut self) {
+        self.pos = 0;
+        self.active = self.len > 0;
+    }
+    pub fn is_empty(&self) -> bool {
+        !self.active
+    }
+    /// Start sending the staged frame from the beginning again.
+    pub fn rewind(&mut self) {
+        self.pos = 0;
+    }
+    pub fn next_byte(&mut self) -> u8 {
+        if self.active && self.pos < self.len {
+            self.pos += 1;
+            self.buf[self.pos - 1]
+        } else {
+            IDLE_BYTE
+        }
+    }
+}
+impl Default for FrameTransmitter {
+    fn default() -> Self {
+        FrameTransmitter::new()
+    }
+}
+
+/// A decoded request from the OBC.
+pub enum Request<'a> {
+    Ping,
+    /// The first payload byte is the command ID, the rest are its arguments.
+    Command { id: u8, args: &'a [u8] },
+    GetHousekeeping,
+    GetTestResults,
+}
+
+/// What to send back to the OBC. Data responses borrow their payload from the caller.
+pub enum Response<'a> {
+    Ack,
+    Nack(NackReason),
+    Housekeeping(&'a [u8]),
+    TestResults(&'a [u8]),
+}
+
+/// Slave side of the protocol. Feed it the bytes of each transaction and it will stage the response for the next one.
+pub struct OBCProtocol {
+    receiver: FrameReceiver,
+    transmitter: FrameTransmitter,
+    last_seq: Option<u8>,
+}
+impl OBCProtocol {
+    pub fn new() -> OBCProtocol {
+        OBCProtocol { receiver: FrameReceiver::new(), transmitter: FrameTransmitter::new(), last_seq: None }
+    }
+    /// Call when the OBC asserts CS, before the first byte is loaded.
+    pub fn start_transaction(&mut self) {
+        self.receiver.reset();
+        self.transmitter.rewind();
+    }
+    /// The next byte to load into the transmit buffer.
+    pub fn next_tx_byte(&mut self) -> u8 {
+        self.transmitter.next_byte()
+    }
+    pub fn receive_byte(&mut self, byte: u8) {
+        self.receiver.push(byte);
+    }
+    /// Whether a response is staged that the OBC hasn't read yet.
+    pub fn response_pending(&self) -> bool {
+        !self.transmitter.is_empty()
+    }
+    /// Call when the OBC releases CS. If a request arrived, handler is called and its response is staged for the next transaction.
+    /// Returns None if the transaction was read-only, otherwise the type of the frame received (or why it was rejected).
+    pub fn end_transaction<'r>(&mut self, handler: impl FnOnce(Request) -> Response<'r>) -> Option<Result<FrameType, FrameError>> {
+        let frame = match self.receiver.finish() {
+            None => {
+                // The OBC has read the staged response.
+                self.transmitter.clear();
+                return None;
+            }
+            Some(Err(err)) => {
+                self.stage(self.receiver.seq().unwrap_or(0), Response::Nack(err.into()));
+                return Some(Err(err));
+            }
+            Some(Ok(frame)) => frame,
+        };
+
+        // Repeated sequence number: the OBC didn't get our last response, so send it again without re-running the request.
+        if self.last_seq == Some(frame.seq) {
+            self.transmitter.restage();
+            return Some(Ok(frame.frame_type));
+        }
+
+        let response = match frame.frame_type {
+            FrameType::Ping => handler(Request::Ping),
+            FrameType::GetHousekeeping => handler(Request::GetHousekeeping),
+            FrameType::GetTestResults => handler(Request::GetTestResults),
+            FrameType::Command => match frame.payload().split_first() {
+                Some((id, args)) => handler(Request::Command { id: *id, args }),
+                None => Response::Nack(NackReason::BadLength),
+            },
+            // The OBC shouldn't be sending us responses.
+            _ => Response::Nack(NackReason::UnknownType),
+        };
+        self.stage(frame.seq, response);
+        self.last_seq = Some(frame.seq);
+        Some(Ok(frame.frame_type))
+    }
+    fn stage(&mut self, seq: u8, response: Response) {
+        let frame = match response {
+            Response::Ack => Frame::new(seq, FrameType::Ack, &[]),
+            Response::Nack(reason) => Frame::new(seq, FrameType::Nack, &[reason as u8]),
+            Response::Housekeeping(data) => Frame::new(seq, FrameType::Housekeeping, data),
+            Response::TestResults(data) => Frame::new(seq, FrameType::TestResults, data),
+        };
+        // Only fails if the handler gave us too much data
+        let frame = frame.unwrap_or(Frame { seq, frame_type: FrameType::Nack, len: 1, payload: [NackReason::BadLength as u8; MAX_PAYLOAD_LEN] });
+        self.transmitter.stage(&frame);
+    }
+}
+impl Default for OBCProtocol {
+    fn default() -> Self {
+        OBCProtocol::new()
+    }
+}
+
+/// Stand-in for the OBC's side of the link, so OBCProtocol can be exercised on a host without hardware.
+#[cfg(any(test, feature = "sim"))]
+pub struct SimulatedOBC {
+    seq: u8,
+}
+#[cfg(any(test, feature = "sim"))]
+impl SimulatedOBC {
+    pub fn new() -> SimulatedOBC {
+        SimulatedOBC { seq: 0 }
+    }
+    /// Clock one full-duplex transaction of MAX_FRAME_LEN bytes through the slave. mosi is padded with IDLE_BYTE.
+    pub fn transaction<'r>(&mut self, slave: &mut OBCProtocol, mosi: &[u8], miso: &mut [u8; MAX_FRAME_LEN],
+                           handler: impl FnOnce(Request) -> Response<'r>) -> Option<Result<FrameType, FrameError>> {
+        slave.start_transaction();
+        for (n, received) in miso.iter_mut().enumerate() {
+            *received = slave.next_tx_byte();
+            slave.receive_byte(*mosi.get(n).unwrap_or(&IDLE_BYTE));
+        }
+        slave.end_transaction(handler)
+    }
+    /// Send a request, then clock a read-only transaction and decode the response.
+    pub fn request<'r>(&mut self, slave: &mut OBCProtocol, frame_type: FrameType, payload: &[u8],
+                       handler: impl FnOnce(Request) -> Response<'r>) -> Result<Frame, FrameError> {
+        self.seq = self.seq.wrapping_add(1);
+        self.resend(slave, frame_type, payload, handler)
+    }
+    /// As request(), but reuses the previous sequence number, like a retry after a lost response.
+    pub fn resend<'r>(&mut self, slave: &mut OBCProtocol, frame_type: FrameType, payload: &[u8],
+                      handler: impl FnOnce(Request) -> Response<'r>) -> Result<Frame, FrameError> {
+        let mut mosi = [IDLE_BYTE; MAX_FRAME_LEN];
+        let mut miso = [IDLE_BYTE; MAX_FRAME_LEN];
+        Frame::new(self.seq, frame_type, payload)?.encode(&mut mosi);
+        self.transaction(slave, &mosi, &mut miso, handler);
+        self.transaction(slave, &[], &mut miso, |_| Response::Ack);
+
+        let mut receiver = FrameReceiver::new();
+        for byte in miso {
+            receiver.push(byte);
+        }
+        receiver.finish().unwrap_or(Err(FrameError::Incomplete))
+    }
+}
+#[cfg(any(test, feature = "sim"))]
+impl Default for SimulatedOBC {
+    fn default() -> Self {
+        SimulatedOBC::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_sim::SimBoard;
+    use crate::housekeeping::{HOUSEKEEPING_RECORD_LEN, HOUSEKEEPING_RECORD_VERSION};
+    use std::boxed::Box;
+
+    #[test]
+    fn frames_survive_encode_and_decode() {
+        let mut buf = [0; MAX_FRAME_LEN];
+        for payload in [&[][..], &[0x12, 0x34, FRAME_SYNC], &[0xA5; MAX_PAYLOAD_LEN]] {
+            let len = Frame::new(200, FrameType::Command, payload).unwrap().encode(&mut buf);
+            assert_eq!(len, NUM_HEADER_BYTES + payload.len() + NUM_CRC_BYTES);
+            let frame = Frame::decode(&buf[..len]).unwrap();
+            assert_eq!((frame.seq, frame.frame_type, frame.payload()), (200, FrameType::Command, payload));
+        }
+    }
+
+    #[test]
+    fn corrupted_frames_are_rejected() {
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = Frame::new(1, FrameType::Ping, &[7]).unwrap().encode(&mut buf);
+
+        let mut bad_crc = buf;
+        bad_crc[len - 1] ^= 0x01;
+        assert_eq!(Frame::decode(&bad_crc[..len]).err(), Some(FrameError::BadCrc));
+        let mut bad_payload = buf;
+        bad_payload[NUM_HEADER_BYTES] ^= 0x80;
+        assert_eq!(Frame::decode(&bad_payload[..len]).err(), Some(FrameError::BadCrc));
+
+        let mut bad_len = buf;
+        bad_len[LEN_OFFSET] = MAX_PAYLOAD_LEN as u8 + 1;
+        assert_eq!(Frame::decode(&bad_len).err(), Some(FrameError::BadLength));
+        assert_eq!(Frame::decode(&buf[..len - 1]).err(), Some(FrameError::Incomplete));
+        assert_eq!(Frame::new(1, FrameType::Housekeeping, &[0; MAX_PAYLOAD_LEN + 1]).err(), Some(FrameError::BadLength));
+    }
+
+    #[test]
+    fn rejected_requests_are_nacked() {
+        let mut slave = OBCProtocol::new();
+        let mut obc = SimulatedOBC::new();
+        let mut mosi = [IDLE_BYTE; MAX_FRAME_LEN];
+        let mut miso = [IDLE_BYTE; MAX_FRAME_LEN];
+
+        let len = Frame::new(5, FrameType::Ping, &[]).unwrap().encode(&mut mosi);
+        mosi[len - 1] ^= 0xFF;
+        assert_eq!(obc.transaction(&mut slave, &mosi, &mut miso, |_| panic!("handler called for a bad frame")), Some(Err(FrameError::BadCrc)));
+        obc.transaction(&mut slave, &[], &mut miso, |_| Response::Ack);
+        let nack = Frame::decode(&miso).unwrap();
+        assert_eq!((nack.seq, nack.frame_type, nack.payload()), (5, FrameType::Nack, &[NackReason::BadCrc as u8][..]));
+
+        mosi[LEN_OFFSET] = 0xFF;
+        assert_eq!(obc.transaction(&mut slave, &mosi, &mut miso, |_| panic!("handler called for a bad frame")), Some(Err(FrameError::BadLength)));
+        obc.transaction(&mut slave, &[], &mut miso, |_| Response::Ack);
+        assert_eq!(Frame::decode(&miso).unwrap().payload(), &[NackReason::BadLength as u8]);
+
+        // A command needs at least an ID byte
+        let response = obc.request(&mut slave, FrameType::Command, &[], |_| panic!("handler called without a command ID")).unwrap();
+        assert_eq!((response.frame_type, response.payload()), (FrameType::Nack, &[NackReason::BadLength as u8][..]));
+    }
+
+    #[test]
+    fn requests_get_their_responses() {
+        let mut slave = OBCProtocol::new();
+        let mut obc = SimulatedOBC::new();
+
+        let response = obc.request(&mut slave, FrameType::Ping, &[], |request| {
+            assert!(matches!(request, Request::Ping));
+            Response::Ack
+        }).unwrap();
+        assert_eq!(response.frame_type, FrameType::Ack);
+
+        let response = obc.request(&mut slave, FrameType::Command, &[0x10, 1, 2], |request| match request {
+            Request::Command { id: 0x10, args: [1, 2] } => Response::TestResults(&[9, 8, 7]),
+            _ => panic!("wrong request"),
+        }).unwrap();
+        assert_eq!((response.frame_type, response.payload()), (FrameType::TestResults, &[9, 8, 7][..]));
+        assert!(!slave.response_pending());
+    }
+
+    #[test]
+    fn repeated_sequence_numbers_resend_without_rerunning() {
+        let mut slave = OBCProtocol::new();
+        let mut obc = SimulatedOBC::new();
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]);
+        let mut record = [0; HOUSEKEEPING_RECORD_LEN];
+        let first = obc.request(&mut slave, FrameType::GetHousekeeping, &[], |_| payload.housekeeping_response(&mut record)).unwrap();
+        assert_eq!((first.frame_type, first.payload()), (FrameType::Housekeeping, &record[..]));
+        assert_eq!(record[0], HOUSEKEEPING_RECORD_VERSION);
+        let resent = obc.resend(&mut slave, FrameType::GetHousekeeping, &[], |_| panic!("request handled twice")).unwrap();
+        assert_eq!((resent.seq, resent.frame_type, resent.payload()), (first.seq, first.frame_type, first.payload()));
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        let mut slave = OBCProtocol::new();
+        let mut obc = SimulatedOBC::new();
+        let mut num_handled = 0;
+        // Twice round, so every sequence number (including the wrap from 255 to 0) is seen after a different one
+        for n in 1..=512u32 {
+            let response = obc.request(&mut slave, FrameType::Ping, &[], |_| { num_handled += 1; Response::Ack }).unwrap();
+            assert_eq!(response.seq, n as u8);
+            assert_eq!(response.frame_type, FrameType::Ack);
+        }
+        assert_eq!(num_handled, 512);
+    }
+}
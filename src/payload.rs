@@ -2,16 +2,20 @@
 
 use core::marker::PhantomData;
 
-use embedded_hal::digital::v2::OutputPin;
-use msp430fr2355::{E_USCI_A1, TB0};
-use msp430fr2x5x_hal::serial::Rx;
-use msp430fr2x5x_hal::timer::Timer;
+use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin};
 
 use crate::digipot::Digipot; 
-use crate::adc::{ApertureADC, MiscADC, TemperatureADC, TemperatureSensor, TetherADC, VccType};
-use crate::dac::{DAC, DACCommand};
-use crate::pcb_common::{DeploySensePins, LEDPins, PinpullerActivationPins, TetherLMSPins};
-use crate::spi::{PayloadSPI, PayloadSPIController, SckPolarity::IdleLow, SckPolarity::IdleHigh, SckPhase::SampleFirstEdge};
+use crate::adc::{ADCError, ADCFilter, ApertureADC, MiscADC, TargetADC, TemperatureADC, TemperatureSensor, TetherADC, TetherSensor, VccType};
+use crate::calibration::CalibrationStore;
+use crate::housekeeping::{Housekeeping, ReadingGroup, HOUSEKEEPING_RECORD_LEN, ValidReadings, NUM_LMS_RECEIVERS, NUM_TEMPERATURE_SENSORS, TEMPERATURE_SENSORS};
+use crate::obc::Response;
+use crate::protection::Protection;
+use crate::power::{LiveDomains, PowerDomain, PowerDomains, PowerError, SwitchableDomain};
+use crate::regulator::PIController;
+use crate::dac::{DAC, DACReference};
+use crate::pcb_common::{DeploySensePins, LEDPins, PayloadHardware, PinpullerActivationPins, TetherLMSPins};
+use crate::spi::{PayloadSPI, PayloadSPIController, SckPolarity::IdleLow, SckPolarity::IdleHigh, SckPhase::SampleFirstEdge, SckPhase::SampleSecondEdge};
+use crate::units::{Kelvin, Microamps, Milliamps, Millivolts, Milliwatts, Ohms};
 use crate::pcb_mapping::{sensor_equations::*, sensor_locations::*, power_supply_locations::*, power_supply_limits::*, power_supply_equations::*, PayloadControlPins, PayloadPeripherals};
 
 // Returns num such that "lower bound <= num <= upper_bound"
@@ -33,22 +37,43 @@ pub enum HeaterState {
     HeaterOff,
 } use HeaterState::*;
 
+// Filter applied to the high voltage sense channels by PayloadBuilder::build. These are the noisiest sensors, and a median rejects the occasional spike.
+pub const HV_SENSE_FILTER: ADCFilter = ADCFilter::Median(5);
+
 pub struct PayloadBuilder {}
 impl PayloadBuilder{
-    pub fn build(
-        periph: PayloadPeripherals, 
-        mut pins: PayloadControlPins, 
-        spi: PayloadSPIController, 
-        pinpuller_pins: PinpullerActivationPins, 
-        lms_control_pins: TetherLMSPins, 
-        deploy_sense_pins: DeploySensePins, 
-        serial_reader: Rx<E_USCI_A1>, 
-        led_pins: LEDPins, 
-        timer: Timer<TB0>) -> Payload<{PayloadOff}, {HeaterOff}> {
+    #[allow(clippy::too_many_arguments, reason = "one argument per peripheral group, each built separately by the caller")]
+    pub fn build<H: PayloadHardware>(
+        mut periph: PayloadPeripherals<H>, 
+        mut pins: PayloadControlPins<H>, 
+        spi: PayloadSPIController<H::PayloadSPIBus>, 
+        pinpuller_pins: PinpullerActivationPins<H>, 
+        mut lms_control_pins: TetherLMSPins<H>, 
+        deploy_sense_pins: DeploySensePins<H>, 
+        serial_reader: H::SerialReader, 
+        led_pins: LEDPins<H>, 
+        timer: H::Timer,
+        calibration_store: CalibrationStore,
+        power_domains: PowerDomains<H::PowerTimer>) -> Payload<{PayloadOff}, {HeaterOff}, H> {
         pins.heater_enable.set_low().ok();
         pins.payload_enable.set_low().ok();
+        pins.cathode_switch.set_low().ok();
+        pins.tether_switch.set_low().ok();
+        lms_control_pins.lms_receiver_enable.set_low().ok();
+        lms_control_pins.lms_led_enable.set_low().ok();
+        periph.aperture_adc.cs_pin.set_high().ok();
+
+        for sensor in [&TETHER_BIAS_VOLTAGE_SENSOR, &TETHER_BIAS_CURRENT_SENSOR, &CATHODE_OFFSET_VOLTAGE_SENSOR, &CATHODE_OFFSET_CURRENT_SENSOR, &REPELLER_VOLTAGE_SENSOR] {
+            periph.tether_adc.set_filter(sensor, HV_SENSE_FILTER);
+        }
+
+        // ADCs without a valid record keep their ideal calibration
+        if let Ok(calibration) = calibration_store.load(TargetADC::TetherADC)       { periph.tether_adc.calibration = calibration; }
+        if let Ok(calibration) = calibration_store.load(TargetADC::TemperatureADC)  { periph.temperature_adc.calibration = calibration; }
+        if let Ok(calibration) = calibration_store.load(TargetADC::MiscADC)         { periph.misc_adc.calibration = calibration; }
+        if let Ok(calibration) = calibration_store.load(TargetADC::ApertureTestADC) { periph.aperture_adc.calibration = calibration; }
         
-        Payload::<{PayloadOff}, {HeaterOff}>{
+        Payload::<{PayloadOff}, {HeaterOff}, H>{
             tether_adc: periph.tether_adc, 
             temperature_adc: periph.temperature_adc, 
             misc_adc: periph.misc_adc, 
@@ -57,67 +82,82 @@ impl PayloadBuilder{
             digipot: periph.digipot, 
             pins, spi, pinpuller_pins, lms_control_pins,
             deploy_sense_pins, serial_reader, led_pins,
-            timer
+            timer, calibration_store, power_domains,
+            protection: Protection::default(),
         }
     }
 }
 
-pub struct Payload<const PSTATE: PayloadState, const HSTATE: HeaterState> {
-    pub tether_adc: TetherADC,
-    pub temperature_adc: TemperatureADC,
-    pub misc_adc: MiscADC,
-    pub aperture_adc: ApertureADC,
-    pub dac: DAC,
-    pub digipot: Digipot,
-    pub spi: PayloadSPIController,
-    pub pinpuller_pins: PinpullerActivationPins,
-    pub lms_control_pins: TetherLMSPins,
-    pub deploy_sense_pins: DeploySensePins,
-    pub serial_reader: Rx<E_USCI_A1>,
-    pub led_pins: LEDPins,
-    pub timer: Timer<TB0>,
-    pins: PayloadControlPins,
+// H supplies the pin, bus and timer types, e.g. MSP430Hardware for the real board. See PayloadHardware.
+pub struct Payload<const PSTATE: PayloadState, const HSTATE: HeaterState, H: PayloadHardware> {
+    pub tether_adc: TetherADC<H::TetherADCCSPin>,
+    pub temperature_adc: TemperatureADC<H::TemperatureADCCSPin>,
+    pub misc_adc: MiscADC<H::MiscADCCSPin>,
+    pub aperture_adc: ApertureADC<H::ApertureADCCSPin>,
+    pub dac: DAC<H::DACCSPin>,
+    pub digipot: Digipot<H::DigipotCSPin>,
+    pub spi: PayloadSPIController<H::PayloadSPIBus>,
+    pub pinpuller_pins: PinpullerActivationPins<H>,
+    pub deploy_sense_pins: DeploySensePins<H>,
+    pub serial_reader: H::SerialReader,
+    pub led_pins: LEDPins<H>,
+    pub timer: H::Timer,
+    pub calibration_store: CalibrationStore,
+    // Checked against every housekeeping snapshot, and carried across state changes so a latched fault isn't lost
+    pub protection: Protection,
+    pins: PayloadControlPins<H>,
+    // Switched through power_up and power_down so the power domain state stays in sync
+    lms_control_pins: TetherLMSPins<H>,
+    power_domains: PowerDomains<H::PowerTimer>,
 }
-impl<const PSTATE: PayloadState, const HSTATE: HeaterState> Payload<PSTATE, HSTATE>{
-    pub fn return_peripherals(self) -> (PayloadPeripherals, PayloadControlPins){
+impl<const PSTATE: PayloadState, const HSTATE: HeaterState, H: PayloadHardware> Payload<PSTATE, HSTATE, H>{
+    pub fn return_peripherals(self) -> (PayloadPeripherals<H>, PayloadControlPins<H>){
         (PayloadPeripherals {tether_adc: self.tether_adc, temperature_adc: self.temperature_adc, misc_adc: self.misc_adc, aperture_adc: self.aperture_adc, dac: self.dac, digipot: self.digipot}, self.pins)
     }
 }
 
 // Transition functions
-impl Payload<{PayloadOff}, {HeaterOff}>{
-    pub fn into_enabled_payload(mut self) -> Payload<{PayloadOn}, {HeaterOff}> {
+impl<H: PayloadHardware> Payload<{PayloadOff}, {HeaterOff}, H>{
+    pub fn into_enabled_payload(mut self) -> Payload<{PayloadOn}, {HeaterOff}, H> {
         self.pins.payload_enable.set_high().ok();
-        self.dac.send_command(crate::dac::DACCommand::SelectExternalReference, crate::dac::DACChannel::ChannelA, 0x000, self.spi.borrow());
+        // The digipot resets to midscale at power-up, so put back whatever was last set
+        self.digipot.restore_wipers(&mut self.spi);
+        // The DAC was unpowered, so anything we remember about it is stale
+        self.dac.forget_state();
+        self.dac.select_reference(DACReference::External, &mut self.spi.borrow());
         Payload { tether_adc: self.tether_adc, temperature_adc: self.temperature_adc, misc_adc: self.misc_adc, aperture_adc: self.aperture_adc, dac: self.dac, digipot: self.digipot, 
                             pins: self.pins, spi: self.spi, pinpuller_pins: self.pinpuller_pins, lms_control_pins: self.lms_control_pins, deploy_sense_pins: self.deploy_sense_pins, 
-                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer}
+                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer, calibration_store: self.calibration_store, power_domains: self.power_domains, protection: self.protection}
     }
 }
-impl Payload<{PayloadOn}, {HeaterOff}>{
-    pub fn into_enabled_heater(mut self) -> Payload<{PayloadOn}, {HeaterOn}> {
+impl<H: PayloadHardware> Payload<{PayloadOn}, {HeaterOff}, H>{
+    pub fn into_enabled_heater(mut self) -> Payload<{PayloadOn}, {HeaterOn}, H> {
         self.pins.heater_enable.set_high().ok();
+        self.power_domains.record_power_up(PowerDomain::Heater);
         Payload { tether_adc: self.tether_adc, temperature_adc: self.temperature_adc, misc_adc: self.misc_adc, aperture_adc: self.aperture_adc, dac: self.dac, digipot: self.digipot, 
                             pins: self.pins, spi: self.spi, pinpuller_pins: self.pinpuller_pins, lms_control_pins: self.lms_control_pins, deploy_sense_pins: self.deploy_sense_pins, 
-                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer}
+                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer, calibration_store: self.calibration_store, power_domains: self.power_domains, protection: self.protection}
     }
-    pub fn into_disabled_payload(mut self) -> Payload<{PayloadOff}, {HeaterOff}> {
+    // Opens the relays and zeroes the HVDC supplies before removing power, so nothing is left connected to the tether.
+    pub fn into_disabled_payload(mut self) -> Payload<{PayloadOff}, {HeaterOff}, H> {
+        self.discharge_hvdc();
         self.pins.payload_enable.set_low().ok();
         Payload { tether_adc: self.tether_adc, temperature_adc: self.temperature_adc, misc_adc: self.misc_adc, aperture_adc: self.aperture_adc, dac: self.dac, digipot: self.digipot, 
                             pins: self.pins, spi: self.spi, pinpuller_pins: self.pinpuller_pins, lms_control_pins: self.lms_control_pins, deploy_sense_pins: self.deploy_sense_pins, 
-                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer}
+                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer, calibration_store: self.calibration_store, power_domains: self.power_domains, protection: self.protection}
     }
 }
-impl Payload<{PayloadOn}, {HeaterOn}>{
-    pub fn into_disabled_heater(mut self) -> Payload<{PayloadOn}, {HeaterOff}> {
+impl<H: PayloadHardware> Payload<{PayloadOn}, {HeaterOn}, H>{
+    pub fn into_disabled_heater(mut self) -> Payload<{PayloadOn}, {HeaterOff}, H> {
         self.pins.heater_enable.set_low().ok();
+        self.power_domains.record_power_down(PowerDomain::Heater);
         Payload { tether_adc: self.tether_adc, temperature_adc: self.temperature_adc, misc_adc: self.misc_adc, aperture_adc: self.aperture_adc, dac: self.dac, digipot: self.digipot, 
                             pins: self.pins, spi: self.spi, pinpuller_pins: self.pinpuller_pins, lms_control_pins: self.lms_control_pins, deploy_sense_pins: self.deploy_sense_pins, 
-                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer}
+                            serial_reader: self.serial_reader, led_pins: self.led_pins, timer: self.timer, calibration_store: self.calibration_store, power_domains: self.power_domains, protection: self.protection}
     }
 }
 // Actual sensor functions. These are always available.
-impl<const PSTATE: PayloadState, const HSTATE: HeaterState> Payload<PSTATE, HSTATE>{
+impl<const PSTATE: PayloadState, const HSTATE: HeaterState, H: PayloadHardware> Payload<PSTATE, HSTATE, H>{
     // Temperature sensors
     // TODO: Remove these once new temperature funciton has been tested
     /*pub fn get_lms_temperature_kelvin(&mut self, temp_sensor: &TemperatureSensor, spi_bus: &mut impl PayloadSPI<IdleHigh,{SampleFirstEdge}>) -> u16{
@@ -128,105 +168,284 @@ impl<const PSTATE: PayloadState, const HSTATE: HeaterState> Payload<PSTATE, HSTA
         let adc_voltage = self.temperature_adc.read_voltage_from(temp_sensor, spi_bus);
         payload_temperature_eq(adc_voltage)
     }*/
-    pub fn get_temperature_kelvin(&mut self, temp_sensor: &TemperatureSensor) -> u16 {
-        let adc_voltage = self.temperature_adc.read_voltage_from(temp_sensor, &mut self.spi);
-        match &temp_sensor.vcc {
-            VccType::LMS     => lms_temperature_eq(adc_voltage),
-            VccType::Payload => payload_temperature_eq(adc_voltage)
+    pub fn get_temperature_kelvin(&mut self, temp_sensor: &TemperatureSensor) -> Result<Kelvin, ADCError> {
+        let adc_voltage = self.temperature_adc.read_voltage_from(temp_sensor, &mut self.spi)?;
+        Ok(temperature_eq(temp_sensor, adc_voltage))
+    }
+    // Reads every sensor in one ADC scan, which takes roughly half the bus time of calling get_temperature_kelvin for each.
+    pub fn get_temperatures_kelvin<const N: usize>(&mut self, temp_sensors: [&TemperatureSensor; N]) -> Result<[Kelvin; N], ADCError> {
+        let adc_voltages = self.temperature_adc.scan_voltages_from(temp_sensors, &mut self.spi)?;
+        let mut temperatures = [Kelvin::ZERO; N];
+        for ((temperature, temp_sensor), adc_voltage) in temperatures.iter_mut().zip(temp_sensors).zip(adc_voltages) {
+            *temperature = temperature_eq(temp_sensor, adc_voltage);
         }
+        Ok(temperatures)
+    }
+    // Power domains
+    pub fn power_up(&mut self, domain: SwitchableDomain) {
+        match domain {
+            SwitchableDomain::Aperture     => self.aperture_adc.cs_pin.set_low().ok(),
+            SwitchableDomain::LMSReceivers => self.lms_control_pins.lms_receiver_enable.set_high().ok(),
+            SwitchableDomain::LMSLEDs      => self.lms_control_pins.lms_led_enable.set_high().ok(),
+        };
+        self.power_domains.record_power_up(domain.into());
+    }
+    pub fn power_down(&mut self, domain: SwitchableDomain) {
+        match domain {
+            SwitchableDomain::Aperture     => self.aperture_adc.cs_pin.set_high().ok(),
+            SwitchableDomain::LMSReceivers => self.lms_control_pins.lms_receiver_enable.set_low().ok(),
+            SwitchableDomain::LMSLEDs      => self.lms_control_pins.lms_led_enable.set_low().ok(),
+        };
+        self.power_domains.record_power_down(domain.into());
+    }
+    /// Block until the domain has been powered for its warm-up time. Fails if the domain is off.
+    pub fn wait_until_live(&mut self, domain: PowerDomain) -> Result<(), PowerError> {
+        self.power_domains.wait_until_live(domain)
+    }
+    pub fn is_live(&mut self, domain: PowerDomain) -> bool {
+        self.power_domains.is_live(domain)
     }
+    pub fn live_domains(&mut self) -> LiveDomains {
+        self.power_domains.live_domains()
+    }
+    pub fn millis_since_power_up(&mut self, domain: PowerDomain) -> Option<u32> {
+        self.power_domains.millis_since_power_up(domain)
+    }
+
+    // Relays. These report what was last commanded, and always read Disconnected after power-down.
+    pub fn get_cathode_offset_switch(&self) -> SwitchState {
+        switch_state(self.pins.cathode_switch.is_set_high())
+    }
+    pub fn get_tether_bias_switch(&self) -> SwitchState {
+        switch_state(self.pins.tether_switch.is_set_high())
+    }
+
     // Aperture
-    pub fn get_aperture_current_microamps(&mut self) -> u16 {
-        // The aperture CS pin also controls whether the aperture ADC and circuitry are powered.
-        // They should be powered for at least 5ms before a value is requested.
-        self.aperture_adc.cs_pin.set_low().ok();
-        crate::delay_cycles(5_000); // 5ms
-        
-        let adc_voltage = self.aperture_adc.read_voltage_from(&APERTURE_CURRENT_SENSOR, &mut self.spi);
-        aperture_current_sensor_eq(adc_voltage)
+    pub fn get_aperture_current_microamps(&mut self) -> Result<Microamps, ADCError> {
+        let adc_voltage = self.read_aperture_adc_voltage()?;
+        Ok(aperture_current_sensor_eq(adc_voltage))
+    }
+    /// Powers up the aperture domain and waits for it to warm up before reading, then powers it down.
+    /// The aperture CS pin is also its power switch, so it's held low for every sample and retry rather than toggled by each transaction.
+    pub fn read_aperture_adc_voltage(&mut self) -> Result<Millivolts, ADCError> {
+        self.with_aperture_powered(|payload| payload.aperture_adc.read_voltage_while_selected_from(&APERTURE_CURRENT_SENSOR, &mut payload.spi))
+    }
+    /// Runs f with the aperture ADC powered, live and selected, then powers it down.
+    ///
+    /// f must only read the aperture ADC with its "while selected" methods, as anything that toggles its CS pin also toggles its power.
+    pub fn with_aperture_powered<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        // Put the bus into the ADC's mode first, as changing SCK's idle level with the ADC selected would look like a clock edge
+        self.spi.borrow::<{IdleHigh}, {SampleSecondEdge}>();
+        self.power_up(SwitchableDomain::Aperture);
+        self.power_domains.wait_until_live(PowerDomain::Aperture).ok(); // Can't fail, we just powered it
+        let result = f(self);
+        self.power_down(SwitchableDomain::Aperture);
+        result
     }
 
     // Pinpuller
-    pub fn get_pinpuller_current_milliamps(&mut self) -> u16 {
-        let adc_voltage = self.misc_adc.read_voltage_from(&PINPULLER_CURRENT_SENSOR, &mut self.spi);
-        pinpuller_current_sensor_eq(adc_voltage)
+    pub fn get_pinpuller_current_milliamps(&mut self) -> Result<Milliamps, ADCError> {
+        let adc_voltage = self.misc_adc.read_voltage_from(&PINPULLER_CURRENT_SENSOR, &mut self.spi)?;
+        Ok(pinpuller_current_sensor_eq(adc_voltage))
     }
 
     // LMS
-    pub fn get_lms_receiver_1_millivolts(&mut self) -> u16 {
+    pub fn get_lms_receiver_1_millivolts(&mut self) -> Result<Millivolts, ADCError> {
         self.misc_adc.read_voltage_from(&LMS_RECEIVER_1_SENSOR, &mut self.spi)
     }
-    pub fn get_lms_receiver_2_millivolts(&mut self) -> u16 {
+    pub fn get_lms_receiver_2_millivolts(&mut self) -> Result<Millivolts, ADCError> {
         self.misc_adc.read_voltage_from(&LMS_RECEIVER_2_SENSOR, &mut self.spi)
     }
-    pub fn get_lms_receiver_3_millivolts(&mut self) -> u16 {
+    pub fn get_lms_receiver_3_millivolts(&mut self) -> Result<Millivolts, ADCError> {
         self.misc_adc.read_voltage_from(&LMS_RECEIVER_3_SENSOR, &mut self.spi)
     }
+
+    // Every sensor at once. Readings that can't be taken in the current state are left at zero and marked invalid rather than powering anything up,
+    // except the aperture, which is only ever powered for the duration of a reading anyway.
+    // The snapshot is checked against self.protection, with the temperatures (and the tether ADC while the payload is on) required to be valid.
+    // If that latches a fault while the payload is on, the relays are opened and the HVDC supplies zeroed before returning. Leaving the heater
+    // and payload on is up to the caller, since the state can't change here. DynPayload::get_housekeeping shuts everything down.
+    pub fn get_housekeeping(&mut self) -> Housekeeping {
+        let mut valid = ValidReadings::default();
+        let timestamp_millis = self.power_domains.uptime_millis();
+
+        let temperatures = self.get_temperatures_kelvin(TEMPERATURE_SENSORS.each_ref().map(|(sensor, _)| sensor));
+        let temperatures = check_reading(temperatures, ReadingGroup::Temperatures, &mut valid).unwrap_or([Kelvin::ZERO; NUM_TEMPERATURE_SENSORS]);
+        let tether = match PSTATE {
+            PayloadState::PayloadOn => check_reading(self.read_tether_adc(), ReadingGroup::TetherADC, &mut valid).unwrap_or_default(),
+            PayloadState::PayloadOff => TetherADCReadings::default(),
+        };
+        let aperture_current_microamps = check_reading(self.get_aperture_current_microamps(), ReadingGroup::Aperture, &mut valid).unwrap_or_default();
+        let pinpuller_current_milliamps = check_reading(self.get_pinpuller_current_milliamps(), ReadingGroup::Pinpuller, &mut valid).unwrap_or_default();
+        let mut lms_receivers_millivolts = [Millivolts::ZERO; NUM_LMS_RECEIVERS];
+        if self.is_live(PowerDomain::LMSReceivers) {
+            let readings = self.misc_adc.scan_voltages_from([&LMS_RECEIVER_1_SENSOR, &LMS_RECEIVER_2_SENSOR, &LMS_RECEIVER_3_SENSOR], &mut self.spi);
+            lms_receivers_millivolts = check_reading(readings, ReadingGroup::LMSReceivers, &mut valid).unwrap_or(lms_receivers_millivolts);
+        }
+
+        let housekeeping = Housekeeping {
+            timestamp_millis, valid, temperatures, tether, aperture_current_microamps, pinpuller_current_milliamps, lms_receivers_millivolts,
+            cathode_offset_switch: self.get_cathode_offset_switch(),
+            tether_bias_switch: self.get_tether_bias_switch(),
+            endmass_sense_1: self.deploy_sense_pins.endmass_sense_1.is_high().unwrap_or(false),
+            endmass_sense_2: self.deploy_sense_pins.endmass_sense_2.is_high().unwrap_or(false),
+            pinpuller_sense: self.deploy_sense_pins.pinpuller_sense.is_high().unwrap_or(false),
+        };
+
+        let mut required = ValidReadings::default();
+        required.insert(ReadingGroup::Temperatures);
+        if PSTATE == PayloadState::PayloadOn {
+            required.insert(ReadingGroup::TetherADC);
+        }
+        if self.protection.check(&housekeeping, required).is_some() && PSTATE == PayloadState::PayloadOn {
+            self.open_relays_and_zero_hvdc();
+        }
+        housekeeping
+    }
+    /// Answers the OBC's GetHousekeeping with a fresh snapshot, encoded into record.
+    pub fn housekeeping_response<'r>(&mut self, record: &'r mut [u8; HOUSEKEEPING_RECORD_LEN]) -> Response<'r> {
+        *record = self.get_housekeeping().encode();
+        Response::Housekeeping(record)
+    }
+
+    // Body of safe_hvdc_shutdown, here so get_housekeeping can use it. Only call while the payload is on, the DAC is unpowered otherwise.
+    fn open_relays_and_zero_hvdc(&mut self) {
+        self.pins.cathode_switch.set_low().ok();
+        self.pins.tether_switch.set_low().ok();
+        self.dac.write_and_update(CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL, 0, &mut self.spi.borrow());
+        self.dac.write_and_update(TETHER_BIAS_SUPPLY_CONTROL_CHANNEL, 0, &mut self.spi.borrow());
+    }
+
+    // Every tether ADC channel in one scan. Only meaningful while the payload is on.
+    fn read_tether_adc(&mut self) -> Result<TetherADCReadings, ADCError> {
+        let [heater_voltage, heater_current, tether_bias_voltage, tether_bias_current, cathode_offset_voltage, cathode_offset_current, repeller_voltage] = 
+            self.tether_adc.scan_voltages_from([
+                &HEATER_VOLTAGE_SENSOR, &HEATER_CURRENT_SENSOR, 
+                &TETHER_BIAS_VOLTAGE_SENSOR, &TETHER_BIAS_CURRENT_SENSOR, 
+                &CATHODE_OFFSET_VOLTAGE_SENSOR, &CATHODE_OFFSET_CURRENT_SENSOR, 
+                &REPELLER_VOLTAGE_SENSOR], &mut self.spi)?;
+        Ok(TetherADCReadings {
+            heater_voltage_millivolts: heater_voltage_eq(heater_voltage),
+            heater_current_milliamps: heater_current_eq(heater_current),
+            tether_bias_voltage_millivolts: tether_bias_voltage_eq(tether_bias_voltage),
+            tether_bias_current_microamps: tether_bias_current_eq(tether_bias_current),
+            cathode_offset_voltage_millivolts: cathode_offset_voltage_eq(cathode_offset_voltage),
+            cathode_offset_current_microamps: cathode_offset_current_eq(cathode_offset_current),
+            repeller_voltage_millivolts: repeller_voltage_eq(repeller_voltage),
+        })
+    }
 }
 // These functions are only available when the payload is on.
-impl<const HSTATE: HeaterState> Payload<{PayloadOn}, HSTATE>{
+impl<const HSTATE: HeaterState, H: PayloadHardware> Payload<{PayloadOn}, HSTATE, H>{
     /* Supplies */
     // Heater
     // Note that we *can* change the heater voltage without the heater being enabled.
-    pub fn set_heater_voltage(&mut self, mut target_millivolts: u16){
-        target_millivolts = enforce_bounds( 
+    pub fn set_heater_voltage(&mut self, mut target: Millivolts){
+        target = enforce_bounds( 
             HEATER_MIN_VOLTAGE_MILLIVOLTS, 
-            target_millivolts, 
+            target, 
             HEATER_MAX_VOLTAGE_MILLIVOLTS);
-        let target_digipot_resistance = heater_target_voltage_to_digipot_resistance(target_millivolts);
+        let target_digipot_resistance = heater_target_voltage_to_digipot_resistance(target);
         self.digipot.set_channel_to_resistance(HEATER_DIGIPOT_CHANNEL,target_digipot_resistance, &mut self.spi);
     }
-    pub fn get_heater_voltage_millivolts(&mut self) -> u16{
-        let adc_millivolts = self.tether_adc.read_voltage_from(&HEATER_VOLTAGE_SENSOR, &mut self.spi);
-        heater_voltage_eq(adc_millivolts)
+    // The heater voltage implied by the digipot's last setting, not a measurement.
+    pub fn get_heater_voltage_setpoint(&self) -> Millivolts {
+        digipot_resistance_to_heater_voltage(self.get_heater_digipot_resistance())
+    }
+    pub fn get_heater_digipot_resistance(&self) -> Ohms {
+        self.digipot.channel_resistance(HEATER_DIGIPOT_CHANNEL)
+    }
+    // Channel with nothing attached on this PCB revision, see SPARE_DIGIPOT_CHANNEL.
+    pub fn set_spare_digipot_resistance(&mut self, resistance: Ohms){
+        self.digipot.set_channel_to_resistance(SPARE_DIGIPOT_CHANNEL, resistance, &mut self.spi);
+    }
+    pub fn get_spare_digipot_resistance(&self) -> Ohms {
+        self.digipot.channel_resistance(SPARE_DIGIPOT_CHANNEL)
     }
-    pub fn get_heater_current_milliamps(&mut self) -> i16{
-        let adc_millivolts = self.tether_adc.read_voltage_from(&HEATER_CURRENT_SENSOR, &mut self.spi);
-        heater_current_eq(adc_millivolts)
+    pub fn get_heater_voltage_millivolts(&mut self) -> Result<Millivolts, ADCError> {
+        let adc_millivolts = self.tether_adc.read_voltage_from(&HEATER_VOLTAGE_SENSOR, &mut self.spi)?;
+        Ok(heater_voltage_eq(adc_millivolts))
+    }
+    pub fn get_heater_current_milliamps(&mut self) -> Result<Milliamps, ADCError> {
+        let adc_millivolts = self.tether_adc.read_voltage_from(&HEATER_CURRENT_SENSOR, &mut self.spi)?;
+        Ok(heater_current_eq(adc_millivolts))
     }
 
     // Tether Bias
-    pub fn set_tether_bias_voltage(&mut self, mut target_millivolts: u32){
-        target_millivolts = enforce_bounds( 
+    pub fn set_tether_bias_voltage(&mut self, mut target: Millivolts){
+        target = enforce_bounds( 
             TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS,
-            target_millivolts,
+            target,
             TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS);
-        let dac_voltage = tether_bias_target_voltage_to_dac_voltage(target_millivolts);
-        let count = DAC::voltage_to_count(dac_voltage);
-        self.dac.send_command(DACCommand::WriteToAndUpdateRegisterX, TETHER_BIAS_SUPPLY_CONTROL_CHANNEL, count, self.spi.borrow())
+        let dac_voltage = tether_bias_target_voltage_to_dac_voltage(target);
+        let count = self.dac.voltage_to_count(dac_voltage);
+        self.dac.write_and_update(TETHER_BIAS_SUPPLY_CONTROL_CHANNEL, count, &mut self.spi.borrow())
+    }
+    // Last DAC output commanded for the supply (not the supply's output voltage). None if it hasn't been set since power-up.
+    pub fn get_tether_bias_dac_setpoint(&self) -> Option<Millivolts> {
+        self.dac.output_voltage(TETHER_BIAS_SUPPLY_CONTROL_CHANNEL)
     }
-    pub fn get_tether_bias_voltage_millivolts(&mut self) -> i32 {
-        let adc_voltage = self.tether_adc.read_voltage_from(&TETHER_BIAS_VOLTAGE_SENSOR, &mut self.spi);
-        tether_bias_voltage_eq(adc_voltage)
+    pub fn get_tether_bias_voltage_millivolts(&mut self) -> Result<Millivolts, ADCError> {
+        let adc_voltage = self.tether_adc.read_voltage_from(&TETHER_BIAS_VOLTAGE_SENSOR, &mut self.spi)?;
+        Ok(tether_bias_voltage_eq(adc_voltage))
     }
-    pub fn get_tether_bias_current_microamps(&mut self) -> i32 {
-        let adc_voltage = self.tether_adc.read_voltage_from(&TETHER_BIAS_CURRENT_SENSOR, &mut self.spi);
-        tether_bias_current_eq(adc_voltage)
+    pub fn get_tether_bias_current_microamps(&mut self) -> Result<Microamps, ADCError> {
+        let adc_voltage = self.tether_adc.read_voltage_from(&TETHER_BIAS_CURRENT_SENSOR, &mut self.spi)?;
+        Ok(tether_bias_current_eq(adc_voltage))
     }
 
     // Cathode Offset
-    pub fn set_cathode_offset_voltage(&mut self, mut target_millivolts: u32){
-        target_millivolts = enforce_bounds( 
+    pub fn set_cathode_offset_voltage(&mut self, mut target: Millivolts){
+        target = enforce_bounds( 
             CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS,
-            target_millivolts,
+            target,
             CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS);
-        let dac_voltage = cathode_offset_target_voltage_to_dac_voltage(target_millivolts);
-        let count = DAC::voltage_to_count(dac_voltage);
-        self.dac.send_command(DACCommand::WriteToAndUpdateRegisterX, CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL, count, self.spi.borrow())
+        let dac_voltage = cathode_offset_target_voltage_to_dac_voltage(target);
+        let count = self.dac.voltage_to_count(dac_voltage);
+        self.dac.write_and_update(CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL, count, &mut self.spi.borrow())
+    }
+    pub fn get_cathode_offset_dac_setpoint(&self) -> Option<Millivolts> {
+        self.dac.output_voltage(CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL)
     }
-    pub fn get_cathode_offset_voltage_millivolts(&mut self) -> i32 {
-        let adc_voltage = self.tether_adc.read_voltage_from(&CATHODE_OFFSET_VOLTAGE_SENSOR, &mut self.spi);
-        cathode_offset_voltage_eq(adc_voltage)
+    pub fn get_cathode_offset_voltage_millivolts(&mut self) -> Result<Millivolts, ADCError> {
+        let adc_voltage = self.tether_adc.read_voltage_from(&CATHODE_OFFSET_VOLTAGE_SENSOR, &mut self.spi)?;
+        Ok(cathode_offset_voltage_eq(adc_voltage))
     }
-    pub fn get_cathode_offset_current_microamps(&mut self) -> i32 {
-        let adc_voltage = self.tether_adc.read_voltage_from(&CATHODE_OFFSET_CURRENT_SENSOR, &mut self.spi);
-        cathode_offset_current_eq(adc_voltage)
+    pub fn get_cathode_offset_current_microamps(&mut self) -> Result<Microamps, ADCError> {
+        let adc_voltage = self.tether_adc.read_voltage_from(&CATHODE_OFFSET_CURRENT_SENSOR, &mut self.spi)?;
+        Ok(cathode_offset_current_eq(adc_voltage))
     }
 
     // Repeller
-    pub fn get_repeller_voltage_millivolts(&mut self) -> i32 {
-        let adc_voltage = self.tether_adc.read_voltage_from(&REPELLER_VOLTAGE_SENSOR, &mut self.spi);
-        repeller_voltage_eq(adc_voltage)
+    pub fn get_repeller_voltage_millivolts(&mut self) -> Result<Millivolts, ADCError> {
+        let adc_voltage = self.tether_adc.read_voltage_from(&REPELLER_VOLTAGE_SENSOR, &mut self.spi)?;
+        Ok(repeller_voltage_eq(adc_voltage))
+    }
+
+    // Spread of the samples behind the last reading of each filtered sensor (see HV_SENSE_FILTER), in the same units as the reading.
+    pub fn get_tether_bias_voltage_spread_millivolts(&self) -> Millivolts {
+        Millivolts(self.spread_in_sensor_units(&TETHER_BIAS_VOLTAGE_SENSOR, |v_adc| tether_bias_voltage_eq(v_adc).0))
+    }
+    pub fn get_tether_bias_current_spread_microamps(&self) -> Microamps {
+        Microamps(self.spread_in_sensor_units(&TETHER_BIAS_CURRENT_SENSOR, |v_adc| tether_bias_current_eq(v_adc).0))
+    }
+    pub fn get_cathode_offset_voltage_spread_millivolts(&self) -> Millivolts {
+        Millivolts(self.spread_in_sensor_units(&CATHODE_OFFSET_VOLTAGE_SENSOR, |v_adc| cathode_offset_voltage_eq(v_adc).0))
+    }
+    pub fn get_cathode_offset_current_spread_microamps(&self) -> Microamps {
+        Microamps(self.spread_in_sensor_units(&CATHODE_OFFSET_CURRENT_SENSOR, |v_adc| cathode_offset_current_eq(v_adc).0))
+    }
+    pub fn get_repeller_voltage_spread_millivolts(&self) -> Millivolts {
+        Millivolts(self.spread_in_sensor_units(&REPELLER_VOLTAGE_SENSOR, |v_adc| repeller_voltage_eq(v_adc).0))
+    }
+    // The sensor equations are linear, so a spread at the ADC scales by the equation's gradient. Some gradients are negative.
+    fn spread_in_sensor_units(&self, sensor: &TetherSensor, sensor_eq: impl Fn(Millivolts) -> i32) -> i32 {
+        (sensor_eq(self.tether_adc.last_spread_millivolts(sensor)) - sensor_eq(Millivolts::ZERO)).abs()
+    }
+
+    // Every tether ADC channel in one scan.
+    pub fn get_tether_adc_readings(&mut self) -> Result<TetherADCReadings, ADCError> {
+        self.read_tether_adc()
     }
 
     // Relays
@@ -242,9 +461,550 @@ impl<const HSTATE: HeaterState> Payload<{PayloadOn}, HSTATE>{
             SwitchState::Disconnected => self.pins.tether_switch.set_low().ok(),
         };
     }
+
+    // Opens both relays and drops both HVDC supplies to zero at once, without waiting for the outputs to fall. Used when shutting down on a fault.
+    pub fn safe_hvdc_shutdown(&mut self) {
+        self.open_relays_and_zero_hvdc();
+    }
+    // Normal HVDC shutdown, used on power-down. Drops both supplies to zero, waits for their outputs to fall below HVDC_DISCHARGED_MILLIVOLTS, then opens the relays,
+    // so the relays never break a loaded high voltage. The relays open after HVDC_DISCHARGE_TIMEOUT_MILLIS regardless. Returns whether both outputs fell in time.
+    pub fn discharge_hvdc(&mut self) -> bool {
+        self.set_hvdc_voltage(HVDCSupply::CathodeOffset, Millivolts::ZERO);
+        self.set_hvdc_voltage(HVDCSupply::TetherBias, Millivolts::ZERO);
+        let start_tick = self.power_domains.now();
+        let discharged = loop {
+            let cathode_offset = self.get_cathode_offset_voltage_millivolts();
+            let tether_bias = self.get_tether_bias_voltage_millivolts();
+            if [cathode_offset, tether_bias].iter().all(|voltage| voltage.is_ok_and(|voltage| voltage.0.abs() <= HVDC_DISCHARGED_MILLIVOLTS.0)) {
+                break true;
+            }
+            if self.power_domains.millis_since(start_tick) >= HVDC_DISCHARGE_TIMEOUT_MILLIS {
+                break false;
+            }
+        };
+        self.open_relays_and_zero_hvdc();
+        discharged
+    }
+
+    // HVDC ramps
+    // Steps the supply from its last setpoint towards target (clamped to the supply's range) at volts_per_second, one step every HVDC_RAMP_STEP_MILLIS, checking its sense channels after every step.
+    // If a check fails the ramp stops and the supply is left at the step that failed, which is reported in the error. Returns the final setpoint.
+    // A rate of zero would never arrive, so it's refused with RampFault::ZeroRate before anything is changed.
+    pub fn ramp_hvdc_supply(&mut self, supply: HVDCSupply, target: Millivolts, volts_per_second: u32, limits: RampLimits) -> Result<Millivolts, RampError> {
+        let target = enforce_bounds(supply.min_voltage(), target, supply.max_voltage());
+        let start = self.hvdc_setpoint(supply);
+        if volts_per_second == 0 {
+            return Err(RampError{stopped_at: start, cause: RampFault::ZeroRate});
+        }
+        let distance = (target - start).0.unsigned_abs();
+        let start_tick = self.power_domains.now();
+        let mut setpoint = start;
+        loop {
+            // V/s is the same as mV/ms
+            let step = self.power_domains.millis_since(start_tick).saturating_mul(volts_per_second).min(distance) as i32;
+            let next_setpoint = if target >= start {start + Millivolts(step)} else {start - Millivolts(step)};
+            if next_setpoint != setpoint {
+                setpoint = next_setpoint;
+                self.set_hvdc_voltage(supply, setpoint);
+            }
+            self.check_hvdc_limits(supply, &limits).map_err(|cause| RampError{stopped_at: setpoint, cause})?;
+            if setpoint == target {
+                return Ok(target);
+            }
+            self.power_domains.wait_millis(HVDC_RAMP_STEP_MILLIS);
+        }
+    }
+
+    // Closed-loop HVDC control
+    // Trims the supply's setpoint using its voltage sensor until the measured output is within tolerance of target, or the iteration limit is reached.
+    // The supply should already be near target, e.g. after ramp_hvdc_supply. The regulator keeps its integral between calls, so calling this periodically also tracks drift.
+    // Every reading is checked against limits as in ramp_hvdc_supply. If a check fails regulation stops and the supply is left at the setpoint that failed, which is reported in the error.
+    pub fn regulate_hvdc_supply(&mut self, supply: HVDCSupply, target: Millivolts, regulator: &mut HVDCRegulator, limits: RampLimits) -> Result<Regulation, RampError> {
+        let target = enforce_bounds(supply.min_voltage(), target, supply.max_voltage());
+        let mut setpoint = self.hvdc_setpoint(supply);
+        let mut iterations = 0;
+        loop {
+            let measured = self.check_hvdc_limits(supply, &limits).map_err(|cause| RampError{stopped_at: setpoint, cause})?;
+            let error = target - measured;
+            if error.0.abs() <= regulator.tolerance.0 {
+                return Ok(Regulation { status: RegulationStatus::Converged, measured, iterations });
+            }
+            if iterations == regulator.max_iterations {
+                let status = if regulator.controller.is_saturated() {RegulationStatus::Saturated} else {RegulationStatus::NotConverged};
+                return Ok(Regulation { status, measured, iterations });
+            }
+            let correction = regulator.controller.update(error.0);
+            setpoint = enforce_bounds(supply.min_voltage(), target + Millivolts(correction), supply.max_voltage());
+            self.set_hvdc_voltage(supply, setpoint);
+            self.power_domains.wait_millis(HVDC_SETTLING_MILLIS);
+            iterations += 1;
+        }
+    }
+
+    // Supply voltage corresponding to the DAC's current output. The DAC powers up at zero scale, so that's assumed if it hasn't been set.
+    fn hvdc_setpoint(&self, supply: HVDCSupply) -> Millivolts {
+        match supply {
+            HVDCSupply::TetherBias => dac_voltage_to_tether_bias_voltage(self.get_tether_bias_dac_setpoint().unwrap_or(Millivolts::ZERO)),
+            HVDCSupply::CathodeOffset => dac_voltage_to_cathode_offset_voltage(self.get_cathode_offset_dac_setpoint().unwrap_or(Millivolts::ZERO)),
+        }
+    }
+    pub fn set_hvdc_voltage(&mut self, supply: HVDCSupply, target: Millivolts) {
+        match supply {
+            HVDCSupply::TetherBias => self.set_tether_bias_voltage(target),
+            HVDCSupply::CathodeOffset => self.set_cathode_offset_voltage(target),
+        }
+    }
+    // Returns the measured supply voltage if it and the current are within limits.
+    fn check_hvdc_limits(&mut self, supply: HVDCSupply, limits: &RampLimits) -> Result<Millivolts, RampFault> {
+        let (voltage, current) = match supply {
+            HVDCSupply::TetherBias => (self.get_tether_bias_voltage_millivolts(), self.get_tether_bias_current_microamps()),
+            HVDCSupply::CathodeOffset => (self.get_cathode_offset_voltage_millivolts(), self.get_cathode_offset_current_microamps()),
+        };
+        let (voltage, current) = (voltage.map_err(RampFault::ADC)?, current.map_err(RampFault::ADC)?);
+        if voltage > limits.max_voltage {
+            return Err(RampFault::OverVoltage(voltage));
+        }
+        if current.0.abs() > limits.max_current.0 {
+            return Err(RampFault::OverCurrent(current));
+        }
+        Ok(voltage)
+    }
+}
+
+// Heater power control. Emission depends on filament power rather than voltage, and the filament's resistance rises as it heats.
+impl<H: PayloadHardware> Payload<{PayloadOn}, {HeaterOn}, H>{
+    // One control step: measure filament power, then adjust the heater voltage towards control's target. Call periodically, or use settle_heater_power.
+    // The new voltage is what the target power needs at the filament's present resistance, plus a PI trim for whatever that misses.
+    pub fn step_heater_power_control(&mut self, control: &mut HeaterPowerControl) -> Result<HeaterPowerReading, ADCError> {
+        let voltage = self.get_heater_voltage_millivolts()?;
+        let current = self.get_heater_current_milliamps()?;
+        let power = voltage.power_with(current);
+        let error = control.target - power;
+
+        // V = sqrt(P * R), and mW * mOhm = mV^2. Without current there's no resistance to go on, so build on the present setpoint.
+        let resistance_milliohms = (current.0 > 0).then(|| voltage.0.max(0) as u64 * 1000 / current.0 as u64);
+        let feedforward = match resistance_milliohms {
+            Some(resistance) => Millivolts((control.target.0.max(0) as u64 * resistance).isqrt().min(i32::MAX as u64) as i32),
+            None => self.get_heater_voltage_setpoint(),
+        };
+        // Kept so the update can be undone if the output ends up limited, as integrating an error the heater can't correct only winds the controller up.
+        let unlimited_controller = control.controller;
+        let mut command = feedforward + Millivolts(control.controller.update(error.0));
+
+        let mut status = None;
+        if let Some(resistance) = resistance_milliohms {
+            let current_limited_voltage = Millivolts((control.max_current.0.max(0) as u64 * resistance / 1000).min(i32::MAX as u64) as i32);
+            if command > current_limited_voltage {
+                command = current_limited_voltage;
+                status = Some(HeaterPowerStatus::CurrentLimited);
+            }
+        }
+        if current > control.max_current {
+            status = Some(HeaterPowerStatus::CurrentLimited);
+        }
+        if status.is_none() && command != enforce_bounds(HEATER_MIN_VOLTAGE_MILLIVOLTS, command, HEATER_MAX_VOLTAGE_MILLIVOLTS) {
+            status = Some(HeaterPowerStatus::VoltageLimited);
+        }
+        if status.is_some() {
+            control.controller = unlimited_controller;
+        }
+        self.set_heater_voltage(command);
+
+        control.settled_readings = if error.0.abs() <= control.tolerance.0 {control.settled_readings.saturating_add(1)} else {0};
+        let settled = control.settled_readings >= HEATER_SETTLED_READINGS;
+        let status = status.unwrap_or(if settled {HeaterPowerStatus::Settled} else {HeaterPowerStatus::Settling});
+        Ok(HeaterPowerReading { status, power, voltage, current })
+    }
+    // Steps the controller every HEATER_SETTLING_MILLIS until it settles, or max_steps have been taken. Returns the last reading either way.
+    pub fn settle_heater_power(&mut self, control: &mut HeaterPowerControl, max_steps: u16) -> Result<HeaterPowerReading, ADCError> {
+        let mut steps = 0;
+        loop {
+            let reading = self.step_heater_power_control(control)?;
+            steps += 1;
+            if reading.status == HeaterPowerStatus::Settled || steps >= max_steps {
+                return Ok(reading);
+            }
+            self.power_domains.wait_millis(HEATER_SETTLING_MILLIS);
+        }
+    }
+}
+
+// Marks group valid if the reading succeeded.
+fn check_reading<T>(reading: Result<T, ADCError>, group: ReadingGroup, valid: &mut ValidReadings) -> Option<T> {
+    if reading.is_ok() {
+        valid.insert(group);
+    }
+    reading.ok()
+}
+
+// The relay pins are outputs, so this is what was last commanded.
+fn switch_state<E>(pin_high: Result<bool, E>) -> SwitchState {
+    match pin_high {
+        Ok(true) => SwitchState::Connected,
+        _ => SwitchState::Disconnected,
+    }
+}
+
+fn temperature_eq(temp_sensor: &TemperatureSensor, adc_voltage: Millivolts) -> Kelvin {
+    match &temp_sensor.vcc {
+        VccType::LMS     => lms_temperature_eq(adc_voltage),
+        VccType::Payload => payload_temperature_eq(adc_voltage)
+    }
+}
+
+// Values from Payload::get_tether_adc_readings, with the same units as the individual getters.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TetherADCReadings {
+    pub heater_voltage_millivolts: Millivolts,
+    pub heater_current_milliamps: Milliamps,
+    pub tether_bias_voltage_millivolts: Millivolts,
+    pub tether_bias_current_microamps: Microamps,
+    pub cathode_offset_voltage_millivolts: Millivolts,
+    pub cathode_offset_current_microamps: Microamps,
+    pub repeller_voltage_millivolts: Millivolts,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum HVDCSupply {
+    TetherBias,
+    CathodeOffset,
+}
+impl HVDCSupply {
+    pub const fn min_voltage(self) -> Millivolts {
+        match self {
+            HVDCSupply::TetherBias => TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS,
+            HVDCSupply::CathodeOffset => CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS,
+        }
+    }
+    pub const fn max_voltage(self) -> Millivolts {
+        match self {
+            HVDCSupply::TetherBias => TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS,
+            HVDCSupply::CathodeOffset => CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS,
+        }
+    }
+}
+
+// Bounds on the sensed supply voltage and current magnitude during a ramp.
+#[derive(Copy, Clone, Debug)]
+pub struct RampLimits {
+    pub max_voltage: Millivolts,
+    pub max_current: Microamps,
+}
+impl RampLimits {
+    pub const fn default_for(supply: HVDCSupply) -> RampLimits {
+        let max_current = match supply {
+            HVDCSupply::TetherBias => TETHER_BIAS_MAX_CURRENT_MICROAMPS,
+            HVDCSupply::CathodeOffset => CATHODE_OFFSET_MAX_CURRENT_MICROAMPS,
+        };
+        RampLimits { max_voltage: Millivolts(supply.max_voltage().0 + HVDC_OVERVOLTAGE_MARGIN_MILLIVOLTS.0), max_current }
+    }
 }
 
+// Controller and stopping conditions for Payload::regulate_hvdc_supply. Use one per supply, as the controller's integral is that supply's learned trim.
+#[derive(Copy, Clone, Debug)]
+pub struct HVDCRegulator {
+    pub controller: PIController,
+    pub tolerance: Millivolts,
+    pub max_iterations: u16,
+}
+impl Default for HVDCRegulator {
+    fn default() -> Self {
+        HVDCRegulator {
+            controller: PIController::new(HVDC_REGULATOR_KP_PERCENT, HVDC_REGULATOR_KI_PERCENT, HVDC_MAX_CORRECTION_MILLIVOLTS.0),
+            tolerance: HVDC_REGULATION_TOLERANCE_MILLIVOLTS,
+            max_iterations: HVDC_REGULATION_MAX_ITERATIONS,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum RegulationStatus {
+    /// Measured output is within tolerance of the target.
+    Converged,
+    /// Ran out of iterations while still correcting.
+    NotConverged,
+    /// Ran out of iterations with the correction at its limit, so the open-loop equation is further off than the regulator is allowed to trim.
+    Saturated,
+}
+
+// Result of Payload::regulate_hvdc_supply. measured is the last reading of the supply's output.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub struct Regulation {
+    pub status: RegulationStatus,
+    pub measured: Millivolts,
+    pub iterations: u16,
+}
+
+// Consecutive in-tolerance readings before heater power counts as settled
+const HEATER_SETTLED_READINGS: u8 = 3;
+
+// Target and state for heater power control. Keep it for as long as the heater is being controlled, and call controller.reset() if the heater is switched off in between.
+#[derive(Copy, Clone, Debug)]
+pub struct HeaterPowerControl {
+    target: Milliwatts,
+    pub max_current: Milliamps,
+    pub tolerance: Milliwatts,
+    pub controller: PIController,
+    settled_readings: u8,
+}
+impl HeaterPowerControl {
+    pub fn new(target: Milliwatts) -> Self {
+        let mut control = HeaterPowerControl {
+            target: Milliwatts::ZERO,
+            max_current: HEATER_MAX_CURRENT_MILLIAMPS,
+            tolerance: HEATER_POWER_TOLERANCE_MILLIWATTS,
+            controller: PIController::new(HEATER_REGULATOR_KP_PERCENT, HEATER_REGULATOR_KI_PERCENT, HEATER_MAX_CORRECTION_MILLIVOLTS.0),
+            settled_readings: 0,
+        };
+        control.set_target(target);
+        control
+    }
+    // Clamped to between zero and HEATER_MAX_POWER_MILLIWATTS.
+    pub fn set_target(&mut self, target: Milliwatts) {
+        self.target = enforce_bounds(Milliwatts::ZERO, target, HEATER_MAX_POWER_MILLIWATTS);
+        self.settled_readings = 0;
+    }
+    pub fn target(&self) -> Milliwatts {
+        self.target
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum HeaterPowerStatus {
+    /// Within tolerance for several consecutive steps.
+    Settled,
+    Settling,
+    /// The heater voltage is being held down to respect max_current.
+    CurrentLimited,
+    /// The heater voltage needed is outside what the digipot can set.
+    VoltageLimited,
+}
+
+// Measurements taken at the start of a heater power control step, and the resulting status.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub struct HeaterPowerReading {
+    pub status: HeaterPowerStatus,
+    pub power: Milliwatts,
+    pub voltage: Millivolts,
+    pub current: Milliamps,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum RampFault {
+    OverVoltage(Millivolts),
+    OverCurrent(Microamps),
+    ADC(ADCError),
+    /// The ramp was asked for zero volts per second.
+    ZeroRate,
+}
+
+// Why a ramp stopped early, and the supply voltage it was left at.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub struct RampError {
+    pub stopped_at: Millivolts,
+    pub cause: RampFault,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
 pub enum SwitchState{
     Connected,
     Disconnected,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_sim::{SimBoard, SimChip, SimFault, SimHardware, SimLine};
+    use crate::dyn_payload::{DynPayload, DynState};
+    use crate::protection::{ProtectionTrip, DEFAULT_MAX_MISSED_READINGS};
+    use std::boxed::Box;
+
+    // Payload on with both HVDC supplies up and connected
+    fn emitting(board: &SimBoard) -> Payload<{PayloadOn}, {HeaterOff}, SimHardware<'_>> {
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        payload.set_cathode_offset_switch(SwitchState::Connected);
+        payload.set_tether_bias_switch(SwitchState::Connected);
+        payload.set_hvdc_voltage(HVDCSupply::CathodeOffset, Millivolts(100_000));
+        payload.set_hvdc_voltage(HVDCSupply::TetherBias, Millivolts(100_000));
+        payload
+    }
+    fn assert_hvdc_shut_down(board: &SimBoard) {
+        for line in [SimLine::CathodeSwitch, SimLine::TetherSwitch] {
+            assert!(!board.line(line), "{:?}", line);
+        }
+        assert_eq!(board.dac_output_voltage(CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL), Millivolts::ZERO);
+        assert_eq!(board.dac_output_voltage(TETHER_BIAS_SUPPLY_CONTROL_CHANNEL), Millivolts::ZERO);
+    }
+
+    #[test]
+    fn state_changes_drive_the_enable_pins() {
+        let board = SimBoard::new();
+        let payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]);
+        assert!(!board.line(SimLine::PayloadEnable));
+
+        let payload = payload.into_enabled_payload();
+        assert!(board.line(SimLine::PayloadEnable));
+        let payload = payload.into_enabled_heater();
+        assert!(board.line(SimLine::HeaterEnable));
+        let payload = payload.into_disabled_heater();
+        assert!(!board.line(SimLine::HeaterEnable));
+
+        let mut payload = payload.into_enabled_heater();
+        payload.set_cathode_offset_switch(SwitchState::Connected);
+        payload.set_tether_bias_switch(SwitchState::Connected);
+        payload.into_disabled_heater().into_disabled_payload();
+        for line in [SimLine::PayloadEnable, SimLine::HeaterEnable, SimLine::CathodeSwitch, SimLine::TetherSwitch] {
+            assert!(!board.line(line), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn housekeeping_trip_shuts_down_hvdc() {
+        let board = SimBoard::new();
+        let mut payload = emitting(&board);
+        payload.get_housekeeping();
+        assert_eq!(payload.protection.latched_fault(), None);
+
+        let mut environment = board.environment();
+        environment.temperatures[3] = Kelvin(400);
+        board.set_environment(environment);
+        payload.get_housekeeping();
+        assert!(matches!(payload.protection.latched_fault().map(|fault| fault.trip), Some(ProtectionTrip::OverTemperature(3, _))));
+        assert_hvdc_shut_down(&board);
+        // Still latched after a state change
+        assert!(payload.into_disabled_payload().protection.latched_fault().is_some());
+    }
+
+    #[test]
+    fn lost_tether_adc_shuts_down_hvdc() {
+        let board = SimBoard::new();
+        let mut payload = emitting(&board);
+        board.inject_fault(SimFault::StuckMISO(SimChip::TetherADC, true));
+        for _ in 1..DEFAULT_MAX_MISSED_READINGS {
+            payload.get_housekeeping();
+            assert_eq!(payload.protection.latched_fault(), None);
+        }
+        payload.get_housekeeping();
+        assert_eq!(payload.protection.latched_fault().map(|fault| fault.trip), Some(ProtectionTrip::SensorLost(ReadingGroup::TetherADC)));
+        assert_hvdc_shut_down(&board);
+    }
+
+    #[test]
+    fn dyn_payload_trip_shuts_everything_down() {
+        let board = SimBoard::new();
+        let mut payload = DynPayload::from(emitting(&board).into_enabled_heater());
+        board.inject_fault(SimFault::StuckMISO(SimChip::TemperatureADC, true));
+        for _ in 0..DEFAULT_MAX_MISSED_READINGS {
+            payload.get_housekeeping();
+        }
+        assert_eq!(payload.latched_fault().map(|fault| fault.trip), Some(ProtectionTrip::SensorLost(ReadingGroup::Temperatures)));
+        assert_eq!(payload.state(), DynState::PayloadOff);
+        assert_eq!(payload.enable_payload().map_err(|err| matches!(err, crate::dyn_payload::DynPayloadError::FaultLatched(_))), Err(true));
+
+        board.clear_faults();
+        payload.clear_fault();
+        assert_eq!(payload.enable_payload(), Ok(()));
+    }
+
+    #[test]
+    fn regulation_stops_at_the_current_limit() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        let mut regulator = HVDCRegulator::default();
+        payload.set_hvdc_voltage(HVDCSupply::TetherBias, Millivolts(100_000));
+        let regulation = payload.regulate_hvdc_supply(HVDCSupply::TetherBias, Millivolts(100_000), &mut regulator, RampLimits::default_for(HVDCSupply::TetherBias));
+        assert_eq!(regulation.map(|regulation| regulation.status), Ok(RegulationStatus::Converged));
+
+        // Connecting a heavy load puts the supply over its current limit on the first reading
+        let mut environment = board.environment();
+        environment.tether_bias_load = Ohms(1000);
+        board.set_environment(environment);
+        payload.set_tether_bias_switch(SwitchState::Connected);
+        let err = payload.regulate_hvdc_supply(HVDCSupply::TetherBias, Millivolts(100_000), &mut regulator, RampLimits::default_for(HVDCSupply::TetherBias)).unwrap_err();
+        assert!(matches!(err.cause, RampFault::OverCurrent(_)));
+        assert_eq!(err.stopped_at, payload.hvdc_setpoint(HVDCSupply::TetherBias));
+    }
+
+    #[test]
+    fn heater_control_doesnt_wind_up_while_limited() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload().into_enabled_heater();
+        let mut control = HeaterPowerControl::new(HEATER_MAX_POWER_MILLIWATTS);
+        control.max_current = Milliamps(100);
+        payload.set_heater_voltage(Millivolts(500));
+        let mut statuses = std::vec::Vec::new();
+        for _ in 0..10 {
+            statuses.push(payload.step_heater_power_control(&mut control).unwrap().status);
+        }
+        assert!(statuses[1..].iter().all(|status| *status == HeaterPowerStatus::CurrentLimited), "{:?}", statuses);
+        assert_eq!(control.controller.integral_correction(), 0);
+
+        // Lifting the limit lets it reach the target straight away, with nothing to unwind
+        control.max_current = HEATER_MAX_CURRENT_MILLIAMPS;
+        control.set_target(Milliwatts(500));
+        let reading = payload.settle_heater_power(&mut control, 20).unwrap();
+        assert_eq!(reading.status, HeaterPowerStatus::Settled, "{:?}", reading);
+    }
+
+    #[test]
+    fn aperture_reads_power_the_domain_down_afterwards() {
+        let board = SimBoard::new();
+        let mut environment = board.environment();
+        environment.aperture_current = Microamps(500);
+        board.set_environment(environment);
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        payload.aperture_adc.set_filter(&APERTURE_CURRENT_SENSOR, ADCFilter::Median(5));
+        let current = payload.get_aperture_current_microamps().unwrap();
+        assert!((current.0 - 500).abs() <= 10, "{:?}", current);
+        assert_eq!(payload.millis_since_power_up(PowerDomain::Aperture), None);
+        assert!(!payload.aperture_adc.cs_pin.is_selected());
+    }
+
+    #[test]
+    fn discharge_waits_for_the_outputs_before_opening_the_relays() {
+        let board = SimBoard::new();
+        let mut payload = emitting(&board);
+        assert!(payload.discharge_hvdc());
+        assert_hvdc_shut_down(&board);
+
+        // If the outputs can't be confirmed low the relays still open, once the timeout has passed
+        let mut payload = emitting(&board);
+        board.inject_fault(SimFault::DeadChipSelect(SimChip::TetherADC));
+        let start = payload.power_domains.uptime_millis();
+        assert!(!payload.discharge_hvdc());
+        assert!(payload.power_domains.uptime_millis() - start >= HVDC_DISCHARGE_TIMEOUT_MILLIS);
+        assert_hvdc_shut_down(&board);
+    }
+
+    #[test]
+    fn zero_rate_ramps_are_refused() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        payload.set_hvdc_voltage(HVDCSupply::TetherBias, Millivolts(50_000));
+        let start = payload.hvdc_setpoint(HVDCSupply::TetherBias);
+        let result = payload.ramp_hvdc_supply(HVDCSupply::TetherBias, Millivolts(100_000), 0, RampLimits::default_for(HVDCSupply::TetherBias));
+        assert_eq!(result, Err(RampError{stopped_at: start, cause: RampFault::ZeroRate}));
+        assert_eq!(payload.hvdc_setpoint(HVDCSupply::TetherBias), start);
+
+        let mut payload = DynPayload::from(payload);
+        assert!(payload.ramp_hvdc_supply(HVDCSupply::TetherBias, Millivolts(100_000), 0, RampLimits::default_for(HVDCSupply::TetherBias)).is_err());
+    }
+
+    #[test]
+    fn ramps_wait_between_steps() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        let limits = RampLimits::default_for(HVDCSupply::TetherBias);
+        let before = board.transactions();
+        payload.check_hvdc_limits(HVDCSupply::TetherBias, &limits).unwrap();
+        let transactions_per_check = board.transactions() - before;
+
+        // At 50V/s (50mV/ms) the ramp takes expected_millis, so it should check once per HVDC_RAMP_STEP_MILLIS rather than on every pass
+        let expected_millis = (Millivolts(10_000) - payload.hvdc_setpoint(HVDCSupply::TetherBias)).0 as u32 / 50;
+        let (start_millis, start_transactions) = (payload.power_domains.uptime_millis(), board.transactions());
+        assert_eq!(payload.ramp_hvdc_supply(HVDCSupply::TetherBias, Millivolts(10_000), 50, limits), Ok(Millivolts(10_000)));
+        let elapsed = payload.power_domains.uptime_millis() - start_millis;
+        assert!((expected_millis..expected_millis + 2 * HVDC_RAMP_STEP_MILLIS).contains(&elapsed), "{} vs {}", elapsed, expected_millis);
+        let checks = (board.transactions() - start_transactions) / transactions_per_check;
+        assert!(checks <= expected_millis / HVDC_RAMP_STEP_MILLIS + 2, "{} checks", checks);
+    }
+}
@@ -1,26 +1,116 @@
  // this file contains PCB-specific structures that are unlikely to change under new versions.
 
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal::{serial, timer::CountDown};
+#[cfg(target_arch = "msp430")]
+use msp430fr2355::{E_USCI_A1, TB0, TB1};
+#[cfg(target_arch = "msp430")]
+use msp430fr2x5x_hal::{serial::Rx, timer::Timer};
+use void::Void;
 
-// Structures that group commonly used pins together
+#[cfg(target_arch = "msp430")]
 use crate::pcb_mapping::pin_name_types::*;
+use crate::power::PowerTimer;
+use crate::spi::PayloadSPIAnyMode;
+#[cfg(target_arch = "msp430")]
+use crate::spi::{PayloadSPIBus, SckPolarity::IdleHigh, SckPhase::SampleFirstEdge};
 use crate::{Digipot, adc::*, dac::DAC};
-pub struct LEDPins{
-    pub red_led: RedLEDPin,
-    pub yellow_led: YellowLEDPin,
-    pub green_led: GreenLEDPin,
+
+// Every hardware type the Payload and its pin groups use. MSP430Hardware is the real board, and only exists when building for it.
+// Everything else takes the implementation as a parameter, so it also builds against mocks on the host.
+pub trait PayloadHardware {
+    type RedLEDPin: OutputPin;
+    type YellowLEDPin: OutputPin;
+    type GreenLEDPin: OutputPin;
+
+    type DigipotCSPin: OutputPin;
+    type DACCSPin: OutputPin;
+    type TetherADCCSPin: ADCCSPin;
+    type TemperatureADCCSPin: ADCCSPin;
+    type MiscADCCSPin: ADCCSPin;
+    type ApertureADCCSPin: ADCCSPin;
+    type PayloadSPIBus: PayloadSPIAnyMode;
+
+    type PayloadEnablePin: OutputPin;
+    type HeaterEnablePin: OutputPin;
+    type CathodeSwitchPin: StatefulOutputPin;
+    type TetherSwitchPin: StatefulOutputPin;
+
+    type EndmassSense1Pin: InputPin;
+    type EndmassSense2Pin: InputPin;
+    type PinpullerDeploySensePin: InputPin;
+
+    // Void so pin_select can hand out any one of them
+    type BurnWire1Pin: OutputPin<Error = Void>;
+    type BurnWire1BackupPin: OutputPin<Error = Void>;
+    type BurnWire2Pin: OutputPin<Error = Void>;
+    type BurnWire2BackupPin: OutputPin<Error = Void>;
+
+    type TetherLMSReceiverEnablePin: OutputPin;
+    type TetherLMSLEDEnablePin: OutputPin;
+
+    type SerialReader: serial::Read<u8>;
+    type Timer: CountDown<Time = u16>; // Counts ACLK (32768Hz) ticks
+    type PowerTimer: PowerTimer;
+}
+#[cfg(target_arch = "msp430")]
+pub struct MSP430Hardware;
+#[cfg(target_arch = "msp430")]
+impl PayloadHardware for MSP430Hardware {
+    type RedLEDPin = RedLEDPin;
+    type YellowLEDPin = YellowLEDPin;
+    type GreenLEDPin = GreenLEDPin;
+
+    type DigipotCSPin = DigipotCSPin;
+    type DACCSPin = DACCSPin;
+    type TetherADCCSPin = TetherADCCSPin;
+    type TemperatureADCCSPin = TemperatureADCCSPin;
+    type MiscADCCSPin = MiscADCCSPin;
+    type ApertureADCCSPin = ApertureADCCSPin;
+    type PayloadSPIBus = PayloadSPIBus<{IdleHigh}, {SampleFirstEdge}>;
+
+    type PayloadEnablePin = PayloadEnablePin;
+    type HeaterEnablePin = HeaterEnablePin;
+    type CathodeSwitchPin = CathodeSwitchPin;
+    type TetherSwitchPin = TetherSwitchPin;
+
+    type EndmassSense1Pin = EndmassSense1Pin;
+    type EndmassSense2Pin = EndmassSense2Pin;
+    type PinpullerDeploySensePin = PinpullerDeploySensePin;
+
+    type BurnWire1Pin = BurnWire1Pin;
+    type BurnWire1BackupPin = BurnWire1BackupPin;
+    type BurnWire2Pin = BurnWire2Pin;
+    type BurnWire2BackupPin = BurnWire2BackupPin;
+
+    type TetherLMSReceiverEnablePin = TetherLMSReceiverEnablePin;
+    type TetherLMSLEDEnablePin = TetherLMSLEDEnablePin;
+
+    #[cfg(not(feature = "obc_spi"))]
+    type SerialReader = Rx<E_USCI_A1>;
+    #[cfg(feature = "obc_spi")]
+    type SerialReader = crate::serial::NoSerialInput; // eUSCI_A1 is the OBC bus
+    type Timer = Timer<TB0>;
+    type PowerTimer = Timer<TB1>;
+}
+
+// Structures that group commonly used pins together
+pub struct LEDPins<H: PayloadHardware>{
+    pub red_led: H::RedLEDPin,
+    pub yellow_led: H::YellowLEDPin,
+    pub green_led: H::GreenLEDPin,
 }
 
-pub struct PayloadSPIChipSelectPins{
-    pub digipot:        DigipotCSPin, // used to control the heater supply
-    pub dac:            DACCSPin, // DAC outputs are used to control the cathode offset and tether bias supply's target voltages
-    pub tether_adc:     TetherADCCSPin, //ADC1, measures voltages and currents from tether circuitry
-    pub temperature_adc:TemperatureADCCSPin, //ADC2, measures board temperatures
-    pub misc_adc:       MiscADCCSPin, //ADC0, measures everything else
-    pub aperture_adc:   ApertureADCCSPin, //ADC4, measures aperture current
+pub struct PayloadSPIChipSelectPins<H: PayloadHardware>{
+    pub digipot:        H::DigipotCSPin, // used to control the heater supply
+    pub dac:            H::DACCSPin, // DAC outputs are used to control the cathode offset and tether bias supply's target voltages
+    pub tether_adc:     H::TetherADCCSPin, //ADC1, measures voltages and currents from tether circuitry
+    pub temperature_adc:H::TemperatureADCCSPin, //ADC2, measures board temperatures
+    pub misc_adc:       H::MiscADCCSPin, //ADC0, measures everything else
+    pub aperture_adc:   H::ApertureADCCSPin, //ADC4, measures aperture current
 }
-impl PayloadSPIChipSelectPins {
-    pub fn new(mut digipot: DigipotCSPin, mut dac: DACCSPin, mut tether_adc: TetherADCCSPin, mut temperature_adc: TemperatureADCCSPin, mut misc_adc: MiscADCCSPin, mut aperture_adc: ApertureADCCSPin) -> PayloadSPIChipSelectPins{
+impl<H: PayloadHardware> PayloadSPIChipSelectPins<H> {
+    pub fn new(mut digipot: H::DigipotCSPin, mut dac: H::DACCSPin, mut tether_adc: H::TetherADCCSPin, mut temperature_adc: H::TemperatureADCCSPin, mut misc_adc: H::MiscADCCSPin, mut aperture_adc: H::ApertureADCCSPin) -> PayloadSPIChipSelectPins<H>{
         digipot.set_high().ok(); // in lieu of accepting stateful output pins just set them high in the constructor
         dac.set_high().ok();
         tether_adc.set_high().ok();
@@ -33,11 +123,13 @@ impl PayloadSPIChipSelectPins {
 }
 
 //eUSCI_B1
+#[cfg(target_arch = "msp430")]
 pub struct PayloadSPIPins{
     pub miso: PayloadMISOPin, 
     pub mosi: PayloadMOSIPin, 
     pub sck:  PayloadSCKPin, 
 }
+#[cfg(target_arch = "msp430")]
 pub struct PayloadSPIBitBangPins{
     pub miso: PayloadMISOBitBangPin, 
     pub mosi: PayloadMOSIBitBangPin, 
@@ -45,6 +137,7 @@ pub struct PayloadSPIBitBangPins{
 }
 
 //eUSCI_A1
+#[cfg(target_arch = "msp430")]
 pub struct OBCSPIPins{
     pub miso:                   OBCMISOPin,
     pub mosi:                   OBCMOSIPin,
@@ -52,40 +145,41 @@ pub struct OBCSPIPins{
     pub chip_select:            OBCCSPin,
     pub chip_select_interrupt:  OBCCSInterruptPin, 
 }
+#[cfg(target_arch = "msp430")]
 pub struct DebugSerialPins {
     pub rx: DebugSerialRx,
     pub tx: DebugSerialTx,
 }
-pub struct PayloadControlPins{
-    pub payload_enable: PayloadEnablePin, // turns on most payload devices (power supplies, isolators, etc.)
-    pub heater_enable:  HeaterEnablePin, // turns on heater step-down converter
-    pub cathode_switch: CathodeSwitchPin, // connects cathode offset+ to exterior
-    pub tether_switch:  TetherSwitchPin, // connects tether bias+ to tether
+pub struct PayloadControlPins<H: PayloadHardware>{
+    pub payload_enable: H::PayloadEnablePin, // turns on most payload devices (power supplies, isolators, etc.)
+    pub heater_enable:  H::HeaterEnablePin, // turns on heater step-down converter
+    pub cathode_switch: H::CathodeSwitchPin, // connects cathode offset+ to exterior
+    pub tether_switch:  H::TetherSwitchPin, // connects tether bias+ to tether
 }
 
-pub struct DeploySensePins{
-    pub endmass_sense_1:    EndmassSense1Pin, // Detects whether the endmass has ejected
-    pub endmass_sense_2:    EndmassSense2Pin, // Detects whether the endmass has ejected
-    pub pinpuller_sense:    PinpullerDeploySensePin, // Detects whether the pinpuller has deployed
+pub struct DeploySensePins<H: PayloadHardware>{
+    pub endmass_sense_1:    H::EndmassSense1Pin, // Detects whether the endmass has ejected
+    pub endmass_sense_2:    H::EndmassSense2Pin, // Detects whether the endmass has ejected
+    pub pinpuller_sense:    H::PinpullerDeploySensePin, // Detects whether the pinpuller has deployed
 }
 
-pub struct TetherLMSPins{
-    pub lms_receiver_enable: TetherLMSReceiverEnablePin,
-    pub lms_led_enable:      TetherLMSLEDEnablePin,
+pub struct TetherLMSPins<H: PayloadHardware>{
+    pub lms_receiver_enable: H::TetherLMSReceiverEnablePin,
+    pub lms_led_enable:      H::TetherLMSLEDEnablePin,
 }
 
-pub struct PinpullerActivationPins{
-    pub burn_wire_1:        BurnWire1Pin,
-    pub burn_wire_1_backup: BurnWire1BackupPin,
-    pub burn_wire_2:        BurnWire2Pin,
-    pub burn_wire_2_backup: BurnWire2BackupPin,
+pub struct PinpullerActivationPins<H: PayloadHardware>{
+    pub burn_wire_1:        H::BurnWire1Pin,
+    pub burn_wire_1_backup: H::BurnWire1BackupPin,
+    pub burn_wire_2:        H::BurnWire2Pin,
+    pub burn_wire_2_backup: H::BurnWire2BackupPin,
 }
 
-pub struct PayloadPeripherals{
-    pub digipot:        Digipot,
-    pub dac:            DAC,
-    pub tether_adc:     TetherADC, 
-    pub temperature_adc:TemperatureADC,
-    pub misc_adc:       MiscADC,
-    pub aperture_adc:   ApertureADC,
+pub struct PayloadPeripherals<H: PayloadHardware>{
+    pub digipot:        Digipot<H::DigipotCSPin>,
+    pub dac:            DAC<H::DACCSPin>,
+    pub tether_adc:     TetherADC<H::TetherADCCSPin>, 
+    pub temperature_adc:TemperatureADC<H::TemperatureADCCSPin>,
+    pub misc_adc:       MiscADC<H::MiscADCCSPin>,
+    pub aperture_adc:   ApertureADC<H::ApertureADCCSPin>,
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 // This file acts as an abstraction layer for PCB-specific values that may change between revisions.
 
+#[cfg(target_arch = "msp430")]
 pub mod pin_name_types {
     use msp430fr2x5x_hal::gpio::*;
 
@@ -50,23 +51,58 @@ pub mod pin_name_types {
 }
 
 pub use crate::pcb_common::*;
+use crate::units::Ohms;
 
 pub mod power_supply_limits {
+    use crate::units::{Kelvin, Microamps, Milliamps, Millivolts, Milliwatts};
     // Maximum and minimum values producable by controllable power supplies
-    pub const HEATER_MAX_VOLTAGE_MILLIVOLTS: u16 =
-        super::power_supply_equations::digipot_resistance_to_heater_voltage_mv(
+    pub const HEATER_MAX_VOLTAGE_MILLIVOLTS: Millivolts =
+        super::power_supply_equations::digipot_resistance_to_heater_voltage(
             crate::digipot::DIGIPOT_MAX_RESISTANCE,
         );
-    pub const HEATER_MIN_VOLTAGE_MILLIVOLTS: u16 =
-        super::power_supply_equations::digipot_resistance_to_heater_voltage_mv(
+    pub const HEATER_MIN_VOLTAGE_MILLIVOLTS: Millivolts =
+        super::power_supply_equations::digipot_resistance_to_heater_voltage(
             crate::digipot::DIGIPOT_MIN_RESISTANCE,
         );
 
-    pub const CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS: u32 = 250000;
-    pub const CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS: u32 = 0;
-
-    pub const TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS: u32 = 250000;
-    pub const TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS: u32 = 0;
+    pub const CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS: Millivolts = Millivolts(250000);
+    pub const CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS: Millivolts = Millivolts(0);
+
+    pub const TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS: Millivolts = Millivolts(250000);
+    pub const TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS: Millivolts = Millivolts(0);
+
+    // Default limits for Payload::ramp_hvdc_supply. Currents are magnitudes.
+    pub const HVDC_RAMP_VOLTS_PER_SECOND: u32 = 50; // TODO: Verify
+    pub const HVDC_RAMP_STEP_MILLIS: u32 = 20; // TODO: Verify
+    pub const HVDC_OVERVOLTAGE_MARGIN_MILLIVOLTS: Millivolts = Millivolts(10_000); // TODO: Verify
+    pub const CATHODE_OFFSET_MAX_CURRENT_MICROAMPS: Microamps = Microamps(4000); // TODO: Verify
+    pub const TETHER_BIAS_MAX_CURRENT_MICROAMPS: Microamps = Microamps(4000); // TODO: Verify
+    // Payload::discharge_hvdc waits for both supply outputs to fall this low before opening the relays
+    pub const HVDC_DISCHARGED_MILLIVOLTS: Millivolts = Millivolts(5_000); // TODO: Verify
+    pub const HVDC_DISCHARGE_TIMEOUT_MILLIS: u32 = 2_000; // TODO: Verify
+
+    // Defaults for Payload::regulate_hvdc_supply. Gains are in percent.
+    pub const HVDC_REGULATOR_KP_PERCENT: i32 = 30; // TODO: Tune
+    pub const HVDC_REGULATOR_KI_PERCENT: i32 = 20; // TODO: Tune
+    pub const HVDC_MAX_CORRECTION_MILLIVOLTS: Millivolts = Millivolts(20_000);
+    pub const HVDC_REGULATION_TOLERANCE_MILLIVOLTS: Millivolts = Millivolts(1_000);
+    pub const HVDC_REGULATION_MAX_ITERATIONS: u16 = 20;
+    pub const HVDC_SETTLING_MILLIS: u32 = 100; // TODO: Verify
+
+    // Defaults for heater power control (HeaterPowerControl). Gains are in percent, mV of correction per mW of error.
+    pub const HEATER_MAX_POWER_MILLIWATTS: Milliwatts = Milliwatts(1000); // TODO: Verify
+    pub const HEATER_MAX_CURRENT_MILLIAMPS: Milliamps = Milliamps(1000); // TODO: Verify
+    pub const HEATER_POWER_TOLERANCE_MILLIWATTS: Milliwatts = Milliwatts(20);
+    pub const HEATER_REGULATOR_KP_PERCENT: i32 = 50; // TODO: Tune
+    pub const HEATER_REGULATOR_KI_PERCENT: i32 = 20; // TODO: Tune
+    pub const HEATER_MAX_CORRECTION_MILLIVOLTS: Millivolts = Millivolts(500);
+    pub const HEATER_SETTLING_MILLIS: u32 = 500; // TODO: Verify
+
+    // Defaults for ProtectionLimits. Above the limits used while controlling the supplies, so only a fault should trip them.
+    pub const HEATER_TRIP_CURRENT_MILLIAMPS: Milliamps = Milliamps(1200); // TODO: Verify
+    pub const TETHER_BIAS_TRIP_CURRENT_MICROAMPS: Microamps = Microamps(5000); // TODO: Verify
+    pub const CATHODE_OFFSET_TRIP_CURRENT_MICROAMPS: Microamps = Microamps(5000); // TODO: Verify
+    pub const MAX_BOARD_TEMPERATURE_KELVIN: Kelvin = Kelvin(358); // 85C. TODO: Verify
 }
 pub mod peripheral_vcc_values {
     // VCC Supply voltages
@@ -161,84 +197,84 @@ pub mod sensor_locations {
 pub mod power_supply_locations {
     use crate::{dac::*, digipot::*};
     // DAC
+    pub const DAC_RESOLUTION: DACResolution = DACResolution::Twelve; // Fitted LTC2634 variant
     pub const CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL: DACChannel = DACChannel::ChannelC;
     pub const TETHER_BIAS_SUPPLY_CONTROL_CHANNEL: DACChannel = DACChannel::ChannelD;
 
     // Digipot
     pub const HEATER_DIGIPOT_CHANNEL: DigipotChannel = DigipotChannel::Channel1;
+    pub const SPARE_DIGIPOT_CHANNEL: DigipotChannel = DigipotChannel::Channel2; // Not connected to a supply on this revision
 }
 /* Sensor equations. Takes in the voltage reported at the ADC (in millivolts) and produces the voltage/current being sensed in millivolts/milliamps */
 
 pub mod sensor_equations {
+    use crate::units::{Kelvin, Microamps, Milliamps, Millivolts};
     use fixed::FixedI64;
 
-    pub fn heater_voltage_eq(v_adc_millivolts: u16) -> u16 {
+    pub fn heater_voltage_eq(v_adc: Millivolts) -> Millivolts {
         #[cfg(feature = "7A")]
         compile_error!("Not yet calibrated");
 
         #[cfg(feature = "7B")]
-        return (((((((((v_adc_millivolts as i32 * 1035) / 310) - 90) * 964) / 1000) + 75) * 979)
-            / 1000)
-            + 30)
-            .max(0) as u16;
+        return Millivolts(
+            (((((((((v_adc.0 * 1035) / 310) - 90) * 964) / 1000) + 75) * 979) / 1000) + 30).max(0),
+        );
 
         #[cfg(feature = "7C")]
-        return ((((((v_adc_millivolts as i32 * 1035) / 310) - 90) * 964) / 1000) + 75).max(0)
-            as u16;
+        return Millivolts(((((((v_adc.0 * 1035) / 310) - 90) * 964) / 1000) + 75).max(0));
 
         #[cfg(feature = "7D")]
         compile_error!("Not yet calibrated");
     }
-    pub fn repeller_voltage_eq(v_adc_millivolts: u16) -> i32 {
-        (2755 - v_adc_millivolts as i32) * 102
+    pub fn repeller_voltage_eq(v_adc: Millivolts) -> Millivolts {
+        Millivolts((2755 - v_adc.0) * 102)
     }
-    pub fn tether_bias_voltage_eq(v_adc_millivolts: u16) -> i32 {
-        ((v_adc_millivolts as i32 * 10891) / 100) + 3708
+    pub fn tether_bias_voltage_eq(v_adc: Millivolts) -> Millivolts {
+        Millivolts(((v_adc.0 * 10891) / 100) + 3708)
     }
-    pub fn cathode_offset_voltage_eq(v_adc_millivolts: u16) -> i32 {
-        ((v_adc_millivolts as i32) * -84714 / 1000) + 406089
+    pub fn cathode_offset_voltage_eq(v_adc: Millivolts) -> Millivolts {
+        Millivolts((v_adc.0 * -84714 / 1000) + 406089)
     }
-    pub fn heater_current_eq(v_adc_millivolts: u16) -> i16 {
-        (((v_adc_millivolts * 9) / 50) - 3) as i16
+    pub fn heater_current_eq(v_adc: Millivolts) -> Milliamps {
+        Milliamps(((v_adc.0 * 9) / 50) - 3)
     }
-    pub fn tether_bias_current_eq(v_adc_millivolts: u16) -> i32 {
-        // Output in MICROamps
-        ((1011 - v_adc_millivolts as i32) * 50_750) / 10_239
+    pub fn tether_bias_current_eq(v_adc: Millivolts) -> Microamps {
+        Microamps(((1011 - v_adc.0) * 50_750) / 10_239)
     }
-    pub fn cathode_offset_current_eq(v_adc_millivolts: u16) -> i32 {
-        // output in MICROamps
-        ((2576 - v_adc_millivolts as i32) * 883) / 500
+    pub fn cathode_offset_current_eq(v_adc: Millivolts) -> Microamps {
+        Microamps(((2576 - v_adc.0) * 883) / 500)
     }
 
-    pub fn aperture_current_sensor_eq(v_adc_millivolts: u16) -> u16 {
+    pub fn aperture_current_sensor_eq(v_adc: Millivolts) -> Microamps {
         // TODO: Does this need to be updated?
-        (((-(v_adc_millivolts as i32) + (40_000 / 9)) * 43) / 10) as u16
+        Microamps(((-v_adc.0 + (40_000 / 9)) * 43) / 10)
     }
 
-    pub fn pinpuller_current_sensor_eq(v_adc_millivolts: u16) -> u16 {
+    pub fn pinpuller_current_sensor_eq(v_adc: Millivolts) -> Milliamps {
         // 832/625 offset added to tune pinpuller
-        ((v_adc_millivolts as u32 * 1000 * 832) / (1804 * 625)) as u16
+        Milliamps(((v_adc.0 as i64 * 1000 * 832) / (1804 * 625)) as i32)
     }
 
-    //Returns temperature in Kelvin
-    pub fn payload_temperature_eq(v_adc_millivolts: u16) -> u16 {
-        generic_temperature_eq(v_adc_millivolts, 5000)
+    pub fn payload_temperature_eq(v_adc: Millivolts) -> Kelvin {
+        generic_temperature_eq(v_adc, Millivolts(5000))
     }
-    pub fn lms_temperature_eq(v_adc_millivolts: u16) -> u16 {
-        generic_temperature_eq(v_adc_millivolts, 3300)
+    pub fn lms_temperature_eq(v_adc: Millivolts) -> Kelvin {
+        generic_temperature_eq(v_adc, Millivolts(3300))
     }
-    fn generic_temperature_eq(v_adc_millivolts: u16, vcc: u16) -> u16 {
+    fn generic_temperature_eq(v_adc: Millivolts, vcc: Millivolts) -> Kelvin {
         // ln(R_t) = ln( 10_000 * adc_voltage / (vcc - adc_voltage) )
-        let ln_resistance = (10_000 * FixedI64::<32>::from(v_adc_millivolts))
-            .checked_div((vcc - v_adc_millivolts).into())
+        let ln_resistance = (10_000 * FixedI64::<32>::from(v_adc.0))
+            .checked_div((vcc.0 - v_adc.0).into())
             .and_then(checked_ln)
             .unwrap_or(FixedI64::ZERO);
 
         // 1,028,000 / (705 + 298*ln(R_t))
-        FixedI64::<32>::from(1_028_000)
-            .checked_div(FixedI64::<32>::from(705) + 298 * ln_resistance)
-            .map(|t| t.saturating_to_num())
-            .unwrap_or(0)
+        Kelvin(
+            FixedI64::<32>::from(1_028_000)
+                .checked_div(FixedI64::<32>::from(705) + 298 * ln_resistance)
+                .map(|t| t.saturating_to_num())
+                .unwrap_or(0),
+        )
     }
     // Cheap first order natural log approximation. Good to maybe 2dp.
     // Can improve by replacing 2t with  2(t+t^3/3+t^5/5+...)
@@ -263,28 +299,35 @@ pub mod sensor_equations {
 
 /* Supply control equations */
 pub mod power_supply_equations {
-    use super::*;
-    use fixed::FixedI64;
+    use crate::units::{Millivolts, Ohms};
 
     const R118_OHMS: u32 = 30_080;
     //NOTE: This is the inverse of the below function. These two equations should be kept in sync.
-    pub fn heater_target_voltage_to_digipot_resistance(millivolts: u16) -> u32 {
-        ((millivolts as u32 - 21) * R118_OHMS) / 794 - R118_OHMS
+    pub fn heater_target_voltage_to_digipot_resistance(target: Millivolts) -> Ohms {
+        Ohms((((target.0 - 21).max(0) as u32 * R118_OHMS) / 794).saturating_sub(R118_OHMS))
     }
     //NOTE: This is the inverse of the above function. These two equations should be kept in sync.
-    pub const fn digipot_resistance_to_heater_voltage_mv(resistance: u32) -> u16 {
-        ((resistance * 794) / R118_OHMS + 794 + 21) as u16
+    pub const fn digipot_resistance_to_heater_voltage(resistance: Ohms) -> Millivolts {
+        Millivolts(((resistance.0 * 794) / R118_OHMS + 794 + 21) as i32)
+    }
+    // Output is the DAC voltage. Negative for targets below what the supply can produce.
+    //NOTE: The dac_voltage_to_* functions below are the inverses of these two. They should be kept in sync.
+    pub fn tether_bias_target_voltage_to_dac_voltage(target: Millivolts) -> Millivolts {
+        Millivolts((target.0 - 1215) * 100 / 5249)
+    }
+    pub fn cathode_offset_target_voltage_to_dac_voltage(target: Millivolts) -> Millivolts {
+        //Millivolts(target.0 / 51) // ideal
+        Millivolts((target.0 * 100) / 5020)
     }
-    pub fn tether_bias_target_voltage_to_dac_voltage(millivolts: u32) -> u16 {
-        ((millivolts - 1215) * 100 / 5249) as u16
+    pub fn dac_voltage_to_tether_bias_voltage(dac_voltage: Millivolts) -> Millivolts {
+        Millivolts(dac_voltage.0 * 5249 / 100 + 1215)
     }
-    pub fn cathode_offset_target_voltage_to_dac_voltage(millivolts: u32) -> u16 {
-        //(millivolts / 51) as u16 // ideal
-        ((millivolts * 100) / 5020) as u16
+    pub fn dac_voltage_to_cathode_offset_voltage(dac_voltage: Millivolts) -> Millivolts {
+        Millivolts((dac_voltage.0 * 5020) / 100)
     }
 }
 
-pub const TETHER_SENSE_RESISTANCE_OHMS: u32 = 1;
-pub const CATHODE_SENSE_RESISTANCE_OHMS: u32 = 1;
-pub const APERTURE_SENSE_RESISTANCE_OHMS: u32 = 1;
+pub const TETHER_SENSE_RESISTANCE_OHMS: Ohms = Ohms(1);
+pub const CATHODE_SENSE_RESISTANCE_OHMS: Ohms = Ohms(1);
+pub const APERTURE_SENSE_RESISTANCE_OHMS: Ohms = Ohms(1);
 pub const HEATER_SENSE_RESISTANCE_MILLIOHMS: u32 = 10;
@@ -0,0 +1,210 @@
+// This file keeps track of which switchable sub-circuits (power domains) on the payload are powered, when they were powered up, and whether they've had time to warm up.
+// Time comes from a dedicated free-running timer, so warm-up waits don't disturb Payload::timer (which the TVAC loop uses for its one second tick).
+
+#[cfg(target_arch = "msp430")]
+use msp430fr2355::TB1;
+#[cfg(target_arch = "msp430")]
+use msp430fr2x5x_hal::timer::Timer;
+use ufmt::{uDisplay, uWrite, uwrite, Formatter};
+
+/// The power domain timer runs from ACLK (32768Hz) divided by 64.
+pub const POWER_TIMER_HZ: u32 = 512;
+/// Counts between timer wraparounds. The timer is run with its period set to the full 16 bits.
+const TIMER_PERIOD: u32 = 1 << 16;
+
+pub const NUM_POWER_DOMAINS: usize = 4;
+
+/// The parts of a free-running 16-bit timer PowerDomains needs.
+pub trait PowerTimer {
+    /// Start counting up from zero, wrapping after period.
+    fn start(&mut self, period: u16);
+    fn count(&mut self) -> u16;
+}
+#[cfg(target_arch = "msp430")]
+impl PowerTimer for Timer<TB1> {
+    fn start(&mut self, period: u16) {
+        Timer::start(self, period)
+    }
+    fn count(&mut self) -> u16 {
+        Timer::count(self)
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum PowerDomain {
+    /// Aperture ADC and current sense circuitry. Powered while the aperture ADC's CS pin is low.
+    Aperture = 0,
+    LMSReceivers = 1,
+    LMSLEDs = 2,
+    /// Switched by Payload::into_enabled_heater and into_disabled_heater.
+    Heater = 3,
+}
+impl PowerDomain {
+    pub const ALL: [PowerDomain; NUM_POWER_DOMAINS] = [PowerDomain::Aperture, PowerDomain::LMSReceivers, PowerDomain::LMSLEDs, PowerDomain::Heater];
+    /// How long the domain must be powered before its readings can be trusted.
+    pub const fn warm_up_millis(self) -> u32 {
+        match self {
+            PowerDomain::Aperture => 5,
+            PowerDomain::LMSReceivers => 100,
+            PowerDomain::LMSLEDs => 100,
+            PowerDomain::Heater => 100, // TODO: Verify
+        }
+    }
+    pub const fn name(self) -> &'static str {
+        match self {
+            PowerDomain::Aperture => "Aperture",
+            PowerDomain::LMSReceivers => "LMS receivers",
+            PowerDomain::LMSLEDs => "LMS LEDs",
+            PowerDomain::Heater => "Heater",
+        }
+    }
+}
+
+/// The domains that Payload::power_up and power_down control directly. The heater is switched through the HeaterState typestate instead.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum SwitchableDomain {
+    Aperture,
+    LMSReceivers,
+    LMSLEDs,
+}
+impl From<SwitchableDomain> for PowerDomain {
+    fn from(domain: SwitchableDomain) -> PowerDomain {
+        match domain {
+            SwitchableDomain::Aperture => PowerDomain::Aperture,
+            SwitchableDomain::LMSReceivers => PowerDomain::LMSReceivers,
+            SwitchableDomain::LMSLEDs => PowerDomain::LMSLEDs,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum PowerError {
+    DomainOff,
+}
+
+/// Set of domains that are powered and warmed up, from PowerDomains::live_domains. Prints as a comma separated list of names.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct LiveDomains(u8);
+impl LiveDomains {
+    pub fn contains(&self, domain: PowerDomain) -> bool {
+        self.0 & (1 << domain as u8) != 0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    /// One bit per domain, bit n set if the domain with discriminant n is live.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+}
+impl uDisplay for LiveDomains {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        if self.is_empty() {
+            return uwrite!(f, "none");
+        }
+        let mut first = true;
+        for domain in PowerDomain::ALL.iter().filter(|domain| self.contains(**domain)) {
+            if !first {
+                uwrite!(f, ", ")?;
+            }
+            uwrite!(f, "{}", domain.name())?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the power state of each domain. This doesn't own any pins, Payload switches them and records the change here.
+///
+/// The 16-bit timer wraps every two minutes, and is extended to 32 bits whenever the manager is used.
+/// If it goes unused for longer than that, power-up times will read short, but a domain that was already live stays live.
+pub struct PowerDomains<T: PowerTimer> {
+    timer: T,
+    last_count: u16,
+    wraps: u16,
+    // Timer ticks at power-up, None while off
+    powered_at: [Option<u32>; NUM_POWER_DOMAINS],
+    // Latched once a domain is seen to be warm, so a missed wraparound can't make a live domain look cold again
+    warm: [bool; NUM_POWER_DOMAINS],
+}
+impl<T: PowerTimer> PowerDomains<T> {
+    /// timer should be configured for POWER_TIMER_HZ, i.e. ACLK with TimerDiv::_8 and TimerExDiv::_8.
+    pub fn new(mut timer: T) -> PowerDomains<T> {
+        timer.start(u16::MAX);
+        PowerDomains { timer, last_count: 0, wraps: 0, powered_at: [None; NUM_POWER_DOMAINS], warm: [false; NUM_POWER_DOMAINS] }
+    }
+    /// Ticks of POWER_TIMER_HZ since the manager was created.
+    pub fn now(&mut self) -> u32 {
+        let count = self.timer.count();
+        if count < self.last_count {
+            self.wraps = self.wraps.wrapping_add(1);
+        }
+        self.last_count = count;
+        self.wraps as u32 * TIMER_PERIOD + count as u32
+    }
+    /// Milliseconds since the manager was created.
+    pub fn uptime_millis(&mut self) -> u32 {
+        ticks_to_millis(self.now())
+    }
+    /// Milliseconds since tick, a value previously returned by now(). Rounds down.
+    pub fn millis_since(&mut self, tick: u32) -> u32 {
+        ticks_to_millis(self.now().wrapping_sub(tick))
+    }
+    /// Busy-wait using the power timer.
+    pub fn wait_millis(&mut self, millis: u32) {
+        let start = self.now();
+        while self.millis_since(start) < millis {}
+    }
+    /// Call after switching a domain on. Powering up a domain that's already on keeps the original power-up time.
+    pub fn record_power_up(&mut self, domain: PowerDomain) {
+        if self.powered_at[domain as usize].is_none() {
+            self.powered_at[domain as usize] = Some(self.now());
+            self.warm[domain as usize] = false;
+        }
+    }
+    /// Call after switching a domain off.
+    pub fn record_power_down(&mut self, domain: PowerDomain) {
+        self.powered_at[domain as usize] = None;
+        self.warm[domain as usize] = false;
+    }
+    pub fn is_on(&self, domain: PowerDomain) -> bool {
+        self.powered_at[domain as usize].is_some()
+    }
+    /// None while the domain is off. This is a lower bound, as the domain may have been switched on late in the tick it was recorded in.
+    pub fn millis_since_power_up(&mut self, domain: PowerDomain) -> Option<u32> {
+        let powered_at = self.powered_at[domain as usize]?;
+        let ticks = self.now().wrapping_sub(powered_at).saturating_sub(1);
+        Some(ticks_to_millis(ticks))
+    }
+    /// Powered, and powered for at least its warm-up time.
+    pub fn is_live(&mut self, domain: PowerDomain) -> bool {
+        if self.warm[domain as usize] {
+            return true;
+        }
+        let warm = self.millis_since_power_up(domain).is_some_and(|millis| millis >= domain.warm_up_millis());
+        self.warm[domain as usize] = warm;
+        warm
+    }
+    /// Block until the domain has warmed up. Returns immediately if it already has.
+    pub fn wait_until_live(&mut self, domain: PowerDomain) -> Result<(), PowerError> {
+        if !self.is_on(domain) {
+            return Err(PowerError::DomainOff);
+        }
+        while !self.is_live(domain) {}
+        Ok(())
+    }
+    pub fn live_domains(&mut self) -> LiveDomains {
+        let mut live = LiveDomains::default();
+        for domain in PowerDomain::ALL {
+            if self.is_live(domain) {
+                live.0 |= 1 << domain as u8;
+            }
+        }
+        live
+    }
+}
+
+// Rounds down, so a domain is never reported warm early. Split up so ticks * 1000 can't overflow.
+fn ticks_to_millis(ticks: u32) -> u32 {
+    (ticks / POWER_TIMER_HZ) * 1000 + (ticks % POWER_TIMER_HZ) * 1000 / POWER_TIMER_HZ
+}
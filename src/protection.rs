@@ -0,0 +1,257 @@
+// This file contains the overcurrent/overtemperature protection checked against every housekeeping snapshot, see Payload::get_housekeeping.
+// It only decides whether a limit has tripped and remembers the first trip. The payload carries out the shutdown.
+
+use crate::housekeeping::{Housekeeping, ReadingGroup, ValidReadings, NUM_READING_GROUPS, NUM_TEMPERATURE_SENSORS};
+use crate::pcb_mapping::power_supply_limits::*;
+use crate::units::{Kelvin, Microamps, Milliamps};
+
+// A single failed read is usually a glitch, so a required group has to be missing from this many snapshots in a row before it trips.
+pub const DEFAULT_MAX_MISSED_READINGS: u8 = 3;
+
+/// Trip thresholds. Currents are magnitudes. Readings from invalid groups are never checked against the limits, they count towards max_missed_readings instead.
+#[derive(Copy, Clone, Debug)]
+pub struct ProtectionLimits {
+    pub heater_max_current: Milliamps,
+    pub tether_bias_max_current: Microamps,
+    pub cathode_offset_max_current: Microamps,
+    pub max_temperature: Kelvin,
+    /// Consecutive snapshots a required group can be invalid for before SensorLost trips.
+    pub max_missed_readings: u8,
+}
+impl Default for ProtectionLimits {
+    fn default() -> Self {
+        ProtectionLimits {
+            heater_max_current: HEATER_TRIP_CURRENT_MILLIAMPS,
+            tether_bias_max_current: TETHER_BIAS_TRIP_CURRENT_MICROAMPS,
+            cathode_offset_max_current: CATHODE_OFFSET_TRIP_CURRENT_MICROAMPS,
+            max_temperature: MAX_BOARD_TEMPERATURE_KELVIN,
+            max_missed_readings: DEFAULT_MAX_MISSED_READINGS,
+        }
+    }
+}
+
+/// Which limit tripped. Each carries the reading that tripped it.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub enum ProtectionTrip {
+    HeaterOverCurrent(Milliamps),
+    TetherBiasOverCurrent(Microamps),
+    CathodeOffsetOverCurrent(Microamps),
+    /// Index into housekeeping::TEMPERATURE_SENSORS.
+    OverTemperature(usize, Kelvin),
+    /// A group required in the current state was invalid for max_missed_readings snapshots in a row, so its limits couldn't be checked.
+    SensorLost(ReadingGroup),
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, ufmt::derive::uDebug)]
+pub struct ProtectionFault {
+    pub trip: ProtectionTrip,
+    /// Timestamp of the housekeeping snapshot that tripped.
+    pub timestamp_millis: u32,
+}
+
+/// Limits, plus the first fault seen. Once latched the fault stays until clear_fault is called, even if the readings recover.
+#[derive(Copy, Clone, Debug)]
+pub struct Protection {
+    pub limits: ProtectionLimits,
+    latched: Option<ProtectionFault>,
+    // Consecutive snapshots each group has been invalid for, indexed by discriminant
+    missed: [u8; NUM_READING_GROUPS],
+}
+impl Protection {
+    pub fn new(limits: ProtectionLimits) -> Protection {
+        Protection { limits, latched: None, missed: [0; NUM_READING_GROUPS] }
+    }
+    /// Check a snapshot against the limits, and that every group in required was valid recently enough.
+    /// Returns the newly tripped fault, or None if nothing tripped or a fault was already latched. Limit trips take precedence over SensorLost.
+    pub fn check(&mut self, housekeeping: &Housekeeping, required: ValidReadings) -> Option<ProtectionFault> {
+        if self.latched.is_some() {
+            return None;
+        }
+        for group in ReadingGroup::ALL {
+            let missed = &mut self.missed[group as usize];
+            *missed = if housekeeping.valid.contains(group) { 0 } else { missed.saturating_add(1) };
+        }
+        let trip = self.find_trip(housekeeping).or_else(|| self.find_lost_sensor(required))?;
+        let fault = ProtectionFault { trip, timestamp_millis: housekeeping.timestamp_millis };
+        self.latched = Some(fault);
+        Some(fault)
+    }
+    pub fn latched_fault(&self) -> Option<ProtectionFault> {
+        self.latched
+    }
+    /// Forget the latched fault and the missed reading counts, e.g. once the cause has been dealt with. Returns the fault that was latched.
+    pub fn clear_fault(&mut self) -> Option<ProtectionFault> {
+        self.missed = [0; NUM_READING_GROUPS];
+        self.latched.take()
+    }
+    fn find_trip(&self, housekeeping: &Housekeeping) -> Option<ProtectionTrip> {
+        let limits = &self.limits;
+        if housekeeping.valid.contains(ReadingGroup::TetherADC) {
+            let tether = &housekeeping.tether;
+            if tether.heater_current_milliamps.0.abs() > limits.heater_max_current.0 {
+                return Some(ProtectionTrip::HeaterOverCurrent(tether.heater_current_milliamps));
+            }
+            if tether.tether_bias_current_microamps.0.abs() > limits.tether_bias_max_current.0 {
+                return Some(ProtectionTrip::TetherBiasOverCurrent(tether.tether_bias_current_microamps));
+            }
+            if tether.cathode_offset_current_microamps.0.abs() > limits.cathode_offset_max_current.0 {
+                return Some(ProtectionTrip::CathodeOffsetOverCurrent(tether.cathode_offset_current_microamps));
+            }
+        }
+        if housekeeping.valid.contains(ReadingGroup::Temperatures) {
+            let n = (0..NUM_TEMPERATURE_SENSORS).find(|&n| housekeeping.temperatures[n] > limits.max_temperature)?;
+            return Some(ProtectionTrip::OverTemperature(n, housekeeping.temperatures[n]));
+        }
+        None
+    }
+    fn find_lost_sensor(&self, required: ValidReadings) -> Option<ProtectionTrip> {
+        ReadingGroup::ALL.into_iter()
+            .find(|&group| required.contains(group) && self.missed[group as usize] >= self.limits.max_missed_readings.max(1))
+            .map(ProtectionTrip::SensorLost)
+    }
+}
+impl Default for Protection {
+    fn default() -> Self {
+        Protection::new(ProtectionLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::{SwitchState, TetherADCReadings};
+    use crate::units::Millivolts;
+
+    const BOTH_REQUIRED: u8 = (1 << ReadingGroup::Temperatures as u8) | (1 << ReadingGroup::TetherADC as u8);
+
+    fn readings(groups: u8) -> ValidReadings {
+        let mut valid = ValidReadings::default();
+        for group in ReadingGroup::ALL.into_iter().filter(|&group| groups & (1 << group as u8) != 0) {
+            valid.insert(group);
+        }
+        valid
+    }
+    // Room temperature, no current flowing, every group valid
+    fn nominal() -> Housekeeping {
+        Housekeeping {
+            timestamp_millis: 1000, valid: readings(0xFF), temperatures: [Kelvin(293); NUM_TEMPERATURE_SENSORS],
+            tether: TetherADCReadings::default(), aperture_current_microamps: Microamps(0), pinpuller_current_milliamps: Milliamps(0),
+            lms_receivers_millivolts: [Millivolts::ZERO; 3], cathode_offset_switch: SwitchState::Disconnected, tether_bias_switch: SwitchState::Disconnected,
+            endmass_sense_1: false, endmass_sense_2: false, pinpuller_sense: false,
+        }
+    }
+    fn trip_of(housekeeping: Housekeeping) -> Option<ProtectionTrip> {
+        Protection::default().check(&housekeeping, readings(BOTH_REQUIRED)).map(|fault| fault.trip)
+    }
+
+    #[test]
+    fn nominal_readings_dont_trip() {
+        assert_eq!(trip_of(nominal()), None);
+    }
+
+    #[test]
+    fn each_limit_trips() {
+        let limits = ProtectionLimits::default();
+        let mut housekeeping = nominal();
+        housekeeping.tether.heater_current_milliamps = Milliamps(limits.heater_max_current.0 + 1);
+        assert_eq!(trip_of(housekeeping), Some(ProtectionTrip::HeaterOverCurrent(housekeeping.tether.heater_current_milliamps)));
+
+        // Currents are magnitudes, so a negative reading trips too
+        let mut housekeeping = nominal();
+        housekeeping.tether.tether_bias_current_microamps = Microamps(-limits.tether_bias_max_current.0 - 1);
+        assert_eq!(trip_of(housekeeping), Some(ProtectionTrip::TetherBiasOverCurrent(housekeeping.tether.tether_bias_current_microamps)));
+
+        let mut housekeeping = nominal();
+        housekeeping.tether.cathode_offset_current_microamps = Microamps(limits.cathode_offset_max_current.0 + 1);
+        assert_eq!(trip_of(housekeeping), Some(ProtectionTrip::CathodeOffsetOverCurrent(housekeeping.tether.cathode_offset_current_microamps)));
+
+        let mut housekeeping = nominal();
+        housekeeping.temperatures[4] = Kelvin(limits.max_temperature.0 + 1);
+        assert_eq!(trip_of(housekeeping), Some(ProtectionTrip::OverTemperature(4, housekeeping.temperatures[4])));
+
+        // Readings at the limit are fine
+        let mut housekeeping = nominal();
+        housekeeping.tether.heater_current_milliamps = limits.heater_max_current;
+        housekeeping.temperatures[0] = limits.max_temperature;
+        assert_eq!(trip_of(housekeeping), None);
+    }
+
+    #[test]
+    fn invalid_groups_arent_checked_against_limits() {
+        let mut housekeeping = nominal();
+        housekeeping.valid = readings(1 << ReadingGroup::Temperatures as u8);
+        housekeeping.tether.heater_current_milliamps = Milliamps(i16::MAX as i32);
+        let mut protection = Protection::default();
+        assert_eq!(protection.check(&housekeeping, readings(1 << ReadingGroup::Temperatures as u8)), None);
+    }
+
+    #[test]
+    fn sensor_lost_after_consecutive_misses() {
+        let mut protection = Protection::default();
+        let mut housekeeping = nominal();
+        housekeeping.valid = readings(1 << ReadingGroup::Temperatures as u8);
+        for _ in 1..DEFAULT_MAX_MISSED_READINGS {
+            assert_eq!(protection.check(&housekeeping, readings(BOTH_REQUIRED)), None);
+        }
+        let fault = protection.check(&housekeeping, readings(BOTH_REQUIRED)).unwrap();
+        assert_eq!(fault.trip, ProtectionTrip::SensorLost(ReadingGroup::TetherADC));
+        assert_eq!(fault.timestamp_millis, housekeeping.timestamp_millis);
+    }
+
+    #[test]
+    fn valid_reading_resets_missed_count() {
+        let mut protection = Protection::default();
+        let mut missing = nominal();
+        missing.valid = readings(1 << ReadingGroup::TetherADC as u8);
+        for _ in 0..10 {
+            for _ in 1..DEFAULT_MAX_MISSED_READINGS {
+                assert_eq!(protection.check(&missing, readings(BOTH_REQUIRED)), None);
+            }
+            assert_eq!(protection.check(&nominal(), readings(BOTH_REQUIRED)), None);
+        }
+    }
+
+    #[test]
+    fn only_required_groups_can_be_lost() {
+        let mut protection = Protection::default();
+        let mut housekeeping = nominal();
+        housekeeping.valid = readings(1 << ReadingGroup::Temperatures as u8);
+        for _ in 0..2 * DEFAULT_MAX_MISSED_READINGS {
+            assert_eq!(protection.check(&housekeeping, readings(1 << ReadingGroup::Temperatures as u8)), None);
+        }
+        // The misses still counted while it wasn't required
+        assert_eq!(protection.check(&housekeeping, readings(BOTH_REQUIRED)).map(|fault| fault.trip), Some(ProtectionTrip::SensorLost(ReadingGroup::TetherADC)));
+    }
+
+    #[test]
+    fn fault_stays_latched_until_cleared() {
+        let mut protection = Protection::default();
+        let mut overheated = nominal();
+        overheated.temperatures[0] = Kelvin(500);
+        let fault = protection.check(&overheated, readings(BOTH_REQUIRED)).unwrap();
+
+        // Only reported once, and later trips don't replace it, even once the readings recover
+        let mut overcurrent = nominal();
+        overcurrent.tether.heater_current_milliamps = Milliamps(5000);
+        assert_eq!(protection.check(&overcurrent, readings(BOTH_REQUIRED)), None);
+        assert_eq!(protection.check(&nominal(), readings(BOTH_REQUIRED)), None);
+        assert_eq!(protection.latched_fault(), Some(fault));
+
+        assert_eq!(protection.clear_fault(), Some(fault));
+        assert_eq!(protection.latched_fault(), None);
+        assert_eq!(protection.check(&nominal(), readings(BOTH_REQUIRED)), None);
+        assert_eq!(protection.check(&overcurrent, readings(BOTH_REQUIRED)).map(|fault| fault.trip), Some(ProtectionTrip::HeaterOverCurrent(Milliamps(5000))));
+    }
+
+    #[test]
+    fn clear_forgets_missed_readings() {
+        let mut protection = Protection::default();
+        let mut missing = nominal();
+        missing.valid = readings(1 << ReadingGroup::TetherADC as u8);
+        for _ in 0..DEFAULT_MAX_MISSED_READINGS {
+            protection.check(&missing, readings(BOTH_REQUIRED));
+        }
+        assert_eq!(protection.clear_fault().map(|fault| fault.trip), Some(ProtectionTrip::SensorLost(ReadingGroup::Temperatures)));
+        assert_eq!(protection.check(&missing, readings(BOTH_REQUIRED)), None);
+    }
+}
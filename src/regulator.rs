@@ -0,0 +1,114 @@
+// This file contains a small integer PI controller, used to trim open-loop supply setpoints using ADC feedback.
+// It only does the arithmetic. Payload owns the read-adjust-settle loop for each supply.
+
+/// Correction = (kp_percent * error + ki_percent * accumulated error) / 100, limited to +/- max_correction.
+///
+/// Anti-windup: the accumulated error is clamped so the integral term alone can't exceed max_correction,
+/// and it stops accumulating while the output is saturated unless the new error would pull it back into range.
+#[derive(Copy, Clone, Debug)]
+pub struct PIController {
+    kp_percent: i32,
+    ki_percent: i32,
+    max_correction: i32,
+    integral: i32,
+    saturated: bool,
+}
+impl PIController {
+    pub const fn new(kp_percent: i32, ki_percent: i32, max_correction: i32) -> PIController {
+        PIController { kp_percent, ki_percent, max_correction, integral: 0, saturated: false }
+    }
+    /// Forget the accumulated error, e.g. after the supply has been switched off.
+    pub fn reset(&mut self) {
+        self.integral = 0;
+        self.saturated = false;
+    }
+    /// Feed in the latest error (target - measured) and get the correction to add to the open-loop setpoint.
+    pub fn update(&mut self, error: i32) -> i32 {
+        let candidate = self.integral.saturating_add(error);
+        let winding_down = candidate.unsigned_abs() < self.integral.unsigned_abs();
+        if !self.saturated || winding_down {
+            let max_integral = match self.ki_percent {
+                0 => 0,
+                ki => self.max_correction.saturating_mul(100) / ki.abs(),
+            };
+            self.integral = candidate.clamp(-max_integral, max_integral);
+        }
+        let correction = (self.kp_percent.saturating_mul(error)).saturating_add(self.ki_percent.saturating_mul(self.integral)) / 100;
+        self.saturated = correction.unsigned_abs() >= self.max_correction.unsigned_abs();
+        correction.clamp(-self.max_correction, self.max_correction)
+    }
+    /// Whether the last correction hit max_correction.
+    pub fn is_saturated(&self) -> bool {
+        self.saturated
+    }
+    /// Current integral term, i.e. the correction that would remain with zero error.
+    pub fn integral_correction(&self) -> i32 {
+        self.ki_percent.saturating_mul(self.integral) / 100
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrections_are_clamped() {
+        let mut controller = PIController::new(100, 0, 50);
+        assert_eq!(controller.update(20), 20);
+        assert!(!controller.is_saturated());
+        assert_eq!(controller.update(1000), 50);
+        assert!(controller.is_saturated());
+        assert_eq!(controller.update(-1000), -50);
+        assert_eq!(controller.update(i32::MIN), -50);
+    }
+
+    #[test]
+    fn integral_alone_cant_exceed_max_correction() {
+        let mut controller = PIController::new(0, 10, 50);
+        for _ in 0..1000 {
+            controller.update(100);
+        }
+        assert_eq!(controller.integral_correction(), 50);
+        // Proportional-only controllers have nothing to wind up
+        let mut controller = PIController::new(100, 0, 50);
+        controller.update(1000);
+        assert_eq!(controller.integral_correction(), 0);
+    }
+
+    #[test]
+    fn integral_holds_while_saturated() {
+        // The proportional term alone saturates, so the integral must not grow behind it
+        let mut controller = PIController::new(100, 10, 50);
+        controller.update(10);
+        assert_eq!(controller.update(100), 50);
+        let integral = controller.integral_correction();
+        for _ in 0..20 {
+            assert_eq!(controller.update(100), 50);
+        }
+        assert_eq!(controller.integral_correction(), integral);
+    }
+
+    #[test]
+    fn saturated_integral_can_wind_down() {
+        let mut controller = PIController::new(0, 50, 50);
+        while !controller.is_saturated() {
+            controller.update(40);
+        }
+        let wound_up = controller.integral_correction();
+        // An error the other way pulls the correction back straight away, rather than waiting to unwind an oversized integral
+        let correction = controller.update(-40);
+        assert!(correction < wound_up, "{} vs {}", correction, wound_up);
+        assert!(controller.integral_correction() < wound_up);
+    }
+
+    #[test]
+    fn reset_forgets_the_integral() {
+        let mut controller = PIController::new(0, 50, 50);
+        controller.update(40);
+        controller.update(40);
+        controller.reset();
+        assert_eq!(controller.integral_correction(), 0);
+        assert!(!controller.is_saturated());
+        assert_eq!(controller.update(0), 0);
+    }
+}
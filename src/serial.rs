@@ -2,8 +2,10 @@ use core::cell::{RefCell, UnsafeCell};
 
 use critical_section::Mutex;
 use embedded_hal::serial::{Write, Read};
+#[cfg(target_arch = "msp430")]
 use msp430fr2355::E_USCI_A1;
-use msp430fr2x5x_hal::serial::{SerialUsci, Tx, Rx};
+#[cfg(target_arch = "msp430")]
+use msp430fr2x5x_hal::serial::{SerialUsci, Tx};
 use ufmt::{uWrite, uwrite, uwriteln, uDisplay};
 use void::Void;
 
@@ -35,7 +37,15 @@ macro_rules! uwrite_coloured {
     }
 }
 
-pub static SERIAL_WR: Mutex<UnsafeCell<Option< SerialWriter<E_USCI_A1> >>> = Mutex::new(UnsafeCell::new(None));
+// Where println! and friends end up. On the MSP430 this is the debug serial port, which main.rs sets up.
+#[cfg(target_arch = "msp430")]
+pub type DebugWriter = SerialWriter<E_USCI_A1>;
+#[cfg(target_arch = "msp430")]
+pub static SERIAL_WR: Mutex<UnsafeCell<Option< DebugWriter >>> = Mutex::new(UnsafeCell::new(None));
+#[cfg(not(target_arch = "msp430"))]
+pub type DebugWriter = HostWriter;
+#[cfg(not(target_arch = "msp430"))]
+pub static SERIAL_WR: Mutex<UnsafeCell<Option< DebugWriter >>> = Mutex::new(UnsafeCell::new(Some(HostWriter)));
 
 #[macro_export]
 macro_rules! println {
@@ -88,9 +98,11 @@ pub enum TextColours {
     Yellow,
 }
 
+#[cfg(target_arch = "msp430")]
 pub struct SerialWriter<USCI: SerialUsci>{
     serial: Tx<USCI>
 }
+#[cfg(target_arch = "msp430")]
 impl<USCI: SerialUsci> SerialWriter<USCI>{
     pub fn new(serial: Tx<USCI>) -> SerialWriter<USCI> {
         SerialWriter{serial}
@@ -99,6 +111,7 @@ impl<USCI: SerialUsci> SerialWriter<USCI>{
         self.serial
     }
 }
+#[cfg(target_arch = "msp430")]
 impl<USCI: SerialUsci> uWrite for SerialWriter<USCI>{
     type Error = nb::Error<Void>;
     fn write_char(&mut self, c: char) -> Result<(), Self::Error>{
@@ -114,6 +127,28 @@ impl<USCI: SerialUsci> uWrite for SerialWriter<USCI>{
     }
 }
 
+/// Stands in for the debug serial port on the host. Under 'cargo test' output goes to the test harness, which shows it for failing tests.
+#[cfg(not(target_arch = "msp430"))]
+pub struct HostWriter;
+#[cfg(not(target_arch = "msp430"))]
+impl uWrite for HostWriter{
+    type Error = Void;
+    fn write_str(&mut self, string: &str) -> Result<(), Self::Error> {
+        #[cfg(test)]
+        std::print!("{}", string);
+        Ok(())
+    }
+}
+
+/// Serial input for when eUSCI_A1 is the OBC bus rather than the debug serial port (the obc_spi feature). Nothing ever arrives.
+pub struct NoSerialInput;
+impl Read<u8> for NoSerialInput{
+    type Error = Void;
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
 /*  Fixed point numbers from the 'fixed' library do not implement uDisplay from the 'ufmt' library
     We can't implement an external trait on an external struct.
     Instead, we make a trait Printable which can be implemented on fixed numbers by calling x.to_prnt()
@@ -162,7 +197,7 @@ impl<const N: i32> Printable<N> for fixed::FixedI64::<N> {
 }
 
 // Block until we receive any packet over serial
-pub fn wait_for_any_packet<USCI: SerialUsci>(serial_reader: &mut Rx<USCI>) -> u8{
+pub fn wait_for_any_packet(serial_reader: &mut impl Read<u8>) -> u8{
     loop {
         if let Ok(packet) = serial_reader.read(){
             return packet;
@@ -170,17 +205,17 @@ pub fn wait_for_any_packet<USCI: SerialUsci>(serial_reader: &mut Rx<USCI>) -> u8
     }
 }
 // Block until we receive the specified character
-pub fn wait_for_character<USCI: SerialUsci>(wanted_char: u8, serial_reader: &mut Rx<USCI>) {
+pub fn wait_for_character(wanted_char: u8, serial_reader: &mut impl Read<u8>) {
     while wait_for_any_packet(serial_reader) != wanted_char {}
 }
-pub fn wait_for_string<USCI: SerialUsci>(wanted_str: &str, serial_reader: &mut Rx<USCI>) {
+pub fn wait_for_string(wanted_str: &str, serial_reader: &mut impl Read<u8>) {
     for chr in wanted_str.as_bytes(){
         wait_for_character(*chr, serial_reader);
     }
 }
 
 // Query the user for a number. Return None if invalid.
-pub fn maybe_read_num<USCI: SerialUsci>(serial_reader: &mut Rx<USCI>) -> Option<i32> {
+pub fn maybe_read_num(serial_reader: &mut impl Read<u8>) -> Option<i32> {
     let mut num: i32 = 0;
     let mut sign = 1;
     // First character needs to be treated differently since '-' makes a number negative when first, but is invalid in other places.
@@ -202,7 +237,7 @@ pub fn maybe_read_num<USCI: SerialUsci>(serial_reader: &mut Rx<USCI>) -> Option<
 }
 
 // Repeatedly queries the user to input a number until a valid one is received.
-pub fn read_num<USCI: SerialUsci>(serial_reader: &mut Rx<USCI> ) -> i32 {
+pub fn read_num(serial_reader: &mut impl Read<u8>) -> i32 {
     loop {
         match maybe_read_num(serial_reader) {
             Some(n) => return n,
@@ -1,7 +1,15 @@
+#[cfg(target_arch = "msp430")]
 use crate::pcb_mapping::{OBCSPIPins, PayloadSPIPins, pin_name_types::{PayloadMOSIBitBangPin, PayloadMISOBitBangPin, PayloadSCKBitBangPin}, PayloadSPIBitBangPins};
 use embedded_hal::digital::v2::{OutputPin, ToggleableOutputPin, InputPin};
-use msp430fr2x5x_hal::gpio::*;
+use embedded_hal::spi::FullDuplex;
+use ehal::spi::{Mode, Phase, Polarity};
+#[cfg(target_arch = "msp430")]
+use msp430fr2355::{E_USCI_A1, E_USCI_B1};
+#[cfg(target_arch = "msp430")]
+use msp430fr2x5x_hal::{clock::Smclk, gpio::*, spi::{Spi, SpiConfig, SpiErr, SpiSlave, StePolarity}};
+use nb::block;
 use crate::delay_cycles;
+use crate::obc::{FrameError, FrameType, OBCProtocol, Request, Response};
 
 // Trait because we can implement by either bitbanging or using peripheral
 // Separate traits befause OBC_SPI might be expanded in the future (e.g. pin interrupts)
@@ -10,18 +18,64 @@ pub trait OBCSPI{
     fn receive(&mut self, len: u8) -> u32;
     fn send_receive(&mut self, len: u8, data: u32) -> u32;
 }
+/// A payload SPI bus in one fixed SCK polarity and phase.
+///
+/// Slices hold packets MSB-first: the first bit on the bus is the MSB of data[0], and bits past len are ignored.
+/// The u32 methods take and return right-aligned packets, i.e. the last bit on the bus is the LSB.
 pub trait PayloadSPI<const POLARITY: SckPolarity, const PHASE: SckPhase>{
-    /// Send a packet up to 32 bits long.
-    fn send(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin);
-    /// Receive a packet up to 32 bits long.
-    fn receive(&mut self, len: u8, cs_pin: &mut impl OutputPin) -> u32;
-    /// Send a packet up to 32 bits long while receiving another 32 at the same time (duplex).
-    fn send_receive(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) -> u32;
+    /// Send a packet len bits long. data must hold at least len bits.
+    fn send_slice(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin);
+    /// Receive a packet len bits long into result, which must hold at least len bits. The unused bits of the last byte are cleared.
+    fn receive_slice(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin);
+    /// Send a packet len bits long while receiving another at the same time (duplex).
+    fn send_receive_slice(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin);
+
+    /// Send a packet up to 32 bits long. Longer lengths are treated as 32.
+    fn send(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) {
+        let len = len.min(32);
+        self.send_slice(len as usize, &u32_to_bits(len, data), cs_pin)
+    }
+    /// Receive a packet up to 32 bits long. Longer lengths are treated as 32.
+    fn receive(&mut self, len: u8, cs_pin: &mut impl OutputPin) -> u32 {
+        let len = len.min(32);
+        let mut result = [0; 4];
+        self.receive_slice(len as usize, &mut result, cs_pin);
+        bits_to_u32(len, result)
+    }
+    /// Send a packet up to 32 bits long while receiving another 32 at the same time (duplex). Longer lengths are treated as 32.
+    fn send_receive(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) -> u32 {
+        let len = len.min(32);
+        let mut result = [0; 4];
+        self.send_receive_slice(len as usize, &u32_to_bits(len, data), &mut result, cs_pin);
+        bits_to_u32(len, result)
+    }
+}
+
+/// Convert a right-aligned packet of up to 32 bits into the MSB-first form used by the slice methods. Lengths over 32 are treated as 32.
+pub fn u32_to_bits(len: u8, data: u32) -> [u8; 4] {
+    data.checked_shl(32 - len.min(32) as u32).unwrap_or(0).to_be_bytes()
+}
+/// Convert the first len bits of an MSB-first packet into a right-aligned u32. Lengths over 32 are treated as 32.
+pub fn bits_to_u32(len: u8, bits: [u8; 4]) -> u32 {
+    u32::from_be_bytes(bits).checked_shr(32 - len.min(32) as u32).unwrap_or(0)
+}
+/// Whether bit n of an MSB-first packet is set.
+#[inline(always)]
+pub fn bit_is_set(bits: &[u8], n: usize) -> bool {
+    bits[n / 8] & (0x80 >> (n % 8)) != 0
+}
+#[inline(always)]
+pub fn set_bit(bits: &mut [u8], n: usize) {
+    bits[n / 8] |= 0x80 >> (n % 8);
+}
+/// Zero every byte that holds part of a len-bit packet.
+pub fn clear_bits(bits: &mut [u8], len: usize) {
+    bits[..len.div_ceil(8)].fill(0);
 }
 
 // Peripherals expect the bus left high or low when idle, and some read rising edges while others read falling edges.
 // Encode this in types so peripherals can enforce a correct configuration
-#[derive(PartialEq, Eq, core::marker::ConstParamTy)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, core::marker::ConstParamTy)]
 pub enum SckPolarity {
     IdleHigh,
     IdleLow,
@@ -29,7 +83,7 @@ pub enum SckPolarity {
 use SckPolarity::*;
 
 /// Not quite equivalent to standard SPI clock phase (i.e. first/second edge instead of rising/falling). All our devices use the first edge though, so it's easier this way.
-#[derive(PartialEq, Eq, core::marker::ConstParamTy)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, core::marker::ConstParamTy)]
 pub enum SckPhase {
     /// Read the bus on the first edge, write on the second.
     SampleFirstEdge,
@@ -38,20 +92,110 @@ pub enum SckPhase {
 }
 use SckPhase::*;
 
+/// Runtime equivalent of the polarity and phase typestates.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct BusMode {
+    pub polarity: SckPolarity,
+    pub phase: SckPhase,
+}
+impl BusMode {
+    pub const fn of<const POLARITY: SckPolarity, const PHASE: SckPhase>() -> BusMode {
+        BusMode{polarity: POLARITY, phase: PHASE}
+    }
+}
+
+/// A payload SPI bus that can switch modes at runtime without changing type. This is what lets PayloadSPIController lend out one bus in whichever mode a device needs.
+pub trait PayloadSPIAnyMode {
+    /// Reconfigure the bus for mode, leaving SCK at the idle level for that mode.
+    fn set_mode(&mut self, mode: BusMode);
+    /// As PayloadSPI::send_slice. The bus must already be in mode.
+    fn send_in_mode(&mut self, mode: BusMode, len: usize, data: &[u8], cs_pin: &mut impl OutputPin);
+    /// As PayloadSPI::receive_slice. The bus must already be in mode.
+    fn receive_in_mode(&mut self, mode: BusMode, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin);
+    /// As PayloadSPI::send_receive_slice. The bus must already be in mode.
+    fn send_receive_in_mode(&mut self, mode: BusMode, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin);
+}
+
+/// MCLK frequency set up in configure_board (and the DCO's default after reset).
+pub const DEFAULT_MCLK_HZ: u32 = 1_000_000;
+/// SCK frequency the bitbang buses run at unless told otherwise.
+pub const DEFAULT_BITBANG_SCK_HZ: u32 = 5_000;
+const MAX_MCLK_HZ: u32 = 24_000_000;
+/// Rough number of MCLK cycles each bitbanged bit takes without any added delay (pin toggles, reading MISO, loop overhead, etc.).
+/// This has not been measured on hardware, so the SCK rates derived from it are nominal.
+const BITBANG_OVERHEAD_CYCLES_PER_BIT: u32 = 120; // TODO: Measure
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum BitBangTimingError {
+    ZeroFrequency,
+    /// Faster than the MSP430FR2355 can run.
+    MclkTooFast,
+    /// The bitbang loop can't toggle SCK this quickly at the given MCLK.
+    SckTooFast,
+}
+
+/// Delays used by the bitbang buses, derived from the wanted SCK frequency and the MCLK frequency.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct BitBangTiming {
+    // Added once per bit, on top of BITBANG_OVERHEAD_CYCLES_PER_BIT. Where the other half-period does more work only half of this is added (duty cycle correction).
+    delay_cycles: u32,
+}
+impl BitBangTiming {
+    pub const fn new(sck_hz: u32, mclk_hz: u32) -> Result<BitBangTiming, BitBangTimingError> {
+        if sck_hz == 0 || mclk_hz == 0 {
+            return Err(BitBangTimingError::ZeroFrequency);
+        }
+        if mclk_hz > MAX_MCLK_HZ {
+            return Err(BitBangTimingError::MclkTooFast);
+        }
+        let cycles_per_bit = mclk_hz / sck_hz;
+        if cycles_per_bit < BITBANG_OVERHEAD_CYCLES_PER_BIT {
+            return Err(BitBangTimingError::SckTooFast);
+        }
+        Ok(BitBangTiming{delay_cycles: cycles_per_bit - BITBANG_OVERHEAD_CYCLES_PER_BIT})
+    }
+    /// SCK frequency these delays give at mclk_hz if the bitbang loop takes BITBANG_OVERHEAD_CYCLES_PER_BIT per bit.
+    /// That overhead is an unmeasured estimate, so treat this as nominal rather than what a scope would show.
+    pub const fn nominal_sck_hz(&self, mclk_hz: u32) -> u32 {
+        mclk_hz / (self.delay_cycles + BITBANG_OVERHEAD_CYCLES_PER_BIT)
+    }
+    fn full_delay(&self) {
+        delay_cycles(self.delay_cycles);
+    }
+    fn half_delay(&self) {
+        delay_cycles(self.delay_cycles / 2);
+    }
+}
+impl Default for BitBangTiming {
+    fn default() -> Self {
+        match BitBangTiming::new(DEFAULT_BITBANG_SCK_HZ, DEFAULT_MCLK_HZ) {
+            Ok(timing) => timing,
+            Err(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(target_arch = "msp430")]
 pub struct OBCSPIBitBang{
     pub miso:   Pin<P4, Pin2, Input<Pulldown>>, 
     pub mosi:   Pin<P4, Pin3, Output>, 
     pub sck:    Pin<P4, Pin1, Output>, 
     _chip_select:            Pin<P4, Pin0, Alternate1<Output>>, //direction is DontCare
     _chip_select_interrupt:  Pin<P2, Pin0, Input<Pullup>>, 
+    timing: BitBangTiming,
 }
+#[cfg(target_arch = "msp430")]
 impl OBCSPIBitBang {
     pub fn new(pins: OBCSPIPins) -> OBCSPIBitBang {
+        Self::new_with_timing(pins, BitBangTiming::default())
+    }
+    pub fn new_with_timing(pins: OBCSPIPins, timing: BitBangTiming) -> OBCSPIBitBang {
         OBCSPIBitBang{  miso: pins.miso.to_gpio().to_input_pulldown(),
                         mosi: pins.mosi.to_gpio(),
                         sck:  pins.sck.to_gpio(),
                         _chip_select: pins.chip_select,
                         _chip_select_interrupt: pins.chip_select_interrupt,
+                        timing,
         }
     }
     pub fn return_pins(self) -> OBCSPIPins {
@@ -61,6 +205,9 @@ impl OBCSPIBitBang {
                     chip_select: self._chip_select, 
                     chip_select_interrupt: self._chip_select_interrupt}
     }
+    pub fn set_timing(&mut self, timing: BitBangTiming) {
+        self.timing = timing;
+    }
     fn set_sck_idle_low(&mut self){
         self.sck.set_low().ok();
     }
@@ -68,6 +215,7 @@ impl OBCSPIBitBang {
         self.sck.set_high().ok();
     }
 }
+#[cfg(target_arch = "msp430")]
 impl OBCSPI for OBCSPIBitBang {
     fn send(&mut self, len: u8, data: u32) {
         let mut current_pos: u8 = 0;
@@ -79,7 +227,7 @@ impl OBCSPI for OBCSPIBitBang {
                 self.mosi.set_low().ok();
             }
             self.sck.toggle().ok();
-            delay_cycles(80); // duty cycle correction
+            self.timing.full_delay(); // duty cycle correction
             self.sck.toggle().ok();
             current_pos += 1;
         }
@@ -89,7 +237,7 @@ impl OBCSPI for OBCSPIBitBang {
         let mut current_pos: u8 = 0;
         while current_pos < len {
             self.sck.toggle().ok();
-            delay_cycles(40); // duty cycle correction
+            self.timing.half_delay(); // duty cycle correction
             result = (result << 1) | (self.miso.is_high().unwrap() as u32);
             self.sck.toggle().ok();
             current_pos += 1;
@@ -107,7 +255,7 @@ impl OBCSPI for OBCSPIBitBang {
                 self.mosi.set_low().ok();
             }
             self.sck.toggle().ok();
-            delay_cycles(80); // duty cycle correction
+            self.timing.full_delay(); // duty cycle correction
             result = (result << 1) | (self.miso.is_high().unwrap() as u32);
             self.sck.toggle().ok();
             current_pos += 1;
@@ -115,125 +263,187 @@ impl OBCSPI for OBCSPIBitBang {
         result
     }
 }
+/// Bus mode the OBC uses to talk to us.
+const OBC_SPI_MODE: Mode = Mode { polarity: Polarity::IdleLow, phase: Phase::CaptureOnFirstTransition };
+
+#[cfg(target_arch = "msp430")]
+/// OBC SPI implementation that uses the eUSCI_A1 peripheral as an SPI slave, running the protocol in obc.rs.
+/// 
+/// Note that the OBC bus shares eUSCI_A1 and P4.2/P4.3 with the debug serial port, so only one can be in use at a time.
+pub struct OBCSPISlave {
+    spi: SpiSlave<E_USCI_A1>,
+    chip_select_interrupt: Pin<P2, Pin0, Input<Pullup>>,
+}
+#[cfg(target_arch = "msp430")]
+impl OBCSPISlave {
+    pub fn new(usci: E_USCI_A1, pins: OBCSPIPins) -> OBCSPISlave {
+        let mut chip_select_interrupt = pins.chip_select_interrupt;
+        chip_select_interrupt.select_falling_edge_trigger();
+        let spi = SpiConfig::new(usci, OBC_SPI_MODE, true)
+            .to_slave()
+            .shared_bus(pins.miso, pins.mosi, pins.sck, pins.chip_select, StePolarity::EnabledWhenLow);
+        OBCSPISlave{spi, chip_select_interrupt}
+    }
+    /// If the OBC has asserted CS since the last call, service the transaction, blocking until CS is released. 
+    /// Any request received is passed to handler, and the response is clocked out on the next transaction.
+    /// 
+    /// Returns None if there was no transaction or it was read-only, otherwise the type of the frame received (or why it was rejected).
+    pub fn poll<'r>(&mut self, protocol: &mut OBCProtocol, handler: impl FnOnce(Request) -> Response<'r>) -> Option<Result<FrameType, FrameError>> {
+        if self.chip_select_interrupt.wait_for_ifg().is_err() {
+            return None;
+        }
+        protocol.start_transaction();
+        self.spi.write(protocol.next_tx_byte()).ok();
+        while self.chip_select_interrupt.is_low().unwrap_or(false) {
+            match self.spi.read() {
+                // On overrun we've still got the latest byte. If an earlier one was lost the CRC will catch it.
+                Ok(byte) | Err(nb::Error::Other(SpiErr::Overrun(byte))) => {
+                    protocol.receive_byte(byte);
+                    self.spi.write(protocol.next_tx_byte()).ok();
+                },
+                Err(nb::Error::WouldBlock) => (),
+            }
+        }
+        // The last byte can finish arriving just as CS is released
+        if let Ok(byte) = self.spi.read() {
+            protocol.receive_byte(byte);
+        }
+        protocol.end_transaction(handler)
+    }
+}
+#[cfg(target_arch = "msp430")]
 /// Payload SPI implementation that uses bit banging.
 pub struct PayloadSPIBitBang<const POLARITY: SckPolarity, const PHASE: SckPhase>{
     pub miso:   PayloadMISOBitBangPin, 
     pub mosi:   PayloadMOSIBitBangPin, 
     pub sck:    PayloadSCKBitBangPin,
+    timing: BitBangTiming,
 }
 
 //Internal functions to reduce code duplication. (IdleHigh and SampleRising) == (IdleLow and SampleFalling), except the initial state of the clock is inverted. Vice versa for the other pair
 //Could combine each pair into one function, but I don't want branches inside the main bitbang loop, as bitbanging is already slow enough.
+#[cfg(target_arch = "msp430")]
 impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPIBitBang<POLARITY, PHASE>{
     /// Create a new SPI bus by consuming SPI pins.
-    pub fn new(mut pins: PayloadSPIBitBangPins) -> Self {
+    pub fn new(pins: PayloadSPIBitBangPins) -> Self {
+        Self::new_with_timing(pins, BitBangTiming::default())
+    }
+    /// Create a new SPI bus by consuming SPI pins, running SCK at the rate given by timing.
+    pub fn new_with_timing(mut pins: PayloadSPIBitBangPins, timing: BitBangTiming) -> Self {
         match POLARITY {
             IdleHigh => pins.sck.set_high().ok(),
             IdleLow => pins.sck.set_low().ok(),
         };
-        Self {miso: pins.miso, mosi:pins.mosi, sck:pins.sck}
+        Self {miso: pins.miso, mosi:pins.mosi, sck:pins.sck, timing}
     }
-    fn receive_after_second_edge(&mut self, len: u8, cs_pin: &mut impl OutputPin) -> u32 {
-        let mut result: u32 = 0;
-        let mut current_pos: u8 = 0;
+    pub fn set_timing(&mut self, timing: BitBangTiming) {
+        self.timing = timing;
+    }
+    fn receive_after_second_edge(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        let mut current_pos: usize = 0;
+        clear_bits(result, len);
         cs_pin.set_low().ok();
         while current_pos < len {
             self.sck.toggle().ok();
-            delay_cycles(80); // duty cycle correction
+            self.timing.full_delay(); // duty cycle correction
             self.sck.toggle().ok();
-            result = (result << 1) | (self.miso.is_high().unwrap() as u32);
+            if self.miso.is_high().unwrap() {
+                set_bit(result, current_pos);
+            }
             current_pos += 1;
         }
         cs_pin.set_high().ok();
-        result
     }
-    fn receive_after_first_edge(&mut self, len: u8, cs_pin: &mut impl OutputPin) -> u32 {
-        let mut result: u32 = 0;
-        let mut current_pos: u8 = 0;
+    fn receive_after_first_edge(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        let mut current_pos: usize = 0;
+        clear_bits(result, len);
         cs_pin.set_low().ok();
         while current_pos < len {
             self.sck.toggle().ok();
-            result = (result << 1) | (self.miso.is_high().unwrap() as u32);
+            if self.miso.is_high().unwrap() {
+                set_bit(result, current_pos);
+            }
             self.sck.toggle().ok();
-            delay_cycles(80); // duty cycle correction
+            self.timing.full_delay(); // duty cycle correction
             current_pos += 1;
         }
         cs_pin.set_high().ok();
-        result
     }
-    fn send_before_first_edge(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) {
-        let mut current_pos: u8 = 0;
+    fn send_before_first_edge(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) {
+        let mut current_pos: usize = 0;
         cs_pin.set_low().ok();
         while current_pos < len {
-            if (data & (1_u32 << (len - current_pos - 1_u8))) > 0 {
+            if bit_is_set(data, current_pos) {
                 self.mosi.set_high().ok();
             }
             else{
                 self.mosi.set_low().ok();
             }
             self.sck.toggle().ok();
-            delay_cycles(80); // duty cycle correction
+            self.timing.full_delay(); // duty cycle correction
             self.sck.toggle().ok();
             current_pos += 1;
         }
         cs_pin.set_high().ok();
     }
-    fn send_after_first_edge(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) {
-        let mut current_pos: u8 = 0;
+    fn send_after_first_edge(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) {
+        let mut current_pos: usize = 0;
         cs_pin.set_low().ok();
         while current_pos < len {
             self.sck.toggle().ok();
-            if  (data & (1_u32 << (len - current_pos - 1_u8))) > 0 {
+            if bit_is_set(data, current_pos) {
                 self.mosi.set_high().ok();
             }
             else{
                 self.mosi.set_low().ok();
             }
-            delay_cycles(40); // duty cycle correction
+            self.timing.half_delay(); // duty cycle correction
             self.sck.toggle().ok();
             current_pos += 1;
         }
         cs_pin.set_high().ok();
     }
-    fn send_before_first_receive_after_first(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) -> u32{
-        let mut result: u32 = 0;
-        let mut current_pos: u8 = 0;
+    fn send_before_first_receive_after_first(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        let mut current_pos: usize = 0;
+        clear_bits(result, len);
         cs_pin.set_low().ok();
         while current_pos < len {
-            if  (data & (1_u32 << (len - current_pos - 1_u8))) > 0 {
+            if bit_is_set(data, current_pos) {
                 self.mosi.set_high().ok();
             }
             else{
                 self.mosi.set_low().ok();
             }
             self.sck.toggle().ok();
-            result = (result << 1) | (self.miso.is_high().unwrap() as u32);
-            delay_cycles(80); // duty cycle correction
+            if self.miso.is_high().unwrap() {
+                set_bit(result, current_pos);
+            }
+            self.timing.full_delay(); // duty cycle correction
             self.sck.toggle().ok();
             current_pos += 1;
         }
         cs_pin.set_high().ok();
-        result
     }
-    fn send_after_first_receive_after_second(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) -> u32{
-        let mut result: u32 = 0;
-        let mut current_pos: u8 = 0;
+    fn send_after_first_receive_after_second(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        let mut current_pos: usize = 0;
+        clear_bits(result, len);
         cs_pin.set_low().ok();
         while current_pos < len {
             self.sck.toggle().ok();
-            if  (data & (1_u32 << (len - current_pos - 1_u8))) > 0 {
+            if bit_is_set(data, current_pos) {
                 self.mosi.set_high().ok();
             }
             else{
                 self.mosi.set_low().ok();
             }
-            delay_cycles(80); // duty cycle correction
+            self.timing.full_delay(); // duty cycle correction
             self.sck.toggle().ok();
-            result = (result << 1) | (self.miso.is_high().unwrap() as u32);
+            if self.miso.is_high().unwrap() {
+                set_bit(result, current_pos);
+            }
             current_pos += 1;
         }
         cs_pin.set_high().ok();
-        result
     }
     pub fn return_pins(self) -> PayloadSPIPins {
         PayloadSPIPins{miso:self.miso.to_output().to_alternate1(), mosi:self.mosi.to_alternate1(), sck:self.sck.to_alternate1()}
@@ -244,69 +454,312 @@ impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPIBitBang<POLAR
 }
 
 // Transformation functions
+#[cfg(target_arch = "msp430")]
 impl<const CURRENT_POL: SckPolarity, const CURRENT_PHA: SckPhase> PayloadSPIBitBang<CURRENT_POL, CURRENT_PHA> {
     /// Consumes the old bus to produces a new one of a different type. Output type is usually inferred automatically.
     pub fn into<const NEW_POL: SckPolarity, const NEW_PHA: SckPhase>(mut self) -> PayloadSPIBitBang<NEW_POL, NEW_PHA>{
-        match NEW_POL {
+        self.set_mode(BusMode::of::<NEW_POL, NEW_PHA>());
+        PayloadSPIBitBang::<NEW_POL, NEW_PHA>{miso: self.miso, mosi: self.mosi, sck: self.sck, timing: self.timing}
+    }
+}
+// Actual trait implementations
+#[cfg(target_arch = "msp430")]
+impl<const POLARITY: SckPolarity> PayloadSPI<POLARITY, {SampleSecondEdge}> for PayloadSPIBitBang<POLARITY, {SampleSecondEdge}> {
+    fn send_slice(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) { self.send_after_first_edge(len, data, cs_pin) }
+    fn receive_slice(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) { self.receive_after_second_edge(len, result, cs_pin) }
+    fn send_receive_slice(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) { self.send_after_first_receive_after_second(len, data, result, cs_pin) }
+}
+#[cfg(target_arch = "msp430")]
+impl<const POLARITY: SckPolarity> PayloadSPI<POLARITY, {SampleFirstEdge}> for PayloadSPIBitBang<POLARITY, {SampleFirstEdge}> {
+    fn send_slice(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) { self.send_before_first_edge(len, data, cs_pin) } // technically this should be 'send after second edge' to fit the pattern, but we need to have data on the bus before the first rising edge.
+    fn receive_slice(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) { self.receive_after_first_edge(len, result, cs_pin) }
+    fn send_receive_slice(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) { self.send_before_first_receive_after_first(len, data, result, cs_pin) }
+}
+// Only the idle level of SCK depends on polarity, so mode changes just move SCK. The phase picks which routine to run.
+#[cfg(target_arch = "msp430")]
+impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPIAnyMode for PayloadSPIBitBang<POLARITY, PHASE> {
+    fn set_mode(&mut self, mode: BusMode) {
+        match mode.polarity {
             IdleHigh => self.sck.set_high().ok(),
             IdleLow => self.sck.set_low().ok(),
         };
+    }
+    fn send_in_mode(&mut self, mode: BusMode, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) {
+        match mode.phase {
+            SampleFirstEdge => self.send_before_first_edge(len, data, cs_pin),
+            SampleSecondEdge => self.send_after_first_edge(len, data, cs_pin),
+        }
+    }
+    fn receive_in_mode(&mut self, mode: BusMode, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        match mode.phase {
+            SampleFirstEdge => self.receive_after_first_edge(len, result, cs_pin),
+            SampleSecondEdge => self.receive_after_second_edge(len, result, cs_pin),
+        }
+    }
+    fn send_receive_in_mode(&mut self, mode: BusMode, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        match mode.phase {
+            SampleFirstEdge => self.send_before_first_receive_after_first(len, data, result, cs_pin),
+            SampleSecondEdge => self.send_after_first_receive_after_second(len, data, result, cs_pin),
+        }
+    }
+}
+
+/// SCK = SMCLK / PAYLOAD_SPI_CLOCK_DIVIDER. With the 1MHz SMCLK set up in configure_board this gives 250kHz, which the isolators on the tether and aperture ADCs are happy with.
+#[cfg(target_arch = "msp430")]
+const PAYLOAD_SPI_CLOCK_DIVIDER: u16 = 4;
 
-        PayloadSPIBitBang::<NEW_POL, NEW_PHA>{miso: self.miso, mosi: self.mosi, sck: self.sck}
+#[cfg(target_arch = "msp430")]
+/// Payload SPI implementation that uses the eUSCI_B1 peripheral.
+/// 
+/// The eUSCI only deals in 8-bit characters, so packets that aren't a multiple of 8 bits long are padded out to the next byte:
+/// zeroes are sent *before* the packet on MOSI (shift-register devices like the AD5162 only latch the last N bits clocked in), 
+/// and only the *first* N bits received on MISO are kept (devices start transmitting on the first edge after CS goes low).
+pub struct PayloadSPIHardware<const POLARITY: SckPolarity, const PHASE: SckPhase>{
+    spi: Spi<E_USCI_B1>,
+}
+#[cfg(target_arch = "msp430")]
+impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPIHardware<POLARITY, PHASE>{
+    /// Create a new SPI bus by consuming the eUSCI_B1 peripheral and its pins.
+    pub fn new(usci: E_USCI_B1, pins: PayloadSPIPins, smclk: &Smclk) -> Self {
+        let spi = SpiConfig::new(usci, BusMode::of::<POLARITY, PHASE>().into(), true)
+            .to_master_using_smclk(smclk, PAYLOAD_SPI_CLOCK_DIVIDER)
+            .single_master_bus(pins.miso, pins.mosi, pins.sck);
+        Self {spi}
+    }
+    // Unlike the bitbang implementation, the peripheral handles both clock phases so one function covers every operation.
+    // Send zeroes if data is None, and discard what we receive if result is None.
+    fn transfer(&mut self, len: usize, data: Option<&[u8]>, mut result: Option<&mut [u8]>, cs_pin: &mut impl OutputPin) {
+        let num_padding_bits = len.next_multiple_of(8) - len;
+        if let Some(result) = result.as_deref_mut() {
+            clear_bits(result, len);
+        }
+        cs_pin.set_low().ok();
+        for byte_start in (0..len + num_padding_bits).step_by(8) {
+            let mut tx_byte: u8 = 0;
+            if let Some(data) = data {
+                for bit in 0..8 {
+                    let n = byte_start + bit;
+                    if n >= num_padding_bits && bit_is_set(data, n - num_padding_bits) {
+                        tx_byte |= 0x80 >> bit;
+                    }
+                }
+            }
+            block!(self.spi.send(tx_byte)).ok();
+            // A failed read (e.g. an overrun) comes back as all ones, like MISO pulled high by an unresponsive chip, so frame checks reject it rather than trusting zeroes.
+            let rx_byte = block!(self.spi.read()).unwrap_or(0xFF);
+            if let Some(result) = result.as_deref_mut() {
+                // Keep the first 'len' bits we received, discard the padding clocked in afterwards.
+                for bit in 0..8 {
+                    let n = byte_start + bit;
+                    if n < len && rx_byte & (0x80 >> bit) != 0 {
+                        set_bit(result, n);
+                    }
+                }
+            }
+        }
+        cs_pin.set_high().ok();
     }
 }
-// Actual trait implementations
-impl<const POLARITY: SckPolarity> PayloadSPI<POLARITY, {SampleSecondEdge}> for PayloadSPIBitBang<POLARITY, {SampleSecondEdge}> {
-    fn send(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) { self.send_after_first_edge(len, data, cs_pin) }
-    fn receive(&mut self, len: u8, cs_pin: &mut impl OutputPin) -> u32  { self.receive_after_second_edge(len, cs_pin) }
-    fn send_receive(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) -> u32 { self.send_after_first_receive_after_second(len, data, cs_pin) }
+// Transformation functions
+#[cfg(target_arch = "msp430")]
+impl<const CURRENT_POL: SckPolarity, const CURRENT_PHA: SckPhase> PayloadSPIHardware<CURRENT_POL, CURRENT_PHA> {
+    /// Consumes the old bus to produces a new one of a different type. Output type is usually inferred automatically.
+    pub fn into<const NEW_POL: SckPolarity, const NEW_PHA: SckPhase>(mut self) -> PayloadSPIHardware<NEW_POL, NEW_PHA>{
+        self.set_mode(BusMode::of::<NEW_POL, NEW_PHA>());
+        PayloadSPIHardware::<NEW_POL, NEW_PHA>{spi: self.spi}
+    }
 }
-impl<const POLARITY: SckPolarity> PayloadSPI<POLARITY, {SampleFirstEdge}> for PayloadSPIBitBang<POLARITY, {SampleFirstEdge}> {
-    fn send(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) { self.send_before_first_edge(len, data, cs_pin) } // technically this should be 'send after second edge' to fit the pattern, but we need to have data on the bus before the first rising edge.
-    fn receive(&mut self, len: u8, cs_pin: &mut impl OutputPin) -> u32  { self.receive_after_first_edge(len, cs_pin) }
-    fn send_receive(&mut self, len: u8, data: u32, cs_pin: &mut impl OutputPin) -> u32 { self.send_before_first_receive_after_first(len, data, cs_pin) }
+#[cfg(target_arch = "msp430")]
+impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPI<POLARITY, PHASE> for PayloadSPIHardware<POLARITY, PHASE> {
+    fn send_slice(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) { self.transfer(len, Some(data), None, cs_pin) }
+    fn receive_slice(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) { self.transfer(len, None, Some(result), cs_pin) }
+    fn send_receive_slice(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) { self.transfer(len, Some(data), Some(result), cs_pin) }
 }
+// The peripheral holds the mode itself, so only set_mode needs to look at it.
+#[cfg(target_arch = "msp430")]
+impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPIAnyMode for PayloadSPIHardware<POLARITY, PHASE> {
+    fn set_mode(&mut self, mode: BusMode) { self.spi.change_mode(mode.into()); }
+    fn send_in_mode(&mut self, _mode: BusMode, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) { self.transfer(len, Some(data), None, cs_pin) }
+    fn receive_in_mode(&mut self, _mode: BusMode, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) { self.transfer(len, None, Some(result), cs_pin) }
+    fn send_receive_in_mode(&mut self, _mode: BusMode, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) { self.transfer(len, Some(data), Some(result), cs_pin) }
+}
+/// Translate our modes into the equivalent embedded-hal SPI mode.
+impl From<BusMode> for Mode {
+    fn from(mode: BusMode) -> Mode {
+        Mode {
+            polarity: match mode.polarity {
+                IdleHigh => Polarity::IdleHigh,
+                IdleLow => Polarity::IdleLow,
+            },
+            phase: match mode.phase {
+                SampleFirstEdge => Phase::CaptureOnFirstTransition,
+                SampleSecondEdge => Phase::CaptureOnSecondTransition,
+            },
+        }
+    }
+}
+
+/// The SPI implementation used by PayloadSPIController. Enable the 'hardware_payload_spi' feature to use the eUSCI_B1 peripheral instead of bitbanging.
+#[cfg(all(target_arch = "msp430", not(feature = "hardware_payload_spi")))]
+pub type PayloadSPIBus<const POLARITY: SckPolarity, const PHASE: SckPhase> = PayloadSPIBitBang<POLARITY, PHASE>;
+#[cfg(all(target_arch = "msp430", feature = "hardware_payload_spi"))]
+pub type PayloadSPIBus<const POLARITY: SckPolarity, const PHASE: SckPhase> = PayloadSPIHardware<POLARITY, PHASE>;
 
 /// A wrapper class that automates changing the typestate of the bus. Useful for intermediate functions that don't use the bus themselves, but call functions that do.
 /// 
-/// Functions that require the SPI bus can borrow it using .borrow()
-pub struct PayloadSPIController {
-    // It looks like we can only store one type here, but we'll convert it in .borrow().
-    // Trust me, this is the easiest way.
-    spi_bus: PayloadSPIBitBang<{IdleHigh}, {SampleFirstEdge}>
+/// Functions that require the SPI bus can borrow it using .borrow(), which puts the bus into the mode they ask for.
+pub struct PayloadSPIController<BUS: PayloadSPIAnyMode> {
+    // The type parameters of the stored bus don't matter, every operation goes through PayloadSPIAnyMode with the mode given explicitly.
+    spi_bus: BUS,
+    applied_mode: BusMode,
 }
-impl PayloadSPIController {
+#[cfg(target_arch = "msp430")]
+impl PayloadSPIController<PayloadSPIBus<{IdleHigh}, {SampleFirstEdge}>> {
     /// Generates a new controller by consuming an existing SPI bus.
-    pub fn new_from_bus<const POLARITY: SckPolarity, const PHASE: SckPhase>(bus: PayloadSPIBitBang<POLARITY, PHASE>) -> Self {
-        Self {spi_bus: bus.into()}
+    pub fn new_from_bus<const POLARITY: SckPolarity, const PHASE: SckPhase>(bus: PayloadSPIBus<POLARITY, PHASE>) -> Self {
+        Self::new_from_any_mode_bus(bus.into())
     }
+    #[cfg(not(feature = "hardware_payload_spi"))]
     pub fn new(pins: PayloadSPIBitBangPins) -> Self {
-        let spi_bus = PayloadSPIBitBang::<{IdleHigh}, {SampleFirstEdge}>::new(pins);
-        Self {spi_bus}
+        Self::new_from_any_mode_bus(PayloadSPIBitBang::new(pins))
     }
-    pub fn return_bus<const POLARITY: SckPolarity, const PHASE: SckPhase>(self) -> PayloadSPIBitBang<POLARITY, PHASE> {
+    #[cfg(feature = "hardware_payload_spi")]
+    pub fn new(usci: E_USCI_B1, pins: PayloadSPIPins, smclk: &Smclk) -> Self {
+        Self::new_from_any_mode_bus(PayloadSPIHardware::new(usci, pins, smclk))
+    }
+    pub fn return_bus<const POLARITY: SckPolarity, const PHASE: SckPhase>(self) -> PayloadSPIBus<POLARITY, PHASE> {
         self.spi_bus.into()
     }
-    pub fn return_pins<const POLARITY: SckPolarity, const PHASE: SckPhase>(self) -> PayloadSPIBitBangPins {
+    #[cfg(not(feature = "hardware_payload_spi"))]
+    pub fn return_pins(self) -> PayloadSPIBitBangPins {
         self.spi_bus.return_bit_bang_pins()
     }
-    /// Return a mutable reference to the SPI bus, converting to the correct typestate as required.
-    pub fn borrow<const POLARITY: SckPolarity, const PHASE: SckPhase>(&mut self) -> &mut PayloadSPIBitBang<POLARITY, PHASE> {
-        // Using our knowledge of how PayloadSPIBitBang works, we can safely convert between types manually, bypassing Rusts's type system (necessary to keep the wrapper free of types).
-        // The only thing differentiating PayloadSPIBitBang<A, B> from PayloadSPIBitBang<C, D> is
-        // a) Internal state (just the state of the clock pin in our case)
-        // b) the methods called on the struct. 
+    /// Change the SCK rate, e.g. after changing MCLK or to talk to a slow device.
+    #[cfg(not(feature = "hardware_payload_spi"))]
+    pub fn set_timing(&mut self, timing: BitBangTiming) {
+        self.spi_bus.set_timing(timing);
+    }
+}
+impl<BUS: PayloadSPIAnyMode> PayloadSPIController<BUS> {
+    /// Generates a new controller around any bus that can change modes at runtime (e.g. a mock bus).
+    pub fn new_from_any_mode_bus(mut spi_bus: BUS) -> Self {
+        let applied_mode = BusMode::of::<{IdleHigh}, {SampleFirstEdge}>();
+        spi_bus.set_mode(applied_mode);
+        Self {spi_bus, applied_mode}
+    }
+    /// Put the bus into the requested mode and lend it out.
+    pub fn borrow<const POLARITY: SckPolarity, const PHASE: SckPhase>(&mut self) -> PayloadSPIView<'_, BUS, POLARITY, PHASE> {
+        let mode = BusMode::of::<POLARITY, PHASE>();
+        if mode != self.applied_mode {
+            self.spi_bus.set_mode(mode);
+            self.applied_mode = mode;
+        }
+        PayloadSPIView{spi_bus: &mut self.spi_bus}
+    }
+    /// The mode the bus was last put into.
+    pub fn applied_mode(&self) -> BusMode {
+        self.applied_mode
+    }
+    /// Read-only access to the underlying bus, e.g. to inspect a mock.
+    pub fn spi_bus(&self) -> &BUS {
+        &self.spi_bus
+    }
+}
 
-        // We can deal with a) easily enough.
-        match POLARITY {
-            IdleHigh => self.spi_bus.sck.set_high().ok(),
-            IdleLow =>  self.spi_bus.sck.set_low().ok(),
-        };
+/// The SPI bus, borrowed from a PayloadSPIController and already configured for POLARITY and PHASE.
+pub struct PayloadSPIView<'a, BUS: PayloadSPIAnyMode, const POLARITY: SckPolarity, const PHASE: SckPhase> {
+    spi_bus: &'a mut BUS,
+}
+impl<BUS: PayloadSPIAnyMode, const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPI<POLARITY, PHASE> for PayloadSPIView<'_, BUS, POLARITY, PHASE> {
+    fn send_slice(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) { self.spi_bus.send_in_mode(BusMode::of::<POLARITY, PHASE>(), len, data, cs_pin) }
+    fn receive_slice(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) { self.spi_bus.receive_in_mode(BusMode::of::<POLARITY, PHASE>(), len, result, cs_pin) }
+    fn send_receive_slice(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) { self.spi_bus.send_receive_in_mode(BusMode::of::<POLARITY, PHASE>(), len, data, result, cs_pin) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adc::TetherADC;
+    use crate::dac::{DACChannel, DAC};
+    use crate::digipot::{Digipot, DigipotChannel};
+    use crate::pcb_mapping::sensor_locations::*;
+    use crate::spi_mock::{MockCSPin, MockChipSelects, MockPayloadSPI};
 
-        // Now we only need to trick Rust into calling the methods of PayloadSPIBitBang<POLARITY, PHASE> instead of the methods associated with our PayloadSPIBitBang<{IdleHigh}, {DeviceReadsFirstEdge}> we have stored.        
-        // Ask Rust to treat our PayloadSPIBitBang<{IdleHigh}, {DeviceReadsFirstEdge}> as if it were PayloadSPIBitBang<POLARITY, PHASE>.
-        // This will take care of the rest of the conversion, as Rust will now call the methods associated with PayloadSPIBitBang<POLARITY, PHASE>.
-        // This, combined with the above sck polarity is all that is necessary to convert between the types.
-        return unsafe{ core::mem::transmute(&mut self.spi_bus) };
+    const DAC_ID: u8 = 0;
+    const DIGIPOT_ID: u8 = 1;
+    const ADC_ID: u8 = 2;
+
+    // Interleaves devices with different modes on one controller, and checks the mock saw each device addressed only in its own mode.
+    // The recorded mode is the one applied to the bus, so its polarity is also the level SCK idled at when the chip was selected.
+    #[test]
+    fn devices_are_only_addressed_in_their_own_mode() {
+        let chip_selects = MockChipSelects::new();
+        let mut controller = PayloadSPIController::new_from_any_mode_bus(MockPayloadSPI::<{IdleHigh}, {SampleFirstEdge}>::new(&chip_selects));
+        let mut dac: DAC<MockCSPin> = DAC::new(chip_selects.pin(DAC_ID));
+        let mut digipot = Digipot::new(chip_selects.pin(DIGIPOT_ID));
+        let mut adc = TetherADC::new_mock(chip_selects.pin(ADC_ID));
+
+        for n in 0..3 {
+            dac.write_and_update(DACChannel::ChannelA, n, &mut controller.borrow());
+            adc.read_count_from(&REPELLER_VOLTAGE_SENSOR, &mut controller.borrow()).ok();
+            digipot.set_channel_to_count(DigipotChannel::Channel1, n as u8, &mut controller.borrow());
+            digipot.set_channel_to_count(DigipotChannel::Channel2, n as u8, &mut controller.borrow());
+            adc.scan_counts_from([&HEATER_VOLTAGE_SENSOR, &HEATER_CURRENT_SENSOR], &mut controller.borrow()).ok();
+        }
+
+        let bus = controller.spi_bus();
+        assert_eq!(bus.transactions().len(), 15);
+        assert_eq!(bus.num_dropped(), 0);
+        for transaction in bus.transactions() {
+            let expected = match transaction.cs_id {
+                Some(DAC_ID) | Some(DIGIPOT_ID) => BusMode::of::<{IdleLow}, {SampleFirstEdge}>(),
+                Some(ADC_ID) => BusMode::of::<{IdleHigh}, {SampleSecondEdge}>(),
+                other => panic!("transaction without exactly one chip selected: {:?}", other),
+            };
+            assert_eq!(transaction.mode, expected, "chip {:?}", transaction.cs_id);
+        }
+        // Every mode change happened with no chip selected, and the controller's idea of the mode matches the bus
+        assert_eq!(bus.mode_changes_while_selected(), 0);
+        assert_eq!(chip_selects.selected(), None);
+        assert_eq!(controller.applied_mode(), bus.applied_mode());
+    }
+
+    #[test]
+    fn packets_round_trip_through_bits() {
+        for len in 0..=32u8 {
+            for data in [0, 1, 0x5A5A_5A5A, 0x8000_0001, u32::MAX] {
+                let packet = data & u32::MAX.checked_shr(32 - len as u32).unwrap_or(0);
+                assert_eq!(bits_to_u32(len, u32_to_bits(len, data)), packet, "len {}, data {:#x}", len, data);
+            }
+        }
+        // MSB-first, so a 12-bit packet fills the first byte and the top half of the second
+        assert_eq!(u32_to_bits(12, 0xABC), [0xAB, 0xC0, 0, 0]);
+        assert_eq!(bits_to_u32(12, [0xAB, 0xCF, 0xFF, 0xFF]), 0xABC);
+    }
+
+    #[test]
+    fn packets_over_32_bits_are_treated_as_32() {
+        assert_eq!(u32_to_bits(40, 0x1234_5678), u32_to_bits(32, 0x1234_5678));
+        assert_eq!(bits_to_u32(u8::MAX, [0x12, 0x34, 0x56, 0x78]), 0x1234_5678);
+
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleHigh}, {SampleFirstEdge}>::new(&chip_selects);
+        bus.script_response(0x1234_5678);
+        assert_eq!(bus.send_receive(40, 0x9ABC_DEF0, &mut chip_selects.pin(DAC_ID)), 0x1234_5678);
+        assert_eq!(bus.transactions()[0].len, 32);
+        assert_eq!(bus.transactions()[0].mosi_u32(), 0x9ABC_DEF0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn bitbang_timing_follows_the_requested_rate() {
+        let timing = BitBangTiming::new(DEFAULT_BITBANG_SCK_HZ, DEFAULT_MCLK_HZ).unwrap();
+        assert_eq!(timing.nominal_sck_hz(DEFAULT_MCLK_HZ), DEFAULT_BITBANG_SCK_HZ);
+        assert_eq!(timing, BitBangTiming::default());
+        // The same delays run proportionally faster with a faster MCLK
+        assert_eq!(timing.nominal_sck_hz(2 * DEFAULT_MCLK_HZ), 2 * DEFAULT_BITBANG_SCK_HZ);
+
+        assert_eq!(BitBangTiming::new(0, DEFAULT_MCLK_HZ), Err(BitBangTimingError::ZeroFrequency));
+        assert_eq!(BitBangTiming::new(DEFAULT_BITBANG_SCK_HZ, MAX_MCLK_HZ + 1), Err(BitBangTimingError::MclkTooFast));
+        assert_eq!(BitBangTiming::new(DEFAULT_MCLK_HZ / (BITBANG_OVERHEAD_CYCLES_PER_BIT - 1), DEFAULT_MCLK_HZ), Err(BitBangTimingError::SckTooFast));
+    }
+}
@@ -0,0 +1,177 @@
+// This file exposes the payload SPI bus through the embedded-hal 1.0 SPI traits, so driver crates written against embedded-hal can drive the payload's chips.
+// EhalSpiBus wraps any PayloadSPI bus in a fixed mode. EhalSpiDevice pairs a chip select with a PayloadSPIController in a RefCell and switches the bus to the device's mode for each transaction.
+// These are standalone: Payload owns its PayloadSPIController by value, so neither can be used alongside a Payload. Build them from the bus and PayloadSPIChipSelectPins instead of a Payload.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embedded_hal::digital::v2::OutputPin;
+use ehal::spi::{ErrorType, Operation, SpiBus, SpiDevice};
+
+use crate::delay_cycles;
+use crate::pcb_common::PayloadHardware;
+use crate::spi::{PayloadSPI, PayloadSPIAnyMode, PayloadSPIController, SckPhase, SckPolarity, DEFAULT_MCLK_HZ};
+use crate::spi::{SckPhase::*, SckPolarity::*};
+
+/// Stands in for a chip select when the PayloadSPI methods are called inside an embedded-hal transaction, where CS is handled separately.
+struct NoChipSelect;
+impl OutputPin for NoChipSelect {
+    type Error = Infallible;
+    fn set_low(&mut self) -> Result<(), Infallible> { Ok(()) }
+    fn set_high(&mut self) -> Result<(), Infallible> { Ok(()) }
+}
+
+/// embedded-hal 1.0 SpiBus over a PayloadSPI bus. Chip selects are left alone, so use EhalSpiDevice (or manage CS yourself).
+///
+/// Works with PayloadSPIBitBang, PayloadSPIHardware, or the view returned by PayloadSPIController::borrow().
+pub struct EhalSpiBus<BUS: PayloadSPI<POLARITY, PHASE>, const POLARITY: SckPolarity, const PHASE: SckPhase> {
+    spi_bus: BUS,
+}
+impl<BUS: PayloadSPI<POLARITY, PHASE>, const POLARITY: SckPolarity, const PHASE: SckPhase> EhalSpiBus<BUS, POLARITY, PHASE> {
+    pub fn new(spi_bus: BUS) -> Self {
+        EhalSpiBus{spi_bus}
+    }
+    pub fn into_inner(self) -> BUS {
+        self.spi_bus
+    }
+}
+impl<BUS: PayloadSPI<POLARITY, PHASE>, const POLARITY: SckPolarity, const PHASE: SckPhase> ErrorType for EhalSpiBus<BUS, POLARITY, PHASE> {
+    type Error = Infallible;
+}
+impl<BUS: PayloadSPI<POLARITY, PHASE>, const POLARITY: SckPolarity, const PHASE: SckPhase> SpiBus<u8> for EhalSpiBus<BUS, POLARITY, PHASE> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+        self.spi_bus.receive_slice(words.len() * 8, words, &mut NoChipSelect);
+        Ok(())
+    }
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+        self.spi_bus.send_slice(words.len() * 8, words, &mut NoChipSelect);
+        Ok(())
+    }
+    // Buffers may be different lengths, so go a byte at a time. Zeroes are sent once write runs out, and reads past the end of read are discarded.
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+        for n in 0..read.len().max(write.len()) {
+            let mut received = [0];
+            self.spi_bus.send_receive_slice(8, &[*write.get(n).unwrap_or(&0)], &mut received, &mut NoChipSelect);
+            if let Some(byte) = read.get_mut(n) {
+                *byte = received[0];
+            }
+        }
+        Ok(())
+    }
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+        for byte in words.iter_mut() {
+            let mut received = [0];
+            self.spi_bus.send_receive_slice(8, &[*byte], &mut received, &mut NoChipSelect);
+            *byte = received[0];
+        }
+        Ok(())
+    }
+    // Transfers are blocking, so there's never anything left to flush.
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// embedded-hal 1.0 SpiDevice for one chip on the payload bus. The controller lives in a RefCell so several devices can share it.
+///
+/// Our own drivers (ADC, DAC, Digipot) can use the same controller through borrow_mut(), but a Payload can't, as it owns its controller.
+///
+/// Each transaction puts the bus into POLARITY and PHASE before asserting CS. Use the aliases below to get the right mode for each chip.
+pub struct EhalSpiDevice<'a, BUS: PayloadSPIAnyMode, CsPin: OutputPin, const POLARITY: SckPolarity, const PHASE: SckPhase> {
+    controller: &'a RefCell<PayloadSPIController<BUS>>,
+    cs_pin: CsPin,
+}
+impl<'a, BUS: PayloadSPIAnyMode, CsPin: OutputPin, const POLARITY: SckPolarity, const PHASE: SckPhase> EhalSpiDevice<'a, BUS, CsPin, POLARITY, PHASE> {
+    pub fn new(controller: &'a RefCell<PayloadSPIController<BUS>>, mut cs_pin: CsPin) -> Self {
+        cs_pin.set_high().ok();
+        EhalSpiDevice{controller, cs_pin}
+    }
+    pub fn return_cs_pin(self) -> CsPin {
+        self.cs_pin
+    }
+}
+impl<BUS: PayloadSPIAnyMode, CsPin: OutputPin, const POLARITY: SckPolarity, const PHASE: SckPhase> ErrorType for EhalSpiDevice<'_, BUS, CsPin, POLARITY, PHASE> {
+    type Error = Infallible;
+}
+impl<BUS: PayloadSPIAnyMode, CsPin: OutputPin, const POLARITY: SckPolarity, const PHASE: SckPhase> SpiDevice<u8> for EhalSpiDevice<'_, BUS, CsPin, POLARITY, PHASE> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+        let mut controller = self.controller.borrow_mut();
+        let mut spi_bus = EhalSpiBus::new(controller.borrow::<POLARITY, PHASE>());
+        self.cs_pin.set_low().ok();
+        for operation in operations {
+            match operation {
+                Operation::Read(words) => spi_bus.read(words)?,
+                Operation::Write(words) => spi_bus.write(words)?,
+                Operation::Transfer(read, write) => spi_bus.transfer(read, write)?,
+                Operation::TransferInPlace(words) => spi_bus.transfer_in_place(words)?,
+                // delay_cycles is approximate anyway, so assume the default MCLK.
+                Operation::DelayNs(ns) => delay_cycles(ns.div_ceil(1_000_000_000 / DEFAULT_MCLK_HZ)),
+            }
+        }
+        self.cs_pin.set_high().ok();
+        Ok(())
+    }
+}
+
+// One SpiDevice per chip select in PayloadSPIChipSelectPins, each in the mode that chip expects. H is the board, e.g. MSP430Hardware.
+pub type DACSpiDevice<'a, H>             = EhalSpiDevice<'a, <H as PayloadHardware>::PayloadSPIBus, <H as PayloadHardware>::DACCSPin, {IdleLow}, {SampleFirstEdge}>;
+pub type DigipotSpiDevice<'a, H>         = EhalSpiDevice<'a, <H as PayloadHardware>::PayloadSPIBus, <H as PayloadHardware>::DigipotCSPin, {IdleLow}, {SampleFirstEdge}>;
+pub type TetherADCSpiDevice<'a, H>       = EhalSpiDevice<'a, <H as PayloadHardware>::PayloadSPIBus, <H as PayloadHardware>::TetherADCCSPin, {IdleHigh}, {SampleSecondEdge}>;
+pub type TemperatureADCSpiDevice<'a, H>  = EhalSpiDevice<'a, <H as PayloadHardware>::PayloadSPIBus, <H as PayloadHardware>::TemperatureADCCSPin, {IdleHigh}, {SampleSecondEdge}>;
+pub type MiscADCSpiDevice<'a, H>         = EhalSpiDevice<'a, <H as PayloadHardware>::PayloadSPIBus, <H as PayloadHardware>::MiscADCCSPin, {IdleHigh}, {SampleSecondEdge}>;
+pub type ApertureADCSpiDevice<'a, H>     = EhalSpiDevice<'a, <H as PayloadHardware>::PayloadSPIBus, <H as PayloadHardware>::ApertureADCCSPin, {IdleHigh}, {SampleSecondEdge}>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spi::BusMode;
+    use crate::spi_mock::{MockChipSelects, MockPayloadSPI, TransactionKind};
+
+    #[test]
+    fn device_transactions_hold_cs_in_the_device_mode() {
+        let chip_selects = MockChipSelects::new();
+        let bus = MockPayloadSPI::<{IdleHigh}, {SampleSecondEdge}>::new(&chip_selects);
+        let controller = RefCell::new(PayloadSPIController::new_from_any_mode_bus(bus));
+        let mut device = EhalSpiDevice::<_, _, {IdleLow}, {SampleFirstEdge}>::new(&controller, chip_selects.pin(2));
+
+        let (mut read, mut transfer_read, mut in_place) = ([0; 1], [0; 1], [0x33]);
+        device.transaction(&mut [Operation::Write(&[0x12, 0x34]), Operation::Read(&mut read),
+                                 Operation::Transfer(&mut transfer_read, &[0x56]), Operation::TransferInPlace(&mut in_place)]).unwrap();
+
+        let controller = controller.borrow();
+        let transactions = controller.spi_bus().transactions();
+        let kinds = transactions.iter().map(|transaction| (transaction.kind, transaction.len, transaction.mosi_u32()));
+        assert!(kinds.eq([(TransactionKind::Send, 16, 0x1234), (TransactionKind::Receive, 8, 0),
+                          (TransactionKind::SendReceive, 8, 0x56), (TransactionKind::SendReceive, 8, 0x33)]));
+        for transaction in transactions {
+            assert_eq!(transaction.cs_id, Some(2));
+            assert_eq!(transaction.mode, BusMode::of::<{IdleLow}, {SampleFirstEdge}>());
+        }
+        assert_eq!(chip_selects.selected(), None);
+        // The bus was switched to the device's mode before CS went low
+        assert_eq!(controller.spi_bus().mode_changes_while_selected(), 0);
+    }
+
+    #[test]
+    fn transfers_pad_or_discard_to_the_longer_buffer() {
+        let chip_selects = MockChipSelects::new();
+        let mut bus = MockPayloadSPI::<{IdleLow}, {SampleFirstEdge}>::new(&chip_selects);
+        for response in [0xA1, 0xA2, 0xA3, 0xB1, 0xB2, 0xB3] {
+            bus.script_response(response);
+        }
+        let mut spi_bus = EhalSpiBus::new(bus);
+
+        // Write runs out first, so zeroes are sent
+        let mut read = [0; 3];
+        spi_bus.transfer(&mut read, &[0x11]).unwrap();
+        assert_eq!(read, [0xA1, 0xA2, 0xA3]);
+        // Read runs out first, so the rest of MISO is discarded
+        let mut read = [0; 1];
+        spi_bus.transfer(&mut read, &[0x21, 0x22, 0x23]).unwrap();
+        assert_eq!(read, [0xB1]);
+
+        let bus = spi_bus.into_inner();
+        let mosi: [u32; 6] = core::array::from_fn(|n| bus.transactions()[n].mosi_u32());
+        assert_eq!(mosi, [0x11, 0, 0, 0x21, 0x22, 0x23]);
+        assert_eq!(bus.transactions().len(), 6);
+    }
+}
@@ -0,0 +1,260 @@
+// This file provides a mock payload SPI bus and chip select pins, so drivers like dac.rs, digipot.rs and adc.rs can be checked on a host without a board.
+// The bus records every transaction (length, MOSI word, selected chip and bus mode) and replays scripted MISO responses.
+// Those drivers are generic over their chip select pin so a MockCSPin can stand in for the real one.
+
+use core::cell::Cell;
+use core::convert::Infallible;
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::adc::ADCCSPin;
+use crate::spi::{bit_is_set, bits_to_u32, clear_bits, set_bit, BusMode, PayloadSPI, PayloadSPIAnyMode, SckPhase, SckPolarity};
+
+pub const MAX_RECORDED_TRANSACTIONS: usize = 32;
+pub const MAX_SCRIPTED_RESPONSES: usize = 32;
+/// Longer transactions still happen, but only this many bits are recorded and scripted.
+pub const MAX_RECORDED_BITS: usize = 256;
+const MAX_RECORDED_BYTES: usize = MAX_RECORDED_BITS / 8;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum TransactionKind {
+    Send,
+    Receive,
+    SendReceive,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct MockTransaction {
+    pub kind: TransactionKind,
+    pub len: usize,
+    /// MSB-first, like the PayloadSPI slice methods. Zero for receive-only transactions.
+    pub mosi: [u8; MAX_RECORDED_BYTES],
+    /// What the mock clocked back, MSB-first.
+    pub miso: [u8; MAX_RECORDED_BYTES],
+    /// The MockCSPin held low during the transaction. None if no mock pin, or more than one, was selected.
+    pub cs_id: Option<u8>,
+    /// The mode the bus was actually in, which may differ from what the device expects if something is misconfigured.
+    pub mode: BusMode,
+}
+impl MockTransaction {
+    /// MOSI as a right-aligned word, like the data passed to PayloadSPI::send. Only meaningful for transactions up to 32 bits long.
+    pub fn mosi_u32(&self) -> u32 {
+        bits_to_u32(self.len.min(32) as u8, [self.mosi[0], self.mosi[1], self.mosi[2], self.mosi[3]])
+    }
+    /// MISO as a right-aligned word, like the result of PayloadSPI::receive. Only meaningful for transactions up to 32 bits long.
+    pub fn miso_u32(&self) -> u32 {
+        bits_to_u32(self.len.min(32) as u8, [self.miso[0], self.miso[1], self.miso[2], self.miso[3]])
+    }
+}
+
+#[derive(Copy, Clone)]
+enum ScriptedResponse {
+    /// Right-aligned, so the LSB is the last bit clocked out regardless of the transaction length.
+    Word(u32),
+    /// MSB-first, the first bit is clocked out first.
+    Bits([u8; MAX_RECORDED_BYTES]),
+}
+impl ScriptedResponse {
+    fn bit(&self, len: usize, n: usize) -> bool {
+        match self {
+            ScriptedResponse::Word(word) => {
+                let bits_from_end = len - n - 1;
+                bits_from_end < 32 && word & (1 << bits_from_end) != 0
+            },
+            ScriptedResponse::Bits(bits) => n < MAX_RECORDED_BITS && bit_is_set(bits, n),
+        }
+    }
+}
+
+/// Shared between a MockPayloadSPI and its MockCSPins, so the bus can tell which chip is selected.
+pub struct MockChipSelects {
+    // Bit n is set while the pin with id n is low
+    selected: Cell<u32>,
+}
+impl MockChipSelects {
+    pub const fn new() -> MockChipSelects {
+        MockChipSelects { selected: Cell::new(0) }
+    }
+    /// Create a chip select pin. id must be less than 32.
+    pub fn pin(&self, id: u8) -> MockCSPin<'_> {
+        MockCSPin { id, chip_selects: self }
+    }
+    /// The only selected pin, if exactly one is low.
+    pub fn selected(&self) -> Option<u8> {
+        let selected = self.selected.get();
+        if selected.is_power_of_two() { Some(selected.trailing_zeros() as u8) } else { None }
+    }
+}
+impl Default for MockChipSelects {
+    fn default() -> Self {
+        MockChipSelects::new()
+    }
+}
+
+/// Chip select pin that reports its state to a MockChipSelects. Starts deselected.
+pub struct MockCSPin<'a> {
+    id: u8,
+    chip_selects: &'a MockChipSelects,
+}
+impl MockCSPin<'_> {
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+    pub fn is_selected(&self) -> bool {
+        self.chip_selects.selected.get() & (1 << self.id) != 0
+    }
+}
+impl OutputPin for MockCSPin<'_> {
+    type Error = Infallible;
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        let selected = self.chip_selects.selected.get();
+        self.chip_selects.selected.set(selected | (1 << self.id));
+        Ok(())
+    }
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        let selected = self.chip_selects.selected.get();
+        self.chip_selects.selected.set(selected & !(1 << self.id));
+        Ok(())
+    }
+}
+impl ADCCSPin for MockCSPin<'_> {}
+
+/// Mock payload SPI bus. Implements PayloadSPI in its own mode, and PayloadSPIAnyMode so it can sit inside a PayloadSPIController.
+///
+/// Transactions past MAX_RECORDED_TRANSACTIONS aren't recorded, but are counted by num_dropped().
+/// Once the scripted responses run out the bus clocks back idle_miso.
+pub struct MockPayloadSPI<'a, const POLARITY: SckPolarity, const PHASE: SckPhase> {
+    chip_selects: &'a MockChipSelects,
+    applied_mode: BusMode,
+    transactions: [MockTransaction; MAX_RECORDED_TRANSACTIONS],
+    num_transactions: usize,
+    num_dropped: usize,
+    responses: [ScriptedResponse; MAX_SCRIPTED_RESPONSES],
+    num_responses: usize,
+    next_response: usize,
+    mode_changes_while_selected: usize,
+    pub idle_miso: u32,
+}
+impl<'a, const POLARITY: SckPolarity, const PHASE: SckPhase> MockPayloadSPI<'a, POLARITY, PHASE> {
+    pub fn new(chip_selects: &'a MockChipSelects) -> Self {
+        let applied_mode = BusMode::of::<POLARITY, PHASE>();
+        let empty = MockTransaction { kind: TransactionKind::Send, len: 0, mosi: [0; MAX_RECORDED_BYTES], miso: [0; MAX_RECORDED_BYTES], cs_id: None, mode: applied_mode };
+        MockPayloadSPI {
+            chip_selects,
+            applied_mode,
+            transactions: [empty; MAX_RECORDED_TRANSACTIONS],
+            num_transactions: 0,
+            num_dropped: 0,
+            responses: [ScriptedResponse::Word(0); MAX_SCRIPTED_RESPONSES],
+            num_responses: 0,
+            next_response: 0,
+            mode_changes_while_selected: 0,
+            idle_miso: 0,
+        }
+    }
+    /// Queue a MISO word for a future transaction. Responses are right-aligned like PayloadSPI results, i.e. the last bit clocked in is the LSB.
+    /// Returns false if the script is full.
+    pub fn script_response(&mut self, miso: u32) -> bool {
+        self.push_response(ScriptedResponse::Word(miso))
+    }
+    /// Queue an MSB-first MISO packet for a future transaction, for transfers longer than 32 bits. Bits past MAX_RECORDED_BITS are ignored.
+    /// Returns false if the script is full.
+    pub fn script_response_bits(&mut self, miso: &[u8]) -> bool {
+        let mut bits = [0; MAX_RECORDED_BYTES];
+        let len = miso.len().min(MAX_RECORDED_BYTES);
+        bits[..len].copy_from_slice(&miso[..len]);
+        self.push_response(ScriptedResponse::Bits(bits))
+    }
+    fn push_response(&mut self, response: ScriptedResponse) -> bool {
+        if self.num_responses == MAX_SCRIPTED_RESPONSES {
+            return false;
+        }
+        self.responses[self.num_responses] = response;
+        self.num_responses += 1;
+        true
+    }
+    pub fn transactions(&self) -> &[MockTransaction] {
+        &self.transactions[..self.num_transactions]
+    }
+    pub fn num_dropped(&self) -> usize {
+        self.num_dropped
+    }
+    /// The mode the bus is currently in.
+    pub fn applied_mode(&self) -> BusMode {
+        self.applied_mode
+    }
+    /// Times set_mode was called with a chip selected. SCK moves to the new idle level, which the selected chip would see as a clock edge.
+    pub fn mode_changes_while_selected(&self) -> usize {
+        self.mode_changes_while_selected
+    }
+    /// Forget all recorded transactions and scripted responses.
+    pub fn clear(&mut self) {
+        self.num_transactions = 0;
+        self.num_dropped = 0;
+        self.num_responses = 0;
+        self.next_response = 0;
+        self.mode_changes_while_selected = 0;
+    }
+    fn transaction(&mut self, kind: TransactionKind, len: usize, data: Option<&[u8]>, mut result: Option<&mut [u8]>, cs_pin: &mut impl OutputPin) {
+        cs_pin.set_low().ok();
+        let cs_id = self.chip_selects.selected();
+        cs_pin.set_high().ok();
+
+        let response = if self.next_response < self.num_responses {
+            self.next_response += 1;
+            self.responses[self.next_response - 1]
+        } else {
+            ScriptedResponse::Word(self.idle_miso)
+        };
+
+        let mut record = MockTransaction { kind, len, mosi: [0; MAX_RECORDED_BYTES], miso: [0; MAX_RECORDED_BYTES], cs_id, mode: self.applied_mode };
+        if let Some(result) = result.as_deref_mut() {
+            clear_bits(result, len);
+        }
+        for n in 0..len {
+            let miso_bit = response.bit(len, n);
+            if let Some(result) = result.as_deref_mut() {
+                if miso_bit { set_bit(result, n); }
+            }
+            if n < MAX_RECORDED_BITS {
+                if miso_bit { set_bit(&mut record.miso, n); }
+                if data.is_some_and(|data| bit_is_set(data, n)) { set_bit(&mut record.mosi, n); }
+            }
+        }
+
+        if self.num_transactions < MAX_RECORDED_TRANSACTIONS {
+            self.transactions[self.num_transactions] = record;
+            self.num_transactions += 1;
+        } else {
+            self.num_dropped += 1;
+        }
+    }
+}
+impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPI<POLARITY, PHASE> for MockPayloadSPI<'_, POLARITY, PHASE> {
+    fn send_slice(&mut self, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(TransactionKind::Send, len, Some(data), None, cs_pin)
+    }
+    fn receive_slice(&mut self, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(TransactionKind::Receive, len, None, Some(result), cs_pin)
+    }
+    fn send_receive_slice(&mut self, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(TransactionKind::SendReceive, len, Some(data), Some(result), cs_pin)
+    }
+}
+// The recorded mode is whatever set_mode last applied, not the mode passed in, so a controller that forgets to switch modes shows up in the log.
+impl<const POLARITY: SckPolarity, const PHASE: SckPhase> PayloadSPIAnyMode for MockPayloadSPI<'_, POLARITY, PHASE> {
+    fn set_mode(&mut self, mode: BusMode) {
+        if self.chip_selects.selected.get() != 0 {
+            self.mode_changes_while_selected += 1;
+        }
+        self.applied_mode = mode;
+    }
+    fn send_in_mode(&mut self, _mode: BusMode, len: usize, data: &[u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(TransactionKind::Send, len, Some(data), None, cs_pin)
+    }
+    fn receive_in_mode(&mut self, _mode: BusMode, len: usize, result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(TransactionKind::Receive, len, None, Some(result), cs_pin)
+    }
+    fn send_receive_in_mode(&mut self, _mode: BusMode, len: usize, data: &[u8], result: &mut [u8], cs_pin: &mut impl OutputPin) {
+        self.transaction(TransactionKind::SendReceive, len, Some(data), Some(result), cs_pin)
+    }
+}
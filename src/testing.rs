@@ -1,27 +1,32 @@
 use core::convert::Infallible;
 
 use embedded_hal::digital::v2::{InputPin, OutputPin};
-use msp430fr2355::E_USCI_A1;
-use msp430fr2x5x_hal::serial::{Rx, SerialUsci};
-use msp430fr2x5x_hal::{gpio::Batch, pmm::Pmm};
+use embedded_hal::serial::Read;
 use ufmt::{uWrite, uwrite, uwriteln};
 
+use crate::housekeeping::{NUM_TEMPERATURE_SENSORS, TEMPERATURE_SENSORS};
 use crate::payload::{
-    HeaterState, HeaterState::*, Payload, PayloadState, PayloadState::*, SwitchState,
+    HVDCSupply, HeaterState, HeaterState::*, Payload, PayloadState, PayloadState::*, RampLimits,
+    SwitchState,
 };
+use crate::pcb_common::PayloadHardware;
+use crate::power::{PowerDomain, SwitchableDomain};
 #[allow(unused_imports)]
 use crate::pcb_mapping::{
-    peripheral_vcc_values::*, pin_name_types::*, power_supply_limits::*, power_supply_locations::*,
+    peripheral_vcc_values::*, power_supply_limits::*, power_supply_locations::*,
     sensor_locations::*, *,
 };
-use crate::serial::{read_num, wait_for_any_packet, Printable, SerialWriter, TextColours::*};
+use crate::serial::{read_num, wait_for_any_packet, Printable, TextColours::*};
 #[allow(unused_imports)]
 use crate::{
     adc::*,
     dac::*,
     digipot::*,
-    spi::{SckPhase::SampleFirstEdge, SckPolarity::*, *},
+    housekeeping::HOUSEKEEPING_RECORD_LEN,
+    obc::{FrameType, OBCProtocol, Request, Response},
+    spi::{SckPhase::*, SckPolarity::*, *},
 };
+use crate::units::{Kelvin, Microamps, Milliamps, Millivolts, Ohms};
 use crate::{dbg_println, delay_cycles, print, println};
 use fixed::{self, FixedI64};
 
@@ -34,9 +39,9 @@ use fixed::{self, FixedI64};
 type Fxd = FixedI64<32>;
 
 /// Runs board diagnostics to check whether board functionality is working correctly
-pub fn self_test(
-    payload: Payload<{ PayloadOff }, { HeaterOff }>,
-) -> Payload<{ PayloadOff }, { HeaterOff }> {
+pub fn self_test<H: PayloadHardware>(
+    payload: Payload<{ PayloadOff }, { HeaterOff }, H>,
+) -> Payload<{ PayloadOff }, { HeaterOff }, H> {
     let mut payload = payload.into_enabled_payload().into_enabled_heater();
 
     AutomatedFunctionalTests::full_system_test(&mut payload);
@@ -55,8 +60,12 @@ pub fn self_test(
 /// Functional tests are pass/fail.
 pub struct AutomatedFunctionalTests {}
 impl AutomatedFunctionalTests {
-    pub fn full_system_test(payload: &mut Payload<{ PayloadOn }, { HeaterOn }>) {
+    /// Returns whether every test passed.
+    pub fn full_system_test<H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, { HeaterOn }, H>,
+    ) -> bool {
         println!("==== Automated Functional Tests Start ====");
+        let mut all_passed = true;
         for adc_test_fn in [
             Self::tether_adc_functional_test,
             Self::temperature_adc_functional_test,
@@ -65,25 +74,32 @@ impl AutomatedFunctionalTests {
         ]
         .iter()
         {
-            println!("{}", adc_test_fn(payload));
+            let result = adc_test_fn(payload);
+            all_passed &= !result.failed();
+            println!("{}", result);
         }
 
         for pinpuller_lane in Self::pinpuller_functional_test(payload).iter() {
+            all_passed &= !pinpuller_lane.failed();
             println!("{}", pinpuller_lane);
         }
 
-        println!("{}", Self::heater_functional_test(payload));
+        let result = Self::heater_functional_test(payload);
+        all_passed &= !result.failed();
+        println!("{}", result);
 
         for lms_channel in Self::lms_functional_test(payload).iter() {
+            all_passed &= !lms_channel.failed();
             println!("{}", lms_channel);
         }
 
         println!("==== Automated Functional Tests Complete ====");
+        all_passed
     }
     // Internal function to reduce code duplication
     fn test_adc_functional<CsPin: ADCCSPin, SENSOR: ADCSensor, const VCC: u16>(
         adc: &mut ADC<CsPin, SENSOR, VCC>,
-        spi_bus: &mut impl PayloadSPI<{ IdleHigh }, { SampleFirstEdge }>,
+        spi_bus: &mut impl PayloadSPI<{ IdleHigh }, { SampleSecondEdge }>,
         wanted_channel: ADCChannel,
     ) -> bool {
         let packet = (wanted_channel as u32)
@@ -98,12 +114,12 @@ impl AutomatedFunctionalTests {
     /// Return success if SPI packet valid
     ///
     /// Dependencies: Isolated 5V supply, tether ADC, isolators
-    pub fn tether_adc_functional_test<const DONTCARE: HeaterState>(
-        payload: &mut Payload<{ PayloadOn }, DONTCARE>,
+    pub fn tether_adc_functional_test<const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
     ) -> SensorResult<'_> {
         let result = Self::test_adc_functional(
             &mut payload.tether_adc,
-            payload.spi.borrow(),
+            &mut payload.spi.borrow(),
             ADCChannel::IN7,
         );
         SensorResult {
@@ -119,12 +135,13 @@ impl AutomatedFunctionalTests {
     pub fn temperature_adc_functional_test<
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
+        H: PayloadHardware,
     >(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) -> SensorResult<'_> {
         let result = Self::test_adc_functional(
             &mut payload.temperature_adc,
-            payload.spi.borrow(),
+            &mut payload.spi.borrow(),
             ADCChannel::IN7,
         );
         SensorResult {
@@ -137,11 +154,15 @@ impl AutomatedFunctionalTests {
     /// Return success if SPI packet valid
     ///
     /// Dependencies: misc ADC
-    pub fn misc_adc_functional_test<const DONTCARE1: PayloadState, const DONTCARE2: HeaterState>(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+    pub fn misc_adc_functional_test<
+        const DONTCARE1: PayloadState,
+        const DONTCARE2: HeaterState,
+        H: PayloadHardware,
+    >(
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) -> SensorResult<'_> {
         let result =
-            Self::test_adc_functional(&mut payload.misc_adc, payload.spi.borrow(), ADCChannel::IN7);
+            Self::test_adc_functional(&mut payload.misc_adc, &mut payload.spi.borrow(), ADCChannel::IN7);
         SensorResult {
             name: "Misc ADC",
             result,
@@ -155,31 +176,93 @@ impl AutomatedFunctionalTests {
     pub fn aperture_adc_functional_test<
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
+        H: PayloadHardware,
     >(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) -> SensorResult<'_> {
-        payload.aperture_adc.cs_pin.set_low().ok(); // See 'payload.get_aperture_current_microamps'
-        delay_cycles(5000);
-        let result = Self::test_adc_functional(
-            &mut payload.aperture_adc,
-            payload.spi.borrow(),
-            ADCChannel::IN7,
-        );
+        // The aperture CS pin is also its power switch, so the read must leave it selected
+        let sensor = ApertureSensor {
+            channel: ADCChannel::IN7,
+        };
+        let result = payload
+            .with_aperture_powered(|payload| {
+                payload
+                    .aperture_adc
+                    .read_filtered_count_while_selected_from(&sensor, &mut payload.spi.borrow())
+            })
+            .is_ok();
         SensorResult {
             name: "Aperture ADC",
             result,
         }
     }
 
-    /// TODO
+    /// Wait for the OBC to send a ping, then wait for it to read back our acknowledgement.
+    /// Returns success if a valid ping frame arrived before timing out.
+    ///
+    /// Setup: Connect the OBC (or a master sending ping frames). The debug serial port is unavailable while the OBC bus is in use.
     ///
     /// Dependencies: OBC SPI
-    pub fn obc_spi_functional_test() -> bool {
-        // Set interrupt on cs line(?)
-        // Read spi data
-        // Compare against actual value
-        // Return true if recorded packet matches
-        todo!();
+    #[cfg(target_arch = "msp430")]
+    pub fn obc_spi_functional_test<
+        'a,
+        const DONTCARE1: PayloadState,
+        const DONTCARE2: HeaterState,
+        H: PayloadHardware,
+    >(
+        obc_spi: &mut OBCSPISlave,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
+    ) -> SensorResult<'a> {
+        const NUM_POLLS: u32 = 10_000;
+        let mut protocol = OBCProtocol::new();
+        let mut record = [0; HOUSEKEEPING_RECORD_LEN];
+
+        let mut result = false;
+        for _ in 0..NUM_POLLS {
+            if obc_spi.poll(&mut protocol, |request| {
+                Self::obc_response(request, payload, &mut record)
+            }) == Some(Ok(FrameType::Ping))
+            {
+                result = true;
+                break;
+            }
+            delay_cycles(100);
+        }
+
+        // Give the OBC a chance to read the response.
+        if result {
+            for _ in 0..NUM_POLLS {
+                obc_spi.poll(&mut protocol, |request| {
+                    Self::obc_response(request, payload, &mut record)
+                });
+                if !protocol.response_pending() {
+                    break;
+                }
+                delay_cycles(100);
+            }
+        }
+        SensorResult {
+            name: "OBC SPI",
+            result,
+        }
+    }
+
+    // Housekeeping requests are answered with a fresh snapshot, anything else is acknowledged
+    #[cfg(target_arch = "msp430")]
+    fn obc_response<
+        'r,
+        const DONTCARE1: PayloadState,
+        const DONTCARE2: HeaterState,
+        H: PayloadHardware,
+    >(
+        request: Request,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
+        record: &'r mut [u8; HOUSEKEEPING_RECORD_LEN],
+    ) -> Response<'r> {
+        match request {
+            Request::GetHousekeeping => payload.housekeeping_response(record),
+            _ => Response::Ack,
+        }
     }
 
     /// Setup: Place 1.2 ohm (10W+) resistor (e.g. 30J2R0E) between pinpuller terminals
@@ -188,10 +271,11 @@ impl AutomatedFunctionalTests {
     pub fn pinpuller_functional_test<
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
+        H: PayloadHardware,
     >(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) -> [SensorResult<'_>; 4] {
-        const ON_MILLIAMP_THRESHOLD: u16 = 1000; // TODO: Figure out threshhold
+        const ON_MILLIAMP_THRESHOLD: Milliamps = Milliamps(1000); // TODO: Figure out threshhold
         let mut results = [false; 4];
 
         // Enable each of the four redundant lines.
@@ -200,7 +284,9 @@ impl AutomatedFunctionalTests {
 
         for n in 0..4 {
             pin_select(payload, n).0.set_high().ok();
-            results[n] = payload.get_pinpuller_current_milliamps() > ON_MILLIAMP_THRESHOLD;
+            results[n] = payload
+                .get_pinpuller_current_milliamps()
+                .is_ok_and(|current_ma| current_ma > ON_MILLIAMP_THRESHOLD);
             pin_select(payload, n).0.set_low().ok();
             delay_cycles(1000);
         }
@@ -229,8 +315,8 @@ impl AutomatedFunctionalTests {
     /// Check these values are within 10% of expected values.
     ///
     /// Dependencies: Tether ADC, digipot, isolated 5V supply, isolated 12V supply, heater step-down regulator, signal processing circuitry, isolators
-    pub fn heater_functional_test<'a>(
-        payload: &mut Payload<{ PayloadOn }, { HeaterOn }>,
+    pub fn heater_functional_test<'a, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, { HeaterOn }, H>,
     ) -> SensorResult<'a> {
         // Set heater to min
         payload.set_heater_voltage(HEATER_MIN_VOLTAGE_MILLIVOLTS); // set voltage
@@ -238,10 +324,10 @@ impl AutomatedFunctionalTests {
         // Read voltage
         let min_voltage_mv = payload.get_heater_voltage_millivolts();
         dbg_println!(
-            "Min voltage set to {}. Read as {}, expected at most {}",
+            "Min voltage set to {}. Read as {:?}, expected at most {}",
             HEATER_MIN_VOLTAGE_MILLIVOLTS,
             min_voltage_mv,
-            (HEATER_MIN_VOLTAGE_MILLIVOLTS as u32) * 11 / 10
+            HEATER_MIN_VOLTAGE_MILLIVOLTS * 11 / 10
         );
 
         // Set heater to max
@@ -250,10 +336,10 @@ impl AutomatedFunctionalTests {
         // Read voltage
         let max_voltage_mv = payload.get_heater_voltage_millivolts();
         dbg_println!(
-            "Max voltage set to {}. Read as {}, expected at least {}",
+            "Max voltage set to {}. Read as {:?}, expected at least {}",
             HEATER_MAX_VOLTAGE_MILLIVOLTS,
             max_voltage_mv,
-            (HEATER_MAX_VOLTAGE_MILLIVOLTS as u32) * 9 / 10
+            HEATER_MAX_VOLTAGE_MILLIVOLTS * 9 / 10
         );
 
         // Set heater back to min and give time to settle
@@ -262,8 +348,9 @@ impl AutomatedFunctionalTests {
 
         SensorResult {
             name: "Heater",
-            result: ((min_voltage_mv as u32) < (HEATER_MIN_VOLTAGE_MILLIVOLTS as u32) * 11 / 10)
-                && ((max_voltage_mv as u32) > (HEATER_MAX_VOLTAGE_MILLIVOLTS as u32) * 9 / 10),
+            result: min_voltage_mv
+                .is_ok_and(|mv| mv < HEATER_MIN_VOLTAGE_MILLIVOLTS * 11 / 10)
+                && max_voltage_mv.is_ok_and(|mv| mv > HEATER_MAX_VOLTAGE_MILLIVOLTS * 9 / 10),
         }
     }
 
@@ -271,16 +358,20 @@ impl AutomatedFunctionalTests {
     ///
     /// Setup: Connect LMS board, test in a room with minimal (or at least uniform) IR interference.
     /// Dependencies: LMS power switches, misc ADC, LMS LEDs, LMS receivers
-    pub fn lms_functional_test<const DONTCARE1: PayloadState, const DONTCARE2: HeaterState>(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+    pub fn lms_functional_test<
+        const DONTCARE1: PayloadState,
+        const DONTCARE2: HeaterState,
+        H: PayloadHardware,
+    >(
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) -> [SensorResult<'_>; 3] {
-        let mut ambient_counts: [u16; 3] = [0; 3];
-        let mut on_counts: [u16; 3] = [0; 3];
+        let mut ambient_counts: [Result<u16, ADCError>; 3] = [Ok(0); 3];
+        let mut on_counts: [Result<u16, ADCError>; 3] = [Ok(0); 3];
 
         // Enable phototransistors
-        payload.lms_control_pins.lms_led_enable.set_low().ok();
-        payload.lms_control_pins.lms_receiver_enable.set_high().ok();
-        delay_cycles(100_000);
+        payload.power_down(SwitchableDomain::LMSLEDs);
+        payload.power_up(SwitchableDomain::LMSReceivers);
+        payload.wait_until_live(PowerDomain::LMSReceivers).ok();
 
         // Record max voltage/light value
         for (n, sensor) in [
@@ -293,13 +384,13 @@ impl AutomatedFunctionalTests {
         {
             ambient_counts[n] = payload
                 .misc_adc
-                .read_count_from(sensor, payload.spi.borrow());
+                .read_count_from(sensor, &mut payload.spi.borrow());
         }
         dbg_println!("Read ambient counts as: {:?}", ambient_counts);
 
         // Enable LEDs
-        payload.lms_control_pins.lms_led_enable.set_high().ok();
-        delay_cycles(100_000);
+        payload.power_up(SwitchableDomain::LMSLEDs);
+        payload.wait_until_live(PowerDomain::LMSLEDs).ok();
 
         // Record max voltage/light value
         for (n, sensor) in [
@@ -312,33 +403,38 @@ impl AutomatedFunctionalTests {
         {
             on_counts[n] = payload
                 .misc_adc
-                .read_count_from(sensor, payload.spi.borrow());
+                .read_count_from(sensor, &mut payload.spi.borrow());
         }
         dbg_println!("Read max counts as: {:?}", on_counts);
 
-        payload.lms_control_pins.lms_receiver_enable.set_low().ok();
-        payload.lms_control_pins.lms_led_enable.set_low().ok();
+        payload.power_down(SwitchableDomain::LMSReceivers);
+        payload.power_down(SwitchableDomain::LMSLEDs);
 
+        // A failed read in either pass fails that channel
+        let lms_ok = |n: usize| match (on_counts[n], ambient_counts[n]) {
+            (Ok(on), Ok(ambient)) => on > 2 * ambient,
+            _ => false,
+        };
         [
             SensorResult {
                 name: "Length measurement system 1",
-                result: (on_counts[0] > 2 * ambient_counts[0]),
+                result: lms_ok(0),
             },
             SensorResult {
                 name: "Length measurement system 2",
-                result: (on_counts[1] > 2 * ambient_counts[1]),
+                result: lms_ok(1),
             },
             SensorResult {
                 name: "Length measurement system 3",
-                result: (on_counts[2] > 2 * ambient_counts[2]),
+                result: lms_ok(2),
             },
         ]
     }
 }
 
 /// Selects one of the four pinpuller lines based on an integer value
-fn pin_select<'a, 'b: 'a, const DC1: PayloadState, const DC2: HeaterState>(
-    payload: &'b mut Payload<DC1, DC2>,
+fn pin_select<'a, 'b: 'a, const DC1: PayloadState, const DC2: HeaterState, H: PayloadHardware>(
+    payload: &'b mut Payload<DC1, DC2, H>,
     n: usize,
 ) -> (&'a mut dyn OutputPin<Error = void::Void>, &'static str) {
     match n {
@@ -357,7 +453,9 @@ fn pin_select<'a, 'b: 'a, const DC1: PayloadState, const DC2: HeaterState>(
 
 /// Rather than using percent error (which isn't defined when the actual value is zero), we use Relative Percent Difference (RPD).
 /// Outputs are between -1 and 1. Values near zero are close to percentage error, but 1 means measured is infinitely larger than actual, -1 means measured is infinitely smaller than actual.
-pub fn calculate_rpd(measured: i32, actual: i32) -> Fxd {
+/// Both values must have the same unit.
+pub fn calculate_rpd<T: Into<i32>>(measured: T, actual: T) -> Fxd {
+    let (measured, actual): (i32, i32) = (measured.into(), actual.into());
     if actual == 0 && measured == 0 {
         return Fxd::ZERO;
     }
@@ -384,6 +482,38 @@ pub fn in_place_average(acc: Fxd, new: Fxd, n: u16) -> Fxd {
         .checked_div(Fxd::from(n + 1))
         .unwrap_or(Fxd::ZERO)) // unwrap_or should never fire, since n+1 > 0 when n is unsigned.
 }
+/// calculate_rpd for a sensor reading that may have failed.
+pub fn reading_rpd<T: Into<i32>>(
+    measured: Result<T, ADCError>,
+    actual: T,
+) -> Result<Fxd, ADCError> {
+    measured.map(|measured| calculate_rpd(measured, actual))
+}
+
+/// in_place_average for accuracies taken from sensor readings. Once a read has failed the average stays failed, rather than averaging in a zero.
+pub fn in_place_reading_average(
+    acc: Result<Fxd, ADCError>,
+    new: Result<Fxd, ADCError>,
+    n: u16,
+) -> Result<Fxd, ADCError> {
+    Ok(in_place_average(acc?, new?, n))
+}
+
+/// calculate_performance_result for an accuracy taken from sensor readings. A failed read is NotWorking, however good the other readings were.
+pub fn reading_performance_result(
+    name: &str,
+    accuracy: Result<Fxd, ADCError>,
+    succ_percent: u8,
+    inacc_percent: u8,
+) -> PerformanceResult<'_> {
+    match accuracy {
+        Ok(rpd) => calculate_performance_result(name, rpd, succ_percent, inacc_percent),
+        Err(err) => {
+            println!("{} read failed: {:?}", name, err);
+            PerformanceResult::read_failed(name)
+        }
+    }
+}
 
 pub fn calculate_performance_result(
     name: &str,
@@ -424,8 +554,12 @@ const fn fixed_sqrt(x: Fxd) -> Fxd {
 /// Accuracy-based tests that can be run automatically, possibly after some initial setup.
 pub struct AutomatedPerformanceTests {}
 impl AutomatedPerformanceTests {
-    pub fn full_system_test(payload: &mut Payload<{ PayloadOn }, { HeaterOn }>) {
+    /// Returns whether every test passed. Inaccurate results count as passing, see PerformanceResult::failed.
+    pub fn full_system_test<H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, { HeaterOn }, H>,
+    ) -> bool {
         println!("==== Automatic Performance Tests Start ====");
+        let mut all_passed = true;
         // Each of these three fn's takes the same arguments and both return a voltage and current result
         let fn_arr = [
             Self::test_cathode_offset,
@@ -434,14 +568,20 @@ impl AutomatedPerformanceTests {
         ];
         for sensor_fn in fn_arr.iter() {
             for sensor_result in sensor_fn(payload).iter() {
+                all_passed &= !sensor_result.failed();
                 println!("{}", sensor_result);
             }
         }
-        println!("{}", Self::test_pinpuller_current_sensor(payload));
+        let result = Self::test_pinpuller_current_sensor(payload);
+        all_passed &= !result.failed();
+        println!("{}", result);
 
         println!("==== Automatic Performance Tests Complete ====\n");
+        all_passed
     }
-    pub fn full_system_emitter_test(payload: &mut Payload<{ PayloadOn }, { HeaterOn }>) {
+    pub fn full_system_emitter_test<H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, { HeaterOn }, H>,
+    ) {
         println!("==== Automatic Emitter Performance Tests Start ====");
         // Each of these three fn's takes the same arguments and both return a voltage and current result
         let fn_arr = [
@@ -462,8 +602,8 @@ impl AutomatedPerformanceTests {
     /// Setup: Place a 100k resistor between exterior and cathode-
     ///
     /// Dependencies: Isolated 5V supply, tether ADC, DAC, cathode offset supply, signal processing circuitry, isolators
-    pub fn test_cathode_offset<const DONTCARE: HeaterState>(
-        payload: &mut Payload<{ PayloadOn }, DONTCARE>,
+    pub fn test_cathode_offset<const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
     ) -> [PerformanceResult<'_>; 2] {
         let [voltage_accuracy, current_accuracy] = Self::test_hvdc_supply(
             &Payload::set_cathode_offset_switch,
@@ -477,34 +617,34 @@ impl AutomatedPerformanceTests {
         );
 
         let voltage_result =
-            calculate_performance_result("Cathode offset voltage", voltage_accuracy, 5, 20);
+            reading_performance_result("Cathode offset voltage", voltage_accuracy, 5, 20);
         let current_result =
-            calculate_performance_result("Cathode offset current", current_accuracy, 5, 20);
+            reading_performance_result("Cathode offset current", current_accuracy, 5, 20);
         [voltage_result, current_result]
     }
 
-    pub fn test_cathode_offset_voltage<const DONTCARE: HeaterState>(
-        payload: &mut Payload<{ PayloadOn }, DONTCARE>,
+    pub fn test_cathode_offset_voltage<const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
     ) -> PerformanceResult<'_> {
         let voltage_accuracy = Self::test_hvdc_supply_voltage(
             &Payload::set_cathode_offset_switch,
             &Payload::get_cathode_offset_voltage_millivolts,
             &Payload::set_cathode_offset_voltage,
             CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS,
-            200_000,
+            Millivolts(200_000),
             payload,
         );
 
         let voltage_result =
-            calculate_performance_result("Cathode offset voltage", voltage_accuracy, 5, 20);
+            reading_performance_result("Cathode offset voltage", voltage_accuracy, 5, 20);
         voltage_result
     }
 
     /// Setup: Place a 100k resistor between tether and cathode-
     ///
     /// Dependencies: isolated 5V supply, tether ADC, DAC, tether bias supply, signal processing circuitry, isolators
-    pub fn test_tether_bias<const DONTCARE: HeaterState>(
-        payload: &mut Payload<{ PayloadOn }, DONTCARE>,
+    pub fn test_tether_bias<const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
     ) -> [PerformanceResult<'_>; 2] {
         let [voltage_accuracy, current_accuracy] = Self::test_hvdc_supply(
             &Payload::set_tether_bias_switch,
@@ -518,54 +658,62 @@ impl AutomatedPerformanceTests {
         );
 
         let voltage_result =
-            calculate_performance_result("Tether bias voltage", voltage_accuracy, 5, 20);
+            reading_performance_result("Tether bias voltage", voltage_accuracy, 5, 20);
         let current_result =
-            calculate_performance_result("Tether bias current", current_accuracy, 5, 20);
+            reading_performance_result("Tether bias current", current_accuracy, 5, 20);
         [voltage_result, current_result]
     }
 
-    pub fn test_tether_bias_voltage<const DONTCARE: HeaterState>(
-        payload: &mut Payload<{ PayloadOn }, DONTCARE>,
+    pub fn test_tether_bias_voltage<const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
     ) -> PerformanceResult<'_> {
         let voltage_accuracy = Self::test_hvdc_supply_voltage(
             &Payload::set_tether_bias_switch,
             &Payload::get_tether_bias_voltage_millivolts,
             &Payload::set_tether_bias_voltage,
             TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS,
-            200_000,
+            Millivolts(200_000),
             payload,
         );
 
         let voltage_result =
-            calculate_performance_result("Tether bias voltage", voltage_accuracy, 5, 20);
+            reading_performance_result("Tether bias voltage", voltage_accuracy, 5, 20);
         voltage_result
     }
 
     /// Internal function to reduce code duplication.
-    fn test_hvdc_supply<const DONTCARE: HeaterState>(
-        set_switch_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE>, SwitchState),
-        measure_voltage_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE>) -> i32,
-        measure_current_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE>) -> i32,
-        set_voltage_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE>, u32),
-        supply_min: u32,
-        supply_max: u32,
-        test_resistance: u32,
-        payload: &mut Payload<{ PayloadOn }, DONTCARE>,
-    ) -> [Fxd; 2] {
+    #[allow(
+        clippy::too_many_arguments,
+        reason = "one closure or limit per supply-specific step"
+    )]
+    fn test_hvdc_supply<const DONTCARE: HeaterState, H: PayloadHardware>(
+        set_switch_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE, H>, SwitchState),
+        measure_voltage_fn: &dyn Fn(
+            &mut Payload<{ PayloadOn }, DONTCARE, H>,
+        ) -> Result<Millivolts, ADCError>,
+        measure_current_fn: &dyn Fn(
+            &mut Payload<{ PayloadOn }, DONTCARE, H>,
+        ) -> Result<Microamps, ADCError>,
+        set_voltage_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE, H>, Millivolts),
+        supply_min: Millivolts,
+        supply_max: Millivolts,
+        test_resistance: Ohms,
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
+    ) -> [Result<Fxd, ADCError>; 2] {
         const NUM_MEASUREMENTS: usize = 10;
-        const SENSE_RESISTANCE: u32 = 1; // Both supplies use the same sense resistor value
-        const TEST_START_PERCENT: u32 = 10;
-        const TEST_END_PERCENT: u32 = 100;
-        let mut voltage_accuracy: Fxd = Fxd::ZERO;
-        let mut current_accuracy: Fxd = Fxd::ZERO;
+        const SENSE_RESISTANCE: Ohms = Ohms(1); // Both supplies use the same sense resistor value
+        const TEST_START_PERCENT: i32 = 10;
+        const TEST_END_PERCENT: i32 = 100;
+        let mut voltage_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
+        let mut current_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
         set_switch_fn(payload, SwitchState::Connected); // connect to exterior
         for (i, output_percentage) in (TEST_START_PERCENT..=TEST_END_PERCENT)
             .step_by(100 / NUM_MEASUREMENTS)
             .enumerate()
         {
-            let set_voltage_mv: u32 =
-                ((100 - output_percentage) * (supply_min) + output_percentage * (supply_max)) / 100;
+            let set_voltage_mv: Millivolts =
+                (supply_min * (100 - output_percentage) + supply_max * output_percentage) / 100;
             dbg_println!("");
             dbg_println!("Target output voltage: {}mV", set_voltage_mv);
 
@@ -578,22 +726,23 @@ impl AutomatedPerformanceTests {
             // Read voltage, current
             let measured_voltage_mv = measure_voltage_fn(payload);
             let measured_current_ua = measure_current_fn(payload);
-            dbg_println!("Measured output voltage: {}mV", measured_voltage_mv);
-            dbg_println!("Measured output current: {}uA", measured_current_ua);
+            dbg_println!("Measured output voltage: {:?}", measured_voltage_mv);
+            dbg_println!("Measured output current: {:?}", measured_current_ua);
 
             // Calculate expected voltage and current
-            let expected_voltage_mv: i32 = set_voltage_mv as i32;
-            let expected_current_ua: i32 =
-                ((1000 * set_voltage_mv) / (test_resistance + SENSE_RESISTANCE)) as i32;
+            let expected_voltage_mv: Millivolts = set_voltage_mv;
+            let expected_current_ua: Microamps = set_voltage_mv
+                .current_through(test_resistance + SENSE_RESISTANCE)
+                .unwrap_or(Microamps::ZERO);
 
             dbg_println!("Expected output voltage: {}mV", expected_voltage_mv);
             dbg_println!("Expected output current: {}uA", expected_current_ua);
 
-            let voltage_rpd = calculate_rpd(measured_voltage_mv, expected_voltage_mv);
-            let current_rpd = calculate_rpd(measured_current_ua, expected_current_ua);
+            let voltage_rpd = reading_rpd(measured_voltage_mv, expected_voltage_mv);
+            let current_rpd = reading_rpd(measured_current_ua, expected_current_ua);
 
-            voltage_accuracy = in_place_average(voltage_accuracy, voltage_rpd, i as u16);
-            current_accuracy = in_place_average(current_accuracy, current_rpd, i as u16);
+            voltage_accuracy = in_place_reading_average(voltage_accuracy, voltage_rpd, i as u16);
+            current_accuracy = in_place_reading_average(current_accuracy, current_rpd, i as u16);
         }
 
         // Set back to zero
@@ -605,27 +754,29 @@ impl AutomatedPerformanceTests {
     }
 
     /// Internal function to reduce code duplication.
-    fn test_hvdc_supply_voltage<const DONTCARE: HeaterState>(
-        set_switch_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE>, SwitchState),
-        measure_voltage_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE>) -> i32,
-        set_voltage_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE>, u32),
-        supply_min: u32,
-        supply_max: u32,
-        payload: &mut Payload<{ PayloadOn }, DONTCARE>,
-    ) -> Fxd {
+    fn test_hvdc_supply_voltage<const DONTCARE: HeaterState, H: PayloadHardware>(
+        set_switch_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE, H>, SwitchState),
+        measure_voltage_fn: &dyn Fn(
+            &mut Payload<{ PayloadOn }, DONTCARE, H>,
+        ) -> Result<Millivolts, ADCError>,
+        set_voltage_fn: &dyn Fn(&mut Payload<{ PayloadOn }, DONTCARE, H>, Millivolts),
+        supply_min: Millivolts,
+        supply_max: Millivolts,
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
+    ) -> Result<Fxd, ADCError> {
         const NUM_MEASUREMENTS: usize = 10;
-        const SENSE_RESISTANCE: u32 = 1; // Both supplies use the same sense resistor value
-        const TEST_START_PERCENT: u32 = 10;
-        const TEST_END_PERCENT: u32 = 100;
-        let mut voltage_accuracy: Fxd = Fxd::ZERO;
+        const SENSE_RESISTANCE: Ohms = Ohms(1); // Both supplies use the same sense resistor value
+        const TEST_START_PERCENT: i32 = 10;
+        const TEST_END_PERCENT: i32 = 100;
+        let mut voltage_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
         set_switch_fn(payload, SwitchState::Connected); // connect to exterior
         for (i, output_percentage) in (TEST_START_PERCENT..=TEST_END_PERCENT)
             .step_by(100 / NUM_MEASUREMENTS)
             .enumerate()
         {
-            let set_voltage_mv: u32 =
-                ((100 - output_percentage) * (supply_min) + output_percentage * (supply_max)) / 100;
+            let set_voltage_mv: Millivolts =
+                (supply_min * (100 - output_percentage) + supply_max * output_percentage) / 100;
             dbg_println!("Target output voltage: {}mV", set_voltage_mv);
 
             // Set cathode voltage
@@ -636,15 +787,15 @@ impl AutomatedPerformanceTests {
 
             // Read voltage, current
             let measured_voltage_mv = measure_voltage_fn(payload);
-            dbg_println!("Measured output voltage: {}mV", measured_voltage_mv);
+            dbg_println!("Measured output voltage: {:?}", measured_voltage_mv);
 
             // Calculate expected voltage and current
-            let expected_voltage_mv: i32 = set_voltage_mv as i32;
+            let expected_voltage_mv: Millivolts = set_voltage_mv;
             dbg_println!("Expected output voltage: {}mV", expected_voltage_mv);
 
-            let voltage_rpd = calculate_rpd(measured_voltage_mv, expected_voltage_mv);
+            let voltage_rpd = reading_rpd(measured_voltage_mv, expected_voltage_mv);
 
-            voltage_accuracy = in_place_average(voltage_accuracy, voltage_rpd, i as u16);
+            voltage_accuracy = in_place_reading_average(voltage_accuracy, voltage_rpd, i as u16);
             dbg_println!("");
         }
 
@@ -658,19 +809,19 @@ impl AutomatedPerformanceTests {
     /// Setup: 10 ohm resistor across heater+ and heater-
     ///
     /// Dependencies: Tether ADC, digipot, isolated 5V supply, isolated 12V supply, heater step-down regulator, signal processing circuitry, isolators
-    pub fn test_heater(
-        payload: &mut Payload<{ PayloadOn }, { HeaterOn }>,
+    pub fn test_heater<H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, { HeaterOn }, H>,
     ) -> [PerformanceResult<'_>; 2] {
         const NUM_MEASUREMENTS: usize = 10;
 
-        let mut voltage_accuracy: Fxd = Fxd::ZERO;
-        let mut current_accuracy: Fxd = Fxd::ZERO;
+        let mut voltage_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
+        let mut current_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
-        for (i, output_percentage) in (0..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u16 = (((100 - output_percentage)
-                * (HEATER_MIN_VOLTAGE_MILLIVOLTS as u32)
-                + output_percentage * (2_000 as u32))
-                / 100) as u16;
+        for (i, output_percentage) in (0..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts = (HEATER_MIN_VOLTAGE_MILLIVOLTS
+                * (100 - output_percentage)
+                + Millivolts(2_000) * output_percentage)
+                / 100;
 
             // Set cathode voltage
             payload.set_heater_voltage(output_voltage_mv);
@@ -681,34 +832,35 @@ impl AutomatedPerformanceTests {
 
             // Read voltage, current
             let heater_voltage_mv = payload.get_heater_voltage_millivolts();
-            dbg_println!("Read voltage as: {}mV", heater_voltage_mv);
+            dbg_println!("Read voltage as: {:?}", heater_voltage_mv);
             let heater_current_ma = payload.get_heater_current_milliamps();
-            dbg_println!("Read current as: {}mA", heater_current_ma);
+            dbg_println!("Read current as: {:?}", heater_current_ma);
 
             // Calculate expected voltage and current
-            let expected_voltage_mv: u16 = output_voltage_mv;
-            let expected_current_ma: i16 =
-                (expected_voltage_mv as u32 * 1000 / heater_mock::CIRCUIT_RESISTANCE_MOHMS as u32)
-                    .min(heater_mock::POWER_LIMITED_MAX_CURRENT_MA.to_num()) as i16;
+            let expected_voltage_mv: Millivolts = output_voltage_mv;
+            let expected_current_ma: Milliamps = Milliamps(
+                expected_voltage_mv.0 * 1000 / heater_mock::CIRCUIT_RESISTANCE_MOHMS as i32,
+            )
+            .min(Milliamps(heater_mock::POWER_LIMITED_MAX_CURRENT_MA.to_num()));
             dbg_println!("Expected current is: {}mA", expected_current_ma);
 
-            let voltage_rpd = calculate_rpd(heater_voltage_mv as i32, expected_voltage_mv as i32);
-            dbg_println!(
-                "Voltage milliRPD is: {}",
-                (voltage_rpd * 1000).to_num::<i32>()
-            );
-            voltage_accuracy = in_place_average(voltage_accuracy, voltage_rpd, i as u16);
-            current_accuracy = in_place_average(
+            let voltage_rpd = reading_rpd(heater_voltage_mv, expected_voltage_mv);
+            if let Ok(voltage_rpd) = voltage_rpd {
+                dbg_println!(
+                    "Voltage milliRPD is: {}",
+                    (voltage_rpd * 1000).to_num::<i32>()
+                );
+            }
+            voltage_accuracy = in_place_reading_average(voltage_accuracy, voltage_rpd, i as u16);
+            current_accuracy = in_place_reading_average(
                 current_accuracy,
-                calculate_rpd(heater_current_ma as i32, expected_current_ma as i32),
+                reading_rpd(heater_current_ma, expected_current_ma),
                 i as u16,
             );
         }
 
-        let voltage_result =
-            calculate_performance_result("Heater voltage", voltage_accuracy, 5, 20);
-        let current_result =
-            calculate_performance_result("Heater current", current_accuracy, 5, 20);
+        let voltage_result = reading_performance_result("Heater voltage", voltage_accuracy, 5, 20);
+        let current_result = reading_performance_result("Heater current", current_accuracy, 5, 20);
         [voltage_result, current_result]
     }
 
@@ -718,10 +870,11 @@ impl AutomatedPerformanceTests {
     pub fn test_pinpuller_current_sensor<
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
+        H: PayloadHardware,
     >(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) -> PerformanceResult<'_> {
-        let mut accuracy: Fxd = Fxd::ZERO;
+        let mut accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
         // For each pin, activate the pinpuller through that channel and measure the current
         dbg_println!("");
@@ -729,12 +882,12 @@ impl AutomatedPerformanceTests {
             pin_select(payload, n).0.set_high().ok();
             delay_cycles(1_000);
             let measured_current = payload.get_pinpuller_current_milliamps();
-            dbg_println!("Measured current as {}mA", measured_current);
-            accuracy = in_place_average(
+            dbg_println!("Measured current as {:?}", measured_current);
+            accuracy = in_place_reading_average(
                 accuracy,
-                calculate_rpd(
-                    measured_current as i32,
-                    pinpuller_mock::EXPECTED_ON_CURRENT.to_num(),
+                reading_rpd(
+                    measured_current,
+                    Milliamps(pinpuller_mock::EXPECTED_ON_CURRENT.to_num()),
                 ),
                 n as u16,
             );
@@ -742,21 +895,21 @@ impl AutomatedPerformanceTests {
             delay_cycles(1000);
         }
 
-        calculate_performance_result("Pinpuller current sense", accuracy, 5, 20)
+        reading_performance_result("Pinpuller current sense", accuracy, 5, 20)
     }
 
     // Connect repeller plate to HVDC tether supply (Pin 3 of S1_TBS) to cover a range of 25-250V
-    pub fn test_repeller_voltage<'a, const DONTCARE: HeaterState, USCI: SerialUsci>(
-        payload: &'a mut Payload<{ PayloadOn }, DONTCARE>,
-        spi_bus: &'a mut PayloadSPIController,
-        debug_writer: &mut SerialWriter<USCI>,
+    pub fn test_repeller_voltage<'a, const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &'a mut Payload<{ PayloadOn }, DONTCARE, H>,
+        spi_bus: &'a mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        debug_writer: &mut impl uWrite,
     ) -> [PerformanceResult<'a>; 1] {
-        let mut voltage_accuracy: Fxd = Fxd::ZERO;
-        let supply_min: u32 = TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS;
-        let supply_max: u32 = TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS;
+        let mut voltage_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
+        let supply_min: Millivolts = TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS;
+        let supply_max: Millivolts = TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS;
         const NUM_MEASUREMENTS: usize = 10;
-        const TEST_START_PERCENT: u32 = 10;
-        const TEST_END_PERCENT: u32 = 100;
+        const TEST_START_PERCENT: i32 = 10;
+        const TEST_END_PERCENT: i32 = 100;
 
         Payload::set_tether_bias_switch(payload, SwitchState::Connected);
 
@@ -765,8 +918,8 @@ impl AutomatedPerformanceTests {
             .enumerate()
         {
             // Set tether voltage
-            let set_voltage_mv: u32 =
-                ((100 - output_percentage) * (supply_min) + output_percentage * (supply_max)) / 100;
+            let set_voltage_mv: Millivolts =
+                (supply_min * (100 - output_percentage) + supply_max * output_percentage) / 100;
             payload.set_tether_bias_voltage(set_voltage_mv);
             dbg_println!("Target output voltage: {}mV", set_voltage_mv);
             delay_cycles(100_000); //settling time
@@ -775,35 +928,53 @@ impl AutomatedPerformanceTests {
             let measured_repeller_voltage_mv = payload.get_repeller_voltage_millivolts();
             let measured_tether_voltage_mv = payload.get_tether_bias_voltage_millivolts();
             dbg_println!(
-                "Measured repeller voltage: {}mV",
+                "Measured repeller voltage: {:?}",
                 measured_repeller_voltage_mv
             );
-            dbg_println!("Measured tether voltage: {}mV", measured_tether_voltage_mv);
-
-            // Measure rpd and accuracy
-            let voltage_rpd =
-                calculate_rpd(measured_repeller_voltage_mv as i32, set_voltage_mv as i32);
+            dbg_println!("Measured tether voltage: {:?}", measured_tether_voltage_mv);
             dbg_println!(
-                "Voltage milliRPD is: {}",
-                (voltage_rpd * 1000).to_num::<i32>()
+                "Repeller sample spread: {}mV",
+                payload.get_repeller_voltage_spread_millivolts()
             );
-            voltage_accuracy = in_place_average(voltage_accuracy, voltage_rpd, i as u16);
+
+            // Measure rpd and accuracy
+            let voltage_rpd = reading_rpd(measured_repeller_voltage_mv, set_voltage_mv);
+            if let Ok(voltage_rpd) = voltage_rpd {
+                dbg_println!(
+                    "Voltage milliRPD is: {}",
+                    (voltage_rpd * 1000).to_num::<i32>()
+                );
+            }
+            voltage_accuracy = in_place_reading_average(voltage_accuracy, voltage_rpd, i as u16);
         }
 
         Payload::set_tether_bias_switch(payload, SwitchState::Disconnected);
 
         let voltage_result =
-            calculate_performance_result("Repeller voltage", voltage_accuracy, 5, 20);
+            reading_performance_result("Repeller voltage", voltage_accuracy, 5, 20);
         [voltage_result]
     }
 
-    pub fn test_aperture_current_sensor<USCI: SerialUsci>(
-        payload: &mut Payload<{ PayloadOn }, { HeaterOn }>,
-        spi_bus: &mut PayloadSPIController,
-        serial_writer: &mut SerialWriter<USCI>,
+    pub fn test_aperture_current_sensor<H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, { HeaterOn }, H>,
+        spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        serial_writer: &mut impl uWrite,
     ) {
         uwriteln!(serial_writer, "Here1").ok();
-        payload.set_cathode_offset_voltage(CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS);
+        if let Err(err) = payload.ramp_hvdc_supply(
+            HVDCSupply::CathodeOffset,
+            CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS,
+            HVDC_RAMP_VOLTS_PER_SECOND,
+            RampLimits::default_for(HVDCSupply::CathodeOffset),
+        ) {
+            uwriteln!(
+                serial_writer,
+                "Cathode offset ramp stopped at {}mV: {:?}",
+                err.stopped_at,
+                err.cause
+            )
+            .ok();
+        }
         uwriteln!(serial_writer, "Here2").ok();
         payload.set_cathode_offset_switch(SwitchState::Connected);
         uwriteln!(serial_writer, "Here3").ok();
@@ -813,7 +984,7 @@ impl AutomatedPerformanceTests {
         uwriteln!(serial_writer, "Here5").ok();
 
         for cycles in 1..4 {
-            for heater_voltage_mv in (900..3100).step_by(100) {
+            for heater_voltage_mv in (900..3100).step_by(100).map(Millivolts) {
                 uwriteln!(
                     serial_writer,
                     "Heater voltage set to: {}mV",
@@ -828,39 +999,37 @@ impl AutomatedPerformanceTests {
                     payload.get_cathode_offset_voltage_millivolts();
                 let measured_cathode_offset_current_ua =
                     payload.get_cathode_offset_current_microamps();
-                let measured_aperture_adc_mv = payload
-                    .aperture_adc
-                    .read_voltage_from(&APERTURE_CURRENT_SENSOR, &mut payload.spi);
-                let measured_aperture_current_ua =
-                    self::sensor_equations::aperture_current_sensor_eq(measured_aperture_adc_mv);
+                let measured_aperture_adc_mv = payload.read_aperture_adc_voltage();
+                let measured_aperture_current_ua = measured_aperture_adc_mv
+                    .map(self::sensor_equations::aperture_current_sensor_eq);
 
                 uwriteln!(
                     serial_writer,
-                    "Measured heater voltage: {}mV",
+                    "Measured heater voltage: {:?}",
                     measured_heater_voltage_mv
                 )
                 .ok();
                 uwriteln!(
                     serial_writer,
-                    "Measured cathode offset voltage: {}mV",
+                    "Measured cathode offset voltage: {:?}",
                     measured_cathode_offset_voltage_mv
                 )
                 .ok();
                 uwriteln!(
                     serial_writer,
-                    "Measured cathode offset current: {}uA",
+                    "Measured cathode offset current: {:?}",
                     measured_cathode_offset_current_ua
                 )
                 .ok();
                 uwriteln!(
                     serial_writer,
-                    "Measured aperture ADC voltage: {}mV",
+                    "Measured aperture ADC voltage: {:?}",
                     measured_aperture_adc_mv
                 )
                 .ok();
                 uwriteln!(
                     serial_writer,
-                    "Measured aperture current: {}uA",
+                    "Measured aperture current: {:?}",
                     measured_aperture_current_ua
                 )
                 .ok();
@@ -868,7 +1037,18 @@ impl AutomatedPerformanceTests {
                 delay_cycles(3_000_000);
             }
         }
-        payload.set_cathode_offset_voltage(CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS);
+        // Don't leave the supply part way up if the ramp down is aborted
+        if payload
+            .ramp_hvdc_supply(
+                HVDCSupply::CathodeOffset,
+                CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS,
+                HVDC_RAMP_VOLTS_PER_SECOND,
+                RampLimits::default_for(HVDCSupply::CathodeOffset),
+            )
+            .is_err()
+        {
+            payload.set_cathode_offset_voltage(CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS);
+        }
         payload.set_cathode_offset_switch(SwitchState::Disconnected);
     }
 }
@@ -876,8 +1056,12 @@ impl AutomatedPerformanceTests {
 /// Tests that require human intervention. These are pass/fail tests.
 pub struct ManualFunctionalTests {}
 impl ManualFunctionalTests {
-    pub fn full_system_test<const DONTCARE1: PayloadState, const DONTCARE2: HeaterState>(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+    pub fn full_system_test<
+        const DONTCARE1: PayloadState,
+        const DONTCARE2: HeaterState,
+        H: PayloadHardware,
+    >(
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) {
         println!("==== Manual Functional Tests Start ====");
 
@@ -892,8 +1076,9 @@ impl ManualFunctionalTests {
         'b,
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
+        H: PayloadHardware,
     >(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
     ) -> [SensorResult<'b>; 2] {
         println!("Depress switches then press enter");
         wait_for_any_packet(&mut payload.serial_reader);
@@ -945,10 +1130,10 @@ impl ManualFunctionalTests {
         'a,
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
-        USCI: SerialUsci,
+        H: PayloadHardware,
     >(
-        payload: &mut Payload<DONTCARE1, DONTCARE2>,
-        serial_reader: &mut Rx<USCI>,
+        payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
+        serial_reader: &mut impl Read<u8>,
     ) -> [PerformanceResult<'a>; 4] {
         // Enable each of the four redundant lines.
 
@@ -1014,54 +1199,40 @@ pub mod heater_mock {
         MOCK_HEATER_RESISTANCE_MOHMS + super::HEATER_SENSE_RESISTANCE_MILLIOHMS as u16; // heater resistance + shunt resistor
     pub const CIRCUIT_AND_PROBE_RESISTANCE_MOHMS: u16 =
         CIRCUIT_RESISTANCE_MOHMS + PROBE_RESISTANCE_MOHMS;
-    const HEATER_MAX_POWER_MWATTS: u16 = 1000; // TODO: Verify?
 
     pub const POWER_LIMITED_MAX_CURRENT_MA: Fxd = fixed_sqrt(
-        Fxd::const_from_int(HEATER_MAX_POWER_MWATTS as i64)
+        Fxd::const_from_int(super::HEATER_MAX_POWER_MILLIWATTS.0 as i64)
             .unwrapped_div_int(CIRCUIT_RESISTANCE_MOHMS as i64),
     )
     .unwrapped_mul_int(1000); //sqrt(heater_max_power_mw / circuit_resistance_mohm) * 1000;
 }
 
 pub mod hvdc_mock {
-    pub const MOCK_TETHER_BIAS_RESISTANCE_OHMS: u32 = 98_150;
-    pub const MOCK_CATHODE_OFFSET_RESISTANCE_OHMS: u32 = 98_300;
+    use crate::units::Ohms;
+
+    pub const MOCK_TETHER_BIAS_RESISTANCE_OHMS: Ohms = Ohms(98_150);
+    pub const MOCK_CATHODE_OFFSET_RESISTANCE_OHMS: Ohms = Ohms(98_300);
 }
 
-fn test_temperature_sensors_against_known_temp<
-    'a,
+/// Compare each temperature sensor against a known room temperature.
+pub fn test_temperature_sensors_against_known_temp<
     const DONTCARE1: PayloadState,
     const DONTCARE2: HeaterState,
-    USCI: SerialUsci,
+    H: PayloadHardware,
 >(
-    room_temp_k: u16,
-    payload: &'a mut Payload<DONTCARE1, DONTCARE2>,
-    serial_writer: &'a mut SerialWriter<USCI>,
-    serial_reader: &'a mut Rx<USCI>,
-    spi_bus: &'a mut PayloadSPIController,
-) -> [PerformanceResult<'static>; 8] {
-    const TEMP_SENSORS: [(TemperatureSensor, &str); 8] = [
-        (LMS_EMITTER_TEMPERATURE_SENSOR, "LMS Emitter"),
-        (LMS_RECEIVER_TEMPERATURE_SENSOR, "LMS Receiver"),
-        (MSP430_TEMPERATURE_SENSOR, "MSP430"),
-        (HEATER_SUPPLY_TEMPERATURE_SENSOR, "Heater supply"),
-        (HVDC_SUPPLIES_TEMPERATURE_SENSOR, "HVDC Supplies"),
-        (TETHER_MONITORING_TEMPERATURE_SENSOR, "Tether monitoring"),
-        (TETHER_CONNECTOR_TEMPERATURE_SENSOR, "Tether connector"),
-        (MSP_3V3_TEMPERATURE_SENSOR, "MSP 3V3 supply"),
-    ];
-
-    let mut output_arr: [PerformanceResult; 8] = [PerformanceResult::default(); 8];
-    for (n, (sensor, name)) in TEMP_SENSORS.iter().enumerate() {
-        let tempr = payload.get_temperature_kelvin(sensor);
-        let accuracy = calculate_rpd(tempr as i32, room_temp_k as i32);
-        output_arr[n] = calculate_performance_result(name, accuracy, 5, 20)
+    room_temp_k: Kelvin,
+    payload: &mut Payload<DONTCARE1, DONTCARE2, H>,
+) -> [PerformanceResult<'static>; NUM_TEMPERATURE_SENSORS] {
+    let mut output_arr: [PerformanceResult; NUM_TEMPERATURE_SENSORS] =
+        [PerformanceResult::default(); NUM_TEMPERATURE_SENSORS];
+    for (n, (sensor, name)) in TEMPERATURE_SENSORS.iter().enumerate() {
+        let accuracy = reading_rpd(payload.get_temperature_kelvin(sensor), room_temp_k);
+        output_arr[n] = reading_performance_result(name, accuracy, 5, 20)
     }
 
     output_arr
 }
 
-const CELCIUS_TO_KELVIN_OFFSET: u16 = 273;
 // Accuracy-based tests
 pub struct ManualPerformanceTests {}
 impl ManualPerformanceTests {
@@ -1088,43 +1259,31 @@ impl ManualPerformanceTests {
         todo!();
     }*/
     /// Get room temp from user
-    fn query_room_temp<USCI: SerialUsci>(
-        serial_writer: &mut SerialWriter<USCI>,
-        serial_reader: &mut Rx<USCI>,
-    ) -> u16 {
+    fn query_room_temp(
+        serial_writer: &mut impl uWrite,
+        serial_reader: &mut impl Read<u8>,
+    ) -> Kelvin {
         println!("Enter current temp (in celcius)");
         let celcius_num = read_num(serial_reader);
-        (celcius_num + CELCIUS_TO_KELVIN_OFFSET as i32) as u16
+        Kelvin::from_celcius(celcius_num)
     }
     pub fn two_point_test_temperature_sensor_test<
         'a,
-        USCI: SerialUsci,
         const DONTCARE: HeaterState,
+        H: PayloadHardware,
     >(
-        payload: &'a mut Payload<{ PayloadOff }, DONTCARE>, // Minimise heat generation
-        serial_writer: &'a mut SerialWriter<USCI>,
-        serial_reader: &'a mut Rx<USCI>,
-        spi_bus: &'a mut PayloadSPIController,
+        payload: &'a mut Payload<{ PayloadOff }, DONTCARE, H>, // Minimise heat generation
+        serial_writer: &'a mut impl uWrite,
+        serial_reader: &'a mut impl Read<u8>,
+        spi_bus: &'a mut PayloadSPIController<impl PayloadSPIAnyMode>,
     ) -> [PerformanceResult<'a>; 8] {
-        let mut room_temp_k: u16 = Self::query_room_temp(serial_writer, serial_reader);
+        let mut room_temp_k: Kelvin = Self::query_room_temp(serial_writer, serial_reader);
 
-        let arr1 = test_temperature_sensors_against_known_temp(
-            room_temp_k,
-            payload,
-            serial_writer,
-            serial_reader,
-            spi_bus,
-        );
+        let arr1 = test_temperature_sensors_against_known_temp(room_temp_k, payload);
 
         room_temp_k = Self::query_room_temp(serial_writer, serial_reader);
 
-        let arr2 = test_temperature_sensors_against_known_temp(
-            room_temp_k,
-            payload,
-            serial_writer,
-            serial_reader,
-            spi_bus,
-        );
+        let arr2 = test_temperature_sensors_against_known_temp(room_temp_k, payload);
 
         let mut result_arr: [PerformanceResult; 8] = [PerformanceResult::default(); 8];
 
@@ -1136,19 +1295,19 @@ impl ManualPerformanceTests {
     }
 
     /// Dependencies: Isolated 5V supply, DAC, isolators
-    pub fn test_dac<'a, const DONTCARE: HeaterState, USCI: SerialUsci>(
-        payload: &'a mut Payload<{ PayloadOn }, DONTCARE>,
+    pub fn test_dac<'a, const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &'a mut Payload<{ PayloadOn }, DONTCARE, H>,
         spi_bus: &'a mut impl PayloadSPI<{ IdleLow }, { SampleFirstEdge }>,
-        debug_writer: &mut SerialWriter<USCI>,
-        serial_reader: &mut Rx<USCI>,
+        debug_writer: &mut impl uWrite,
+        serial_reader: &mut impl Read<u8>,
     ) -> PerformanceResult<'a> {
         const NUM_MEASUREMENTS: usize = 5;
         let mut voltage_accuracy: Fxd = Fxd::ZERO;
 
-        for (i, output_percentage) in (1..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u16 =
-                ((output_percentage * DAC_VCC_VOLTAGE_MILLIVOLTS as u32) / 100) as u16;
-            let dac_count = DAC::voltage_to_count(output_voltage_mv);
+        for (i, output_percentage) in (1..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts =
+                Millivolts::from(DAC_VCC_VOLTAGE_MILLIVOLTS) * output_percentage / 100;
+            let dac_count = payload.dac.voltage_to_count(output_voltage_mv);
             uwriteln!(
                 debug_writer,
                 "Target output voltage: {}mV. DAC count: {}",
@@ -1158,21 +1317,18 @@ impl ManualPerformanceTests {
             .ok();
 
             // Set DAC voltage
-            payload.dac.send_command(
-                DACCommand::WriteToAndUpdateRegisterX,
-                DACChannel::ChannelC,
-                dac_count,
-                spi_bus,
-            );
+            payload
+                .dac
+                .write_and_update(DACChannel::ChannelC, dac_count, spi_bus);
 
             delay_cycles(1000); //settling time
 
             // Read cathode voltage, current
             uwrite!(debug_writer, "Measure voltage and enter in mV: ").ok();
-            let measured_voltage_mv = read_num(serial_reader);
+            let measured_voltage_mv = Millivolts(read_num(serial_reader));
             uwriteln!(debug_writer, "").ok();
 
-            let voltage_rpd = calculate_rpd(measured_voltage_mv, output_voltage_mv as i32);
+            let voltage_rpd = calculate_rpd(measured_voltage_mv, output_voltage_mv);
             uwriteln!(
                 debug_writer,
                 "Calculated voltage millirpd: {}",
@@ -1184,12 +1340,10 @@ impl ManualPerformanceTests {
         }
 
         // Set back to zero
-        payload.dac.send_command(
-            DACCommand::WriteToAndUpdateRegisterX,
-            DACChannel::ChannelA,
-            DAC::voltage_to_count(0),
-            spi_bus,
-        );
+        let zero_count = payload.dac.voltage_to_count(Millivolts::ZERO);
+        payload
+            .dac
+            .write_and_update(DACChannel::ChannelA, zero_count, spi_bus);
 
         let voltage_result =
             calculate_performance_result("Cathode offset voltage", voltage_accuracy, 5, 20);
@@ -1214,21 +1368,21 @@ impl ManualPerformanceTests {
     }*/
 
     /// Dependencies: DAC
-    pub fn test_cathode_offset_voltage<'a, const DONTCARE: HeaterState>(
-        payload: &'a mut Payload<{ PayloadOn }, DONTCARE>,
-        // spi_bus: &'a mut PayloadSPIController,
-        // debug_writer: &mut SerialWriter<USCI>,
-        // serial_reader: &mut Rx<USCI>,
-    ) -> PerformanceResult<'a> {
+    pub fn test_cathode_offset_voltage<const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
+        // spi_bus: &'a mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        // debug_writer: &mut impl uWrite,
+        // serial_reader: &mut impl Read<u8>,
+    ) -> PerformanceResult<'_> {
         const NUM_MEASUREMENTS: usize = 10;
-        const TEST_RESISTANCE: u32 = 100_000;
+        const TEST_RESISTANCE: Ohms = Ohms(100_000);
         let mut voltage_accuracy: Fxd = Fxd::ZERO;
 
         payload.set_cathode_offset_switch(SwitchState::Connected); // connect to exterior
-        for (i, output_percentage) in (10..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u32 = ((100 - output_percentage)
-                * (CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS)
-                + output_percentage * (CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS))
+        for (i, output_percentage) in (10..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts = (CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS
+                * (100 - output_percentage)
+                + CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS * output_percentage)
                 / 100;
             println!(
                 "Target output voltage: {}mV",
@@ -1242,15 +1396,15 @@ impl ManualPerformanceTests {
 
             // Read cathode voltage, current
             print!("Measure voltage and input (in mV): ");
-            let measured_voltage_mv = read_num(&mut payload.serial_reader);
+            let measured_voltage_mv = Millivolts(read_num(&mut payload.serial_reader));
             println!("");
 
             println!(
-                "Cathode offset mv: {}",
+                "Cathode offset: {:?}",
                 payload.get_cathode_offset_voltage_millivolts()
             );
 
-            let voltage_rpd = calculate_rpd(measured_voltage_mv, output_voltage_mv as i32);
+            let voltage_rpd = calculate_rpd(measured_voltage_mv, output_voltage_mv);
             println!(
                 "Calculated voltage millirpd: {}",
                 (voltage_rpd * 1000).to_num::<i32>()
@@ -1268,47 +1422,49 @@ impl ManualPerformanceTests {
         voltage_result
     }
 
-    pub fn test_cathode_offset_current<'a, const DONTCARE: HeaterState, USCI: SerialUsci>(
-        payload: &'a mut Payload<{ PayloadOn }, DONTCARE>,
-        spi_bus: &'a mut PayloadSPIController,
-        debug_writer: &mut SerialWriter<USCI>,
-        serial_reader: &mut Rx<USCI>,
+    pub fn test_cathode_offset_current<'a, const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &'a mut Payload<{ PayloadOn }, DONTCARE, H>,
+        spi_bus: &'a mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        debug_writer: &mut impl uWrite,
+        serial_reader: &mut impl Read<u8>,
     ) -> PerformanceResult<'a> {
         const NUM_MEASUREMENTS: usize = 10;
-        const TEST_RESISTANCE: u32 = 100_000;
-        let mut current_accuracy: Fxd = Fxd::ZERO;
+        const TEST_RESISTANCE: Ohms = Ohms(100_000);
+        let mut current_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
         payload.set_cathode_offset_switch(SwitchState::Connected); // connect to exterior
-        for (i, output_percentage) in (10..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u32 = ((100 - output_percentage)
-                * (CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS)
-                + output_percentage * (CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS))
+        for (i, output_percentage) in (10..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts = (CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS
+                * (100 - output_percentage)
+                + CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS * output_percentage)
                 / 100;
 
-            let expected_voltage_mv: u32 = output_voltage_mv; // assume zero error between target voltage and actual voltage
-            let expected_current_ua: i16 = ((1000 * expected_voltage_mv)
-                / (hvdc_mock::MOCK_CATHODE_OFFSET_RESISTANCE_OHMS + CATHODE_SENSE_RESISTANCE_OHMS))
-                as i16;
+            let expected_voltage_mv: Millivolts = output_voltage_mv; // assume zero error between target voltage and actual voltage
+            let expected_current_ua: Microamps = expected_voltage_mv
+                .current_through(hvdc_mock::MOCK_CATHODE_OFFSET_RESISTANCE_OHMS + CATHODE_SENSE_RESISTANCE_OHMS)
+                .unwrap_or(Microamps::ZERO);
             dbg_println!("Expected current is: {}mA", expected_current_ua);
 
             //Manually measure the current
             uwrite!(debug_writer, "Measure current and input (in uA): ").ok();
-            let actual_current_ua = read_num(serial_reader);
+            let actual_current_ua = Microamps(read_num(serial_reader));
             uwriteln!(debug_writer, "").ok();
 
             // Measure current
-            let measured_current_ua: i32 = payload.get_cathode_offset_current_microamps();
-            dbg_println!("Measured current is: {}uA", measured_current_ua);
+            let measured_current_ua = payload.get_cathode_offset_current_microamps();
+            dbg_println!("Measured current is: {:?}", measured_current_ua);
 
             //Determine accuracy
-            let current_rpd = calculate_rpd(measured_current_ua, actual_current_ua);
-            uwriteln!(
-                debug_writer,
-                "Calculated current millirpd: {}",
-                (current_rpd * 1000).to_num::<i32>()
-            )
-            .ok();
-            current_accuracy = in_place_average(current_accuracy, current_rpd, i as u16);
+            let current_rpd = reading_rpd(measured_current_ua, actual_current_ua);
+            if let Ok(current_rpd) = current_rpd {
+                uwriteln!(
+                    debug_writer,
+                    "Calculated current millirpd: {}",
+                    (current_rpd * 1000).to_num::<i32>()
+                )
+                .ok();
+            }
+            current_accuracy = in_place_reading_average(current_accuracy, current_rpd, i as u16);
         }
 
         // Set back to zero
@@ -1316,22 +1472,22 @@ impl ManualPerformanceTests {
         payload.set_cathode_offset_switch(SwitchState::Disconnected);
 
         let current_result =
-            calculate_performance_result("Cathode offset current", current_accuracy, 5, 20);
+            reading_performance_result("Cathode offset current", current_accuracy, 5, 20);
         current_result
     }
 
-    pub fn test_tether_bias_voltage<'a, const DONTCARE: HeaterState>(
-        payload: &'a mut Payload<{ PayloadOn }, DONTCARE>,
-    ) -> PerformanceResult<'a> {
+    pub fn test_tether_bias_voltage<const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, DONTCARE, H>,
+    ) -> PerformanceResult<'_> {
         const NUM_MEASUREMENTS: usize = 10;
-        const TEST_RESISTANCE: u32 = 100_000;
+        const TEST_RESISTANCE: Ohms = Ohms(100_000);
         let mut voltage_accuracy: Fxd = Fxd::ZERO;
 
         payload.set_tether_bias_switch(SwitchState::Connected); // connect to exterior
-        for (i, output_percentage) in (10..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u32 = ((100 - output_percentage)
-                * (TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS)
-                + output_percentage * (TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS))
+        for (i, output_percentage) in (10..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts = (TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS
+                * (100 - output_percentage)
+                + TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS * output_percentage)
                 / 100;
             println!("Target output voltage: {}mV", output_voltage_mv);
 
@@ -1342,11 +1498,11 @@ impl ManualPerformanceTests {
 
             // Read tether bias voltage, current
             println!("Measure voltage and input (in mV): ");
-            let measured_voltage_mv = read_num(&mut payload.serial_reader);
+            let measured_voltage_mv = Millivolts(read_num(&mut payload.serial_reader));
 
-            let voltage_rpd = calculate_rpd(measured_voltage_mv, output_voltage_mv as i32);
+            let voltage_rpd = calculate_rpd(measured_voltage_mv, output_voltage_mv);
             println!(
-                "Tether mv: {}",
+                "Tether bias: {:?}",
                 payload.get_tether_bias_voltage_millivolts()
             );
             voltage_accuracy = in_place_average(voltage_accuracy, voltage_rpd, i as u16);
@@ -1362,49 +1518,51 @@ impl ManualPerformanceTests {
         voltage_result
     }
 
-    pub fn test_tether_bias_current<'a, const DONTCARE: HeaterState, USCI: SerialUsci>(
-        payload: &'a mut Payload<{ PayloadOn }, DONTCARE>,
-        spi_bus: &'a mut PayloadSPIController,
-        debug_writer: &mut SerialWriter<USCI>,
-        serial_reader: &mut Rx<USCI>,
+    pub fn test_tether_bias_current<'a, const DONTCARE: HeaterState, H: PayloadHardware>(
+        payload: &'a mut Payload<{ PayloadOn }, DONTCARE, H>,
+        spi_bus: &'a mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        debug_writer: &mut impl uWrite,
+        serial_reader: &mut impl Read<u8>,
     ) -> PerformanceResult<'a> {
         const NUM_MEASUREMENTS: usize = 10;
-        const TEST_RESISTANCE: u32 = 100_000;
-        let mut current_accuracy: Fxd = Fxd::ZERO;
+        const TEST_RESISTANCE: Ohms = Ohms(100_000);
+        let mut current_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
         payload.set_tether_bias_switch(SwitchState::Connected); // connect to exterior
-        for (i, output_percentage) in (10..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u32 = ((100 - output_percentage)
-                * (TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS)
-                + output_percentage * (TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS))
+        for (i, output_percentage) in (10..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts = (TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS
+                * (100 - output_percentage)
+                + TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS * output_percentage)
                 / 100;
 
             payload.set_tether_bias_voltage(output_voltage_mv);
 
-            let expected_voltage_mv: u32 = output_voltage_mv; // assume zero error between target voltage and actual voltage
-            let expected_current_ua: i16 = ((1000 * expected_voltage_mv)
-                / (hvdc_mock::MOCK_TETHER_BIAS_RESISTANCE_OHMS + TETHER_SENSE_RESISTANCE_OHMS))
-                as i16;
+            let expected_voltage_mv: Millivolts = output_voltage_mv; // assume zero error between target voltage and actual voltage
+            let expected_current_ua: Microamps = expected_voltage_mv
+                .current_through(hvdc_mock::MOCK_TETHER_BIAS_RESISTANCE_OHMS + TETHER_SENSE_RESISTANCE_OHMS)
+                .unwrap_or(Microamps::ZERO);
             dbg_println!("Expected current is: {}mA", expected_current_ua);
 
             //Manually measure the current
             uwrite!(debug_writer, "Measure current and input (in uA): ").ok();
-            let actual_current_ua = read_num(serial_reader);
+            let actual_current_ua = Microamps(read_num(serial_reader));
             uwriteln!(debug_writer, "").ok();
 
             // Measure current
-            let measured_current_ua: i32 = payload.get_tether_bias_current_microamps();
-            dbg_println!("Measured current is: {}uA", measured_current_ua);
+            let measured_current_ua = payload.get_tether_bias_current_microamps();
+            dbg_println!("Measured current is: {:?}", measured_current_ua);
 
             //Determine accuracy
-            let current_rpd = calculate_rpd(measured_current_ua, actual_current_ua);
-            uwriteln!(
-                debug_writer,
-                "Calculated current millirpd: {}",
-                (current_rpd * 1000).to_num::<i32>()
-            )
-            .ok();
-            current_accuracy = in_place_average(current_accuracy, current_rpd, i as u16);
+            let current_rpd = reading_rpd(measured_current_ua, actual_current_ua);
+            if let Ok(current_rpd) = current_rpd {
+                uwriteln!(
+                    debug_writer,
+                    "Calculated current millirpd: {}",
+                    (current_rpd * 1000).to_num::<i32>()
+                )
+                .ok();
+            }
+            current_accuracy = in_place_reading_average(current_accuracy, current_rpd, i as u16);
         }
 
         // Set back to zero
@@ -1412,21 +1570,21 @@ impl ManualPerformanceTests {
         payload.set_tether_bias_switch(SwitchState::Disconnected);
 
         let current_result =
-            calculate_performance_result("Tether bias current", current_accuracy, 5, 20);
+            reading_performance_result("Tether bias current", current_accuracy, 5, 20);
         current_result
     }
 
-    pub fn test_heater_voltage(
-        payload: &mut Payload<{ PayloadOn }, { HeaterOn }>,
+    pub fn test_heater_voltage<H: PayloadHardware>(
+        payload: &mut Payload<{ PayloadOn }, { HeaterOn }, H>,
     ) -> PerformanceResult<'_> {
         const NUM_MEASUREMENTS: usize = 10;
-        let mut voltage_accuracy: Fxd = Fxd::ZERO;
+        let mut voltage_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
-        for (i, output_percentage) in (0..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u16 = (((100 - output_percentage)
-                * (HEATER_MIN_VOLTAGE_MILLIVOLTS as u32)
-                + output_percentage * (HEATER_MAX_VOLTAGE_MILLIVOLTS as u32))
-                / 100) as u16;
+        for (i, output_percentage) in (0..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts = (HEATER_MIN_VOLTAGE_MILLIVOLTS
+                * (100 - output_percentage)
+                + HEATER_MAX_VOLTAGE_MILLIVOLTS * output_percentage)
+                / 100;
 
             // Set cathode voltage
             payload.set_heater_voltage(output_voltage_mv);
@@ -1434,41 +1592,42 @@ impl ManualPerformanceTests {
             println!("Target set to: {}mV", output_voltage_mv);
 
             print!("Measure voltage and input (in mV): ");
-            let actual_voltage_mv = read_num(&mut payload.serial_reader);
+            let actual_voltage_mv = Millivolts(read_num(&mut payload.serial_reader));
             println!("");
 
             let measured_voltage_mv = payload.get_heater_voltage_millivolts();
-            println!("Measured as: {}", measured_voltage_mv);
-
-            let voltage_rpd = calculate_rpd(measured_voltage_mv as i32, actual_voltage_mv);
-            println!(
-                "Calculated voltage millirpd: {}",
-                (voltage_rpd * 1000).to_num::<i32>()
-            );
-            voltage_accuracy = in_place_average(voltage_accuracy, voltage_rpd, i as u16);
+            println!("Measured as: {:?}", measured_voltage_mv);
+
+            let voltage_rpd = reading_rpd(measured_voltage_mv, actual_voltage_mv);
+            if let Ok(voltage_rpd) = voltage_rpd {
+                println!(
+                    "Calculated voltage millirpd: {}",
+                    (voltage_rpd * 1000).to_num::<i32>()
+                );
+            }
+            voltage_accuracy = in_place_reading_average(voltage_accuracy, voltage_rpd, i as u16);
             println!("");
         }
 
-        let voltage_result =
-            calculate_performance_result("Heater voltage", voltage_accuracy, 5, 20);
+        let voltage_result = reading_performance_result("Heater voltage", voltage_accuracy, 5, 20);
         voltage_result
     }
 
-    pub fn test_heater_current<'a, USCI: SerialUsci>(
-        payload: &'a mut Payload<{ PayloadOn }, { HeaterOn }>,
-        spi_bus: &'a mut PayloadSPIController,
-        debug_writer: &mut SerialWriter<USCI>,
-        serial_reader: &mut Rx<USCI>,
+    pub fn test_heater_current<'a, H: PayloadHardware>(
+        payload: &'a mut Payload<{ PayloadOn }, { HeaterOn }, H>,
+        spi_bus: &'a mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        debug_writer: &mut impl uWrite,
+        serial_reader: &mut impl Read<u8>,
     ) -> PerformanceResult<'a> {
         const NUM_MEASUREMENTS: usize = 10;
 
-        let mut current_accuracy: Fxd = Fxd::ZERO;
+        let mut current_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
 
-        for (i, output_percentage) in (0..=100u32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
-            let output_voltage_mv: u16 = (((100 - output_percentage)
-                * (HEATER_MIN_VOLTAGE_MILLIVOLTS as u32)
-                + output_percentage * (HEATER_MAX_VOLTAGE_MILLIVOLTS as u32))
-                / 100) as u16;
+        for (i, output_percentage) in (0..=100i32).step_by(100 / NUM_MEASUREMENTS).enumerate() {
+            let output_voltage_mv: Millivolts = (HEATER_MIN_VOLTAGE_MILLIVOLTS
+                * (100 - output_percentage)
+                + HEATER_MAX_VOLTAGE_MILLIVOLTS * output_percentage)
+                / 100;
 
             // Set heater voltage
             payload.set_heater_voltage(output_voltage_mv);
@@ -1476,35 +1635,37 @@ impl ManualPerformanceTests {
             delay_cycles(100_000); //settling time
 
             // Calculate expected voltage and current (only for reference)
-            let expected_voltage_mv: u32 = output_voltage_mv as u32; // assume zero error between target voltage and actual voltage
-            let expected_current_ma: i16 = ((1000 * expected_voltage_mv)
-                / (heater_mock::CIRCUIT_AND_PROBE_RESISTANCE_MOHMS as u32))
-                .min(heater_mock::POWER_LIMITED_MAX_CURRENT_MA.to_num())
-                as i16;
+            let expected_voltage_mv: Millivolts = output_voltage_mv; // assume zero error between target voltage and actual voltage
+            let expected_current_ma: Milliamps = Milliamps(
+                expected_voltage_mv.0 * 1000
+                    / heater_mock::CIRCUIT_AND_PROBE_RESISTANCE_MOHMS as i32,
+            )
+            .min(Milliamps(heater_mock::POWER_LIMITED_MAX_CURRENT_MA.to_num()));
             dbg_println!("Expected current is: {}mA", expected_current_ma);
 
             // Measure current
-            let measured_current_ma: i16 = payload.get_heater_current_milliamps();
-            dbg_println!("Measured current is: {}mA", measured_current_ma);
+            let measured_current_ma = payload.get_heater_current_milliamps();
+            dbg_println!("Measured current is: {:?}", measured_current_ma);
 
             //Manually measure the current
             uwrite!(debug_writer, "Measure current and input (in mA): ").ok();
-            let actual_current_ma = read_num(serial_reader);
+            let actual_current_ma = Milliamps(read_num(serial_reader));
             uwriteln!(debug_writer, "").ok();
 
             //Determine accuracy
-            let current_rpd = calculate_rpd(measured_current_ma as i32, actual_current_ma);
-            uwriteln!(
-                debug_writer,
-                "Calculated current millirpd: {}",
-                (current_rpd * 1000).to_num::<i32>()
-            )
-            .ok();
-            current_accuracy = in_place_average(current_accuracy, current_rpd, i as u16);
+            let current_rpd = reading_rpd(measured_current_ma, actual_current_ma);
+            if let Ok(current_rpd) = current_rpd {
+                uwriteln!(
+                    debug_writer,
+                    "Calculated current millirpd: {}",
+                    (current_rpd * 1000).to_num::<i32>()
+                )
+                .ok();
+            }
+            current_accuracy = in_place_reading_average(current_accuracy, current_rpd, i as u16);
         }
 
-        let current_result =
-            calculate_performance_result("Heater current", current_accuracy, 5, 20);
+        let current_result = reading_performance_result("Heater current", current_accuracy, 5, 20);
         current_result
     }
     /// Setup: Place 1.2 ohm (10W+) resistor between pinpuller pins.
@@ -1514,17 +1675,17 @@ impl ManualPerformanceTests {
         'a,
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
-        USCI: SerialUsci,
+        H: PayloadHardware,
     >(
-        payload: &'a mut Payload<DONTCARE1, DONTCARE2>,
-        p_pins: &'a mut PinpullerActivationPins,
-        spi_bus: &'a mut PayloadSPIController,
-        serial_writer: &mut SerialWriter<USCI>,
-        serial_reader: &mut Rx<USCI>,
+        payload: &'a mut Payload<DONTCARE1, DONTCARE2, H>,
+        p_pins: &'a mut PinpullerActivationPins<H>,
+        spi_bus: &'a mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        serial_writer: &mut impl uWrite,
+        serial_reader: &mut impl Read<u8>,
     ) -> PerformanceResult<'a> {
-        let mut current_accuracy: Fxd = Fxd::ZERO;
-        let mut expected_current_ma: i16;
-        let mut measured_current_ma: i16;
+        let mut current_accuracy: Result<Fxd, ADCError> = Ok(Fxd::ZERO);
+        let mut expected_current_ma: Milliamps;
+        let mut measured_current_ma: Result<Milliamps, ADCError>;
         let voltage_values_mv: [i32; 9] = [400, 800, 1200, 1600, 2000, 2400, 2800, 3200, 3300];
         let rp_sense: i32 = 82;
         let r122: i32 = 400;
@@ -1547,24 +1708,26 @@ impl ManualPerformanceTests {
             wait_for_any_packet(serial_reader);
 
             // Obtain expected (I = V/R) and measured current in mA
-            expected_current_ma = ((set_voltage * 1000) / total_resistance) as i16;
-            measured_current_ma = payload.get_pinpuller_current_milliamps() as i16;
+            expected_current_ma = Milliamps((set_voltage * 1000) / total_resistance);
+            measured_current_ma = payload.get_pinpuller_current_milliamps();
             // User inputs actual current from manual measurement
             uwrite!(serial_writer, "Measure current and input (in mA): ").ok();
-            let actual_current_ma = read_num(serial_reader) as i16;
+            let actual_current_ma = Milliamps(read_num(serial_reader));
 
             // Print results
             println!("Expected current is {} mA", expected_current_ma);
-            println!("Measured current is {} mA", measured_current_ma);
+            println!("Measured current is {:?}", measured_current_ma);
             println!("Actual current is {} mA", actual_current_ma);
 
             // Calculate RPD and accuracy
-            let current_rpd = calculate_rpd(measured_current_ma as i32, actual_current_ma as i32);
-            println!(
-                "Calculated current millirpd: {}",
-                (current_rpd * 1000).to_num::<i32>()
-            );
-            current_accuracy = in_place_average(current_accuracy, current_rpd, i as u16);
+            let current_rpd = reading_rpd(measured_current_ma, actual_current_ma);
+            if let Ok(current_rpd) = current_rpd {
+                println!(
+                    "Calculated current millirpd: {}",
+                    (current_rpd * 1000).to_num::<i32>()
+                );
+            }
+            current_accuracy = in_place_reading_average(current_accuracy, current_rpd, i as u16);
         }
 
         PerformanceResult::default()
@@ -1574,26 +1737,15 @@ impl ManualPerformanceTests {
         'a,
         const DONTCARE1: PayloadState,
         const DONTCARE2: HeaterState,
-        USCI: SerialUsci,
+        H: PayloadHardware,
     >(
-        payload: &mut Payload<{ DONTCARE1 }, { DONTCARE2 }>,
-        spi_bus: &mut PayloadSPIController,
-        debug_writer: &'a mut SerialWriter<USCI>,
-        serial_reader: &'a mut Rx<USCI>,
+        payload: &mut Payload<{ DONTCARE1 }, { DONTCARE2 }, H>,
+        spi_bus: &mut PayloadSPIController<impl PayloadSPIAnyMode>,
+        debug_writer: &'a mut impl uWrite,
+        serial_reader: &'a mut impl Read<u8>,
     ) -> ! {
         // Does not return
 
-        const TEMP_SENSORS: [(TemperatureSensor, &str); 8] = [
-            (LMS_EMITTER_TEMPERATURE_SENSOR, "LMS Emitter"),
-            (LMS_RECEIVER_TEMPERATURE_SENSOR, "LMS Receiver"),
-            (MSP430_TEMPERATURE_SENSOR, "MSP430"),
-            (HEATER_SUPPLY_TEMPERATURE_SENSOR, "Heater supply"),
-            (HVDC_SUPPLIES_TEMPERATURE_SENSOR, "HVDC Supplies"),
-            (TETHER_MONITORING_TEMPERATURE_SENSOR, "Tether monitoring"),
-            (TETHER_CONNECTOR_TEMPERATURE_SENSOR, "Tether connector"),
-            (MSP_3V3_TEMPERATURE_SENSOR, "MSP 3V3 supply"),
-        ];
-
         // Prompt to setup thermal chamber
         uwriteln!(debug_writer, "Thermal Chamber Test").ok();
         uwriteln!(debug_writer, "--------------------").ok();
@@ -1608,15 +1760,12 @@ impl ManualPerformanceTests {
         // 8 temperature sensor values will be printed every second or so
         // INFINITE loop so manually turn off power supply to exit loop.
         loop {
-            for (n, (sensor, name)) in TEMP_SENSORS.iter().enumerate() {
-                let tempr = payload.get_temperature_kelvin(sensor) as i16;
+            for (n, (sensor, name)) in TEMPERATURE_SENSORS.iter().enumerate() {
                 uwrite!(debug_writer, "{}: ", name).ok();
-                uwriteln!(
-                    debug_writer,
-                    "{}",
-                    tempr - (CELCIUS_TO_KELVIN_OFFSET as i16)
-                )
-                .ok();
+                match payload.get_temperature_kelvin(sensor) {
+                    Ok(tempr) => uwriteln!(debug_writer, "{}", tempr.to_celcius()).ok(),
+                    Err(err) => uwriteln!(debug_writer, "read failed: {:?}", err).ok(),
+                };
             }
             uwriteln!(debug_writer, "").ok();
             delay_cycles(1_000_000);
@@ -1629,6 +1778,14 @@ pub struct SensorResult<'a> {
     name: &'a str,
     result: bool,
 }
+impl SensorResult<'_> {
+    pub fn name(&self) -> &str {
+        self.name
+    }
+    pub fn failed(&self) -> bool {
+        !self.result
+    }
+}
 // Define how to print a SensorResult
 impl ufmt::uDisplay for SensorResult<'_> {
     fn fmt<W: uWrite + ?Sized>(&self, f: &mut ufmt::Formatter<W>) -> Result<(), W::Error> {
@@ -1658,6 +1815,21 @@ impl PerformanceResult<'_> {
             accuracy: Fxd::ZERO,
         }
     }
+    /// Result for a test whose sensor couldn't be read.
+    pub fn read_failed(name: &str) -> PerformanceResult<'_> {
+        PerformanceResult {
+            name,
+            performance: Performance::NotWorking,
+            accuracy: Fxd::ZERO,
+        }
+    }
+    pub fn name(&self) -> &str {
+        self.name
+    }
+    /// Whether this result prints as FAIL. Inaccurate results still pass.
+    pub fn failed(&self) -> bool {
+        self.performance == Performance::NotWorking
+    }
 }
 // Define how to print a PerformanceResult
 impl ufmt::uDisplay for PerformanceResult<'_> {
@@ -1680,10 +1852,50 @@ impl ufmt::uDisplay for PerformanceResult<'_> {
     }
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
 pub enum Performance {
     Nominal,
     Inaccurate,
     #[default]
     NotWorking,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_sim::{SimBoard, SimChip, SimFault};
+    use std::boxed::Box;
+
+    #[test]
+    fn failed_reads_are_not_working() {
+        let accuracy = in_place_reading_average(Ok(Fxd::ZERO), Err(ADCError::StuckHigh), 0);
+        let accuracy = in_place_reading_average(accuracy, Ok(Fxd::ZERO), 1);
+        assert_eq!(accuracy, Err(ADCError::StuckHigh));
+        assert!(reading_performance_result("Test", accuracy, 5, 20).failed());
+        assert!(!reading_performance_result("Test", Ok(Fxd::ZERO), 5, 20).failed());
+    }
+
+    #[test]
+    fn dead_adc_fails_comparisons_against_zero() {
+        // A failed read used to count as zero, so comparing against zero would pass
+        let board = SimBoard::new();
+        board.inject_fault(SimFault::StuckMISO(SimChip::TemperatureADC, true));
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]);
+        for result in test_temperature_sensors_against_known_temp(Kelvin(0), &mut payload).iter() {
+            assert!(result.failed(), "{}", result.name());
+        }
+    }
+
+    #[test]
+    fn aperture_functional_test_powers_the_domain_down_afterwards() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]);
+        assert!(!AutomatedFunctionalTests::aperture_adc_functional_test(&mut payload).failed());
+        assert_eq!(payload.millis_since_power_up(PowerDomain::Aperture), None);
+        assert!(!payload.aperture_adc.cs_pin.is_selected());
+
+        board.inject_fault(SimFault::StuckMISO(SimChip::ApertureADC, true));
+        assert!(AutomatedFunctionalTests::aperture_adc_functional_test(&mut payload).failed());
+        assert_eq!(payload.millis_since_power_up(PowerDomain::Aperture), None);
+    }
+}
@@ -1,101 +1,140 @@
 use embedded_hal::{digital::v2::{OutputPin, InputPin}, timer::CountDown};
-use msp430fr2355::{E_USCI_A1, TB0};
-use msp430fr2x5x_hal::serial::{SerialUsci, Rx};
-use msp430fr2x5x_hal::timer::Timer;
-use msp430fr2x5x_hal::{pmm::Pmm, gpio::Batch};
 use nb::block;
 use ufmt::{uWrite, uwrite, uwriteln};
 use void::ResultVoidExt;
 
 use crate::{dbg_println, delay_cycles, println};
-use crate::payload::{Payload, PayloadState, PayloadState::*, HeaterState, HeaterState::*, SwitchState};
-use crate::serial::{SerialWriter, wait_for_any_packet};
+use crate::payload::{Payload, PayloadState, PayloadState::*, HeaterState, HeaterState::*, HVDCSupply, RampError, RampLimits, SwitchState, TetherADCReadings};
+use crate::serial::wait_for_any_packet;
+use crate::units::{Microamps, Milliamps, Millivolts};
+use crate::power::SwitchableDomain;
+use crate::housekeeping::TEMPERATURE_SENSORS;
+use crate::dyn_payload::DynPayload;
+use crate::pcb_common::PayloadHardware;
 #[allow(unused_imports)]
 use crate::{spi::{*, SckPolarity::*, SckPhase::SampleFirstEdge}, adc::*, digipot::*, dac::*};
 #[allow(unused_imports)]
-use crate::pcb_mapping::{pin_name_types::*, sensor_locations::*, power_supply_limits::*, power_supply_locations::*, peripheral_vcc_values::*, *};
+use crate::pcb_mapping::{sensor_locations::*, power_supply_limits::*, power_supply_locations::*, peripheral_vcc_values::*, *};
 use crate::serial::{read_num, TextColours::*};
 use fixed::{self, FixedI64};
 type Fxd = FixedI64::<32>;
 
-use crate::testing::{calculate_performance_result, calculate_rpd, in_place_average, hvdc_mock,heater_mock,pinpuller_mock, PerformanceResult};
-
-const CELCIUS_TO_KELVIN_OFFSET: u16 = 273;
-
-pub fn emission_sensing(
-    expected_heater_voltage_mv: u32,
-    expected_tb_voltage_mv: u32,
-    expected_co_voltage_mv: u32,
-    payload: &mut Payload<{PayloadOn}, {HeaterOn}>){
+use crate::testing::{calculate_performance_result, calculate_rpd, in_place_average, reading_performance_result, reading_rpd, hvdc_mock,heater_mock,pinpuller_mock, PerformanceResult};
+
+// Ramps each HVDC supply in turn, printing where it stopped if a ramp was aborted. Returns the first aborted ramp.
+// An aborted ramp up drops that supply straight to its minimum and leaves the rest alone, since the supply didn't behave on the way up.
+// An aborted ramp down jumps straight to the target rather than leaving the supply part way up, and carries on with the rest.
+// Protection is checked after each supply. If it trips (which zeroes the supplies) the remaining supplies are left alone.
+fn ramp_hvdc_supplies<const HSTATE: HeaterState, H: PayloadHardware>(cathode_offset_target: Millivolts, tether_bias_target: Millivolts, payload: &mut Payload<{PayloadOn}, HSTATE, H>) -> Result<(), RampError> {
+    let mut result = Ok(());
+    for (supply, target) in [(HVDCSupply::CathodeOffset, cathode_offset_target), (HVDCSupply::TetherBias, tether_bias_target)] {
+        if let Err(err) = payload.ramp_hvdc_supply(supply, target, HVDC_RAMP_VOLTS_PER_SECOND, RampLimits::default_for(supply)) {
+            println!("{:?} ramp stopped at {}mV: {:?}", supply, err.stopped_at, err.cause);
+            if err.stopped_at < target {
+                payload.set_hvdc_voltage(supply, supply.min_voltage());
+                return Err(err);
+            }
+            payload.set_hvdc_voltage(supply, target);
+            if result.is_ok() {
+                result = Err(err);
+            }
+        }
+        if check_protection(payload) {
+            break;
+        }
+    }
+    result
+}
 
-    // Compare heater voltage AND current against expected values
-    dbg_println!("");
-    for sensor_result in compare_heater(expected_heater_voltage_mv, payload).iter(){
-        println!("{}", sensor_result);
+// Takes a housekeeping snapshot, which checks the protection limits. Prints the fault if this snapshot latched one.
+// Returns whether a fault is latched, new or not.
+fn check_protection<const PSTATE: PayloadState, const HSTATE: HeaterState, H: PayloadHardware>(payload: &mut Payload<PSTATE, HSTATE, H>) -> bool {
+    let already_latched = payload.protection.latched_fault().is_some();
+    payload.get_housekeeping();
+    match payload.protection.latched_fault() {
+        Some(fault) if !already_latched => { println!("PROTECTION TRIPPED: {:?}", fault); true },
+        latched => latched.is_some(),
     }
+}
+
+pub fn emission_sensing<H: PayloadHardware>(
+    expected_heater_voltage_mv: Millivolts,
+    expected_tb_voltage_mv: Millivolts,
+    expected_co_voltage_mv: Millivolts,
+    payload: &mut Payload<{PayloadOn}, {HeaterOn}, H>){
+
+    // One scan of the tether ADC covers everything but the aperture
+    let readings = payload.get_tether_adc_readings();
+
+    match readings {
+        Ok(readings) => {
+            // Compare heater voltage AND current against expected values
+            dbg_println!("");
+            for sensor_result in compare_heater(expected_heater_voltage_mv, &readings).iter(){
+                println!("{}", sensor_result);
+            }
 
-    // Compare tether bias and cathode offset voltages against expected values
-    let fn_arr          = [compare_cathode_offset, compare_tether_bias];
-    let expected_values = [expected_co_voltage_mv, expected_tb_voltage_mv];
-    for (sensor_fn, expected_voltage) in fn_arr.iter().zip(expected_values) {
-        dbg_println!("");
-        let result = sensor_fn(expected_voltage, payload);
-        println!("{}", result);
+            // Compare tether bias and cathode offset voltages against expected values
+            let fn_arr          = [compare_cathode_offset, compare_tether_bias];
+            let expected_values = [expected_co_voltage_mv, expected_tb_voltage_mv];
+            for (sensor_fn, expected_voltage) in fn_arr.iter().zip(expected_values) {
+                dbg_println!("");
+                let result = sensor_fn(expected_voltage, &readings);
+                println!("{}", result);
+            }
+        },
+        Err(err) => {
+            println!("Tether ADC read failed: {:?}", err);
+            for name in ["Heater voltage", "Heater current", "Cathode offset voltage", "Tether bias voltage"] {
+                println!("{}", PerformanceResult::read_failed(name));
+            }
+        },
     }
 
     // We don't have a good idea of what these *should* be, so just print out their value
     dbg_println!("");
     measure_aperture_current(payload);
-    measure_repeller_voltage(payload);
+    if let Ok(readings) = readings {
+        print_repeller_voltage(&readings);
+    }
     print_temperatures(payload);
+    println!("Live power domains: {}", payload.live_domains());
 }
 
-pub fn deployment_sensing(payload: &mut Payload<{PayloadOff}, {HeaterOff}>) {
+pub fn deployment_sensing<H: PayloadHardware>(payload: &mut Payload<{PayloadOff}, {HeaterOff}, H>) {
     println!("{}", compare_pinpuller_current(payload));
     print_temperatures(payload);
+    println!("Live power domains: {}", payload.live_domains());
 }
 
-pub fn payload_off_sensing(payload: &mut Payload<{PayloadOff}, {HeaterOff}>) {
+pub fn payload_off_sensing<H: PayloadHardware>(payload: &mut Payload<{PayloadOff}, {HeaterOff}, H>) {
     print_temperatures(payload);
 }
 
-pub fn print_temperatures<const DONTCARE1:PayloadState, const DONTCARE2:HeaterState>(payload: &mut Payload<{DONTCARE1}, {DONTCARE2}>){
-
-    const TEMP_SENSORS: [(TemperatureSensor, &str); 8] = [
-        (LMS_EMITTER_TEMPERATURE_SENSOR,        "LMS Emitter"),
-        (LMS_RECEIVER_TEMPERATURE_SENSOR,       "LMS Receiver"),
-        (MSP430_TEMPERATURE_SENSOR,             "MSP430"),
-        (HEATER_SUPPLY_TEMPERATURE_SENSOR,      "Heater supply"),
-        (HVDC_SUPPLIES_TEMPERATURE_SENSOR,      "HVDC Supplies"),
-        (TETHER_MONITORING_TEMPERATURE_SENSOR,  "Tether monitoring"),
-        (TETHER_CONNECTOR_TEMPERATURE_SENSOR,   "Tether connector"),
-        (MSP_3V3_TEMPERATURE_SENSOR,            "MSP 3V3 supply"),
-    ];    
-
-    for (n, (sensor, name)) in TEMP_SENSORS.iter().enumerate() {    
-        let tempr = payload.get_temperature_kelvin(sensor) as i16;
-        println!("{}: {}", name, tempr - (CELCIUS_TO_KELVIN_OFFSET as i16));     
+pub fn print_temperatures<const DONTCARE1:PayloadState, const DONTCARE2:HeaterState, H: PayloadHardware>(payload: &mut Payload<{DONTCARE1}, {DONTCARE2}, H>){
+
+    match payload.get_temperatures_kelvin(TEMPERATURE_SENSORS.each_ref().map(|(sensor, _)| sensor)) {
+        Ok(temperatures) => {
+            for ((_, name), tempr) in TEMPERATURE_SENSORS.iter().zip(temperatures) {    
+                println!("{}: {}", name, tempr.to_celcius());     
+            }
+        },
+        Err(err) => println!("Temperature read failed: {:?}", err),
     }
     println!("");
 }
 
-fn compare_hvdc_supply<const DONTCARE: HeaterState>(
-    measure_voltage_fn: &dyn Fn(&mut Payload<{PayloadOn}, DONTCARE>) -> i32,
-    measure_current_fn: &dyn Fn(&mut Payload<{PayloadOn}, DONTCARE>) -> i32,
-    expected_voltage_mv: u32,
-    payload: &mut Payload<{PayloadOn}, DONTCARE>) -> Fxd {
+fn compare_hvdc_supply(
+    measured_voltage_mv: Millivolts,
+    measured_current_ua: Microamps,
+    expected_voltage_mv: Millivolts) -> Fxd {
     
     const SENSE_RESISTANCE: u32 = 1; // Both supplies use the same sense resistor value
         
-    // Read voltage, current
-    let measured_voltage_mv = measure_voltage_fn(payload);
-    let measured_current_ua = measure_current_fn(payload);
     dbg_println!("Measured output voltage: {}mV", measured_voltage_mv);
     dbg_println!("Measured output current: {}uA", measured_current_ua);
 
     // Calculate expected voltage and current
-    let expected_voltage_mv: i32 = expected_voltage_mv as i32;
-
     dbg_println!("Expected output voltage: {}mV", expected_voltage_mv);
 
     let voltage_accuracy = calculate_rpd(measured_voltage_mv, expected_voltage_mv);
@@ -103,53 +142,49 @@ fn compare_hvdc_supply<const DONTCARE: HeaterState>(
     voltage_accuracy
 }
 
-pub fn compare_cathode_offset<const DONTCARE: HeaterState>(
-    expected_voltage_mv: u32,
-    payload: &mut Payload<{PayloadOn}, DONTCARE>) -> PerformanceResult<'_> {
+pub fn compare_cathode_offset(
+    expected_voltage_mv: Millivolts,
+    readings: &TetherADCReadings) -> PerformanceResult<'static> {
 
     let voltage_accuracy = self::compare_hvdc_supply(
-            &Payload::get_cathode_offset_voltage_millivolts, 
-            &Payload::get_cathode_offset_current_microamps, 
-            expected_voltage_mv,
-            payload);
+            readings.cathode_offset_voltage_millivolts, 
+            readings.cathode_offset_current_microamps, 
+            expected_voltage_mv);
 
     let voltage_result = calculate_performance_result("Cathode offset voltage", voltage_accuracy, 5, 20);
     voltage_result
 }
 
-pub fn compare_tether_bias<const DONTCARE: HeaterState>(
-    expected_voltage_mv: u32,
-    payload: &mut Payload<{PayloadOn}, DONTCARE>) -> PerformanceResult<'_> { 
+pub fn compare_tether_bias(
+    expected_voltage_mv: Millivolts,
+    readings: &TetherADCReadings) -> PerformanceResult<'static> { 
 
     let voltage_accuracy = self::compare_hvdc_supply(
-            &Payload::get_tether_bias_voltage_millivolts, 
-            &Payload::get_tether_bias_current_microamps, 
-            expected_voltage_mv,
-            payload);
+            readings.tether_bias_voltage_millivolts, 
+            readings.tether_bias_current_microamps, 
+            expected_voltage_mv);
 
     let voltage_result = calculate_performance_result("Tether bias voltage", voltage_accuracy, 5, 20);
     voltage_result
 }
 
 pub fn compare_heater(
-    expected_voltage_mv: u32,
-    payload: &mut Payload<{PayloadOn}, {HeaterOn}>) -> [PerformanceResult<'_>; 2] {
+    expected_voltage_mv: Millivolts,
+    readings: &TetherADCReadings) -> [PerformanceResult<'static>; 2] {
 
-    // Read voltage, current
-    let heater_voltage_mv = payload.get_heater_voltage_millivolts();
+    let heater_voltage_mv = readings.heater_voltage_millivolts;
     dbg_println!("Read voltage as: {}mV", heater_voltage_mv);
-    let heater_current_ma = payload.get_heater_current_milliamps();
+    let heater_current_ma = readings.heater_current_milliamps;
     dbg_println!("Read current as: {}mA", heater_current_ma);
 
     // Calculate expected voltage and current
-    let expected_voltage_mv = expected_voltage_mv as u16;
-    let expected_current_ma: i16 = (expected_voltage_mv as u32 * 1000 / heater_mock::CIRCUIT_RESISTANCE_MOHMS as u32)
-            .min(heater_mock::POWER_LIMITED_MAX_CURRENT_MA.to_num()) as i16;
+    let expected_current_ma = Milliamps(expected_voltage_mv.0 * 1000 / heater_mock::CIRCUIT_RESISTANCE_MOHMS as i32)
+            .min(Milliamps(heater_mock::POWER_LIMITED_MAX_CURRENT_MA.to_num()));
     dbg_println!("Expected current is: {}mA", expected_current_ma);
 
     // RPD and accuracy calculations
-    let voltage_rpd = calculate_rpd(heater_voltage_mv as i32, expected_voltage_mv as i32);
-    let current_rpd = calculate_rpd(heater_current_ma as i32, expected_current_ma as i32);
+    let voltage_rpd = calculate_rpd(heater_voltage_mv, expected_voltage_mv);
+    let current_rpd = calculate_rpd(heater_current_ma, expected_current_ma);
     
 
     let voltage_result = calculate_performance_result("Heater voltage", voltage_rpd, 5, 20);
@@ -158,38 +193,38 @@ pub fn compare_heater(
     [voltage_result, current_result]
 }
 
-pub fn measure_repeller_voltage<const DONTCARE: HeaterState>(
-    payload: &mut Payload<{PayloadOn}, DONTCARE>,) {
+pub fn print_repeller_voltage(readings: &TetherADCReadings) {
     
-    // Read voltage
-    let repeller_voltage_mv = payload.get_repeller_voltage_millivolts();
+    let repeller_voltage_mv = readings.repeller_voltage_millivolts;
     println!("Repeller voltage measured as: {}mV", repeller_voltage_mv);
 
     // Calculate expected voltage/current
     // Do we actually know what the repeller voltage should be?
-    //let voltage_rpd = calculate_rpd(repeller_voltage_mv, expected_voltage_mv as i32);
+    //let voltage_rpd = calculate_rpd(repeller_voltage_mv, expected_voltage_mv);
     
     //calculate_performance_result("Repeller voltage", voltage_rpd, 5, 20)
 }
 
-pub fn compare_pinpuller_current<const DONTCARE1: PayloadState, const DONTCARE2:HeaterState>(
-    payload: &mut Payload<DONTCARE1, DONTCARE2>) -> PerformanceResult<'_>{
+pub fn compare_pinpuller_current<const DONTCARE1: PayloadState, const DONTCARE2:HeaterState, H: PayloadHardware>(
+    payload: &mut Payload<DONTCARE1, DONTCARE2, H>) -> PerformanceResult<'_>{
 
     let measured_current = payload.get_pinpuller_current_milliamps();
-    dbg_println!("Pinpuller current measured as: {}mA", measured_current);
-    let accuracy = calculate_rpd(measured_current as i32, pinpuller_mock::EXPECTED_ON_CURRENT.to_num());
+    dbg_println!("Pinpuller current measured as: {:?}", measured_current);
+    let accuracy = reading_rpd(measured_current, Milliamps(pinpuller_mock::EXPECTED_ON_CURRENT.to_num()));
 
-    calculate_performance_result("Pinpuller current sense",  accuracy,  5, 20)
+    reading_performance_result("Pinpuller current sense",  accuracy,  5, 20)
 }
 
-pub fn measure_aperture_current<const DONTCARE1: PayloadState, const DONTCARE2:HeaterState>(
-    payload: &mut Payload<DONTCARE1, DONTCARE2>) {
+pub fn measure_aperture_current<const DONTCARE1: PayloadState, const DONTCARE2:HeaterState, H: PayloadHardware>(
+    payload: &mut Payload<DONTCARE1, DONTCARE2, H>) {
 
-    let measured_current = payload.get_aperture_current_microamps();
-    println!("Aperture current measured as: {}uA", measured_current);
+    match payload.get_aperture_current_microamps() {
+        Ok(measured_current) => println!("Aperture current measured as: {}uA", measured_current),
+        Err(err) => println!("Aperture current read failed: {:?}", err),
+    }
 }
 
-pub fn aperture_current_sense_validation(mut serial_writer: SerialWriter<E_USCI_A1>, payload: &mut Payload<{PayloadOn}, {HeaterOn}>, mut payload_spi_controller: PayloadSPIController) {
+pub fn aperture_current_sense_validation<H: PayloadHardware>(mut serial_writer: impl uWrite, payload: &mut Payload<{PayloadOn}, {HeaterOn}, H>, mut payload_spi_controller: PayloadSPIController<impl PayloadSPIAnyMode>) {
     // Name of test
     println!("========== VACUUM CHAMBER - APERTURE CURRENT SENSE VALIDATION FIRMWARE ==========");
     println!("");
@@ -221,7 +256,7 @@ pub fn aperture_current_sense_validation(mut serial_writer: SerialWriter<E_USCI_
 }
 
 
-pub fn tvac_test(payload: Payload<{PayloadOff}, {HeaterOff}>) -> ! {
+pub fn tvac_test<H: PayloadHardware>(payload: Payload<{PayloadOff}, {HeaterOff}, H>) -> ! {
     println!("==========TVAC TEST FIRMWARE==========");
     delay_cycles(2_000_000);
 
@@ -235,15 +270,16 @@ pub fn tvac_test(payload: Payload<{PayloadOff}, {HeaterOff}>) -> ! {
     let mut sec_elapsed_phase:u32 = 0;
     let mut sec_elapsed_total:u32 = 0;
 
-    //  To avoid 'use of moved value' as we mutate the type of payload between loop iterations(?), we make a second variable to store payload here between loops. 
-    let mut payload_off: Option<Payload<{PayloadOff}, {HeaterOff}>> = Some(payload);
+    // The payload changes state every loop, so hold it as a DynPayload and borrow the typed payload for each phase.
+    let mut payload = DynPayload::from(payload);
 
     loop{
         // ------------------------------------------------------------------------
         // -------------------------- Payload Off ---------------------------------
         // ------------------------------------------------------------------------
         println!("ENTERING PAYLOAD-OFF PHASE");
-        if let Some(payload) = payload_off.as_mut() { 
+        {
+            let payload = payload.as_payload_off().unwrap();
             for _ in 0..45*60{
                 // LEAVE PAYLOAD OFF FOR 45 MINUTES
                 block!(payload.timer.wait()).unwrap();
@@ -252,7 +288,8 @@ pub fn tvac_test(payload: Payload<{PayloadOff}, {HeaterOff}>) -> ! {
                 println!("{} seconds elapsed in the current phase", sec_elapsed_phase);
                 println!("{} seconds elapsed in the total test", sec_elapsed_total);
                 payload_off_sensing(payload);
-                
+                // Nothing to shut down with the payload off, but a fault here skips the pinpuller and emission phases
+                check_protection(payload);
             }
 
             println!("");
@@ -262,28 +299,35 @@ pub fn tvac_test(payload: Payload<{PayloadOff}, {HeaterOff}>) -> ! {
             // ----------------------  Pinpuller activation ---------------------------
             // ------------------------------------------------------------------------
             println!("ENTERING PINPULLER ACTIVATION PHASE");
-            // activate pinpuller and LMS
-        
-            payload.pinpuller_pins.burn_wire_1.set_high().ok();
-            payload.lms_control_pins.lms_led_enable.set_high().ok();
-            payload.lms_control_pins.lms_receiver_enable.set_high().ok();
-            payload.led_pins.yellow_led.set_high().ok();
-
-            for _ in 0..60{           
-                // LEAVE PINPULLER ON FOR 60 SECONDS
-                block!(payload.timer.wait()).unwrap();
-                sec_elapsed_phase += 1;
-                sec_elapsed_total += 1;
-                println!("{} seconds elapsed in the current phase", sec_elapsed_phase);
-                println!("{} seconds elapsed in the total test", sec_elapsed_total);
-                
-                deployment_sensing(payload);
+            // Pinpuller on for 60 seconds, unless a protection fault is latched or trips part way through
+            if let Some(fault) = payload.protection.latched_fault() {
+                println!("Skipping pinpuller activation, protection fault latched: {:?}", fault);
+            } else {
+                // activate pinpuller and LMS
+                payload.pinpuller_pins.burn_wire_1.set_high().ok();
+                payload.power_up(SwitchableDomain::LMSLEDs);
+                payload.power_up(SwitchableDomain::LMSReceivers);
+                payload.led_pins.yellow_led.set_high().ok();
+
+                for _ in 0..60{           
+                    // LEAVE PINPULLER ON FOR 60 SECONDS
+                    block!(payload.timer.wait()).unwrap();
+                    sec_elapsed_phase += 1;
+                    sec_elapsed_total += 1;
+                    println!("{} seconds elapsed in the current phase", sec_elapsed_phase);
+                    println!("{} seconds elapsed in the total test", sec_elapsed_total);
+                    
+                    deployment_sensing(payload);
+                    if check_protection(payload) {
+                        break;
+                    }
+                }
+
+                // disable pinpuller and LMS
+                payload.pinpuller_pins.burn_wire_1.set_low().ok();
+                payload.power_down(SwitchableDomain::LMSLEDs);
+                payload.power_down(SwitchableDomain::LMSReceivers);
             }
-
-            // disable pinpuller and LMS
-            payload.pinpuller_pins.burn_wire_1.set_low().ok();
-            payload.lms_control_pins.lms_led_enable.set_low().ok();
-            payload.lms_control_pins.lms_receiver_enable.set_low().ok();
         }
         
         println!("");
@@ -293,34 +337,148 @@ pub fn tvac_test(payload: Payload<{PayloadOff}, {HeaterOff}>) -> ! {
         // ---------------------------  Emission  ---------------------------------
         // ------------------------------------------------------------------------
         println!("ENTERING EMISSION PHASE");
-        // Payload On activated for 44 minutes
-        let mut payload = payload_off.unwrap().into_enabled_payload().into_enabled_heater();
-        
-        payload.set_cathode_offset_switch(SwitchState::Connected);
-        payload.set_tether_bias_switch(SwitchState::Connected);
-        payload.set_cathode_offset_voltage(CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS);
-        payload.set_tether_bias_voltage(TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS);
-        payload.set_heater_voltage(3160);
-        payload.led_pins.red_led.set_high().ok();
-
-        for _ in 0..44*60{
-            // ENTER CODE TO READ SENSORS FOR 44 MINUTES
-            block!(payload.timer.wait()).unwrap();
-            sec_elapsed_phase += 1;
-            sec_elapsed_total += 1;
-            println!("{} seconds elapsed in the current phase", sec_elapsed_phase);
-            println!("{} seconds elapsed in the total test", sec_elapsed_total);
-            emission_sensing(3160, TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS, CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS, 
-                &mut payload)
-        }
+        // Payload On activated for 44 minutes, unless a protection limit trips. A latched fault keeps the payload off for the rest of the test.
+        if let Some(fault) = payload.latched_fault() {
+            println!("Skipping emission, protection fault latched: {:?}", fault);
+        } else {
+            payload.enable_payload().unwrap();
+            payload.enable_heater().unwrap();
+            let ramped_up = {
+                let payload = payload.as_heater_on().unwrap();
+                payload.set_cathode_offset_switch(SwitchState::Connected);
+                payload.set_tether_bias_switch(SwitchState::Connected);
+                let ramped_up = ramp_hvdc_supplies(CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS, TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS, payload).is_ok();
+                if ramped_up {
+                    payload.set_heater_voltage(Millivolts(3160));
+                    payload.led_pins.red_led.set_high().ok();
+                } else {
+                    println!("Skipping emission, HVDC ramp up aborted");
+                }
+                ramped_up
+            };
+            // A trip during the ramp has already zeroed the supplies, this finishes the shutdown and skips the emission loop
+            if payload.latched_fault().is_some() {
+                payload.safe_shutdown();
+            }
+
+            while ramped_up && payload.latched_fault().is_none() && sec_elapsed_phase < 44*60 {
+                // ENTER CODE TO READ SENSORS FOR 44 MINUTES
+                {
+                    let payload = payload.as_heater_on().unwrap();
+                    block!(payload.timer.wait()).unwrap();
+                    sec_elapsed_phase += 1;
+                    sec_elapsed_total += 1;
+                    println!("{} seconds elapsed in the current phase", sec_elapsed_phase);
+                    println!("{} seconds elapsed in the total test", sec_elapsed_total);
+                    emission_sensing(Millivolts(3160), TETHER_BIAS_MAX_VOLTAGE_MILLIVOLTS, CATHODE_OFFSET_MAX_VOLTAGE_MILLIVOLTS, 
+                        payload)
+                }
+                // Checks the protection limits, and shuts the payload down if one trips
+                payload.get_housekeeping();
+                if let Some(fault) = payload.latched_fault() {
+                    println!("PROTECTION TRIPPED, payload shut down: {:?}", fault);
+                    break;
+                }
+            }
 
-        payload.set_cathode_offset_switch(SwitchState::Disconnected);
-        payload.set_tether_bias_switch(SwitchState::Disconnected);
-        payload.led_pins.yellow_led.set_low().ok();
-        payload.led_pins.red_led.set_low().ok();
-        payload_off = Some(payload.into_disabled_heater().into_disabled_payload());
+            if payload.latched_fault().is_none() {
+                {
+                    let payload = payload.as_heater_on().unwrap();
+                    ramp_hvdc_supplies(CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS, TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS, payload).ok();
+                    payload.set_cathode_offset_switch(SwitchState::Disconnected);
+                    payload.set_tether_bias_switch(SwitchState::Disconnected);
+                }
+                payload.disable_heater().unwrap();
+                payload.disable_payload().unwrap();
+            }
+            let payload = payload.as_payload_off().unwrap();
+            payload.led_pins.yellow_led.set_low().ok();
+            payload.led_pins.red_led.set_low().ok();
+        }
 
         println!("");
         sec_elapsed_phase = 0;
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_sim::{SimBoard, SimLine};
+    use crate::payload::RampFault;
+    use crate::units::Ohms;
+    use std::boxed::Box;
+
+    #[test]
+    fn sensing_runs_in_every_phase() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]);
+        payload_off_sensing(&mut payload);
+
+        payload.pinpuller_pins.burn_wire_1.set_high().ok();
+        deployment_sensing(&mut payload);
+        payload.pinpuller_pins.burn_wire_1.set_low().ok();
+
+        let mut payload = payload.into_enabled_payload().into_enabled_heater();
+        payload.set_heater_voltage(Millivolts(3160));
+        emission_sensing(Millivolts(3160), TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS, CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS, &mut payload);
+        assert!(board.line(SimLine::HeaterEnable));
+    }
+
+    #[test]
+    fn ramps_reach_their_targets() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        assert_eq!(ramp_hvdc_supplies(Millivolts(100_000), Millivolts(150_000), &mut payload), Ok(()));
+        assert!((board.cathode_offset_voltage() - Millivolts(100_000)).0.abs() < 1000);
+        assert!((board.tether_bias_voltage() - Millivolts(150_000)).0.abs() < 1000);
+
+        assert_eq!(ramp_hvdc_supplies(CATHODE_OFFSET_MIN_VOLTAGE_MILLIVOLTS, TETHER_BIAS_MIN_VOLTAGE_MILLIVOLTS, &mut payload), Ok(()));
+        assert_eq!(board.dac_output_voltage(CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL), Millivolts::ZERO);
+        assert_eq!(board.dac_output_voltage(TETHER_BIAS_SUPPLY_CONTROL_CHANNEL), Millivolts::ZERO);
+    }
+
+    #[test]
+    fn aborted_ramp_up_drops_to_minimum() {
+        // A load heavy enough to trip the ramp's current limit long before it reaches the target
+        let shorted = |board: &SimBoard| {
+            let mut environment = board.environment();
+            environment.tether_bias_load = Ohms(1000);
+            environment.cathode_offset_load = Ohms(1000);
+            board.set_environment(environment);
+        };
+        let board = SimBoard::new();
+        shorted(&board);
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        payload.set_tether_bias_switch(SwitchState::Connected);
+        let err = ramp_hvdc_supplies(Millivolts(100_000), Millivolts(150_000), &mut payload).unwrap_err();
+        assert!(matches!(err.cause, RampFault::OverCurrent(_)));
+        // The cathode offset ramp finished, the tether bias one was dropped straight back down
+        assert!((board.cathode_offset_voltage() - Millivolts(100_000)).0.abs() < 1000);
+        assert_eq!(board.dac_output_voltage(TETHER_BIAS_SUPPLY_CONTROL_CHANNEL), Millivolts::ZERO);
+
+        // Nothing past an aborted ramp is started
+        let board = SimBoard::new();
+        shorted(&board);
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload();
+        payload.set_cathode_offset_switch(SwitchState::Connected);
+        assert!(ramp_hvdc_supplies(Millivolts(100_000), Millivolts(150_000), &mut payload).is_err());
+        assert_eq!(board.dac_output_voltage(CATHODE_OFFSET_SUPPLY_CONTROL_CHANNEL), Millivolts::ZERO);
+        assert_eq!(payload.get_tether_bias_dac_setpoint(), None);
+    }
+
+    #[test]
+    fn comparisons_pass_on_a_working_board() {
+        let board = SimBoard::new();
+        let mut payload = board.build_payload(Box::leak(Box::new([0; 512])), &[]).into_enabled_payload().into_enabled_heater();
+        payload.set_heater_voltage(Millivolts(3160));
+        payload.set_cathode_offset_voltage(Millivolts(100_000));
+        payload.set_tether_bias_voltage(Millivolts(100_000));
+        let readings = payload.get_tether_adc_readings().unwrap();
+
+        for result in compare_heater(Millivolts(3160), &readings).iter() {
+            assert!(!result.failed(), "{}", result.name());
+        }
+        assert!(!compare_cathode_offset(Millivolts(100_000), &readings).failed());
+        assert!(!compare_tether_bias(Millivolts(100_000), &readings).failed());
+    }
+}
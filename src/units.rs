@@ -0,0 +1,125 @@
+// This file provides newtypes for the physical quantities passed around the payload API, so that (e.g.) milliamps can't be passed where microamps are expected.
+// Each is a transparent wrapper, so they cost nothing over the bare integers. Arithmetic is only defined between values of the same unit, or with a plain scalar.
+
+use core::ops::{Add, Div, Mul, Sub};
+use ufmt::{uDisplay, uWrite, Formatter};
+
+macro_rules! unit {
+    ($(#[$meta:meta])* $name:ident($inner:ty)) => {
+        $(#[$meta])*
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Default, ufmt::derive::uDebug)]
+        #[repr(transparent)]
+        pub struct $name(pub $inner);
+        impl $name {
+            pub const ZERO: $name = $name(0);
+            pub const fn get(self) -> $inner {
+                self.0
+            }
+            /// None if `raw` doesn't fit.
+            pub fn try_from_raw<T: TryInto<$inner>>(raw: T) -> Option<$name> {
+                raw.try_into().ok().map($name)
+            }
+            /// None if the value doesn't fit in T, e.g. a negative voltage into a u16.
+            pub fn try_into_raw<T: TryFrom<$inner>>(self) -> Option<T> {
+                T::try_from(self.0).ok()
+            }
+            pub fn checked_add(self, rhs: $name) -> Option<$name> {
+                self.0.checked_add(rhs.0).map($name)
+            }
+            pub fn checked_sub(self, rhs: $name) -> Option<$name> {
+                self.0.checked_sub(rhs.0).map($name)
+            }
+            pub fn checked_mul(self, rhs: $inner) -> Option<$name> {
+                self.0.checked_mul(rhs).map($name)
+            }
+        }
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> $name {
+                $name(value)
+            }
+        }
+        impl From<u16> for $name {
+            fn from(value: u16) -> $name {
+                $name(value.into())
+            }
+        }
+        impl Add for $name {
+            type Output = $name;
+            fn add(self, rhs: $name) -> $name {
+                $name(self.0 + rhs.0)
+            }
+        }
+        impl Sub for $name {
+            type Output = $name;
+            fn sub(self, rhs: $name) -> $name {
+                $name(self.0 - rhs.0)
+            }
+        }
+        impl Mul<$inner> for $name {
+            type Output = $name;
+            fn mul(self, rhs: $inner) -> $name {
+                $name(self.0 * rhs)
+            }
+        }
+        impl Div<$inner> for $name {
+            type Output = $name;
+            fn div(self, rhs: $inner) -> $name {
+                $name(self.0 / rhs)
+            }
+        }
+        // Prints the bare number, so callers keep writing the unit themselves, e.g. "{}mV"
+        impl uDisplay for $name {
+            fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+                uDisplay::fmt(&self.0, f)
+            }
+        }
+    };
+}
+
+unit!(Millivolts(i32));
+unit!(Microamps(i32));
+unit!(Milliamps(i32));
+unit!(Milliwatts(i32));
+unit!(Kelvin(i32));
+unit!(Ohms(u32));
+
+// Signed units can be compared with calculate_rpd and friends
+impl From<Millivolts> for i32 { fn from(value: Millivolts) -> i32 { value.0 } }
+impl From<Microamps> for i32  { fn from(value: Microamps) -> i32 { value.0 } }
+impl From<Milliamps> for i32  { fn from(value: Milliamps) -> i32 { value.0 } }
+impl From<Milliwatts> for i32 { fn from(value: Milliwatts) -> i32 { value.0 } }
+impl From<Kelvin> for i32     { fn from(value: Kelvin) -> i32 { value.0 } }
+
+impl Millivolts {
+    /// Current through `resistance` with this voltage across it. None for zero resistance or on overflow.
+    pub fn current_through(self, resistance: Ohms) -> Option<Microamps> {
+        let resistance = i32::try_from(resistance.0).ok()?;
+        self.0.checked_mul(1000)?.checked_div(resistance).map(Microamps)
+    }
+    /// Power delivered with this voltage across a load drawing `current`. Saturates rather than overflowing.
+    pub fn power_with(self, current: Milliamps) -> Milliwatts {
+        let microwatts = self.0 as i64 * current.0 as i64;
+        Milliwatts((microwatts / 1000).clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+    }
+}
+impl Milliamps {
+    pub fn checked_to_microamps(self) -> Option<Microamps> {
+        self.0.checked_mul(1000).map(Microamps)
+    }
+}
+impl Microamps {
+    /// Rounds towards zero.
+    pub fn to_milliamps(self) -> Milliamps {
+        Milliamps(self.0 / 1000)
+    }
+}
+
+const CELCIUS_TO_KELVIN_OFFSET: i32 = 273;
+impl Kelvin {
+    pub fn from_celcius(celcius: i32) -> Kelvin {
+        Kelvin(celcius + CELCIUS_TO_KELVIN_OFFSET)
+    }
+    pub fn to_celcius(self) -> i32 {
+        self.0 - CELCIUS_TO_KELVIN_OFFSET
+    }
+}